@@ -0,0 +1,522 @@
+//! Kubernetes CEL authorizer library.
+//!
+//! Provides the `authorizer` checks available to admission policy
+//! expressions, matching `k8s.io/apiserver/pkg/cel/library/authz.go`:
+//!
+//! ```text
+//! authorizer.group('apps').resource('deployments').namespace('prod').check('delete').allowed()
+//! authorizer.path('/metrics').check('get').reason()
+//! authorizer.serviceAccount('kube-system', 'deployer').group('').resource('pods').check('create').allowed()
+//! ```
+//!
+//! Decisions come from a user-supplied [`Authorizer`], such as a fake for
+//! tests or a client for the `SubjectAccessReview` API. Bind it as the
+//! `authorizer` variable with [`SharedAuthorizer::value_for`], or pass it to
+//! policy evaluation through `PolicyRequest::authorizer`.
+
+use std::sync::Arc;
+
+use cel::extractors::This;
+use cel::objects::{Opaque, Value};
+use cel::{Context, ExecutionError, ResolveResult};
+
+/// The attributes of one authorization check.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    /// The user the check is made for.
+    pub user: String,
+    /// The user's groups.
+    pub groups: Vec<String>,
+    /// The verb, e.g. `get` or `create`.
+    pub verb: String,
+    /// The non-resource URL path, for `authorizer.path(...)` checks.
+    /// `None` for resource checks.
+    pub path: Option<String>,
+    /// The API group (empty for the core group).
+    pub api_group: String,
+    /// The resource, e.g. `deployments`.
+    pub resource: String,
+    /// The subresource, e.g. `status`, or empty.
+    pub subresource: String,
+    /// The namespace, or empty for cluster-wide checks.
+    pub namespace: String,
+    /// The object name, or empty for checks on every name.
+    pub name: String,
+}
+
+impl Attributes {
+    /// Whether this checks access to a resource rather than a URL path.
+    #[must_use]
+    pub fn is_resource_request(&self) -> bool {
+        self.path.is_none()
+    }
+}
+
+/// An authorizer's verdict.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Verdict {
+    /// The request is allowed.
+    Allow,
+    /// The request is explicitly denied.
+    Deny,
+    /// The authorizer has no opinion; the request is not allowed.
+    NoOpinion,
+}
+
+/// The result of an authorization check, exposed to CEL as
+/// `kubernetes.authorization.Decision`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decision {
+    /// The verdict.
+    pub verdict: Verdict,
+    /// Why the verdict was reached, returned by `reason()`.
+    pub reason: String,
+    /// The error the authorizer hit, if any, returned by `error()`.
+    pub error: Option<String>,
+}
+
+impl Decision {
+    /// An `Allow` decision.
+    #[must_use]
+    pub fn allow(reason: impl Into<String>) -> Self {
+        Self::new(Verdict::Allow, reason)
+    }
+
+    /// A `Deny` decision.
+    #[must_use]
+    pub fn deny(reason: impl Into<String>) -> Self {
+        Self::new(Verdict::Deny, reason)
+    }
+
+    /// A `NoOpinion` decision.
+    #[must_use]
+    pub fn no_opinion(reason: impl Into<String>) -> Self {
+        Self::new(Verdict::NoOpinion, reason)
+    }
+
+    /// A `NoOpinion` decision for a check that failed with `error`.
+    #[must_use]
+    pub fn errored(error: impl Into<String>) -> Self {
+        Self {
+            error: Some(error.into()),
+            ..Self::no_opinion("")
+        }
+    }
+
+    fn new(verdict: Verdict, reason: impl Into<String>) -> Self {
+        Self {
+            verdict,
+            reason: reason.into(),
+            error: None,
+        }
+    }
+}
+
+impl Opaque for Decision {
+    fn runtime_type_name(&self) -> &str {
+        "kubernetes.authorization.Decision"
+    }
+}
+
+/// Makes authorization decisions for `authorizer` checks.
+pub trait Authorizer: Send + Sync {
+    /// Decide whether `attributes.user` may perform the request.
+    fn authorize(&self, attributes: &Attributes) -> Decision;
+}
+
+impl<F> Authorizer for F
+where
+    F: Fn(&Attributes) -> Decision + Send + Sync,
+{
+    fn authorize(&self, attributes: &Attributes) -> Decision {
+        self(attributes)
+    }
+}
+
+/// An [`Authorizer`] that decides asynchronously, e.g. by calling the
+/// `SubjectAccessReview` API. Use it through [`BlockingAuthorizer`].
+#[cfg(feature = "tokio")]
+pub trait AsyncAuthorizer: Send + Sync {
+    /// Decide whether `attributes.user` may perform the request.
+    fn authorize<'a>(
+        &'a self,
+        attributes: &'a Attributes,
+    ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Decision> + Send + 'a>>;
+}
+
+/// Runs an [`AsyncAuthorizer`] to completion on a tokio runtime.
+///
+/// CEL evaluation is synchronous, so each check blocks the calling thread.
+/// Evaluate on a blocking-pool thread (e.g. with
+/// [`ValidatorHandle`](crate::handle::ValidatorHandle) or
+/// `tokio::task::spawn_blocking`), never on an async worker thread.
+#[cfg(feature = "tokio")]
+pub struct BlockingAuthorizer<A> {
+    inner: A,
+    runtime: tokio::runtime::Handle,
+}
+
+#[cfg(feature = "tokio")]
+impl<A: AsyncAuthorizer> BlockingAuthorizer<A> {
+    /// Wrap `inner`, running its checks on the current tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside a tokio runtime.
+    #[must_use]
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner,
+            runtime: tokio::runtime::Handle::current(),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<A: AsyncAuthorizer> Authorizer for BlockingAuthorizer<A> {
+    fn authorize(&self, attributes: &Attributes) -> Decision {
+        self.runtime.block_on(self.inner.authorize(attributes))
+    }
+}
+
+/// A cheaply cloneable handle to an [`Authorizer`].
+#[derive(Clone)]
+pub struct SharedAuthorizer(Arc<dyn Authorizer>);
+
+impl SharedAuthorizer {
+    /// Share `authorizer`.
+    pub fn new(authorizer: impl Authorizer + 'static) -> Self {
+        Self(Arc::new(authorizer))
+    }
+
+    /// The CEL `authorizer` value making checks for `user` in `groups`.
+    #[must_use]
+    pub fn value_for(&self, user: impl Into<String>, groups: Vec<String>) -> Value {
+        Value::Opaque(Arc::new(AuthorizerValue {
+            authorizer: self.clone(),
+            user: user.into(),
+            groups,
+        }))
+    }
+}
+
+impl std::fmt::Debug for SharedAuthorizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedAuthorizer(..)")
+    }
+}
+
+impl PartialEq for SharedAuthorizer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedAuthorizer {}
+
+/// The `authorizer` variable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct AuthorizerValue {
+    authorizer: SharedAuthorizer,
+    user: String,
+    groups: Vec<String>,
+}
+
+impl Opaque for AuthorizerValue {
+    fn runtime_type_name(&self) -> &str {
+        "kubernetes.authorization.Authorizer"
+    }
+}
+
+/// The result of `authorizer.path(...)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PathCheck {
+    authorizer: AuthorizerValue,
+    path: String,
+}
+
+impl Opaque for PathCheck {
+    fn runtime_type_name(&self) -> &str {
+        "kubernetes.authorization.PathCheck"
+    }
+}
+
+/// The result of `authorizer.group(...)`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct GroupCheck {
+    authorizer: AuthorizerValue,
+    group: String,
+}
+
+impl Opaque for GroupCheck {
+    fn runtime_type_name(&self) -> &str {
+        "kubernetes.authorization.GroupCheck"
+    }
+}
+
+/// The result of `.resource(...)` and its refinements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ResourceCheck {
+    authorizer: AuthorizerValue,
+    group: String,
+    resource: String,
+    subresource: String,
+    namespace: String,
+    name: String,
+}
+
+impl Opaque for ResourceCheck {
+    fn runtime_type_name(&self) -> &str {
+        "kubernetes.authorization.ResourceCheck"
+    }
+}
+
+/// Register all authorizer extension functions.
+pub fn register(ctx: &mut Context<'_>) {
+    ctx.add_function("path", authorizer_path);
+    ctx.add_function("group", authorizer_group);
+    ctx.add_function("serviceAccount", authorizer_service_account);
+    ctx.add_function("resource", group_check_resource);
+    ctx.add_function("subresource", resource_check_subresource);
+    ctx.add_function("namespace", resource_check_namespace);
+    ctx.add_function("name", resource_check_name);
+    ctx.add_function("check", check);
+    ctx.add_function("allowed", decision_allowed);
+    ctx.add_function("reason", decision_reason);
+    ctx.add_function("errored", decision_errored);
+    ctx.add_function("error", decision_error);
+}
+
+/// Downcast an opaque `this` to `T`, naming `function` in the error.
+fn extract<'a, T: Opaque>(val: &'a Value, function: &str) -> Result<&'a T, ExecutionError> {
+    match val {
+        Value::Opaque(o) => o.downcast_ref::<T>(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        ExecutionError::function_error(function, format!("{function} not supported on this type"))
+    })
+}
+
+fn opaque(value: impl Opaque) -> ResolveResult {
+    Ok(Value::Opaque(Arc::new(value)))
+}
+
+fn authorizer_path(This(this): This<Value>, path: Arc<String>) -> ResolveResult {
+    let authorizer = extract::<AuthorizerValue>(&this, "path")?;
+    opaque(PathCheck {
+        authorizer: authorizer.clone(),
+        path: (*path).clone(),
+    })
+}
+
+fn authorizer_group(This(this): This<Value>, group: Arc<String>) -> ResolveResult {
+    let authorizer = extract::<AuthorizerValue>(&this, "group")?;
+    opaque(GroupCheck {
+        authorizer: authorizer.clone(),
+        group: (*group).clone(),
+    })
+}
+
+/// `serviceAccount(namespace, name)`: make checks as a service account.
+fn authorizer_service_account(
+    This(this): This<Value>,
+    namespace: Arc<String>,
+    name: Arc<String>,
+) -> ResolveResult {
+    let authorizer = extract::<AuthorizerValue>(&this, "serviceAccount")?;
+    opaque(AuthorizerValue {
+        authorizer: authorizer.authorizer.clone(),
+        user: format!("system:serviceaccount:{namespace}:{name}"),
+        groups: vec![
+            "system:serviceaccounts".to_string(),
+            format!("system:serviceaccounts:{namespace}"),
+        ],
+    })
+}
+
+fn group_check_resource(This(this): This<Value>, resource: Arc<String>) -> ResolveResult {
+    let group = extract::<GroupCheck>(&this, "resource")?;
+    opaque(ResourceCheck {
+        authorizer: group.authorizer.clone(),
+        group: group.group.clone(),
+        resource: (*resource).clone(),
+        subresource: String::new(),
+        namespace: String::new(),
+        name: String::new(),
+    })
+}
+
+/// Copy the resource check in `this` with one field replaced.
+fn refine(this: &Value, function: &str, update: impl FnOnce(&mut ResourceCheck)) -> ResolveResult {
+    let mut check = extract::<ResourceCheck>(this, function)?.clone();
+    update(&mut check);
+    opaque(check)
+}
+
+fn resource_check_subresource(This(this): This<Value>, subresource: Arc<String>) -> ResolveResult {
+    refine(&this, "subresource", |c| {
+        c.subresource = (*subresource).clone()
+    })
+}
+
+fn resource_check_namespace(This(this): This<Value>, namespace: Arc<String>) -> ResolveResult {
+    refine(&this, "namespace", |c| c.namespace = (*namespace).clone())
+}
+
+fn resource_check_name(This(this): This<Value>, name: Arc<String>) -> ResolveResult {
+    refine(&this, "name", |c| c.name = (*name).clone())
+}
+
+/// `check(verb)` on a path or resource check.
+fn check(This(this): This<Value>, verb: Arc<String>) -> ResolveResult {
+    let (authorizer, attributes) = if let Ok(path) = extract::<PathCheck>(&this, "check") {
+        let attributes = Attributes {
+            path: Some(path.path.clone()),
+            ..Attributes::default()
+        };
+        (&path.authorizer, attributes)
+    } else {
+        let resource = extract::<ResourceCheck>(&this, "check")?;
+        let attributes = Attributes {
+            api_group: resource.group.clone(),
+            resource: resource.resource.clone(),
+            subresource: resource.subresource.clone(),
+            namespace: resource.namespace.clone(),
+            name: resource.name.clone(),
+            ..Attributes::default()
+        };
+        (&resource.authorizer, attributes)
+    };
+    let attributes = Attributes {
+        user: authorizer.user.clone(),
+        groups: authorizer.groups.clone(),
+        verb: (*verb).clone(),
+        ..attributes
+    };
+    opaque(authorizer.authorizer.0.authorize(&attributes))
+}
+
+fn decision_allowed(This(this): This<Value>) -> ResolveResult {
+    let decision = extract::<Decision>(&this, "allowed")?;
+    Ok(Value::Bool(decision.verdict == Verdict::Allow))
+}
+
+fn decision_reason(This(this): This<Value>) -> ResolveResult {
+    let decision = extract::<Decision>(&this, "reason")?;
+    Ok(Value::String(Arc::new(decision.reason.clone())))
+}
+
+fn decision_errored(This(this): This<Value>) -> ResolveResult {
+    let decision = extract::<Decision>(&this, "errored")?;
+    Ok(Value::Bool(decision.error.is_some()))
+}
+
+fn decision_error(This(this): This<Value>) -> ResolveResult {
+    let decision = extract::<Decision>(&this, "error")?;
+    Ok(Value::String(Arc::new(
+        decision.error.clone().unwrap_or_default(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel::Program;
+    use std::sync::Mutex;
+
+    /// Allows `alice` to do anything in `dev`, and `get` on `/healthz`.
+    fn fake(attributes: &Attributes) -> Decision {
+        if attributes.path.as_deref() == Some("/healthz") && attributes.verb == "get" {
+            Decision::allow("health checks are public")
+        } else if attributes.user == "alice" && attributes.namespace == "dev" {
+            Decision::allow("alice owns dev")
+        } else if attributes.resource == "secrets" {
+            Decision::errored("secret access is audited elsewhere")
+        } else {
+            Decision::no_opinion("")
+        }
+    }
+
+    fn eval(expr: &str) -> Value {
+        let mut ctx = Context::default();
+        register(&mut ctx);
+        ctx.add_variable_from_value(
+            "authorizer",
+            SharedAuthorizer::new(fake).value_for("alice", vec![]),
+        );
+        Program::compile(expr).unwrap().execute(&ctx).unwrap()
+    }
+
+    #[test]
+    fn resource_checks() {
+        assert_eq!(
+            eval(
+                "authorizer.group('apps').resource('deployments').namespace('dev').check('delete').allowed()"
+            ),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval(
+                "authorizer.group('apps').resource('deployments').namespace('prod').check('delete').allowed()"
+            ),
+            Value::Bool(false)
+        );
+        assert_eq!(
+            eval("authorizer.group('').resource('pods').namespace('dev').check('get').reason()"),
+            Value::String(Arc::new("alice owns dev".into()))
+        );
+    }
+
+    #[test]
+    fn path_checks_and_errors() {
+        assert_eq!(
+            eval("authorizer.path('/healthz').check('get').allowed()"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("authorizer.group('').resource('secrets').check('get').errored()"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("authorizer.group('').resource('secrets').check('get').error()"),
+            Value::String(Arc::new("secret access is audited elsewhere".into()))
+        );
+    }
+
+    #[test]
+    fn service_account_checks_pass_all_attributes() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let authorizer = SharedAuthorizer::new(move |attributes: &Attributes| {
+            sink.lock().unwrap().push(attributes.clone());
+            Decision::deny("no")
+        });
+        let mut ctx = Context::default();
+        register(&mut ctx);
+        ctx.add_variable_from_value("authorizer", authorizer.value_for("bob", vec![]));
+        let expr = "authorizer.serviceAccount('ci', 'deployer').group('apps').resource('deployments').subresource('scale').namespace('ci').name('web').check('update').allowed()";
+        let result = Program::compile(expr).unwrap().execute(&ctx).unwrap();
+        assert_eq!(result, Value::Bool(false));
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(
+            seen[0],
+            Attributes {
+                user: "system:serviceaccount:ci:deployer".into(),
+                groups: vec![
+                    "system:serviceaccounts".into(),
+                    "system:serviceaccounts:ci".into()
+                ],
+                verb: "update".into(),
+                path: None,
+                api_group: "apps".into(),
+                resource: "deployments".into(),
+                subresource: "scale".into(),
+                namespace: "ci".into(),
+                name: "web".into(),
+            }
+        );
+        assert!(seen[0].is_resource_request());
+    }
+}
@@ -3,48 +3,575 @@
 //! This module parses validation rules from CRD schemas and compiles them into
 //! [`cel::Program`] instances that can be evaluated against resource data.
 
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
 
+use cel::common::ast::{EntryExpr, Expr};
+use cel::common::value::CelVal;
+use cel::parser::Expression;
 use cel::{ParseErrors, Program};
 
+use crate::cost::{
+    PER_CALL_LIMIT, STATIC_ESTIMATED_COST_LIMIT, estimate_cost, estimate_cost_with_schema,
+};
+use crate::functions::{References, check_functions};
+use crate::structural::StructuralSchema;
+use crate::typecheck::typecheck;
 use crate::values::SchemaFormat;
 
 /// A single CRD `x-kubernetes-validations` rule.
-#[derive(Clone, Debug, serde::Deserialize)]
+///
+/// Serializes in the CRD format, leaving out unset fields.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Rule {
     /// The CEL expression to evaluate.
     pub rule: String,
     /// Static error message returned when validation fails.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
     /// CEL expression that produces a dynamic error message.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub message_expression: Option<String>,
-    /// Machine-readable reason for the validation failure (e.g. "FieldValueForbidden").
-    #[serde(default)]
-    pub reason: Option<String>,
+    /// Machine-readable reason for the validation failure.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
     /// JSONPath to the field that caused the failure.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub field_path: Option<String>,
     /// Whether `oldSelf` is optional. When `true`, transition rules are
     /// evaluated even on create (with `oldSelf` bound to null).
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub optional_old_self: Option<bool>,
+    /// Severity of a failure, from the `x-kube-cel-severity` extension key.
+    /// Warning rules are reported separately and never fail validation.
+    #[serde(
+        default,
+        rename = "x-kube-cel-severity",
+        skip_serializing_if = "Severity::is_error"
+    )]
+    pub severity: Severity,
+}
+
+impl Rule {
+    /// Start building a rule with the CEL expression `rule`.
+    ///
+    /// ```
+    /// use kube_cel::compilation::{Reason, Rule};
+    ///
+    /// let rule = Rule::builder("self.replicas >= 0")
+    ///     .with_message("replicas must not be negative")
+    ///     .with_reason(Reason::FieldValueInvalid)
+    ///     .with_field_path(".replicas")
+    ///     .build();
+    /// assert_eq!(
+    ///     serde_json::to_value(&rule).unwrap(),
+    ///     serde_json::json!({
+    ///         "rule": "self.replicas >= 0",
+    ///         "message": "replicas must not be negative",
+    ///         "reason": "FieldValueInvalid",
+    ///         "fieldPath": ".replicas"
+    ///     })
+    /// );
+    /// ```
+    #[must_use]
+    pub fn builder(rule: impl Into<String>) -> RuleBuilder {
+        RuleBuilder {
+            rule: Rule {
+                rule: rule.into(),
+                message: None,
+                message_expression: None,
+                reason: None,
+                field_path: None,
+                optional_old_self: None,
+                severity: Severity::Error,
+            },
+        }
+    }
+}
+
+/// Builds a [`Rule`], from [`Rule::builder`].
+#[derive(Clone, Debug)]
+pub struct RuleBuilder {
+    rule: Rule,
+}
+
+impl RuleBuilder {
+    /// Set the static `message` reported when the rule fails.
+    #[must_use]
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.rule.message = Some(message.into());
+        self
+    }
+
+    /// Set the `messageExpression` that produces the failure message.
+    #[must_use]
+    pub fn with_message_expression(mut self, expression: impl Into<String>) -> Self {
+        self.rule.message_expression = Some(expression.into());
+        self
+    }
+
+    /// Set the `reason` reported when the rule fails.
+    #[must_use]
+    pub fn with_reason(mut self, reason: impl Into<Reason>) -> Self {
+        self.rule.reason = Some(reason.into());
+        self
+    }
+
+    /// Set the `fieldPath` the failure is reported at.
+    #[must_use]
+    pub fn with_field_path(mut self, field_path: impl Into<String>) -> Self {
+        self.rule.field_path = Some(field_path.into());
+        self
+    }
+
+    /// Set `optionalOldSelf`.
+    #[must_use]
+    pub fn with_optional_old_self(mut self, optional: bool) -> Self {
+        self.rule.optional_old_self = Some(optional);
+        self
+    }
+
+    /// Set the severity of a failure.
+    #[must_use]
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.rule.severity = severity;
+        self
+    }
+
+    /// Finish building the rule.
+    #[must_use]
+    pub fn build(self) -> Rule {
+        self.rule
+    }
+}
+
+/// The severity of a rule failure.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    /// The failure rejects the object (the default).
+    #[default]
+    Error,
+    /// The failure is advisory only.
+    Warning,
+}
+
+impl Severity {
+    fn is_error(&self) -> bool {
+        *self == Severity::Error
+    }
+}
+
+/// The machine-readable `reason` of a validation rule.
+///
+/// Kubernetes allows the four `FieldValue*` reasons; anything else is kept
+/// verbatim in [`Reason::Unknown`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Reason {
+    /// `FieldValueInvalid` — the value is invalid (the default).
+    FieldValueInvalid,
+    /// `FieldValueForbidden` — the value is not allowed.
+    FieldValueForbidden,
+    /// `FieldValueRequired` — a required value is missing.
+    FieldValueRequired,
+    /// `FieldValueDuplicate` — the value duplicates another one.
+    FieldValueDuplicate,
+    /// Any other reason string.
+    Unknown(String),
+}
+
+impl Reason {
+    /// The reason as it appears in the CRD.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        match self {
+            Reason::FieldValueInvalid => "FieldValueInvalid",
+            Reason::FieldValueForbidden => "FieldValueForbidden",
+            Reason::FieldValueRequired => "FieldValueRequired",
+            Reason::FieldValueDuplicate => "FieldValueDuplicate",
+            Reason::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<String> for Reason {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "FieldValueInvalid" => Reason::FieldValueInvalid,
+            "FieldValueForbidden" => Reason::FieldValueForbidden,
+            "FieldValueRequired" => Reason::FieldValueRequired,
+            "FieldValueDuplicate" => Reason::FieldValueDuplicate,
+            _ => Reason::Unknown(s),
+        }
+    }
+}
+
+impl From<Reason> for String {
+    fn from(reason: Reason) -> Self {
+        match reason {
+            Reason::Unknown(s) => s,
+            known => known.as_str().to_string(),
+        }
+    }
+}
+
+impl From<&str> for Reason {
+    fn from(s: &str) -> Self {
+        Reason::from(s.to_string())
+    }
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// The result of successfully compiling a [`Rule`].
 #[derive(Debug)]
 pub struct CompilationResult {
-    /// The compiled CEL program.
-    pub program: Program,
+    /// The compiled CEL program, shared with identical rules compiled in
+    /// the same [`compile_schema`] call or through the same
+    /// [`ProgramCache`].
+    pub program: Arc<Program>,
     /// The original rule that was compiled.
     pub rule: Rule,
     /// Whether the rule references `oldSelf` (transition rule).
     pub is_transition_rule: bool,
     /// Pre-compiled `messageExpression` program (if present and valid).
     /// `None` if no `messageExpression` was specified or if it failed to compile.
-    pub message_program: Option<Program>,
+    pub message_program: Option<Arc<Program>>,
+    /// Worst-case evaluation cost estimated at compile time
+    /// (see [`crate::cost`]).
+    pub estimated_cost: u64,
+    /// Problems that do not stop the rule from being evaluated.
+    pub warnings: Vec<CompilationWarning>,
+}
+
+/// A problem with a compiled rule that does not stop it from being
+/// evaluated, but deserves attention.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CompilationWarning {
+    /// The `messageExpression` failed to compile, so the static `message`
+    /// is reported instead.
+    InvalidMessageExpression {
+        /// The `messageExpression`.
+        expression: String,
+        /// The parse errors.
+        message: String,
+    },
+    /// The estimated cost is above
+    /// [`CompileOptions::cost_warning_threshold`], by default the
+    /// apiserver's per-call runtime limit, so evaluation may be aborted on
+    /// large objects.
+    HighEstimatedCost {
+        /// The estimated worst-case cost.
+        estimated_cost: u64,
+        /// The threshold.
+        limit: u64,
+    },
+    /// The definition breaks one of the limits checked in
+    /// [strict](CompileOptions::strict) mode.
+    InvalidDefinition {
+        /// What is wrong with the definition.
+        message: String,
+    },
+    /// A transition rule is declared where `oldSelf` can never be bound,
+    /// which [strict](CompileOptions::strict) mode rejects.
+    UncorrelatableTransitionRule,
+}
+
+impl std::fmt::Display for CompilationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompilationWarning::InvalidMessageExpression {
+                expression,
+                message,
+            } => write!(
+                f,
+                "messageExpression \"{expression}\" failed to compile and is ignored: {message}"
+            ),
+            CompilationWarning::HighEstimatedCost {
+                estimated_cost,
+                limit,
+            } => write!(
+                f,
+                "estimated cost {estimated_cost} exceeds the warning threshold of {limit}"
+            ),
+            CompilationWarning::InvalidDefinition { message } => {
+                write!(f, "invalid rule definition: {message}")
+            }
+            CompilationWarning::UncorrelatableTransitionRule => write!(
+                f,
+                "transition rule uses oldSelf on a node that cannot be correlated with the \
+                 old object"
+            ),
+        }
+    }
+}
+
+impl CompilationResult {
+    /// The variables the rule and its `messageExpression` read, sorted,
+    /// e.g. `["oldSelf", "self"]`. Comprehension variables are not listed.
+    #[must_use]
+    pub fn referenced_variables(&self) -> Vec<String> {
+        self.references().variables.into_iter().collect()
+    }
+
+    /// The functions the rule and its `messageExpression` call, sorted,
+    /// e.g. `["matches", "size"]`. Operators are not listed, and namespaced
+    /// functions are listed by their qualified name, e.g. `math.ceil`.
+    #[must_use]
+    pub fn referenced_functions(&self) -> Vec<String> {
+        self.references().functions.into_iter().collect()
+    }
+
+    fn references(&self) -> References {
+        let mut references = References::default();
+        for program in std::iter::once(&self.program).chain(&self.message_program) {
+            references.walk(program.expression());
+        }
+        references
+    }
+}
+
+/// What one compiled rule of a [`CompiledSchema`] depends on, from
+/// [`CompiledSchema::rule_references`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleReferences {
+    /// Location of the rule, e.g.
+    /// `properties[spec].x-kubernetes-validations[0]`.
+    pub schema_path: String,
+    /// The rule's CEL expression.
+    pub rule: String,
+    /// See [`CompilationResult::referenced_variables`].
+    pub variables: Vec<String>,
+    /// See [`CompilationResult::referenced_functions`].
+    pub functions: Vec<String>,
+}
+
+/// Maximum length of a rule's CEL expression kube-apiserver accepts, the
+/// default of [`CompileOptions::max_rule_length`].
+pub const MAX_RULE_LENGTH: usize = 4096;
+
+/// Maximum length of a rule's `message` kube-apiserver accepts, the default
+/// of [`CompileOptions::max_message_length`].
+pub const MAX_MESSAGE_LENGTH: usize = 2048;
+
+/// Default maximum nesting depth of schemas and objects, used by
+/// [`compile_schema`] and the [`Validator`](crate::validation::Validator).
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// How [`compile_rule_with_options`] and [`compile_schema_with_options`]
+/// compile rules: the limits they enforce and the optional checks they run.
+///
+/// New knobs are added here rather than as new compile functions, so build
+/// options with `..CompileOptions::default()`:
+///
+/// ```
+/// use kube_cel::compilation::{CompileOptions, compile_schema_with_options};
+/// use serde_json::json;
+///
+/// let options = CompileOptions {
+///     strict: true,
+///     typecheck: true,
+///     ..CompileOptions::default()
+/// };
+/// let schema = json!({
+///     "type": "object",
+///     "properties": {"replicas": {"type": "integer"}},
+///     "x-kubernetes-validations": [{"rule": "self.replica >= 0"}]
+/// });
+/// assert!(compile_schema_with_options(&schema, &options).has_errors());
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    /// Maximum estimated cost of a single rule. Defaults to
+    /// [`STATIC_ESTIMATED_COST_LIMIT`], the apiserver's per-rule limit.
+    pub max_estimated_cost: u64,
+    /// Maximum nesting depth of the schema tree. Deeper nodes are not
+    /// compiled. Defaults to [`DEFAULT_MAX_DEPTH`].
+    pub max_depth: usize,
+    /// Also reject rule definitions kube-apiserver would refuse in a CRD:
+    /// an empty rule, a rule longer than
+    /// [`max_rule_length`](Self::max_rule_length), a message longer than
+    /// [`max_message_length`](Self::max_message_length), a blank message, a
+    /// message with line breaks, or a multi-line rule without a message. In
+    /// [`compile_schema_with_options`], transition rules on uncorrelatable
+    /// nodes are rejected too. Otherwise these are
+    /// [warnings](CompilationResult::warnings). Defaults to `false`.
+    pub strict: bool,
+    /// Maximum length of a rule's CEL expression. Defaults to
+    /// [`MAX_RULE_LENGTH`].
+    pub max_rule_length: usize,
+    /// Maximum length of a rule's `message`. Defaults to
+    /// [`MAX_MESSAGE_LENGTH`].
+    pub max_message_length: usize,
+    /// Reject rules whose `messageExpression` fails to compile, as
+    /// [`CompilationError::InvalidDefinition`], instead of warning and
+    /// reporting the static `message`. Defaults to `false`.
+    pub reject_invalid_message_expressions: bool,
+    /// Estimated cost above which a rule gets a
+    /// [`CompilationWarning::HighEstimatedCost`]. Defaults to
+    /// [`PER_CALL_LIMIT`], the apiserver's runtime limit per evaluation.
+    pub cost_warning_threshold: u64,
+    /// Also type-check rules against the types the schema gives `self` and
+    /// `oldSelf`, rejecting undeclared fields and mismatched comparisons
+    /// (see [`crate::typecheck`]). Only applies in
+    /// [`compile_schema_with_options`], where the schema is known. Defaults
+    /// to `false`.
+    pub typecheck: bool,
+    /// Also reject rules calling functions that are not declared, such as
+    /// `self.name.trimm()` (see [`crate::functions`]). Leave it off when
+    /// validating with functions registered through
+    /// [`ValidatorOptions::with_functions`](crate::validation::ValidatorOptions::with_functions).
+    /// Defaults to `false`.
+    pub check_functions: bool,
+    /// A cache to share compiled programs with other compilations. Within
+    /// one compilation, identical expressions always share one program.
+    /// Defaults to `None`.
+    pub program_cache: Option<Arc<ProgramCache>>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            max_estimated_cost: STATIC_ESTIMATED_COST_LIMIT,
+            max_depth: DEFAULT_MAX_DEPTH,
+            strict: false,
+            max_rule_length: MAX_RULE_LENGTH,
+            max_message_length: MAX_MESSAGE_LENGTH,
+            reject_invalid_message_expressions: false,
+            cost_warning_threshold: PER_CALL_LIMIT,
+            typecheck: false,
+            check_functions: false,
+            program_cache: None,
+        }
+    }
+}
+
+impl CompileOptions {
+    /// Reject a compiled rule that breaks these limits. `correlatable` is
+    /// whether the rule's node can be correlated with the old object, and
+    /// `schema` the node, if known.
+    fn enforce(
+        &self,
+        mut result: CompilationResult,
+        correlatable: bool,
+        schema: Option<&serde_json::Value>,
+    ) -> Result<CompilationResult, CompilationError> {
+        if self.reject_invalid_message_expressions
+            && let Some(CompilationWarning::InvalidMessageExpression { message, .. }) = result
+                .warnings
+                .iter()
+                .find(|w| matches!(w, CompilationWarning::InvalidMessageExpression { .. }))
+        {
+            return Err(CompilationError::InvalidDefinition {
+                rule: result.rule.rule.clone(),
+                message: format!("messageExpression failed to compile: {message}"),
+                schema_path: String::new(),
+            });
+        }
+        if result.estimated_cost > self.cost_warning_threshold {
+            result.warnings.push(CompilationWarning::HighEstimatedCost {
+                estimated_cost: result.estimated_cost,
+                limit: self.cost_warning_threshold,
+            });
+        }
+        if self.strict {
+            self.check_definition(&result.rule).map_err(|message| {
+                CompilationError::InvalidDefinition {
+                    rule: result.rule.rule.clone(),
+                    message,
+                    schema_path: String::new(),
+                }
+            })?;
+            if result.is_transition_rule && !correlatable {
+                return Err(CompilationError::UncorrelatableTransitionRule {
+                    rule: result.rule.rule,
+                    schema_path: String::new(),
+                });
+            }
+        } else {
+            if let Err(message) = self.check_definition(&result.rule) {
+                result
+                    .warnings
+                    .push(CompilationWarning::InvalidDefinition { message });
+            }
+            if result.is_transition_rule && !correlatable {
+                result
+                    .warnings
+                    .push(CompilationWarning::UncorrelatableTransitionRule);
+            }
+        }
+        if result.estimated_cost > self.max_estimated_cost {
+            return Err(CompilationError::CostLimitExceeded {
+                rule: result.rule.rule,
+                estimated_cost: result.estimated_cost,
+                limit: self.max_estimated_cost,
+                schema_path: String::new(),
+            });
+        }
+        if self.check_functions
+            && let Err(undeclared) = check_functions(result.program.expression())
+        {
+            return Err(CompilationError::UndeclaredFunction {
+                rule: result.rule.rule,
+                function: undeclared.name,
+                suggestion: undeclared.suggestion.map(str::to_string),
+                schema_path: String::new(),
+            });
+        }
+        if self.typecheck
+            && let Some(schema) = schema
+            && let Err(message) = typecheck(&result, schema)
+        {
+            return Err(CompilationError::TypeCheck {
+                rule: result.rule.rule,
+                message,
+                schema_path: String::new(),
+            });
+        }
+        Ok(result)
+    }
+
+    /// The checks kube-apiserver applies to a rule definition, beyond
+    /// compiling it, with these options' length limits.
+    pub(crate) fn check_definition(&self, rule: &Rule) -> Result<(), String> {
+        let has_line_breaks = |s: &str| s.contains(['\n', '\r']);
+        if rule.rule.trim().is_empty() {
+            return Err("rule is not specified".into());
+        }
+        if rule.rule.len() > self.max_rule_length {
+            return Err(format!(
+                "rule is {} characters long, more than the limit of {}",
+                rule.rule.len(),
+                self.max_rule_length
+            ));
+        }
+        match rule.message.as_deref() {
+            Some(message) if message.trim().is_empty() => {
+                Err("message must be non-empty if specified".into())
+            }
+            Some(message) if message.len() > self.max_message_length => Err(format!(
+                "message is {} characters long, more than the limit of {}",
+                message.len(),
+                self.max_message_length
+            )),
+            Some(message) if has_line_breaks(message) => {
+                Err("message must not contain line breaks".into())
+            }
+            None if has_line_breaks(&rule.rule) => {
+                Err("message must be specified if rule contains line breaks".into())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 /// Errors that can occur during rule compilation.
@@ -56,19 +583,237 @@ pub enum CompilationError {
         rule: String,
         /// The parse errors reported by the CEL compiler.
         source: ParseErrors,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
     },
     /// JSON value could not be deserialized into a [`Rule`].
-    InvalidRule(serde_json::Error),
+    InvalidRule {
+        /// The deserialization error.
+        source: serde_json::Error,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The rule's estimated cost exceeds the configured limit.
+    CostLimitExceeded {
+        /// The CEL expression whose cost is too high.
+        rule: String,
+        /// The estimated worst-case cost.
+        estimated_cost: u64,
+        /// The limit that was exceeded.
+        limit: u64,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The rule definition breaks one of the limits checked in strict mode
+    /// (see [`CompileOptions::strict`]).
+    InvalidDefinition {
+        /// The CEL expression of the offending rule.
+        rule: String,
+        /// What is wrong with the definition.
+        message: String,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The rule calls a function that is not declared. Only reported with
+    /// [`CompileOptions::check_functions`].
+    UndeclaredFunction {
+        /// The CEL expression of the offending rule.
+        rule: String,
+        /// The undeclared function, e.g. `trimm`.
+        function: String,
+        /// The closest declared function, e.g. `trim`.
+        suggestion: Option<String>,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The rule passes a literal regular expression that does not compile
+    /// to `matches`, `find`, or `findAll`. kube-apiserver compiles these
+    /// patterns with the rule and rejects the CRD.
+    InvalidRegex {
+        /// The CEL expression of the offending rule.
+        rule: String,
+        /// The regular expression.
+        pattern: String,
+        /// Why the regular expression does not compile.
+        message: String,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The rule does not type-check against the schema of its node. Only
+    /// reported with [`CompileOptions::typecheck`].
+    TypeCheck {
+        /// The CEL expression of the offending rule.
+        rule: String,
+        /// The type error, e.g. `undefined field 'replica'`.
+        message: String,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// A transition rule is declared where `oldSelf` can never be bound:
+    /// below an atomic map or in the items of a list that is not
+    /// `x-kubernetes-list-type: map`. Only reported in strict mode.
+    UncorrelatableTransitionRule {
+        /// The CEL expression of the offending rule.
+        rule: String,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// The schema is nested deeper than the configured maximum depth.
+    DepthLimitExceeded {
+        /// The depth limit.
+        limit: usize,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+    /// A `$ref` could not be resolved, or refers back to one of the schemas
+    /// that contain it.
+    InvalidRef {
+        /// The `$ref` value, e.g. `#/definitions/Container`.
+        reference: String,
+        /// Why the reference could not be used.
+        message: String,
+        /// Where in the schema the error occurred (see
+        /// [`CompilationError::schema_path`]).
+        schema_path: String,
+    },
+}
+
+impl CompilationError {
+    /// Where in the schema the error occurred, in the format of
+    /// [`LintIssue::location`](crate::lint::LintIssue::location), e.g.
+    /// `properties[spec].properties[replicas].x-kubernetes-validations[0]`.
+    ///
+    /// Errors in a rule point at the rule; errors in a schema node
+    /// ([`DepthLimitExceeded`](Self::DepthLimitExceeded),
+    /// [`InvalidRef`](Self::InvalidRef)) point at the node. Empty for the
+    /// root node and for rules compiled on their own.
+    #[must_use]
+    pub fn schema_path(&self) -> &str {
+        match self {
+            CompilationError::Parse { schema_path, .. }
+            | CompilationError::InvalidRule { schema_path, .. }
+            | CompilationError::CostLimitExceeded { schema_path, .. }
+            | CompilationError::InvalidDefinition { schema_path, .. }
+            | CompilationError::UndeclaredFunction { schema_path, .. }
+            | CompilationError::InvalidRegex { schema_path, .. }
+            | CompilationError::TypeCheck { schema_path, .. }
+            | CompilationError::UncorrelatableTransitionRule { schema_path, .. }
+            | CompilationError::DepthLimitExceeded { schema_path, .. }
+            | CompilationError::InvalidRef { schema_path, .. } => schema_path,
+        }
+    }
+
+    /// The parse errors of a [`Parse`](Self::Parse) error, with where they
+    /// are in the rule. Empty for other errors.
+    #[must_use]
+    pub fn parse_diagnostics(&self) -> Vec<ParseDiagnostic> {
+        match self {
+            CompilationError::Parse { rule, source, .. } => source
+                .errors
+                .iter()
+                .map(|error| ParseDiagnostic {
+                    message: error.msg.clone(),
+                    span: SourceSpan::new(rule, error.pos.0, error.pos.1),
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Set the [`schema_path`](Self::schema_path) of this error.
+    fn at(mut self, location: String) -> Self {
+        match &mut self {
+            CompilationError::Parse { schema_path, .. }
+            | CompilationError::InvalidRule { schema_path, .. }
+            | CompilationError::CostLimitExceeded { schema_path, .. }
+            | CompilationError::InvalidDefinition { schema_path, .. }
+            | CompilationError::UndeclaredFunction { schema_path, .. }
+            | CompilationError::InvalidRegex { schema_path, .. }
+            | CompilationError::TypeCheck { schema_path, .. }
+            | CompilationError::UncorrelatableTransitionRule { schema_path, .. }
+            | CompilationError::DepthLimitExceeded { schema_path, .. }
+            | CompilationError::InvalidRef { schema_path, .. } => *schema_path = location,
+        }
+        self
+    }
 }
 
 impl std::fmt::Display for CompilationError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.schema_path().is_empty() {
+            write!(f, "{}: ", self.schema_path())?;
+        }
         match self {
-            CompilationError::Parse { rule, source } => {
-                write!(f, "failed to compile CEL rule \"{rule}\": {source}")
+            CompilationError::Parse { rule, .. } => {
+                write!(f, "failed to compile CEL rule \"{rule}\": ")?;
+                for (i, diagnostic) in self.parse_diagnostics().iter().enumerate() {
+                    if i != 0 {
+                        writeln!(f)?;
+                    }
+                    diagnostic.annotate(f, rule)?;
+                }
+                Ok(())
+            }
+            CompilationError::InvalidRule { source, .. } => {
+                write!(f, "invalid rule definition: {source}")
+            }
+            CompilationError::CostLimitExceeded {
+                rule,
+                estimated_cost,
+                limit,
+                ..
+            } => write!(
+                f,
+                "estimated cost {estimated_cost} of rule \"{rule}\" exceeds the limit of {limit}"
+            ),
+            CompilationError::InvalidDefinition { rule, message, .. } => {
+                write!(f, "invalid rule \"{rule}\": {message}")
             }
-            CompilationError::InvalidRule(err) => {
-                write!(f, "invalid rule definition: {err}")
+            CompilationError::UndeclaredFunction {
+                rule,
+                function,
+                suggestion,
+                ..
+            } => {
+                write!(f, "rule \"{rule}\" calls undeclared function '{function}'")?;
+                match suggestion {
+                    Some(suggestion) => write!(f, "; did you mean '{suggestion}'?"),
+                    None => Ok(()),
+                }
+            }
+            CompilationError::InvalidRegex {
+                rule,
+                pattern,
+                message,
+                ..
+            } => write!(
+                f,
+                "rule \"{rule}\" has an invalid regular expression \"{pattern}\": {message}"
+            ),
+            CompilationError::TypeCheck { rule, message, .. } => {
+                write!(f, "rule \"{rule}\" does not type-check: {message}")
+            }
+            CompilationError::UncorrelatableTransitionRule { rule, .. } => write!(
+                f,
+                "transition rule \"{rule}\" uses oldSelf on a node that cannot be correlated \
+                 with the old object (below an atomic map or a list that is not listType=map)"
+            ),
+            CompilationError::DepthLimitExceeded { limit, .. } => {
+                write!(f, "schema is nested deeper than the limit of {limit}")
+            }
+            CompilationError::InvalidRef {
+                reference, message, ..
+            } => {
+                write!(f, "invalid $ref \"{reference}\": {message}")
             }
         }
     }
@@ -78,42 +823,421 @@ impl std::error::Error for CompilationError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             CompilationError::Parse { source, .. } => Some(source),
-            CompilationError::InvalidRule(err) => Some(err),
+            CompilationError::InvalidRule { source, .. } => Some(source),
+            CompilationError::CostLimitExceeded { .. }
+            | CompilationError::InvalidDefinition { .. }
+            | CompilationError::UndeclaredFunction { .. }
+            | CompilationError::InvalidRegex { .. }
+            | CompilationError::TypeCheck { .. }
+            | CompilationError::UncorrelatableTransitionRule { .. }
+            | CompilationError::DepthLimitExceeded { .. }
+            | CompilationError::InvalidRef { .. } => None,
+        }
+    }
+}
+
+/// One error in a rule that failed to parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseDiagnostic {
+    /// What is wrong, e.g. `Syntax error: mismatched input '<EOF>' ...`.
+    pub message: String,
+    /// Where in the rule the error is, if the parser reported it.
+    pub span: Option<SourceSpan>,
+}
+
+impl ParseDiagnostic {
+    /// Write the message, prefixed with its position, and the offending
+    /// line of `rule` with a caret under the error.
+    fn annotate(&self, f: &mut std::fmt::Formatter<'_>, rule: &str) -> std::fmt::Result {
+        let Some(span) = self.span else {
+            return write!(f, "{}", self.message);
+        };
+        write!(f, "{}:{}: {}", span.line, span.column, self.message)?;
+        let line = rule
+            .split('\n')
+            .nth(span.line - 1)
+            .unwrap_or_default()
+            .trim_end_matches('\r')
+            .replace('\t', " ");
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        write!(
+            f,
+            "\n{gutter} |\n{number} | {line}\n{gutter} | {:>width$}",
+            "^",
+            width = span.column
+        )
+    }
+}
+
+/// A position in a rule's CEL expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SourceSpan {
+    /// The line, starting at 1.
+    pub line: usize,
+    /// The column in characters, starting at 1.
+    pub column: usize,
+    /// The byte offset of the position in the expression.
+    pub offset: usize,
+}
+
+impl SourceSpan {
+    /// The span of a parser position, or `None` if it is not in `source`.
+    fn new(source: &str, line: isize, column: isize) -> Option<SourceSpan> {
+        let line = usize::try_from(line).ok().filter(|line| *line >= 1)?;
+        let column = usize::try_from(column).unwrap_or_default().max(1);
+        let text = source.split('\n').nth(line - 1)?;
+        let line_start: usize = source
+            .split('\n')
+            .take(line - 1)
+            .map(|text| text.len() + 1)
+            .sum();
+        let in_line = text
+            .char_indices()
+            .nth(column - 1)
+            .map_or(text.len(), |(i, _)| i);
+        Some(SourceSpan {
+            line,
+            column,
+            offset: line_start + in_line,
+        })
+    }
+}
+
+/// A least-recently-used cache of compiled programs keyed by their CEL
+/// source, shared between compilations through
+/// [`CompileOptions::program_cache`].
+///
+/// Large CRDs, and sets of CRDs, repeat the same expressions, such as
+/// `self.name.size() > 0`; with a cache each is parsed once.
+///
+/// ```
+/// use std::sync::Arc;
+/// use kube_cel::compilation::{CompileOptions, ProgramCache, compile_schema_with_options};
+/// use serde_json::json;
+///
+/// let options = CompileOptions {
+///     program_cache: Some(Arc::new(ProgramCache::new(1024))),
+///     ..CompileOptions::default()
+/// };
+/// let schema = json!({"type": "string", "x-kubernetes-validations": [{"rule": "self != ''"}]});
+/// let a = compile_schema_with_options(&schema, &options);
+/// let b = compile_schema_with_options(&schema, &options);
+/// let program = |s: &kube_cel::compilation::CompiledSchema| {
+///     s.validations[0].as_ref().unwrap().program.clone()
+/// };
+/// assert!(Arc::ptr_eq(&program(&a), &program(&b)));
+/// ```
+pub struct ProgramCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    /// Each program with the time it was last used.
+    programs: HashMap<String, (Arc<Program>, u64)>,
+    clock: u64,
+}
+
+impl ProgramCache {
+    /// A cache holding at most `capacity` programs.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// The number of cached programs.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries().programs.len()
+    }
+
+    /// Whether the cache holds no programs.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every cached program.
+    pub fn clear(&self) {
+        self.entries().programs.clear();
+    }
+
+    /// The cached program for `source`, compiling and caching it if needed.
+    /// The least recently used program is evicted when the cache is full.
+    fn get_or_compile(&self, source: &str) -> Result<Arc<Program>, ParseErrors> {
+        let mut entries = self.entries();
+        entries.clock += 1;
+        let now = entries.clock;
+        if let Some((program, used)) = entries.programs.get_mut(source) {
+            *used = now;
+            return Ok(program.clone());
+        }
+        // Parse without holding the lock.
+        drop(entries);
+        let program = Arc::new(Program::compile(source)?);
+        if self.capacity == 0 {
+            return Ok(program);
         }
+        let mut entries = self.entries();
+        if entries.programs.len() >= self.capacity
+            && !entries.programs.contains_key(source)
+            && let Some(oldest) = entries
+                .programs
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(source, _)| source.clone())
+        {
+            entries.programs.remove(&oldest);
+        }
+        let program = entries
+            .programs
+            .entry(source.to_string())
+            .or_insert((program, now))
+            .0
+            .clone();
+        Ok(program)
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, CacheEntries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for ProgramCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProgramCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Compiles the CEL sources of one compilation, sharing one [`Program`]
+/// between identical expressions.
+#[derive(Default)]
+pub(crate) struct Programs<'a> {
+    compiled: HashMap<String, Arc<Program>>,
+    shared: Option<&'a ProgramCache>,
+}
+
+impl<'a> Programs<'a> {
+    pub(crate) fn new(options: &'a CompileOptions) -> Self {
+        Self {
+            compiled: HashMap::new(),
+            shared: options.program_cache.as_deref(),
+        }
+    }
+
+    pub(crate) fn compile(&mut self, source: &str) -> Result<Arc<Program>, ParseErrors> {
+        if let Some(program) = self.compiled.get(source) {
+            return Ok(program.clone());
+        }
+        let program = match self.shared {
+            Some(cache) => cache.get_or_compile(source)?,
+            None => Arc::new(Program::compile(source)?),
+        };
+        self.compiled.insert(source.to_string(), program.clone());
+        Ok(program)
     }
 }
 
 /// Compile a single [`Rule`] into a [`CompilationResult`].
 ///
-/// Returns [`CompilationError::Parse`] if the CEL expression is invalid.
+/// Returns [`CompilationError::Parse`] if the CEL expression is invalid, and
+/// [`CompilationError::InvalidRegex`] if it passes an invalid regular
+/// expression literal.
+#[cfg(test)]
 pub(crate) fn compile_rule(rule: &Rule) -> Result<CompilationResult, CompilationError> {
-    let program = Program::compile(&rule.rule).map_err(|e| CompilationError::Parse {
-        rule: rule.rule.clone(),
-        source: e,
-    })?;
+    compile_rule_in(rule, None, &mut Programs::default())
+}
+
+/// Compile a [`Rule`] declared on `schema`. The schema's size bounds are
+/// used for cost estimation when available.
+fn compile_rule_in(
+    rule: &Rule,
+    schema: Option<&serde_json::Value>,
+    programs: &mut Programs<'_>,
+) -> Result<CompilationResult, CompilationError> {
+    let program = programs
+        .compile(&rule.rule)
+        .map_err(|e| CompilationError::Parse {
+            rule: rule.rule.clone(),
+            source: e,
+            schema_path: String::new(),
+        })?;
+    if let Some((pattern, message)) = invalid_regex(program.expression()) {
+        return Err(CompilationError::InvalidRegex {
+            rule: rule.rule.clone(),
+            pattern,
+            message,
+            schema_path: String::new(),
+        });
+    }
     let is_transition_rule = program.references().has_variable("oldSelf");
+    let estimated_cost = match schema {
+        Some(schema) => estimate_cost_with_schema(program.expression(), schema),
+        None => estimate_cost(program.expression()),
+    };
+
+    let mut warnings = Vec::new();
 
-    // Best-effort: compile messageExpression if present, ignore failures
-    let message_program = rule
-        .message_expression
-        .as_deref()
-        .and_then(|expr| Program::compile(expr).ok());
+    // Best-effort: a messageExpression that fails to compile only warns
+    let message_program = rule.message_expression.as_deref().and_then(|expr| {
+        let compiled = programs
+            .compile(expr)
+            .map_err(|e| e.to_string())
+            .and_then(|program| match invalid_regex(program.expression()) {
+                Some((pattern, message)) => Err(format!(
+                    "invalid regular expression \"{pattern}\": {message}"
+                )),
+                None => Ok(program),
+            });
+        compiled
+            .map_err(|message| {
+                warnings.push(CompilationWarning::InvalidMessageExpression {
+                    expression: expr.to_string(),
+                    message,
+                });
+            })
+            .ok()
+    });
 
     Ok(CompilationResult {
         program,
         rule: rule.clone(),
         is_transition_rule,
         message_program,
+        estimated_cost,
+        warnings,
     })
 }
 
+/// The first regular expression literal passed to `matches`, `find`, or
+/// `findAll` in `expr` that does not compile, with the reason.
+fn invalid_regex(expr: &Expression) -> Option<(String, String)> {
+    match &expr.expr {
+        Expr::Call(call) => {
+            let pattern = match (
+                call.func_name.as_str(),
+                call.target.is_some(),
+                call.args.as_slice(),
+            ) {
+                ("matches", true, [pattern])
+                | ("matches", false, [_, pattern])
+                | ("find" | "findAll", true, [pattern, ..]) => Some(&pattern.expr),
+                _ => None,
+            };
+            if let Some(Expr::Literal(CelVal::String(pattern))) = pattern
+                && let Err(e) = regex::Regex::new(pattern)
+            {
+                return Some((pattern.clone(), e.to_string()));
+            }
+            call.target
+                .as_deref()
+                .into_iter()
+                .chain(&call.args)
+                .find_map(invalid_regex)
+        }
+        Expr::Select(select) => invalid_regex(&select.operand),
+        Expr::List(list) => list.elements.iter().find_map(invalid_regex),
+        Expr::Map(map) => map.entries.iter().find_map(|e| invalid_regex_in(&e.expr)),
+        Expr::Struct(st) => st.entries.iter().find_map(|e| invalid_regex_in(&e.expr)),
+        Expr::Comprehension(comp) => [
+            &comp.iter_range,
+            &comp.accu_init,
+            &comp.loop_cond,
+            &comp.loop_step,
+            &comp.result,
+        ]
+        .into_iter()
+        .find_map(invalid_regex),
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => None,
+    }
+}
+
+fn invalid_regex_in(entry: &EntryExpr) -> Option<(String, String)> {
+    match entry {
+        EntryExpr::MapEntry(e) => invalid_regex(&e.key).or_else(|| invalid_regex(&e.value)),
+        EntryExpr::StructField(field) => invalid_regex(&field.value),
+    }
+}
+
+/// Compile a single [`Rule`] with the given options.
+///
+/// Returns [`CompilationError::CostLimitExceeded`] if the rule's estimated
+/// cost is above [`CompileOptions::max_estimated_cost`], mirroring the check
+/// kube-apiserver performs when a CRD is created or updated. In
+/// [strict](CompileOptions::strict) mode, malformed definitions are reported
+/// as [`CompilationError::InvalidDefinition`].
+pub fn compile_rule_with_options(
+    rule: &Rule,
+    options: &CompileOptions,
+) -> Result<CompilationResult, CompilationError> {
+    let compiled = compile_rule_in(rule, None, &mut Programs::new(options))?;
+    options.enforce(compiled, true, None)
+}
+
+/// Compile a list of rules, such as `x-kubernetes-validations` already
+/// deserialized from a CRD, without building a schema node for them.
+///
+/// Each rule is compiled independently, and identical expressions share
+/// one program. Like [`compile_schema`], rule costs are not limited; use
+/// [`compile_rules_with_options`] to enforce them.
+///
+/// ```
+/// use kube_cel::compilation::{Rule, compile_rules};
+///
+/// let rules: Vec<Rule> = serde_json::from_str(r#"[{"rule": "self > 0"}, {"rule": "self <"}]"#).unwrap();
+/// let compiled = compile_rules(&rules);
+/// assert!(compiled[0].is_ok());
+/// assert!(compiled[1].is_err());
+/// ```
+#[must_use]
+pub fn compile_rules(rules: &[Rule]) -> Vec<Result<CompilationResult, CompilationError>> {
+    compile_rules_with_options(
+        rules,
+        &CompileOptions {
+            max_estimated_cost: u64::MAX,
+            ..CompileOptions::default()
+        },
+    )
+}
+
+/// Compile a list of rules with the given options. See
+/// [`compile_rule_with_options`] for the checks each rule is subject to.
+#[must_use]
+pub fn compile_rules_with_options(
+    rules: &[Rule],
+    options: &CompileOptions,
+) -> Vec<Result<CompilationResult, CompilationError>> {
+    let mut programs = Programs::new(options);
+    rules
+        .iter()
+        .map(|rule| options.enforce(compile_rule_in(rule, None, &mut programs)?, true, None))
+        .collect()
+}
+
 /// Extract `x-kubernetes-validations` rules from a schema node and compile them.
 ///
 /// If the schema has no `x-kubernetes-validations` key or it is not an array,
 /// returns an empty `Vec`. Each rule is compiled independently — failures in one
-/// rule do not prevent others from compiling.
+/// rule do not prevent others from compiling. Cost estimates take the schema's
+/// `maxItems`, `maxLength` and `maxProperties` bounds into account.
 pub(crate) fn compile_schema_validations(
     schema: &serde_json::Value,
+) -> Vec<Result<CompilationResult, CompilationError>> {
+    compile_node_validations(schema, &mut Programs::default())
+}
+
+/// [`compile_schema_validations`], compiling through `programs`.
+fn compile_node_validations(
+    schema: &serde_json::Value,
+    programs: &mut Programs<'_>,
 ) -> Vec<Result<CompilationResult, CompilationError>> {
     let rules = match schema.get("x-kubernetes-validations") {
         Some(serde_json::Value::Array(arr)) => arr,
@@ -123,35 +1247,501 @@ pub(crate) fn compile_schema_validations(
     rules
         .iter()
         .map(|raw| {
-            let rule: Rule =
-                serde_json::from_value(raw.clone()).map_err(CompilationError::InvalidRule)?;
-            compile_rule(&rule)
+            let rule: Rule = serde_json::from_value(raw.clone()).map_err(|source| {
+                CompilationError::InvalidRule {
+                    source,
+                    schema_path: String::new(),
+                }
+            })?;
+            compile_rule_in(&rule, Some(schema), programs)
         })
         .collect()
 }
 
-/// A pre-compiled schema tree. Compile once with [`compile_schema`], then
-/// validate many objects via [`Validator::validate_compiled`](crate::validation::Validator::validate_compiled).
-///
-/// # Note
-///
-/// `CompiledSchema` is not `Clone` because [`cel::Program`] is `!Clone`.
-/// Wrap in [`Arc`](std::sync::Arc) for shared ownership across threads.
-#[derive(Debug)]
-pub struct CompiledSchema {
-    /// Compiled validation rules at this schema node.
-    pub validations: Vec<Result<CompilationResult, CompilationError>>,
-    /// Compiled child property schemas.
-    pub properties: HashMap<String, CompiledSchema>,
-    /// Compiled array items schema.
+/// The `x-kubernetes-list-type` of an array schema, which determines how
+/// items of the new and old list are correlated for transition rules.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ListType {
+    /// No list type declared; items are correlated by index.
+    #[default]
+    Unspecified,
+    /// `x-kubernetes-list-type: set` — items are correlated by value, so
+    /// transition rules only run for items present in both revisions.
+    Set,
+    /// `x-kubernetes-list-type: atomic` — the list is replaced as a whole;
+    /// items are never correlated.
+    Atomic,
+    /// `x-kubernetes-list-type: map` — items are correlated by the values of
+    /// the `x-kubernetes-list-map-keys` fields.
+    Map {
+        /// The key fields from `x-kubernetes-list-map-keys`.
+        keys: Vec<String>,
+    },
+}
+
+impl ListType {
+    /// Extract a `ListType` from a raw JSON schema node.
+    pub(crate) fn from_schema(schema: &serde_json::Value) -> Self {
+        match schema
+            .get("x-kubernetes-list-type")
+            .and_then(|t| t.as_str())
+        {
+            Some("map") => ListType::Map {
+                keys: schema
+                    .get("x-kubernetes-list-map-keys")
+                    .and_then(|k| k.as_array())
+                    .map(|keys| {
+                        keys.iter()
+                            .filter_map(|k| k.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            },
+            Some("set") => ListType::Set,
+            Some("atomic") => ListType::Atomic,
+            _ => ListType::Unspecified,
+        }
+    }
+
+    /// Whether the apiserver correlates items of this list with the old
+    /// list, which only `listType: map` lists do.
+    pub(crate) fn correlates_items(&self) -> bool {
+        matches!(self, ListType::Map { .. })
+    }
+
+    /// Pair each item of `new` with the index of its correlated item in
+    /// `old`, if any.
+    pub(crate) fn correlate_indices(
+        &self,
+        new: &[serde_json::Value],
+        old: Option<&serde_json::Value>,
+    ) -> Vec<Option<usize>> {
+        let Some(old) = old.and_then(|o| o.as_array()) else {
+            return vec![None; new.len()];
+        };
+        match self {
+            ListType::Unspecified => (0..new.len())
+                .map(|i| (i < old.len()).then_some(i))
+                .collect(),
+            ListType::Atomic => vec![None; new.len()],
+            ListType::Set => {
+                let by_value: HashMap<String, usize> = old
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| (item.to_string(), i))
+                    .collect();
+                new.iter()
+                    .map(|item| by_value.get(&item.to_string()).copied())
+                    .collect()
+            }
+            ListType::Map { keys } => {
+                let by_key: HashMap<String, usize> = old
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, item)| Some((map_key(item, keys)?, i)))
+                    .collect();
+                new.iter()
+                    .map(|item| map_key(item, keys).and_then(|k| by_key.get(&k).copied()))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The `x-kubernetes-map-type` of an object schema.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum MapType {
+    /// Fields are correlated individually (the default).
+    #[default]
+    Granular,
+    /// `x-kubernetes-map-type: atomic` — the object is replaced as a whole;
+    /// its fields are never correlated with the old object.
+    Atomic,
+}
+
+impl MapType {
+    /// Extract a `MapType` from a raw JSON schema node.
+    pub(crate) fn from_schema(schema: &serde_json::Value) -> Self {
+        match schema.get("x-kubernetes-map-type").and_then(|t| t.as_str()) {
+            Some("atomic") => MapType::Atomic,
+            _ => MapType::Granular,
+        }
+    }
+
+    /// Whether this node's fields are correlated with the old object.
+    pub(crate) fn correlates_fields(&self) -> bool {
+        *self == MapType::Granular
+    }
+
+    /// The old value that this node's fields should be correlated against.
+    pub(crate) fn correlate<'a>(
+        &self,
+        old: Option<&'a serde_json::Value>,
+    ) -> Option<&'a serde_json::Value> {
+        match self {
+            MapType::Granular => old,
+            MapType::Atomic => None,
+        }
+    }
+}
+
+/// The canonical key of a `listType: map` item, or `None` if any key field
+/// is missing.
+fn map_key(item: &serde_json::Value, keys: &[String]) -> Option<String> {
+    if keys.is_empty() {
+        return None;
+    }
+    let values = keys
+        .iter()
+        .map(|k| item.get(k).cloned())
+        .collect::<Option<Vec<_>>>()?;
+    Some(serde_json::Value::Array(values).to_string())
+}
+
+/// Returns `true` if the node sets `x-kubernetes-preserve-unknown-fields: true`.
+pub(crate) fn preserves_unknown_fields(schema: &serde_json::Value) -> bool {
+    schema
+        .get("x-kubernetes-preserve-unknown-fields")
+        .and_then(|v| v.as_bool())
+        == Some(true)
+}
+
+/// The `additionalProperties` schema that applies to undeclared keys of a node.
+///
+/// Nodes that preserve unknown fields keep undeclared keys as opaque JSON, so
+/// no child schema (and none of its rules) applies to them. The boolean form
+/// `additionalProperties: true` allows any value, as a schema that preserves
+/// unknown fields; `false` allows none.
+pub(crate) fn additional_properties_schema(
+    schema: &serde_json::Value,
+) -> Option<&serde_json::Value> {
+    static ANY_VALUE: LazyLock<serde_json::Value> =
+        LazyLock::new(|| serde_json::json!({"x-kubernetes-preserve-unknown-fields": true}));
+
+    if preserves_unknown_fields(schema) {
+        return None;
+    }
+    match schema.get("additionalProperties")? {
+        serde_json::Value::Bool(true) => Some(&ANY_VALUE),
+        additional => Some(additional).filter(|a| a.is_object()),
+    }
+}
+
+/// Returns `true` if the node forbids undeclared keys with
+/// `additionalProperties: false`.
+pub(crate) fn forbids_additional_properties(schema: &serde_json::Value) -> bool {
+    schema.get("additionalProperties").and_then(|a| a.as_bool()) == Some(false)
+        && !preserves_unknown_fields(schema)
+}
+
+/// Expand an `x-kubernetes-embedded-resource` node with the implicit
+/// `apiVersion`, `kind`, and `metadata` properties every embedded object has.
+///
+/// Explicitly declared properties take precedence. Other nodes are returned
+/// unchanged without cloning.
+pub(crate) fn with_embedded_resource(schema: &serde_json::Value) -> Cow<'_, serde_json::Value> {
+    let embedded = schema
+        .get("x-kubernetes-embedded-resource")
+        .and_then(|v| v.as_bool())
+        == Some(true);
+    if !embedded {
+        return Cow::Borrowed(schema);
+    }
+
+    let implicit = serde_json::json!({
+        "apiVersion": {"type": "string"},
+        "kind": {"type": "string"},
+        "metadata": {
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "generateName": {"type": "string"},
+                "namespace": {"type": "string"},
+                "labels": {"type": "object", "additionalProperties": {"type": "string"}},
+                "annotations": {"type": "object", "additionalProperties": {"type": "string"}}
+            }
+        }
+    });
+    let mut schema = schema.clone();
+    if let Some(obj) = schema.as_object_mut() {
+        let properties = obj
+            .entry("properties")
+            .or_insert_with(|| serde_json::Value::Object(Default::default()));
+        if let (Some(props), serde_json::Value::Object(implicit)) =
+            (properties.as_object_mut(), implicit)
+        {
+            for (name, prop_schema) in implicit {
+                props.entry(name).or_insert(prop_schema);
+            }
+        }
+    }
+    Cow::Owned(schema)
+}
+
+/// Composition keywords whose subschemas are folded into their node.
+const COMPOSITION_KEYWORDS: [&str; 3] = ["allOf", "anyOf", "oneOf"];
+
+/// Keywords an `allOf` branch contributes to its node when the node does
+/// not set them itself.
+const ALL_OF_KEYWORDS: [&str; 20] = [
+    "type",
+    "format",
+    "nullable",
+    "enum",
+    "pattern",
+    "minimum",
+    "exclusiveMinimum",
+    "maximum",
+    "exclusiveMaximum",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "uniqueItems",
+    "x-kubernetes-int-or-string",
+    "x-kubernetes-list-type",
+    "x-kubernetes-list-map-keys",
+    "x-kubernetes-map-type",
+    "x-kubernetes-preserve-unknown-fields",
+    "x-kubernetes-embedded-resource",
+];
+
+/// Fold the `allOf`, `anyOf`, and `oneOf` subschemas of a node into the node
+/// itself, so their `x-kubernetes-validations` and the rules below their
+/// `properties`, `items`, and `additionalProperties` are walked.
+///
+/// Rules from every branch apply, including `anyOf`/`oneOf` branches. A
+/// property declared both on the node and in a branch keeps the node's
+/// schema, with the branch's schema added to its `allOf`.
+///
+/// `allOf` branches must all hold, so they also contribute their `format`,
+/// `x-kubernetes-*` and structural keywords ([`ALL_OF_KEYWORDS`]) where the
+/// node does not set them, the first branch winning, and add to its
+/// `required`. Those keywords of `anyOf`/`oneOf` branches are ignored, as
+/// only one of the branches needs to hold. Nodes without composition
+/// keywords are returned unchanged without cloning.
+pub(crate) fn with_compositions(schema: &serde_json::Value) -> Cow<'_, serde_json::Value> {
+    let has_branches = COMPOSITION_KEYWORDS
+        .iter()
+        .any(|k| schema.get(*k).and_then(|b| b.as_array()).is_some());
+    let Some(obj) = schema.as_object().filter(|_| has_branches) else {
+        return Cow::Borrowed(schema);
+    };
+
+    let mut merged = obj.clone();
+    let branches: Vec<(bool, serde_json::Value)> = COMPOSITION_KEYWORDS
+        .iter()
+        .filter_map(|k| Some((*k == "allOf", merged.remove(*k)?)))
+        .filter_map(|(all_of, b)| match b {
+            serde_json::Value::Array(branches) => Some((all_of, branches)),
+            _ => None,
+        })
+        .flat_map(|(all_of, branches)| branches.into_iter().map(move |b| (all_of, b)))
+        .collect();
+    for (all_of, branch) in &branches {
+        let branch = with_compositions(branch);
+        if *all_of {
+            merge_all_of_keywords(&mut merged, &branch);
+        }
+        if let Some(rules) = branch
+            .get("x-kubernetes-validations")
+            .and_then(|r| r.as_array())
+        {
+            let own = merged
+                .entry("x-kubernetes-validations")
+                .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+            if let Some(own) = own.as_array_mut() {
+                own.extend(rules.iter().cloned());
+            }
+        }
+        if let Some(properties) = branch.get("properties").and_then(|p| p.as_object()) {
+            let own = merged
+                .entry("properties")
+                .or_insert_with(|| serde_json::Value::Object(Default::default()));
+            if let Some(own) = own.as_object_mut() {
+                for (name, prop_schema) in properties {
+                    merge_subschema(own.entry(name.clone()), prop_schema);
+                }
+            }
+        }
+        for key in ["items", "additionalProperties"] {
+            if let Some(sub) = branch.get(key).filter(|s| s.is_object()) {
+                merge_subschema(merged.entry(key), sub);
+            }
+        }
+    }
+    Cow::Owned(serde_json::Value::Object(merged))
+}
+
+/// Add the [`ALL_OF_KEYWORDS`] and `required` of an `allOf` branch to its
+/// node.
+fn merge_all_of_keywords(
+    node: &mut serde_json::Map<String, serde_json::Value>,
+    branch: &serde_json::Value,
+) {
+    for key in ALL_OF_KEYWORDS {
+        if let Some(value) = branch.get(key) {
+            node.entry(key).or_insert_with(|| value.clone());
+        }
+    }
+    if let Some(required) = branch.get("required").and_then(|r| r.as_array()) {
+        let own = node
+            .entry("required")
+            .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+        if let Some(own) = own.as_array_mut() {
+            for name in required {
+                if !own.contains(name) {
+                    own.push(name.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Add `sub` to a child schema slot: as the schema itself if the slot is
+/// empty, otherwise as an extra `allOf` branch of the existing schema.
+fn merge_subschema(slot: serde_json::map::Entry<'_>, sub: &serde_json::Value) {
+    match slot {
+        serde_json::map::Entry::Vacant(slot) => {
+            slot.insert(sub.clone());
+        }
+        serde_json::map::Entry::Occupied(mut slot) => {
+            if let Some(existing) = slot.get_mut().as_object_mut() {
+                let all_of = existing
+                    .entry("allOf")
+                    .or_insert_with(|| serde_json::Value::Array(Vec::new()));
+                if let Some(all_of) = all_of.as_array_mut() {
+                    all_of.push(sub.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Replace a node's `$ref` (and those of its composition branches) with the
+/// referenced schema. Sibling keywords of a `$ref` override the target's.
+///
+/// Every expanded reference is pushed onto `refs`; expanding one that is
+/// already there is a cycle.
+pub(crate) fn resolve_refs<'a>(
+    schema: &'a serde_json::Value,
+    root: &serde_json::Value,
+    refs: &mut Vec<String>,
+) -> Result<Cow<'a, serde_json::Value>, CompilationError> {
+    let mut schema = Cow::Borrowed(schema);
+    while let Some(reference) = schema.get("$ref").and_then(|r| r.as_str()) {
+        let reference = reference.to_string();
+        let invalid = |message: &str| CompilationError::InvalidRef {
+            reference: reference.clone(),
+            message: message.to_string(),
+            schema_path: String::new(),
+        };
+        if refs.contains(&reference) {
+            return Err(invalid("cyclic reference"));
+        }
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| root.pointer(pointer))
+            .filter(|t| t.is_object())
+            .ok_or_else(|| invalid("not found in the schema document"))?;
+        let mut resolved = target.clone();
+        if let (Some(resolved), Some(siblings)) = (resolved.as_object_mut(), schema.as_object()) {
+            for (key, value) in siblings {
+                if key != "$ref" {
+                    resolved.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        refs.push(reference);
+        schema = Cow::Owned(resolved);
+    }
+
+    let has_branches = COMPOSITION_KEYWORDS
+        .iter()
+        .any(|k| schema.get(*k).is_some_and(|b| b.is_array()));
+    if !has_branches {
+        return Ok(schema);
+    }
+    // Branches are resolved independently: two branches may refer to the
+    // same schema without forming a cycle.
+    let ancestors = refs.len();
+    let mut expanded = Vec::new();
+    let mut schema = schema.into_owned();
+    for keyword in COMPOSITION_KEYWORDS {
+        if let Some(branches) = schema.get_mut(keyword).and_then(|b| b.as_array_mut()) {
+            for branch in branches {
+                let mut branch_refs = refs[..ancestors].to_vec();
+                *branch = resolve_refs(branch, root, &mut branch_refs)?.into_owned();
+                expanded.extend(branch_refs.drain(ancestors..));
+            }
+        }
+    }
+    for reference in expanded {
+        if !refs.contains(&reference) {
+            refs.push(reference);
+        }
+    }
+    Ok(Cow::Owned(schema))
+}
+
+/// Expand a schema node for walking: fold in composition branches
+/// ([`with_compositions`]), then add the implicit fields of embedded
+/// resources ([`with_embedded_resource`]).
+pub(crate) fn expand_node(schema: &serde_json::Value) -> Cow<'_, serde_json::Value> {
+    match with_compositions(schema) {
+        Cow::Borrowed(schema) => with_embedded_resource(schema),
+        Cow::Owned(schema) => Cow::Owned(with_embedded_resource(&schema).into_owned()),
+    }
+}
+
+/// A pre-compiled schema tree. Compile once with [`compile_schema`], then
+/// validate many objects via [`Validator::validate_compiled`](crate::validation::Validator::validate_compiled).
+///
+/// # Note
+///
+/// `CompiledSchema` is not `Clone` because [`cel::Program`] is `!Clone`.
+/// Wrap in [`Arc`] for shared ownership across threads.
+#[derive(Debug)]
+pub struct CompiledSchema {
+    /// Compiled validation rules at this schema node.
+    pub validations: Vec<Result<CompilationResult, CompilationError>>,
+    /// Compiled child property schemas, ordered by name.
+    pub properties: BTreeMap<String, CompiledSchema>,
+    /// Compiled array items schema.
     pub items: Option<Box<CompiledSchema>>,
     /// Compiled additionalProperties schema.
     pub additional_properties: Option<Box<CompiledSchema>>,
     /// The `format` hint from the schema (e.g., `date-time`, `duration`).
     pub format: SchemaFormat,
+    /// The `x-kubernetes-list-type` of this node, if it is an array.
+    pub list_type: ListType,
+    /// The `x-kubernetes-map-type` of this node, if it is an object.
+    pub map_type: MapType,
+    /// Whether undeclared fields are kept as-is
+    /// (`x-kubernetes-preserve-unknown-fields`). Such fields are visible to
+    /// rules on this node but are never walked.
+    pub preserve_unknown_fields: bool,
+    /// The node's structural constraints (`type`, `required`, `enum`, ...),
+    /// checked when structural validation is enabled.
+    pub structural: StructuralSchema,
 }
 
 impl CompiledSchema {
+    /// An empty node that only reports `error`.
+    fn invalid(error: CompilationError) -> Self {
+        Self {
+            validations: vec![Err(error)],
+            properties: BTreeMap::new(),
+            items: None,
+            additional_properties: None,
+            format: SchemaFormat::default(),
+            list_type: ListType::default(),
+            map_type: MapType::default(),
+            preserve_unknown_fields: false,
+            structural: StructuralSchema::default(),
+        }
+    }
+
     /// Returns references to all compilation errors in this node's validations.
     #[must_use]
     pub fn compilation_errors(&self) -> Vec<&CompilationError> {
@@ -161,43 +1751,405 @@ impl CompiledSchema {
             .collect()
     }
 
+    /// The variables and functions every compiled rule in the tree
+    /// references, in schema order. Rules that failed to compile are
+    /// skipped.
+    ///
+    /// Useful to audit what a CRD's rules depend on, e.g. which rules are
+    /// transition rules (read `oldSelf`) or call regex functions.
+    #[must_use]
+    pub fn rule_references(&self) -> Vec<RuleReferences> {
+        let mut references = Vec::new();
+        self.for_each_rule("", &mut |location, compiled| {
+            references.push(RuleReferences {
+                schema_path: location.to_string(),
+                rule: compiled.rule.rule.clone(),
+                variables: compiled.referenced_variables(),
+                functions: compiled.referenced_functions(),
+            });
+        });
+        references
+    }
+
+    /// The warnings of every compiled rule in the tree, with the rule's
+    /// location, in schema order.
+    #[must_use]
+    pub fn compilation_warnings(&self) -> Vec<(String, &CompilationWarning)> {
+        let mut warnings = Vec::new();
+        self.for_each_rule("", &mut |location, compiled| {
+            warnings.extend(compiled.warnings.iter().map(|w| (location.to_string(), w)));
+        });
+        warnings
+    }
+
+    /// Call `f` with every compiled rule in the tree and its location, in
+    /// schema order.
+    fn for_each_rule<'a>(
+        &'a self,
+        location: &str,
+        f: &mut impl FnMut(&str, &'a CompilationResult),
+    ) {
+        self.for_each_node(location, &mut |location, node| {
+            for (i, result) in node.validations.iter().enumerate() {
+                if let Ok(compiled) = result {
+                    f(
+                        &join_location(location, &format!("x-kubernetes-validations[{i}]")),
+                        compiled,
+                    );
+                }
+            }
+        });
+    }
+
+    /// Call `f` with every node in the tree and its location, in schema
+    /// order.
+    pub(crate) fn for_each_node<'a>(
+        &'a self,
+        location: &str,
+        f: &mut impl FnMut(&str, &'a CompiledSchema),
+    ) {
+        f(location, self);
+        for (name, property) in &self.properties {
+            let child = join_location(location, &format!("properties[{name}]"));
+            property.for_each_node(&child, f);
+        }
+        if let Some(additional) = &self.additional_properties {
+            additional.for_each_node(&join_location(location, "additionalProperties"), f);
+        }
+        if let Some(items) = &self.items {
+            items.for_each_node(&join_location(location, "items"), f);
+        }
+    }
+
     /// Returns `true` if any validation rule at this node failed to compile.
     #[must_use]
     pub fn has_errors(&self) -> bool {
         self.validations.iter().any(|r| r.is_err())
     }
+
+    /// Returns the tree unchanged if every node compiled, or else every
+    /// compilation error in it with its location, in schema order.
+    ///
+    /// Locations use the format of [`LintIssue::location`]: a rule's error is
+    /// at `properties[spec].x-kubernetes-validations[0]`, and a node that
+    /// could not be compiled at all (an invalid `$ref`, or too deep) at the
+    /// node itself, e.g. `properties[spec].items`.
+    ///
+    /// [`LintIssue::location`]: crate::lint::LintIssue::location
+    pub fn into_result(mut self) -> Result<Self, Vec<(String, CompilationError)>> {
+        let mut errors = Vec::new();
+        self.take_errors(&mut errors);
+        if errors.is_empty() {
+            Ok(self)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Move the compilation errors of this subtree into `errors`, each with
+    /// its [`schema_path`](CompilationError::schema_path).
+    fn take_errors(&mut self, errors: &mut Vec<(String, CompilationError)>) {
+        if self.has_errors() {
+            let validations = std::mem::take(&mut self.validations);
+            for result in validations {
+                match result {
+                    Ok(compiled) => self.validations.push(Ok(compiled)),
+                    Err(error) => errors.push((error.schema_path().to_string(), error)),
+                }
+            }
+        }
+        for property in self.properties.values_mut() {
+            property.take_errors(errors);
+        }
+        if let Some(additional) = &mut self.additional_properties {
+            additional.take_errors(errors);
+        }
+        if let Some(items) = &mut self.items {
+            items.take_errors(errors);
+        }
+    }
+}
+
+/// Append `segment` to a dotted schema `location`.
+pub(crate) fn join_location(location: &str, segment: &str) -> String {
+    if location.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{location}.{segment}")
+    }
 }
 
 /// Recursively compile all `x-kubernetes-validations` rules in a schema tree.
 ///
 /// Returns a [`CompiledSchema`] that can be reused across multiple validation
 /// calls, avoiding repeated compilation.
+///
+/// Local `$ref`s (e.g. `#/definitions/Container`) are resolved against
+/// `schema` as the document root, including inside `allOf`/`anyOf`/`oneOf`
+/// branches. A `$ref` that cannot be resolved, or that refers back to a
+/// schema containing it, is reported as [`CompilationError::InvalidRef`] on
+/// its node, which is otherwise left empty.
+///
+/// Nodes nested deeper than [`DEFAULT_MAX_DEPTH`] are not compiled; the node
+/// at the limit reports [`CompilationError::DepthLimitExceeded`]. Rule costs
+/// are not limited; use [`compile_schema_with_options`] to enforce them.
 #[must_use]
 pub fn compile_schema(schema: &serde_json::Value) -> CompiledSchema {
-    let validations = compile_schema_validations(schema);
+    compile_schema_with_options(
+        schema,
+        &CompileOptions {
+            max_estimated_cost: u64::MAX,
+            ..CompileOptions::default()
+        },
+    )
+}
+
+/// Like [`compile_schema`], but fails with every compilation error and its
+/// location instead of leaving the errors in the tree, where they only
+/// surface at validation time. See [`CompiledSchema::into_result`].
+///
+/// Use `compile_schema_with_options(schema, &options).into_result()` for
+/// other [`CompileOptions`].
+pub fn compile_schema_strict(
+    schema: &serde_json::Value,
+) -> Result<CompiledSchema, Vec<(String, CompilationError)>> {
+    compile_schema(schema).into_result()
+}
+
+/// Like [`compile_schema`], but compiles with `options`: rules above
+/// [`CompileOptions::max_estimated_cost`] report
+/// [`CompilationError::CostLimitExceeded`], nodes deeper than
+/// [`CompileOptions::max_depth`] are not compiled, and in
+/// [strict](CompileOptions::strict) mode malformed rule definitions report
+/// [`CompilationError::InvalidDefinition`].
+#[must_use]
+pub fn compile_schema_with_options(
+    schema: &serde_json::Value,
+    options: &CompileOptions,
+) -> CompiledSchema {
+    SchemaCompiler {
+        root: schema,
+        options,
+        programs: Programs::new(options),
+        refs: Vec::new(),
+        depth: 0,
+        correlatable: true,
+        location: String::new(),
+    }
+    .node(schema)
+}
+
+/// A schema that can be compiled, in JSON or a typed form such as a
+/// `schemars` schema (with the `schemars` feature) or a k8s-openapi
+/// `JSONSchemaProps` (with the `k8s-openapi` feature).
+///
+/// The compiler reads every schema as JSON, so the typed forms are
+/// serialized with `serde_json::to_value` on each call to
+/// [`to_schema_json`](Self::to_schema_json); only JSON schemas are
+/// borrowed as-is. Callers are spared that conversion, not its cost:
+/// compiling typed schemas without it would need a second compiler.
+pub trait SchemaSource {
+    /// The schema as JSON, with extensions such as
+    /// `x-kubernetes-validations` as keys of each node.
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value>;
+}
+
+impl SchemaSource for serde_json::Value {
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        Cow::Borrowed(self)
+    }
+}
+
+/// The JSON form of a typed schema, serialized in full.
+///
+/// # Panics
+///
+/// Never for the schema types implemented here: their maps are keyed by
+/// strings and their extensions are already JSON, so `serde_json` cannot
+/// reject them.
+#[cfg(any(feature = "schemars", feature = "k8s-openapi"))]
+fn typed_schema_json(schema: &impl serde::Serialize) -> Cow<'static, serde_json::Value> {
+    Cow::Owned(
+        serde_json::to_value(schema).expect("typed schemas have string keys and serialize to JSON"),
+    )
+}
+
+#[cfg(feature = "schemars")]
+impl SchemaSource for schemars::schema::Schema {
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        typed_schema_json(self)
+    }
+}
+
+#[cfg(feature = "schemars")]
+impl SchemaSource for schemars::schema::SchemaObject {
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        typed_schema_json(self)
+    }
+}
+
+/// A root schema's `definitions` are kept, so `$ref`s to them resolve.
+#[cfg(feature = "schemars")]
+impl SchemaSource for schemars::schema::RootSchema {
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        typed_schema_json(self)
+    }
+}
 
-    let mut properties = HashMap::new();
-    if let Some(props) = schema.get("properties").and_then(|p| p.as_object()) {
-        for (name, prop_schema) in props {
-            properties.insert(name.clone(), compile_schema(prop_schema));
+#[cfg(feature = "k8s-openapi")]
+impl SchemaSource
+    for k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaProps
+{
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        typed_schema_json(self)
+    }
+}
+
+/// The `openAPIV3Schema`, or an empty schema if there is none.
+#[cfg(feature = "k8s-openapi")]
+impl SchemaSource
+    for k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceValidation
+{
+    fn to_schema_json(&self) -> Cow<'_, serde_json::Value> {
+        match &self.open_api_v3_schema {
+            Some(schema) => typed_schema_json(schema),
+            None => Cow::Owned(serde_json::Value::Object(serde_json::Map::new())),
         }
     }
+}
 
-    let items = schema.get("items").map(|s| Box::new(compile_schema(s)));
+/// Compile a schema in any [`SchemaSource`] form, like [`compile_schema`].
+///
+/// Typed schemas are converted to JSON internally, once per call (see
+/// [`SchemaSource`]).
+///
+/// With the `schemars` feature, this compiles the schemas `kube-derive`
+/// generates for a `CustomResource` without serializing them yourself:
+///
+/// ```
+/// # #[cfg(feature = "schemars")]
+/// # {
+/// use kube_cel::compilation::compile_schema_from;
+/// use schemars::schema::{InstanceType, Schema, SchemaObject};
+///
+/// let mut schema = SchemaObject {
+///     instance_type: Some(InstanceType::Integer.into()),
+///     ..Default::default()
+/// };
+/// schema.extensions.insert(
+///     "x-kubernetes-validations".into(),
+///     serde_json::json!([{"rule": "self >= 0"}]),
+/// );
+/// let compiled = compile_schema_from(&Schema::Object(schema));
+/// assert!(compiled.validations[0].is_ok());
+/// # }
+/// ```
+#[must_use]
+pub fn compile_schema_from<S: SchemaSource + ?Sized>(schema: &S) -> CompiledSchema {
+    compile_schema(&schema.to_schema_json())
+}
 
-    let additional_properties = schema
-        .get("additionalProperties")
-        .filter(|a| a.is_object())
-        .map(|s| Box::new(compile_schema(s)));
+/// Compile a schema in any [`SchemaSource`] form with the given options.
+#[must_use]
+pub fn compile_schema_from_with_options<S: SchemaSource + ?Sized>(
+    schema: &S,
+    options: &CompileOptions,
+) -> CompiledSchema {
+    compile_schema_with_options(&schema.to_schema_json(), options)
+}
 
-    let format = SchemaFormat::from_schema(schema);
+/// State for compiling one schema document.
+struct SchemaCompiler<'a> {
+    /// The document `$ref`s are resolved against.
+    root: &'a serde_json::Value,
+    options: &'a CompileOptions,
+    programs: Programs<'a>,
+    /// The `$ref`s being expanded on the path from the root, for cycle detection.
+    refs: Vec<String>,
+    /// Depth of the node being compiled.
+    depth: usize,
+    /// Whether the node being compiled can be correlated with the old object.
+    correlatable: bool,
+    /// Location of the node being compiled, attached to its errors.
+    location: String,
+}
+
+impl SchemaCompiler<'_> {
+    fn node(&mut self, schema: &serde_json::Value) -> CompiledSchema {
+        if self.depth >= self.options.max_depth {
+            return CompiledSchema::invalid(
+                CompilationError::DepthLimitExceeded {
+                    limit: self.options.max_depth,
+                    schema_path: String::new(),
+                }
+                .at(self.location.clone()),
+            );
+        }
+        let refs = self.refs.len();
+        self.depth += 1;
+        let compiled = match resolve_refs(schema, self.root, &mut self.refs) {
+            Ok(resolved) => self.resolved(&resolved),
+            Err(error) => CompiledSchema::invalid(error.at(self.location.clone())),
+        };
+        self.depth -= 1;
+        self.refs.truncate(refs);
+        compiled
+    }
+
+    /// Compile the child node reached through `segment`.
+    fn child(&mut self, segment: &str, schema: &serde_json::Value) -> CompiledSchema {
+        let parent = std::mem::take(&mut self.location);
+        self.location = join_location(&parent, segment);
+        let compiled = self.node(schema);
+        self.location = parent;
+        compiled
+    }
 
-    CompiledSchema {
-        validations,
-        properties,
-        items,
-        additional_properties,
-        format,
+    fn resolved(&mut self, schema: &serde_json::Value) -> CompiledSchema {
+        let schema = &*expand_node(schema);
+        let correlatable = self.correlatable;
+        let validations = compile_node_validations(schema, &mut self.programs)
+            .into_iter()
+            .enumerate()
+            .map(|(i, result)| {
+                result
+                    .and_then(|cr| self.options.enforce(cr, correlatable, Some(schema)))
+                    .map_err(|error| {
+                        let rule = format!("x-kubernetes-validations[{i}]");
+                        error.at(join_location(&self.location, &rule))
+                    })
+            })
+            .collect();
+
+        self.correlatable = correlatable && MapType::from_schema(schema).correlates_fields();
+        let mut properties = BTreeMap::new();
+        if let Some(props) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, prop_schema) in props {
+                let child = self.child(&format!("properties[{name}]"), prop_schema);
+                properties.insert(name.clone(), child);
+            }
+        }
+
+        let additional_properties = additional_properties_schema(schema)
+            .map(|s| Box::new(self.child("additionalProperties", s)));
+
+        self.correlatable = correlatable && ListType::from_schema(schema).correlates_items();
+        let items = schema
+            .get("items")
+            .map(|s| Box::new(self.child("items", s)));
+        self.correlatable = correlatable;
+
+        CompiledSchema {
+            validations,
+            properties,
+            items,
+            additional_properties,
+            format: SchemaFormat::from_schema(schema),
+            list_type: ListType::from_schema(schema),
+            map_type: MapType::from_schema(schema),
+            preserve_unknown_fields: preserves_unknown_fields(schema),
+            structural: StructuralSchema::from_schema(schema),
+        }
     }
 }
 
@@ -215,6 +2167,7 @@ mod tests {
             reason: None,
             field_path: None,
             optional_old_self: None,
+            severity: Severity::Error,
         };
         let result = compile_rule(&rule).unwrap();
         assert!(!result.is_transition_rule);
@@ -229,6 +2182,7 @@ mod tests {
             reason: None,
             field_path: None,
             optional_old_self: None,
+            severity: Severity::Error,
         };
         let result = compile_rule(&rule).unwrap();
         assert!(result.is_transition_rule);
@@ -243,6 +2197,7 @@ mod tests {
             reason: None,
             field_path: None,
             optional_old_self: None,
+            severity: Severity::Error,
         };
         let result = compile_rule(&rule).unwrap();
         assert!(!result.is_transition_rule);
@@ -257,6 +2212,7 @@ mod tests {
             reason: None,
             field_path: None,
             optional_old_self: None,
+            severity: Severity::Error,
         };
         let err = compile_rule(&rule).unwrap_err();
         assert!(matches!(err, CompilationError::Parse { .. }));
@@ -282,144 +2238,963 @@ mod tests {
             rule.message_expression.as_deref(),
             Some("\"x is \" + string(self.x)")
         );
-        assert_eq!(rule.reason.as_deref(), Some("FieldValueInvalid"));
-        assert_eq!(rule.field_path.as_deref(), Some(".spec.x"));
-        assert_eq!(rule.optional_old_self, Some(true));
+        assert_eq!(rule.reason, Some(Reason::FieldValueInvalid));
+        assert_eq!(rule.field_path.as_deref(), Some(".spec.x"));
+        assert_eq!(rule.optional_old_self, Some(true));
+    }
+
+    #[test]
+    fn deserialize_rule_minimal() {
+        let raw = json!({"rule": "self.x > 0"});
+        let rule: Rule = serde_json::from_value(raw).unwrap();
+        assert_eq!(rule.rule, "self.x > 0");
+        assert!(rule.message.is_none());
+        assert!(rule.message_expression.is_none());
+        assert!(rule.reason.is_none());
+        assert!(rule.field_path.is_none());
+        assert!(rule.optional_old_self.is_none());
+    }
+
+    #[test]
+    fn schema_validations_extracts_and_compiles() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.replicas >= 0", "message": "must be non-negative"},
+                {"rule": "self.name.size() > 0"}
+            ]
+        });
+        let results = compile_schema_validations(&schema);
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+    }
+
+    #[test]
+    fn schema_validations_no_key() {
+        let schema = json!({"type": "object"});
+        let results = compile_schema_validations(&schema);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn schema_validations_empty_array() {
+        let schema = json!({
+            "x-kubernetes-validations": []
+        });
+        let results = compile_schema_validations(&schema);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn message_expression_compiled() {
+        let rule = Rule {
+            rule: "self.x > 0".into(),
+            message: Some("x must be positive".into()),
+            message_expression: Some("'x is ' + string(self.x)".into()),
+            reason: None,
+            field_path: None,
+            optional_old_self: None,
+            severity: Severity::Error,
+        };
+        let result = compile_rule(&rule).unwrap();
+        assert!(result.message_program.is_some());
+    }
+
+    #[test]
+    fn message_expression_invalid_ignored() {
+        let rule = Rule {
+            rule: "self.x > 0".into(),
+            message: Some("fallback".into()),
+            message_expression: Some("invalid >=".into()),
+            reason: None,
+            field_path: None,
+            optional_old_self: None,
+            severity: Severity::Error,
+        };
+        let result = compile_rule(&rule).unwrap();
+        // Invalid messageExpression is silently ignored
+        assert!(result.message_program.is_none());
+    }
+
+    #[test]
+    fn message_expression_none() {
+        let rule = Rule {
+            rule: "self.x > 0".into(),
+            message: None,
+            message_expression: None,
+            reason: None,
+            field_path: None,
+            optional_old_self: None,
+            severity: Severity::Error,
+        };
+        let result = compile_rule(&rule).unwrap();
+        assert!(result.message_program.is_none());
+    }
+
+    fn rule(expr: &str) -> Rule {
+        Rule {
+            rule: expr.into(),
+            message: None,
+            message_expression: None,
+            reason: None,
+            field_path: None,
+            optional_old_self: None,
+            severity: Severity::Error,
+        }
+    }
+
+    #[test]
+    fn estimated_cost_recorded() {
+        let result = compile_rule(&rule("self.replicas >= 0")).unwrap();
+        assert_eq!(result.estimated_cost, 3);
+    }
+
+    #[test]
+    fn compile_with_limits_accepts_cheap_rule() {
+        let result = compile_rule_with_options(&rule("self.x > 0"), &CompileOptions::default());
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn compile_with_limits_rejects_expensive_rule() {
+        let err = compile_rule_with_options(
+            &rule("self.items.all(x, x.name.startsWith('a'))"),
+            &CompileOptions::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            CompilationError::CostLimitExceeded { limit, .. } if limit == STATIC_ESTIMATED_COST_LIMIT
+        ));
+        assert!(err.to_string().contains("exceeds the limit"));
+    }
+
+    #[test]
+    fn compile_with_custom_limit() {
+        let limits = CompileOptions {
+            max_estimated_cost: 2,
+            ..CompileOptions::default()
+        };
+        assert!(compile_rule_with_options(&rule("self.x > 0"), &limits).is_err());
+    }
+
+    #[test]
+    fn compile_with_limits_still_reports_parse_errors() {
+        let err = compile_rule_with_options(&rule("self.x >"), &CompileOptions::default());
+        assert!(matches!(err, Err(CompilationError::Parse { .. })));
+    }
+
+    #[test]
+    fn schema_bounds_used_for_estimated_cost() {
+        let rule = json!({"rule": "self.all(x, x.startsWith('a'))"});
+        let unbounded = json!({
+            "type": "array",
+            "items": {"type": "string"},
+            "x-kubernetes-validations": [rule]
+        });
+        let bounded = json!({
+            "type": "array",
+            "maxItems": 10,
+            "items": {"type": "string", "maxLength": 20},
+            "x-kubernetes-validations": [rule]
+        });
+        let cost = |schema| {
+            compile_schema_validations(&schema)[0]
+                .as_ref()
+                .unwrap()
+                .estimated_cost
+        };
+        assert!(cost(unbounded) > STATIC_ESTIMATED_COST_LIMIT);
+        assert!(cost(bounded) < 100);
+    }
+
+    #[test]
+    fn compile_schema_tree() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "has(self.spec)"}],
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "self.replicas >= 0"}],
+                    "properties": {
+                        "replicas": {"type": "integer"}
+                    }
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        assert_eq!(compiled.validations.len(), 1);
+        assert!(compiled.properties.contains_key("spec"));
+        let spec = &compiled.properties["spec"];
+        assert_eq!(spec.validations.len(), 1);
+        assert!(spec.properties.contains_key("replicas"));
+    }
+
+    #[test]
+    fn compile_schema_with_items() {
+        let schema = json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "x-kubernetes-validations": [{"rule": "self.name.size() > 0"}]
+            }
+        });
+        let compiled = compile_schema(&schema);
+        assert!(compiled.items.is_some());
+        assert_eq!(compiled.items.as_ref().unwrap().validations.len(), 1);
+    }
+
+    #[test]
+    fn compile_schema_empty() {
+        let schema = json!({"type": "object"});
+        let compiled = compile_schema(&schema);
+        assert!(compiled.validations.is_empty());
+        assert!(compiled.properties.is_empty());
+        assert!(compiled.items.is_none());
+        assert!(compiled.additional_properties.is_none());
+    }
+
+    #[test]
+    fn list_type_map_parsed() {
+        let schema = json!({
+            "type": "array",
+            "x-kubernetes-list-type": "map",
+            "x-kubernetes-list-map-keys": ["name", "protocol"],
+            "items": {"type": "object"}
+        });
+        assert_eq!(
+            compile_schema(&schema).list_type,
+            ListType::Map {
+                keys: vec!["name".into(), "protocol".into()]
+            }
+        );
+        assert_eq!(
+            compile_schema(&json!({"type": "array"})).list_type,
+            ListType::Unspecified
+        );
+    }
+
+    #[test]
+    fn correlate_map_list_by_keys() {
+        let list_type = ListType::Map {
+            keys: vec!["name".into()],
+        };
+        let new = json!([{"name": "b", "v": 2}, {"name": "c", "v": 3}, {"v": 4}]);
+        let old = json!([{"name": "a", "v": 1}, {"name": "b", "v": 5}]);
+        let pairs = list_type.correlate_indices(new.as_array().unwrap(), Some(&old));
+        assert_eq!(pairs, vec![Some(1), None, None]);
+    }
+
+    #[test]
+    fn correlate_set_by_value() {
+        let new = json!(["b", "c", "a"]);
+        let old = json!(["a", "b"]);
+        let pairs = ListType::Set.correlate_indices(new.as_array().unwrap(), Some(&old));
+        assert_eq!(pairs, vec![Some(1), None, Some(0)]);
+        assert_eq!(
+            compile_schema(&json!({"type": "array", "x-kubernetes-list-type": "set"})).list_type,
+            ListType::Set
+        );
+    }
+
+    #[test]
+    fn embedded_resource_gets_implicit_properties() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-embedded-resource": true,
+            "x-kubernetes-preserve-unknown-fields": true
+        });
+        let compiled = compile_schema(&schema);
+        let names: Vec<_> = compiled.properties.keys().collect();
+        assert_eq!(names, ["apiVersion", "kind", "metadata"]);
+        assert!(
+            compiled.properties["metadata"]
+                .properties
+                .contains_key("name")
+        );
+    }
+
+    #[test]
+    fn embedded_resource_keeps_explicit_properties() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-embedded-resource": true,
+            "properties": {
+                "kind": {
+                    "type": "string",
+                    "x-kubernetes-validations": [{"rule": "self == 'Pod'"}]
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        assert_eq!(compiled.properties["kind"].validations.len(), 1);
+        assert_eq!(compiled.properties.len(), 3);
+        assert!(matches!(
+            with_embedded_resource(&json!({})),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn compositions_are_folded_into_the_node() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "true"}],
+            "properties": {"a": {"type": "string", "format": "date-time"}},
+            "allOf": [{
+                "x-kubernetes-validations": [{"rule": "has(self.a)"}],
+                "properties": {
+                    "a": {"x-kubernetes-validations": [{"rule": "self != ''"}]},
+                    "b": {"type": "integer"}
+                }
+            }],
+            "anyOf": [{"oneOf": [{"x-kubernetes-validations": [{"rule": "1 == 1"}]}]}]
+        });
+        let compiled = compile_schema(&schema);
+        assert_eq!(compiled.validations.len(), 3);
+        assert_eq!(compiled.properties.len(), 2);
+        assert_eq!(compiled.properties["a"].validations.len(), 1);
+        assert_eq!(compiled.properties["a"].format, SchemaFormat::DateTime);
+        assert!(matches!(
+            with_compositions(&json!({"type": "object"})),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn all_of_branches_contribute_keywords() {
+        let schema = json!({
+            "type": "object",
+            "required": ["a"],
+            "properties": {
+                "expires": {"allOf": [{"type": "string", "format": "date-time"}]},
+                "tags": {
+                    "allOf": [{"x-kubernetes-list-type": "set"}],
+                    "anyOf": [{"maxItems": 1}],
+                    "type": "array",
+                    "items": {"type": "string"}
+                }
+            },
+            "allOf": [{"required": ["a", "b"], "type": "string"}],
+            "oneOf": [{"format": "duration"}]
+        });
+        let compiled = compile_schema(&schema);
+        assert_eq!(
+            compiled.properties["expires"].format,
+            SchemaFormat::DateTime
+        );
+        assert_eq!(compiled.properties["tags"].list_type, ListType::Set);
+        assert_eq!(compiled.format, SchemaFormat::default());
+
+        let merged = with_compositions(&schema);
+        assert_eq!(merged["type"], "object");
+        assert_eq!(merged["required"], json!(["a", "b"]));
+        let tags = with_compositions(&schema["properties"]["tags"]);
+        assert!(tags.get("maxItems").is_none());
+    }
+
+    #[test]
+    fn local_refs_are_resolved() {
+        let schema = json!({
+            "definitions": {
+                "Container": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "has(self.image)"}],
+                    "properties": {"image": {"type": "string"}}
+                },
+                "Named": {"properties": {"name": {"type": "string"}}}
+            },
+            "type": "object",
+            "properties": {
+                "main": {"$ref": "#/definitions/Container"},
+                "sidecars": {
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            {"$ref": "#/definitions/Container"},
+                            {"$ref": "#/definitions/Named"},
+                            {"$ref": "#/definitions/Named"}
+                        ]
+                    }
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        assert!(!compiled.has_errors());
+        assert_eq!(compiled.properties["main"].validations.len(), 1);
+        assert!(compiled.properties["main"].properties.contains_key("image"));
+        let item = compiled.properties["sidecars"].items.as_ref().unwrap();
+        assert_eq!(item.validations.len(), 1);
+        assert!(item.properties.contains_key("name"));
+    }
+
+    #[test]
+    fn invalid_and_cyclic_refs_are_reported() {
+        let schema = json!({
+            "definitions": {
+                "Node": {
+                    "type": "object",
+                    "properties": {"child": {"$ref": "#/definitions/Node"}}
+                }
+            },
+            "type": "object",
+            "properties": {
+                "tree": {"$ref": "#/definitions/Node"},
+                "missing": {"$ref": "#/definitions/Missing"}
+            }
+        });
+        let compiled = compile_schema(&schema);
+        let cyclic = &compiled.properties["tree"].properties["child"];
+        assert!(matches!(
+            cyclic.compilation_errors()[..],
+            [CompilationError::InvalidRef { message, .. }] if message == "cyclic reference"
+        ));
+        assert_eq!(
+            compiled.properties["missing"].compilation_errors()[0].to_string(),
+            "properties[missing]: invalid $ref \"#/definitions/Missing\": not found in the schema document"
+        );
+    }
+
+    #[cfg(feature = "strings")]
+    #[test]
+    fn undeclared_functions_are_rejected() {
+        let rule: Rule = serde_json::from_value(json!({"rule": "self.trimm() != ''"})).unwrap();
+        assert!(compile_rule_with_options(&rule, &CompileOptions::default()).is_ok());
+
+        let limits = CompileOptions {
+            check_functions: true,
+            ..CompileOptions::default()
+        };
+        let err = compile_rule_with_options(&rule, &limits).unwrap_err();
+        assert!(matches!(
+            &err,
+            CompilationError::UndeclaredFunction { function, suggestion, .. }
+                if function == "trimm" && suggestion.as_deref() == Some("trim")
+        ));
+        assert_eq!(
+            err.to_string(),
+            "rule \"self.trimm() != ''\" calls undeclared function 'trimm'; did you mean 'trim'?"
+        );
+    }
+
+    #[test]
+    fn parse_errors_point_at_the_offending_position() {
+        let rule: Rule = serde_json::from_value(json!({"rule": "self.x >"})).unwrap();
+        let err = compile_rule(&rule).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "failed to compile CEL rule \"self.x >\": 1:9: Syntax error: mismatched input \
+             '<EOF>' expecting {'[', '{', '(', '.', '-', '!', 'true', 'false', 'null', \
+             NUM_FLOAT, NUM_INT, NUM_UINT, STRING, BYTES, IDENTIFIER}\n  |\n1 | self.x >\n  \
+             |         ^"
+        );
+
+        let rule: Rule = serde_json::from_value(json!({"rule": "self.a &&\n  'é' == )"})).unwrap();
+        let err = compile_rule(&rule).unwrap_err();
+        let diagnostics = err.parse_diagnostics();
+        let span = diagnostics[0].span.unwrap();
+        assert_eq!((span.line, span.column), (2, 10));
+        assert_eq!(&rule.rule[span.offset..], ")");
+        assert!(
+            err.to_string()
+                .ends_with("\n  |\n2 |   'é' == )\n  |          ^")
+        );
+
+        assert!(
+            CompilationError::DepthLimitExceeded {
+                limit: 1,
+                schema_path: String::new()
+            }
+            .parse_diagnostics()
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn invalid_regex_literals_are_rejected() {
+        for rule in [
+            "self.matches('a(')",
+            "matches(self, '[z-a]')",
+            "self.find('*')",
+            "self.items.all(i, i.findAll('a(', 2).size() == 0)",
+        ] {
+            let rule: Rule = serde_json::from_value(json!({"rule": rule})).unwrap();
+            let err = compile_rule(&rule).unwrap_err();
+            assert!(
+                matches!(&err, CompilationError::InvalidRegex { .. }),
+                "{rule:?}: {err}"
+            );
+        }
+
+        let rule: Rule = serde_json::from_value(json!({"rule": "self.matches('a(')"})).unwrap();
+        let err = compile_rule(&rule).unwrap_err();
+        assert!(matches!(
+            &err,
+            CompilationError::InvalidRegex { pattern, .. } if pattern == "a("
+        ));
+        assert!(
+            err.to_string().starts_with(
+                "rule \"self.matches('a(')\" has an invalid regular expression \"a(\": "
+            )
+        );
+
+        for rule in ["self.matches('^a+$')", "self.matches(self.pattern)"] {
+            let rule: Rule = serde_json::from_value(json!({"rule": rule})).unwrap();
+            assert!(compile_rule(&rule).is_ok(), "{rule:?}");
+        }
     }
 
     #[test]
-    fn deserialize_rule_minimal() {
-        let raw = json!({"rule": "self.x > 0"});
-        let rule: Rule = serde_json::from_value(raw).unwrap();
-        assert_eq!(rule.rule, "self.x > 0");
-        assert!(rule.message.is_none());
-        assert!(rule.message_expression.is_none());
-        assert!(rule.reason.is_none());
-        assert!(rule.field_path.is_none());
-        assert!(rule.optional_old_self.is_none());
+    fn invalid_regex_in_message_expressions_warns() {
+        let rule: Rule = serde_json::from_value(json!({
+            "rule": "true",
+            "messageExpression": "self.matches('a(') ? 'a' : 'b'"
+        }))
+        .unwrap();
+        let compiled = compile_rule(&rule).unwrap();
+        assert!(compiled.message_program.is_none());
+        assert!(matches!(
+            &compiled.warnings[..],
+            [CompilationWarning::InvalidMessageExpression { message, .. }]
+                if message.starts_with("invalid regular expression \"a(\"")
+        ));
     }
 
     #[test]
-    fn schema_validations_extracts_and_compiles() {
-        let schema = json!({
-            "type": "object",
-            "x-kubernetes-validations": [
-                {"rule": "self.replicas >= 0", "message": "must be non-negative"},
-                {"rule": "self.name.size() > 0"}
-            ]
-        });
-        let results = compile_schema_validations(&schema);
-        assert_eq!(results.len(), 2);
-        assert!(results[0].is_ok());
-        assert!(results[1].is_ok());
+    fn rules_round_trip_in_the_crd_format() {
+        let rule = Rule::builder("self == oldSelf")
+            .with_message_expression("'immutable: ' + string(self)")
+            .with_reason("FieldValueForbidden")
+            .with_optional_old_self(true)
+            .with_severity(Severity::Warning)
+            .build();
+        let value = serde_json::to_value(&rule).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "rule": "self == oldSelf",
+                "messageExpression": "'immutable: ' + string(self)",
+                "reason": "FieldValueForbidden",
+                "optionalOldSelf": true,
+                "x-kube-cel-severity": "warning"
+            })
+        );
+        let parsed: Rule = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.reason, Some(Reason::FieldValueForbidden));
+        assert_eq!(parsed.severity, Severity::Warning);
+        assert_eq!(
+            serde_json::to_value(Rule::builder("true").build()).unwrap(),
+            json!({"rule": "true"})
+        );
     }
 
     #[test]
-    fn schema_validations_no_key() {
-        let schema = json!({"type": "object"});
-        let results = compile_schema_validations(&schema);
-        assert!(results.is_empty());
+    fn compile_rules_compiles_each_rule() {
+        let rules = [
+            rule("self.size() > 0"),
+            rule("self.size() >"),
+            rule("self.all(x, x.all(y, y.all(z, z > 0)))"),
+            rule("self.size() > 0"),
+        ];
+        let compiled = compile_rules(&rules);
+        assert_eq!(compiled.len(), 4);
+        assert!(matches!(compiled[1], Err(CompilationError::Parse { .. })));
+        assert!(compiled[2].is_ok());
+        assert!(Arc::ptr_eq(
+            &compiled[0].as_ref().unwrap().program,
+            &compiled[3].as_ref().unwrap().program
+        ));
+
+        let limited = compile_rules_with_options(&rules, &CompileOptions::default());
+        assert!(matches!(
+            limited[2],
+            Err(CompilationError::CostLimitExceeded { .. })
+        ));
+        assert!(limited[0].is_ok());
     }
 
     #[test]
-    fn schema_validations_empty_array() {
+    fn identical_rules_share_programs() {
         let schema = json!({
-            "x-kubernetes-validations": []
+            "type": "object",
+            "properties": {
+                "a": {"type": "string", "x-kubernetes-validations": [
+                    {"rule": "self.size() > 0"},
+                    {"rule": "self.size() < 10", "messageExpression": "'too long: ' + self"}
+                ]},
+                "b": {"type": "string", "x-kubernetes-validations": [
+                    {"rule": "self.size() > 0"},
+                    {"rule": "self.size() < 10", "messageExpression": "'too long: ' + self"}
+                ]}
+            }
         });
-        let results = compile_schema_validations(&schema);
-        assert!(results.is_empty());
+        let program = |s: &CompiledSchema, name: &str, i: usize| {
+            s.properties[name].validations[i]
+                .as_ref()
+                .unwrap()
+                .program
+                .clone()
+        };
+        let compiled = compile_schema(&schema);
+        assert!(Arc::ptr_eq(
+            &program(&compiled, "a", 0),
+            &program(&compiled, "b", 0)
+        ));
+        assert!(!Arc::ptr_eq(
+            &program(&compiled, "a", 0),
+            &program(&compiled, "a", 1)
+        ));
+        let message = |name: &str| {
+            compiled.properties[name].validations[1]
+                .as_ref()
+                .unwrap()
+                .message_program
+                .clone()
+                .unwrap()
+        };
+        assert!(Arc::ptr_eq(&message("a"), &message("b")));
+
+        // Separate compilations only share programs through a cache.
+        let again = compile_schema(&schema);
+        assert!(!Arc::ptr_eq(
+            &program(&compiled, "a", 0),
+            &program(&again, "a", 0)
+        ));
+        let cache = Arc::new(ProgramCache::new(8));
+        let options = CompileOptions {
+            program_cache: Some(cache.clone()),
+            ..CompileOptions::default()
+        };
+        let first = compile_schema_with_options(&schema, &options);
+        let second = compile_schema_with_options(&schema, &options);
+        assert!(Arc::ptr_eq(
+            &program(&first, "a", 1),
+            &program(&second, "b", 1)
+        ));
+        assert_eq!(cache.len(), 3);
     }
 
     #[test]
-    fn message_expression_compiled() {
-        let rule = Rule {
-            rule: "self.x > 0".into(),
-            message: Some("x must be positive".into()),
-            message_expression: Some("'x is ' + string(self.x)".into()),
-            reason: None,
-            field_path: None,
-            optional_old_self: None,
-        };
-        let result = compile_rule(&rule).unwrap();
-        assert!(result.message_program.is_some());
+    fn program_cache_evicts_least_recently_used() {
+        let cache = ProgramCache::new(2);
+        let a = cache.get_or_compile("1 + 1").unwrap();
+        cache.get_or_compile("2 + 2").unwrap();
+        assert!(Arc::ptr_eq(&a, &cache.get_or_compile("1 + 1").unwrap()));
+        cache.get_or_compile("3 + 3").unwrap();
+        assert_eq!(cache.len(), 2);
+        // "2 + 2" was least recently used.
+        assert!(Arc::ptr_eq(&a, &cache.get_or_compile("1 + 1").unwrap()));
+        assert!(cache.get_or_compile("1 +").is_err());
+        assert_eq!(cache.len(), 2);
+        cache.clear();
+        assert!(cache.is_empty());
     }
 
     #[test]
-    fn message_expression_invalid_ignored() {
-        let rule = Rule {
-            rule: "self.x > 0".into(),
-            message: Some("fallback".into()),
-            message_expression: Some("invalid >=".into()),
-            reason: None,
-            field_path: None,
-            optional_old_self: None,
+    fn options_configure_compilation() {
+        let rule: Rule = serde_json::from_value(json!({
+            "rule": "self.size() > 3",
+            "message": "too short",
+            "messageExpression": "'length ' +"
+        }))
+        .unwrap();
+        let compiled = compile_rule_with_options(&rule, &CompileOptions::default()).unwrap();
+        assert!(matches!(
+            compiled.warnings[..],
+            [CompilationWarning::InvalidMessageExpression { .. }]
+        ));
+
+        let options = CompileOptions {
+            reject_invalid_message_expressions: true,
+            ..CompileOptions::default()
         };
-        let result = compile_rule(&rule).unwrap();
-        // Invalid messageExpression is silently ignored
-        assert!(result.message_program.is_none());
+        assert!(matches!(
+            compile_rule_with_options(&rule, &options),
+            Err(CompilationError::InvalidDefinition { message, .. })
+                if message.starts_with("messageExpression failed to compile")
+        ));
+
+        let options = CompileOptions {
+            strict: true,
+            max_rule_length: 10,
+            max_message_length: 5,
+            ..CompileOptions::default()
+        };
+        assert!(matches!(
+            compile_rule_with_options(&rule, &options),
+            Err(CompilationError::InvalidDefinition { message, .. })
+                if message == "rule is 15 characters long, more than the limit of 10"
+        ));
+
+        let options = CompileOptions {
+            cost_warning_threshold: 0,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_rule_with_options(&rule, &options).unwrap();
+        assert!(
+            compiled
+                .warnings
+                .iter()
+                .any(|w| matches!(w, CompilationWarning::HighEstimatedCost { limit: 0, .. }))
+        );
     }
 
     #[test]
-    fn message_expression_none() {
-        let rule = Rule {
-            rule: "self.x > 0".into(),
-            message: None,
-            message_expression: None,
-            reason: None,
-            field_path: None,
-            optional_old_self: None,
+    fn warnings_do_not_fail_compilation() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "x-kubernetes-validations": [
+                        {"rule": "self != ''", "messageExpression": "'bad: ' +"},
+                        {"rule": "self != ''", "message": " "}
+                    ]
+                },
+                "tags": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "x-kubernetes-validations": [{"rule": "self == oldSelf"}]
+                    }
+                },
+                "matrix": {
+                    "type": "array",
+                    "items": {"type": "array", "items": {"type": "string"}},
+                    "x-kubernetes-validations": [
+                        {"rule": "self.all(row, row.all(a, self.exists(r, r.exists(b, a == b))))"}
+                    ]
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        assert!(!compiled.has_errors());
+        let warnings: Vec<_> = compiled
+            .compilation_warnings()
+            .into_iter()
+            .map(|(location, warning)| (location, warning.clone()))
+            .collect();
+        assert_eq!(warnings.len(), 4, "{warnings:?}");
+        assert!(matches!(
+            &warnings[0],
+            (location, CompilationWarning::HighEstimatedCost { limit: PER_CALL_LIMIT, .. })
+                if location == "properties[matrix].x-kubernetes-validations[0]"
+        ));
+        assert!(matches!(
+            &warnings[1],
+            (location, CompilationWarning::InvalidMessageExpression { expression, .. })
+                if location == "properties[name].x-kubernetes-validations[0]"
+                    && expression == "'bad: ' +"
+        ));
+        assert_eq!(
+            warnings[2],
+            (
+                "properties[name].x-kubernetes-validations[1]".to_string(),
+                CompilationWarning::InvalidDefinition {
+                    message: "message must be non-empty if specified".into()
+                }
+            )
+        );
+        assert_eq!(
+            warnings[3],
+            (
+                "properties[tags].items.x-kubernetes-validations[0]".to_string(),
+                CompilationWarning::UncorrelatableTransitionRule
+            )
+        );
+
+        let strict = CompileOptions {
+            strict: true,
+            ..CompileOptions::default()
         };
-        let result = compile_rule(&rule).unwrap();
-        assert!(result.message_program.is_none());
+        let compiled = compile_schema_with_options(&schema, &strict);
+        assert!(
+            compiled
+                .compilation_warnings()
+                .iter()
+                .all(|(_, w)| !matches!(w, CompilationWarning::InvalidDefinition { .. }))
+        );
     }
 
     #[test]
-    fn compile_schema_tree() {
+    fn rules_list_their_references() {
         let schema = json!({
             "type": "object",
-            "x-kubernetes-validations": [{"rule": "has(self.spec)"}],
             "properties": {
-                "spec": {
-                    "type": "object",
-                    "x-kubernetes-validations": [{"rule": "self.replicas >= 0"}],
-                    "properties": {
-                        "replicas": {"type": "integer"}
-                    }
+                "name": {
+                    "type": "string",
+                    "x-kubernetes-validations": [{
+                        "rule": "self.matches('^[a-z]+$') && self != oldSelf",
+                        "messageExpression": "'bad name: ' + self + params.suffix"
+                    }]
+                },
+                "sizes": {
+                    "type": "array",
+                    "items": {"type": "number"},
+                    "x-kubernetes-validations": [
+                        {"rule": "self.all(x, math.ceil(x) < limit)"},
+                        {"rule": "self.map(x, x).size() >"}
+                    ]
                 }
             }
         });
         let compiled = compile_schema(&schema);
-        assert_eq!(compiled.validations.len(), 1);
-        assert!(compiled.properties.contains_key("spec"));
+        let references = compiled.rule_references();
+        assert_eq!(references.len(), 2);
+
+        let name = &references[0];
+        assert_eq!(
+            name.schema_path,
+            "properties[name].x-kubernetes-validations[0]"
+        );
+        assert_eq!(name.variables, ["oldSelf", "params", "self"]);
+        assert_eq!(name.functions, ["matches"]);
+
+        let sizes = &references[1];
+        assert_eq!(
+            sizes.schema_path,
+            "properties[sizes].x-kubernetes-validations[0]"
+        );
+        assert_eq!(sizes.variables, ["limit", "self"]);
+        #[cfg(feature = "math")]
+        assert_eq!(sizes.functions, ["math.ceil"]);
+    }
+
+    #[test]
+    fn typecheck_rejects_ill_typed_rules() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"spec": {
+                "type": "object",
+                "properties": {"replicas": {"type": "integer"}},
+                "x-kubernetes-validations": [
+                    {"rule": "self.replicas >= 0"},
+                    {"rule": "self.replica >= 0"},
+                    {"rule": "self.replicas == 'one'"}
+                ]
+            }}
+        });
+        assert!(!compile_schema(&schema).has_errors());
+
+        let limits = CompileOptions {
+            typecheck: true,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_schema_with_options(&schema, &limits);
         let spec = &compiled.properties["spec"];
-        assert_eq!(spec.validations.len(), 1);
-        assert!(spec.properties.contains_key("replicas"));
+        assert!(spec.validations[0].is_ok());
+        assert!(matches!(
+            &spec.validations[1],
+            Err(CompilationError::TypeCheck { message, schema_path, .. })
+                if message == "undefined field 'replica'"
+                    && schema_path == "properties[spec].x-kubernetes-validations[1]"
+        ));
+        assert_eq!(
+            spec.validations[2].as_ref().unwrap_err().to_string(),
+            "properties[spec].x-kubernetes-validations[2]: rule \"self.replicas == 'one'\" \
+             does not type-check: found no matching overload for '_==_' applied to '(int, string)'"
+        );
     }
 
     #[test]
-    fn compile_schema_with_items() {
+    fn errors_carry_their_schema_path() {
         let schema = json!({
-            "type": "array",
-            "items": {
+            "type": "object",
+            "properties": {"spec": {
                 "type": "object",
-                "x-kubernetes-validations": [{"rule": "self.name.size() > 0"}]
-            }
+                "properties": {"replicas": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [{"rule": "self >= 0"}, {"rule": "self >="}]
+                }}
+            }}
         });
         let compiled = compile_schema(&schema);
-        assert!(compiled.items.is_some());
-        assert_eq!(compiled.items.as_ref().unwrap().validations.len(), 1);
+        let errors = compiled.properties["spec"].properties["replicas"].compilation_errors();
+        assert_eq!(
+            errors[0].schema_path(),
+            "properties[spec].properties[replicas].x-kubernetes-validations[1]"
+        );
+        assert!(errors[0].to_string().starts_with(
+            "properties[spec].properties[replicas].x-kubernetes-validations[1]: failed to compile"
+        ));
+
+        let errors = crate::validation::Validator::new().validate_compiled(
+            &compiled,
+            &json!({"spec": {"replicas": 1}}),
+            None,
+        );
+        assert!(errors[0].message.starts_with(
+            "properties[spec].properties[replicas].x-kubernetes-validations[1]: failed to compile rule"
+        ));
+
+        let rule: Rule = serde_json::from_value(json!({"rule": "self >="})).unwrap();
+        assert_eq!(compile_rule(&rule).unwrap_err().schema_path(), "");
     }
 
     #[test]
-    fn compile_schema_empty() {
-        let schema = json!({"type": "object"});
+    fn preserve_unknown_fields_ignores_additional_properties() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true,
+            "additionalProperties": {"type": "string"}
+        });
         let compiled = compile_schema(&schema);
-        assert!(compiled.validations.is_empty());
-        assert!(compiled.properties.is_empty());
-        assert!(compiled.items.is_none());
+        assert!(compiled.preserve_unknown_fields);
         assert!(compiled.additional_properties.is_none());
     }
 
+    #[test]
+    fn severity_parsed() {
+        let rule: Rule =
+            serde_json::from_value(json!({"rule": "true", "x-kube-cel-severity": "warning"}))
+                .unwrap();
+        assert_eq!(rule.severity, Severity::Warning);
+        let rule: Rule = serde_json::from_value(json!({"rule": "true"})).unwrap();
+        assert_eq!(rule.severity, Severity::Error);
+    }
+
+    #[test]
+    fn reason_parsed() {
+        let rule: Rule =
+            serde_json::from_value(json!({"rule": "true", "reason": "FieldValueDuplicate"}))
+                .unwrap();
+        assert_eq!(rule.reason, Some(Reason::FieldValueDuplicate));
+        let rule: Rule =
+            serde_json::from_value(json!({"rule": "true", "reason": "Custom"})).unwrap();
+        assert_eq!(rule.reason, Some(Reason::Unknown("Custom".into())));
+        assert_eq!(rule.reason.unwrap().to_string(), "Custom");
+    }
+
+    #[test]
+    fn correlate_atomic_never() {
+        let new = json!([1, 2]);
+        let old = json!([1, 2]);
+        let pairs = ListType::Atomic.correlate_indices(new.as_array().unwrap(), Some(&old));
+        assert_eq!(pairs, vec![None, None]);
+        assert_eq!(MapType::Atomic.correlate(Some(&old)), None);
+        assert_eq!(MapType::Granular.correlate(Some(&old)), Some(&old));
+    }
+
+    #[test]
+    fn map_type_parsed() {
+        let schema = json!({"type": "object", "x-kubernetes-map-type": "atomic"});
+        assert_eq!(compile_schema(&schema).map_type, MapType::Atomic);
+        assert_eq!(
+            compile_schema(&json!({"type": "object"})).map_type,
+            MapType::Granular
+        );
+    }
+
+    #[test]
+    fn correlate_unspecified_by_index() {
+        let new = json!([1, 2, 3]);
+        let old = json!([4, 5]);
+        let pairs = ListType::Unspecified.correlate_indices(new.as_array().unwrap(), Some(&old));
+        assert_eq!(pairs, vec![Some(0), Some(1), None]);
+    }
+
     #[test]
     fn schema_validations_partial_errors() {
         let schema = json!({
@@ -464,4 +3239,285 @@ mod tests {
         assert!(compiled.compilation_errors().is_empty());
         assert!(!compiled.has_errors());
     }
+
+    #[test]
+    fn schema_depth_limit() {
+        let mut schema =
+            json!({"type": "integer", "x-kubernetes-validations": [{"rule": "self > 0"}]});
+        for _ in 0..4 {
+            schema = json!({"type": "object", "properties": {"a": schema}});
+        }
+        let limits = CompileOptions {
+            max_depth: 2,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_schema_with_options(&schema, &limits);
+        let at_limit = &compiled.properties["a"].properties["a"];
+        assert!(at_limit.properties.is_empty());
+        assert!(matches!(
+            at_limit.validations.as_slice(),
+            [Err(CompilationError::DepthLimitExceeded { limit: 2, .. })]
+        ));
+        let full = compile_schema(&schema);
+        let leaf = &full.properties["a"].properties["a"].properties["a"].properties["a"];
+        assert!(leaf.validations[0].is_ok());
+    }
+
+    #[test]
+    fn schema_cost_limit() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"x": {"type": "integer"}},
+            "x-kubernetes-validations": [{"rule": "self.x > 0"}]
+        });
+        let limits = CompileOptions {
+            max_estimated_cost: 2,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_schema_with_options(&schema, &limits);
+        assert!(matches!(
+            compiled.validations.as_slice(),
+            [Err(CompilationError::CostLimitExceeded { limit: 2, .. })]
+        ));
+        assert!(compile_schema(&schema).validations[0].is_ok());
+    }
+
+    #[test]
+    fn strict_mode_checks_definitions() {
+        let strict = CompileOptions {
+            strict: true,
+            ..CompileOptions::default()
+        };
+        let reject = |raw: serde_json::Value| {
+            let rule: Rule = serde_json::from_value(raw).unwrap();
+            assert!(compile_rule_with_options(&rule, &CompileOptions::default()).is_ok());
+            match compile_rule_with_options(&rule, &strict) {
+                Err(CompilationError::InvalidDefinition { message, .. }) => message,
+                other => panic!("expected InvalidDefinition, got {other:?}"),
+            }
+        };
+        assert_eq!(
+            reject(json!({"rule": "true", "message": "a\nb"})),
+            "message must not contain line breaks"
+        );
+        assert_eq!(
+            reject(json!({"rule": "true", "message": "  "})),
+            "message must be non-empty if specified"
+        );
+        assert_eq!(
+            reject(json!({"rule": "true &&\ntrue"})),
+            "message must be specified if rule contains line breaks"
+        );
+        let long = format!("true{}", " ".repeat(MAX_RULE_LENGTH));
+        assert!(reject(json!({"rule": long})).starts_with("rule is 4100 characters long"));
+        let message = "x".repeat(MAX_MESSAGE_LENGTH + 1);
+        assert!(reject(json!({"rule": "true", "message": message})).contains("limit of 2048"));
+        let ok: Rule =
+            serde_json::from_value(json!({"rule": "true &&\ntrue", "message": "m"})).unwrap();
+        assert!(compile_rule_with_options(&ok, &strict).is_ok());
+
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "true", "message": "bad\nmessage"},
+                {"rule": "true", "message": "fine"}
+            ]
+        });
+        let compiled = compile_schema_with_options(&schema, &strict);
+        assert!(matches!(
+            compiled.validations[0],
+            Err(CompilationError::InvalidDefinition { .. })
+        ));
+        assert!(compiled.validations[1].is_ok());
+        assert!(!compile_schema(&schema).has_errors());
+    }
+
+    #[test]
+    fn strict_mode_rejects_uncorrelatable_transition_rules() {
+        let transition = json!([{"rule": "self == oldSelf"}]);
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": transition,
+            "properties": {
+                "tags": {
+                    "type": "array",
+                    "x-kubernetes-validations": transition,
+                    "items": {"type": "string", "x-kubernetes-validations": transition}
+                },
+                "ports": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "map",
+                    "x-kubernetes-list-map-keys": ["name"],
+                    "items": {
+                        "type": "object",
+                        "x-kubernetes-validations": transition,
+                        "properties": {"name": {"type": "string"}}
+                    }
+                },
+                "config": {
+                    "type": "object",
+                    "x-kubernetes-map-type": "atomic",
+                    "properties": {
+                        "mode": {"type": "string", "x-kubernetes-validations": transition}
+                    }
+                }
+            }
+        });
+        let strict = CompileOptions {
+            strict: true,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_schema_with_options(&schema, &strict);
+        let rejected = |node: &CompiledSchema| {
+            matches!(
+                node.validations.as_slice(),
+                [Err(CompilationError::UncorrelatableTransitionRule { .. })]
+            )
+        };
+        assert!(!rejected(&compiled));
+        assert!(!rejected(&compiled.properties["tags"]));
+        assert!(rejected(
+            compiled.properties["tags"].items.as_ref().unwrap()
+        ));
+        assert!(!rejected(
+            compiled.properties["ports"].items.as_ref().unwrap()
+        ));
+        assert!(rejected(&compiled.properties["config"].properties["mode"]));
+
+        let lenient = compile_schema(&schema);
+        assert!(!rejected(
+            lenient.properties["tags"].items.as_ref().unwrap()
+        ));
+    }
+
+    #[test]
+    fn strict_compilation_reports_error_locations() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "self.a > 0"}, {"rule": "self.a >"}],
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {"type": "integer", "x-kubernetes-validations": [{"rule": "1 +"}]},
+                        "template": {"$ref": "#/definitions/Missing"}
+                    }
+                },
+                "tags": {"type": "array", "items": {"type": "string", "x-kubernetes-validations": [{"rule": ")"}]}}
+            }
+        });
+        let errors = compile_schema_strict(&schema).unwrap_err();
+        let locations: Vec<_> = errors.iter().map(|(l, _)| l.as_str()).collect();
+        assert_eq!(
+            locations,
+            [
+                "x-kubernetes-validations[1]",
+                "properties[spec].properties[replicas].x-kubernetes-validations[0]",
+                "properties[spec].properties[template]",
+                "properties[tags].items.x-kubernetes-validations[0]",
+            ]
+        );
+        assert!(matches!(errors[2].1, CompilationError::InvalidRef { .. }));
+
+        let valid = json!({"type": "object", "x-kubernetes-validations": [{"rule": "true"}]});
+        assert_eq!(compile_schema_strict(&valid).unwrap().validations.len(), 1);
+    }
+
+    #[cfg(feature = "schemars")]
+    #[test]
+    fn compiles_schemars_schemas() {
+        use schemars::schema::{InstanceType, ObjectValidation, RootSchema, Schema, SchemaObject};
+
+        let mut replicas = SchemaObject {
+            instance_type: Some(InstanceType::Integer.into()),
+            ..Default::default()
+        };
+        replicas.extensions.insert(
+            "x-kubernetes-validations".into(),
+            json!([{"rule": "self >= 0", "message": "must not be negative"}]),
+        );
+        let spec = SchemaObject {
+            instance_type: Some(InstanceType::Object.into()),
+            object: Some(Box::new(ObjectValidation {
+                properties: [
+                    ("replicas".to_string(), Schema::Object(replicas.clone())),
+                    (
+                        "template".to_string(),
+                        Schema::new_ref("#/definitions/Replicas".into()),
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        let compiled = compile_schema_from(&spec);
+        let rule = compiled.properties["replicas"].validations[0]
+            .as_ref()
+            .unwrap();
+        assert_eq!(rule.rule.message.as_deref(), Some("must not be negative"));
+        // Without the root schema, the `$ref` cannot be resolved.
+        assert!(compiled.properties["template"].has_errors());
+
+        let root = RootSchema {
+            schema: spec,
+            definitions: [("Replicas".to_string(), Schema::Object(replicas))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let compiled = compile_schema_from(&root);
+        assert!(compiled.properties["template"].validations[0].is_ok());
+        assert!(
+            compile_schema_from(&Schema::Bool(true))
+                .validations
+                .is_empty()
+        );
+    }
+
+    #[cfg(feature = "k8s-openapi")]
+    #[test]
+    fn compiles_k8s_openapi_schemas() {
+        use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+            CustomResourceValidation, JSONSchemaProps, ValidationRule,
+        };
+
+        let props = JSONSchemaProps {
+            type_: Some("string".into()),
+            max_length: Some(8),
+            x_kubernetes_validations: Some(vec![ValidationRule {
+                rule: "self.contains('ab')".into(),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let cost =
+            |compiled: &CompiledSchema| compiled.validations[0].as_ref().unwrap().estimated_cost;
+        // `maxLength` bounds the cost just as it does in JSON.
+        let json = json!({
+            "type": "string",
+            "maxLength": 8,
+            "x-kubernetes-validations": [{"rule": "self.contains('ab')"}]
+        });
+        assert_eq!(
+            cost(&compile_schema_from(&props)),
+            cost(&compile_schema(&json))
+        );
+        assert_ne!(
+            cost(&compile_schema_from(&props)),
+            cost(&compile_schema(&json!({
+                "type": "string",
+                "x-kubernetes-validations": [{"rule": "self.contains('ab')"}]
+            })))
+        );
+
+        let validation = CustomResourceValidation {
+            open_api_v3_schema: Some(props),
+        };
+        assert!(compile_schema_from(&validation).validations[0].is_ok());
+        let empty = CustomResourceValidation::default();
+        assert!(compile_schema_from(&empty).validations.is_empty());
+    }
 }
@@ -0,0 +1,530 @@
+//! CEL cost accounting for validation rules.
+//!
+//! Kubernetes bounds the CPU spent on CEL by assigning each expression a cost
+//! and rejecting evaluations that exceed a limit. This module computes costs
+//! in the same spirit as cel-go's cost model:
+//!
+//! - identifiers, field selections and function calls cost 1 each,
+//! - string and list operations add a cost proportional to the size of their
+//!   operands (0.1 per character, 1 per list element),
+//! - comprehension macros (`all`, `exists`, `map`, `filter`, ...) multiply
+//!   the cost of their body by the size of the iterated collection.
+//!
+//! Costs are upper bounds computed from the sizes of the bound variables, not
+//! from tracing an actual evaluation. At compile time, when no values are
+//! available, [`estimate_cost`] assumes collections and strings may be as
+//! large as [`MAX_REQUEST_SIZE`], mirroring the apiserver's static estimator.
+//! [`estimate_cost_with_schema`] tightens these bounds with the schema's
+//! `maxLength`, `maxItems` and `maxProperties`.
+
+use std::collections::HashMap;
+
+use cel::Value;
+use cel::common::ast::{EntryExpr, Expr, operators};
+use cel::common::value::CelVal;
+use cel::objects::Key;
+use cel::parser::Expression;
+
+use crate::compilation::additional_properties_schema;
+use crate::escaping::escape_field_name;
+
+/// Per-expression runtime cost limit used by kube-apiserver (`PerCallLimit`).
+pub const PER_CALL_LIMIT: u64 = 1_000_000;
+
+/// Per-object runtime cost budget used by kube-apiserver (`RuntimeCELCostBudget`).
+pub const RUNTIME_COST_BUDGET: u64 = 10_000_000;
+
+/// Per-expression static cost limit used by kube-apiserver (`StaticEstimatedCostLimit`).
+pub const STATIC_ESTIMATED_COST_LIMIT: u64 = 10_000_000;
+
+/// Maximum size of a request body accepted by kube-apiserver. Used as the
+/// size bound for values whose size is not otherwise known.
+pub const MAX_REQUEST_SIZE: u64 = 3 * 1024 * 1024;
+
+/// Cost factor applied per character for string operations.
+const STRING_TRAVERSAL_FACTOR: f64 = 0.1;
+
+/// Estimate the worst-case cost of an expression without any data.
+///
+/// Every variable, string and collection is assumed to be as large as
+/// [`MAX_REQUEST_SIZE`], so rules iterating over unbounded lists get very
+/// high estimates, as they do in Kubernetes.
+#[must_use]
+pub fn estimate_cost(expr: &Expression) -> u64 {
+    CostModel {
+        unknown: MAX_REQUEST_SIZE,
+    }
+    .cost(expr, &Scope::new())
+}
+
+/// Estimate the worst-case cost of a rule declared on `schema`.
+///
+/// `self` and `oldSelf` are bounded by the schema: `maxLength`, `maxItems`
+/// and `maxProperties` limit string, list and map sizes, and scalar types
+/// have no traversal cost. Values without a bound are assumed to be as large
+/// as [`MAX_REQUEST_SIZE`], like [`estimate_cost`].
+#[must_use]
+pub fn estimate_cost_with_schema(expr: &Expression, schema: &serde_json::Value) -> u64 {
+    let scope = [
+        ("self".to_string(), Size::Schema(schema)),
+        ("oldSelf".to_string(), Size::Schema(schema)),
+    ]
+    .into_iter()
+    .collect();
+    CostModel {
+        unknown: MAX_REQUEST_SIZE,
+    }
+    .cost(expr, &scope)
+}
+
+/// Compute the cost of evaluating `expr` with the given variables bound.
+///
+/// Sizes of unresolvable sub-expressions (function results, accumulators)
+/// are bounded by the total size of all bound variables.
+pub(crate) fn runtime_cost(expr: &Expression, vars: &[(&str, &Value)]) -> u64 {
+    let unknown = vars
+        .iter()
+        .map(|(_, v)| total_size(v))
+        .fold(1u64, u64::saturating_add);
+    let scope = vars
+        .iter()
+        .map(|(name, v)| (name.to_string(), Size::Values(vec![v])))
+        .collect();
+    CostModel { unknown }.cost(expr, &scope)
+}
+
+/// Total number of characters, bytes, list elements and map entries in a value.
+fn total_size(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => s.len() as u64,
+        Value::Bytes(b) => b.len() as u64,
+        Value::List(items) => items
+            .iter()
+            .map(total_size)
+            .fold(items.len() as u64, u64::saturating_add),
+        Value::Map(m) => m.map.iter().fold(m.map.len() as u64, |acc, (k, v)| {
+            acc.saturating_add(key_len(k)).saturating_add(total_size(v))
+        }),
+        _ => 1,
+    }
+}
+
+fn key_len(key: &Key) -> u64 {
+    match key {
+        Key::String(s) => s.len() as u64,
+        _ => 1,
+    }
+}
+
+/// The size information known for an expression.
+#[derive(Clone, Debug)]
+enum Size<'a> {
+    /// The expression evaluates to one of these concrete values.
+    Values(Vec<&'a Value>),
+    /// The expression evaluates to a value described by this schema node.
+    Schema(&'a serde_json::Value),
+    /// The expression has at most this length; its contents are unknown.
+    Len(u64),
+    /// Nothing is known about the expression.
+    Unknown,
+}
+
+impl<'a> Size<'a> {
+    fn max_len(&self, unknown: u64) -> u64 {
+        match self {
+            Size::Values(values) => values.iter().map(|v| len_of(v)).max().unwrap_or(0),
+            Size::Schema(schema) => schema_max_len(schema).unwrap_or(unknown),
+            Size::Len(n) => *n,
+            Size::Unknown => unknown,
+        }
+    }
+
+    fn field(&self, name: &str) -> Size<'a> {
+        match self {
+            Size::Values(values) => {
+                let key = Key::String(name.to_string().into());
+                Size::Values(
+                    values
+                        .iter()
+                        .filter_map(|v| match v {
+                            Value::Map(m) => m.map.get(&key),
+                            _ => None,
+                        })
+                        .collect(),
+                )
+            }
+            Size::Schema(schema) => schema
+                .get("properties")
+                .and_then(|p| p.as_object())
+                .and_then(|props| {
+                    props
+                        .iter()
+                        .find(|(k, _)| escape_field_name(k) == name)
+                        .map(|(_, v)| v)
+                })
+                .or_else(|| additional_properties_schema(schema))
+                .map_or(Size::Unknown, Size::Schema),
+            _ => Size::Unknown,
+        }
+    }
+
+    /// Size of list elements or map values.
+    fn elements(&self) -> Size<'a> {
+        match self {
+            Size::Values(values) => {
+                let mut out = Vec::new();
+                for v in values {
+                    match v {
+                        Value::List(items) => out.extend(items.iter()),
+                        Value::Map(m) => out.extend(m.map.values()),
+                        _ => {}
+                    }
+                }
+                Size::Values(out)
+            }
+            Size::Schema(schema) => schema
+                .get("items")
+                .or_else(|| additional_properties_schema(schema))
+                .map_or(Size::Unknown, Size::Schema),
+            _ => Size::Unknown,
+        }
+    }
+
+    /// Size of the variable bound by a comprehension over this collection:
+    /// list elements, or map keys.
+    fn iter_var(&self) -> Size<'a> {
+        match self {
+            Size::Values(values) if values.iter().any(|v| matches!(v, Value::Map(_))) => {
+                let longest_key = values
+                    .iter()
+                    .filter_map(|v| match v {
+                        Value::Map(m) => m.map.keys().map(key_len).max(),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                Size::Len(longest_key)
+            }
+            Size::Schema(schema) if schema.get("items").is_none() => Size::Unknown,
+            _ => self.elements(),
+        }
+    }
+}
+
+/// The size bound declared by a schema node, if any. Scalars have size 0.
+fn schema_max_len(schema: &serde_json::Value) -> Option<u64> {
+    let bound = |key| schema.get(key).and_then(|v| v.as_u64());
+    match schema.get("type").and_then(|t| t.as_str()) {
+        Some("string") => bound("maxLength"),
+        Some("array") => bound("maxItems"),
+        Some("object") => bound("maxProperties"),
+        Some("integer" | "number" | "boolean") => Some(0),
+        _ => bound("maxLength")
+            .or_else(|| bound("maxItems"))
+            .or_else(|| bound("maxProperties")),
+    }
+}
+
+fn len_of(value: &Value) -> u64 {
+    match value {
+        Value::String(s) => s.len() as u64,
+        Value::Bytes(b) => b.len() as u64,
+        Value::List(items) => items.len() as u64,
+        Value::Map(m) => m.map.len() as u64,
+        _ => 0,
+    }
+}
+
+type Scope<'a> = HashMap<String, Size<'a>>;
+
+struct CostModel {
+    /// Size assumed for expressions whose size cannot be determined.
+    unknown: u64,
+}
+
+impl CostModel {
+    fn cost(&self, expr: &Expression, scope: &Scope<'_>) -> u64 {
+        match &expr.expr {
+            Expr::Literal(_) | Expr::Unspecified => 0,
+            Expr::Ident(_) => 1,
+            Expr::Select(select) => self.cost(&select.operand, scope).saturating_add(1),
+            Expr::List(list) => list
+                .elements
+                .iter()
+                .map(|e| self.cost(e, scope))
+                .fold(1, u64::saturating_add),
+            Expr::Map(map) => map
+                .entries
+                .iter()
+                .map(|entry| self.entry_cost(&entry.expr, scope))
+                .fold(1, u64::saturating_add),
+            Expr::Struct(st) => st
+                .entries
+                .iter()
+                .map(|entry| self.entry_cost(&entry.expr, scope))
+                .fold(1, u64::saturating_add),
+            Expr::Call(call) => {
+                let operands = call
+                    .target
+                    .iter()
+                    .map(|t| t.as_ref())
+                    .chain(call.args.iter())
+                    .map(|e| self.cost(e, scope))
+                    .fold(1, u64::saturating_add);
+                operands.saturating_add(self.call_cost(
+                    &call.func_name,
+                    call.target.as_deref(),
+                    &call.args,
+                    scope,
+                ))
+            }
+            Expr::Comprehension(comp) => {
+                let range = self.size_of(&comp.iter_range, scope);
+                let iterations = range.max_len(self.unknown);
+                let mut inner = scope.clone();
+                inner.insert(comp.iter_var.clone(), range.iter_var());
+                inner.insert(comp.accu_var.clone(), Size::Unknown);
+                let per_iteration = self
+                    .cost(&comp.loop_cond, &inner)
+                    .saturating_add(self.cost(&comp.loop_step, &inner));
+                self.cost(&comp.iter_range, scope)
+                    .saturating_add(self.cost(&comp.accu_init, scope))
+                    .saturating_add(iterations.saturating_mul(per_iteration))
+                    .saturating_add(self.cost(&comp.result, &inner))
+            }
+        }
+    }
+
+    fn entry_cost(&self, entry: &EntryExpr, scope: &Scope<'_>) -> u64 {
+        match entry {
+            EntryExpr::StructField(field) => self.cost(&field.value, scope),
+            EntryExpr::MapEntry(e) => self
+                .cost(&e.key, scope)
+                .saturating_add(self.cost(&e.value, scope)),
+        }
+    }
+
+    /// Additional, size-dependent cost of a function call beyond its operands.
+    fn call_cost(
+        &self,
+        name: &str,
+        target: Option<&Expression>,
+        args: &[Expression],
+        scope: &Scope<'_>,
+    ) -> u64 {
+        let len = |e: &Expression| self.size_of(e, scope).max_len(self.unknown);
+        let receiver = target.or(args.first());
+        match name {
+            operators::EQUALS | operators::NOT_EQUALS | operators::ADD => {
+                let total = args.iter().map(len).fold(0, u64::saturating_add);
+                traversal(total)
+            }
+            operators::IN => args.get(1).map(len).unwrap_or(0),
+            "contains" | "startsWith" | "endsWith" | "matches" | "find" | "findAll" | "replace"
+            | "split" | "lowerAscii" | "upperAscii" | "trim" | "substring" | "charAt"
+            | "format" | "quote" | "strings.quote" => {
+                let total = receiver
+                    .into_iter()
+                    .chain(args.iter())
+                    .map(len)
+                    .fold(0, u64::saturating_add);
+                traversal(total)
+            }
+            "indexOf" | "lastIndexOf" | "reverse" | "join" | "sum" | "min" | "max" | "isSorted"
+            | "sort" | "distinct" | "flatten" | "slice" | "first" | "last" => {
+                receiver.map(len).unwrap_or(0)
+            }
+            "sets.contains" | "sets.equivalent" | "sets.intersects" => {
+                let a = args.first().map(len).unwrap_or(0);
+                let b = args.get(1).map(len).unwrap_or(0);
+                a.saturating_mul(b)
+            }
+            _ => 0,
+        }
+    }
+
+    fn size_of<'a>(&self, expr: &Expression, scope: &Scope<'a>) -> Size<'a> {
+        match &expr.expr {
+            Expr::Ident(name) => scope.get(name).cloned().unwrap_or(Size::Unknown),
+            Expr::Select(select) if !select.test => {
+                self.size_of(&select.operand, scope).field(&select.field)
+            }
+            Expr::Literal(CelVal::String(s)) => Size::Len(s.len() as u64),
+            Expr::Literal(CelVal::Bytes(b)) => Size::Len(b.len() as u64),
+            Expr::Literal(_) => Size::Len(0),
+            Expr::List(list) => Size::Len(list.elements.len() as u64),
+            Expr::Map(map) => Size::Len(map.entries.len() as u64),
+            Expr::Call(call) if call.func_name == operators::INDEX && call.args.len() == 2 => {
+                let container = self.size_of(&call.args[0], scope);
+                match literal_string(&call.args[1]) {
+                    Some(key) => container.field(&key),
+                    None => container.elements(),
+                }
+            }
+            _ => Size::Unknown,
+        }
+    }
+}
+
+fn literal_string(expr: &Expression) -> Option<String> {
+    match &expr.expr {
+        Expr::Literal(CelVal::String(s)) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn traversal(len: u64) -> u64 {
+    (len as f64 * STRING_TRAVERSAL_FACTOR).ceil() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::values::json_to_cel;
+    use cel::Program;
+    use serde_json::json;
+
+    fn cost_of(expr: &str, self_val: serde_json::Value) -> u64 {
+        let program = Program::compile(expr).unwrap();
+        let value = json_to_cel(&self_val);
+        runtime_cost(program.expression(), &[("self", &value)])
+    }
+
+    #[test]
+    fn simple_comparison_is_cheap() {
+        assert_eq!(cost_of("self.replicas >= 0", json!({"replicas": 3})), 3);
+    }
+
+    #[test]
+    fn literals_are_free() {
+        assert_eq!(cost_of("1 + 2 == 3", json!({})), 3);
+    }
+
+    #[test]
+    fn string_operations_scale_with_length() {
+        let short = cost_of("self.name.startsWith('a')", json!({"name": "ab"}));
+        let long = cost_of(
+            "self.name.startsWith('a')",
+            json!({"name": "a".repeat(1000)}),
+        );
+        assert!(long > short + 90, "short={short} long={long}");
+    }
+
+    #[test]
+    fn comprehension_scales_with_list_size() {
+        let items = |n: usize| json!({"items": (0..n).collect::<Vec<_>>()});
+        let small = cost_of("self.items.all(x, x >= 0)", items(10));
+        let large = cost_of("self.items.all(x, x >= 0)", items(1000));
+        assert!(large >= small * 90, "small={small} large={large}");
+    }
+
+    #[test]
+    fn nested_comprehension_multiplies() {
+        let obj = json!({"rows": (0..100).map(|_| json!({"cols": (0..100).collect::<Vec<_>>()})).collect::<Vec<_>>()});
+        let cost = cost_of("self.rows.all(r, r.cols.all(c, c >= 0))", obj);
+        assert!(cost >= 100 * 100, "cost={cost}");
+    }
+
+    #[test]
+    fn map_comprehension_uses_key_length() {
+        let obj = json!({"labels": {"a": "x", "b": "y"}});
+        let cost = cost_of("self.labels.all(k, k.startsWith('a'))", obj);
+        assert!(cost < 50, "cost={cost}");
+    }
+
+    fn estimate(expr: &str) -> u64 {
+        estimate_cost(Program::compile(expr).unwrap().expression())
+    }
+
+    #[test]
+    fn estimate_scalar_rule() {
+        assert_eq!(estimate("self.replicas >= 0"), 3);
+    }
+
+    #[test]
+    fn estimate_unbounded_comprehension_exceeds_limit() {
+        assert!(
+            estimate("self.items.all(x, x.name.startsWith('a'))") > STATIC_ESTIMATED_COST_LIMIT
+        );
+    }
+
+    #[test]
+    fn estimate_string_op_on_unbounded_string() {
+        let cost = estimate("self.name.startsWith('a')");
+        assert!(cost > MAX_REQUEST_SIZE / 10, "cost={cost}");
+        assert!(cost < STATIC_ESTIMATED_COST_LIMIT, "cost={cost}");
+    }
+
+    fn estimate_with(expr: &str, schema: serde_json::Value) -> u64 {
+        estimate_cost_with_schema(Program::compile(expr).unwrap().expression(), &schema)
+    }
+
+    fn bounded_items_schema(max_items: Option<u64>, max_length: Option<u64>) -> serde_json::Value {
+        let mut item = json!({"type": "object", "properties": {"name": {"type": "string"}}});
+        if let Some(n) = max_length {
+            item["properties"]["name"]["maxLength"] = json!(n);
+        }
+        let mut items = json!({"type": "array", "items": item});
+        if let Some(n) = max_items {
+            items["maxItems"] = json!(n);
+        }
+        json!({"type": "object", "properties": {"items": items}})
+    }
+
+    #[test]
+    fn schema_bounds_lower_estimate() {
+        let rule = "self.items.all(x, x.name.startsWith('a'))";
+        let unbounded = estimate_with(rule, bounded_items_schema(None, None));
+        let bounded = estimate_with(rule, bounded_items_schema(Some(100), Some(63)));
+        assert!(unbounded > STATIC_ESTIMATED_COST_LIMIT);
+        assert!(bounded < 2_000, "bounded={bounded}");
+    }
+
+    #[test]
+    fn max_items_alone_bounds_iterations() {
+        let rule = "self.items.all(x, x.name.startsWith('a'))";
+        let cost = estimate_with(rule, bounded_items_schema(Some(10), None));
+        assert!(cost > MAX_REQUEST_SIZE, "cost={cost}");
+        assert!(cost < STATIC_ESTIMATED_COST_LIMIT, "cost={cost}");
+    }
+
+    #[test]
+    fn max_properties_bounds_map_iteration() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "labels": {
+                    "type": "object",
+                    "maxProperties": 20,
+                    "additionalProperties": {"type": "string", "maxLength": 63}
+                }
+            }
+        });
+        let cost = estimate_with("self.labels.all(k, self.labels[k].size() < 10)", schema);
+        assert!(cost < 1_000, "cost={cost}");
+    }
+
+    #[test]
+    fn scalar_schema_has_no_traversal_cost() {
+        let schema = json!({"type": "integer"});
+        assert_eq!(estimate_with("self == oldSelf", schema), 3);
+    }
+
+    #[test]
+    fn escaped_field_names_resolve_in_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"my-name": {"type": "string", "maxLength": 10}}
+        });
+        assert_eq!(
+            estimate_with("self.my__dash__name.startsWith('a')", schema),
+            5
+        );
+    }
+
+    #[test]
+    fn missing_field_has_zero_size() {
+        let cost = cost_of("self.items.all(x, x >= 0)", json!({}));
+        assert!(cost < 10, "cost={cost}");
+    }
+}
@@ -0,0 +1,494 @@
+//! Validation against whole `CustomResourceDefinition` manifests.
+//!
+//! [`Validator::for_crd`](crate::validation::Validator::for_crd) reads the
+//! schema of every version of a CRD, so objects can be validated without
+//! digging `openAPIV3Schema` out by hand. The version is picked from each
+//! object's `apiVersion`:
+//!
+//! ```
+//! use kube_cel::validation::Validator;
+//! use serde_json::json;
+//!
+//! let crd = json!({
+//!     "apiVersion": "apiextensions.k8s.io/v1",
+//!     "kind": "CustomResourceDefinition",
+//!     "spec": {
+//!         "group": "example.com",
+//!         "names": {"kind": "Widget"},
+//!         "versions": [{
+//!             "name": "v1",
+//!             "served": true,
+//!             "schema": {"openAPIV3Schema": {
+//!                 "type": "object",
+//!                 "x-kubernetes-validations": [{"rule": "self.spec.replicas >= 0"}],
+//!                 "properties": {"spec": {"type": "object", "properties": {"replicas": {"type": "integer"}}}}
+//!             }}
+//!         }]
+//!     }
+//! });
+//! let validator = Validator::new().for_crd(&crd).unwrap();
+//! let object = json!({"apiVersion": "example.com/v1", "kind": "Widget", "spec": {"replicas": -1}});
+//! assert_eq!(validator.validate(&object, None).unwrap().len(), 1);
+//! ```
+//!
+//! Admission webhooks serving several versions can compile every served
+//! version once into a [`CompiledCrd`].
+
+use crate::compilation::{CompiledSchema, compile_schema};
+use crate::validation::{ValidationError, Validator};
+
+/// Errors reading a CRD or matching an object to one of its versions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrdError {
+    /// The document is not a `CustomResourceDefinition`, or lacks `spec.group`.
+    NotACrd,
+    /// A version has no `schema.openAPIV3Schema` (and the CRD has no
+    /// top-level `spec.validation.openAPIV3Schema`).
+    MissingSchema {
+        /// The version name, e.g. `v1`.
+        version: String,
+    },
+    /// The object's `apiVersion` is missing or not `<group>/<version>`.
+    InvalidApiVersion {
+        /// The `apiVersion` found, if any.
+        api_version: Option<String>,
+    },
+    /// The object belongs to another API group than the CRD.
+    GroupMismatch {
+        /// The CRD's `spec.group`.
+        expected: String,
+        /// The object's group.
+        found: String,
+    },
+    /// The CRD has no version with this name.
+    UnknownVersion {
+        /// The object's version.
+        version: String,
+    },
+}
+
+impl std::fmt::Display for CrdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrdError::NotACrd => write!(f, "document is not a CustomResourceDefinition"),
+            CrdError::MissingSchema { version } => {
+                write!(f, "version \"{version}\" has no openAPIV3Schema")
+            }
+            CrdError::InvalidApiVersion {
+                api_version: Some(api_version),
+            } => write!(f, "apiVersion \"{api_version}\" is not <group>/<version>"),
+            CrdError::InvalidApiVersion { api_version: None } => {
+                write!(f, "object has no apiVersion")
+            }
+            CrdError::GroupMismatch { expected, found } => {
+                write!(
+                    f,
+                    "object group \"{found}\" does not match CRD group \"{expected}\""
+                )
+            }
+            CrdError::UnknownVersion { version } => {
+                write!(f, "CRD has no version \"{version}\"")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CrdError {}
+
+/// The group, version names, and per-version schemas of a CRD.
+#[derive(Clone, Debug)]
+pub(crate) struct CrdSchemas {
+    /// `spec.group`.
+    pub(crate) group: String,
+    /// `spec.names.kind`, empty if missing.
+    pub(crate) kind: String,
+    /// `spec.names.plural`, empty if missing.
+    pub(crate) plural: String,
+    /// The versions, in declaration order.
+    pub(crate) versions: Vec<CrdVersion>,
+}
+
+/// One version of a CRD.
+#[derive(Clone, Debug)]
+pub(crate) struct CrdVersion {
+    /// The version name, e.g. `v1`.
+    pub(crate) name: String,
+    /// Whether the version is served (`served` defaults to `true`).
+    pub(crate) served: bool,
+    /// The version's `openAPIV3Schema`.
+    pub(crate) schema: serde_json::Value,
+}
+
+impl CrdSchemas {
+    /// Read the schemas of every version of `crd`.
+    pub(crate) fn from_crd(crd: &serde_json::Value) -> Result<Self, CrdError> {
+        if crd.get("kind").and_then(|k| k.as_str()) != Some("CustomResourceDefinition") {
+            return Err(CrdError::NotACrd);
+        }
+        let spec = crd.get("spec").ok_or(CrdError::NotACrd)?;
+        let group = spec
+            .get("group")
+            .and_then(|g| g.as_str())
+            .ok_or(CrdError::NotACrd)?
+            .to_string();
+        let name = |field: &str| {
+            spec.pointer(&format!("/names/{field}"))
+                .and_then(|n| n.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let (kind, plural) = (name("kind"), name("plural"));
+        // apiextensions.k8s.io/v1beta1 allowed one schema shared by all versions.
+        let shared = spec.pointer("/validation/openAPIV3Schema");
+        let versions = spec
+            .get("versions")
+            .and_then(|v| v.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|version| {
+                let name = version.get("name")?.as_str()?.to_string();
+                Some((name, version))
+            })
+            .map(
+                |(name, version)| match version.pointer("/schema/openAPIV3Schema").or(shared) {
+                    Some(schema) => Ok(CrdVersion {
+                        served: version.get("served").and_then(|s| s.as_bool()) != Some(false),
+                        schema: schema.clone(),
+                        name,
+                    }),
+                    None => Err(CrdError::MissingSchema { version: name }),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            group,
+            kind,
+            plural,
+            versions,
+        })
+    }
+
+    /// The schema of `version`.
+    pub(crate) fn schema(&self, version: &str) -> Result<&serde_json::Value, CrdError> {
+        self.versions
+            .iter()
+            .find(|v| v.name == version)
+            .map(|v| &v.schema)
+            .ok_or_else(|| CrdError::UnknownVersion {
+                version: version.to_string(),
+            })
+    }
+}
+
+/// The compiled schemas of every served version of a CRD.
+///
+/// Compile once with [`CompiledCrd::compile`] and validate objects of any
+/// served version with
+/// [`Validator::validate_compiled_crd`](crate::validation::Validator::validate_compiled_crd).
+/// Like [`CompiledSchema`], it is not `Clone`; wrap it in an
+/// [`Arc`](std::sync::Arc) to share it.
+#[derive(Debug)]
+pub struct CompiledCrd {
+    group: String,
+    versions: Vec<(String, CompiledSchema)>,
+}
+
+impl CompiledCrd {
+    /// Compile the schema of every served version of `crd`.
+    pub fn compile(crd: &serde_json::Value) -> Result<Self, CrdError> {
+        let schemas = CrdSchemas::from_crd(crd)?;
+        Ok(Self {
+            group: schemas.group,
+            versions: schemas
+                .versions
+                .into_iter()
+                .filter(|v| v.served)
+                .map(|v| {
+                    let compiled = compile_schema(&v.schema);
+                    (v.name, compiled)
+                })
+                .collect(),
+        })
+    }
+
+    /// The CRD's API group.
+    #[must_use]
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// The served version names, in declaration order.
+    pub fn versions(&self) -> impl Iterator<Item = &str> {
+        self.versions.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The compiled schema of `version`, if it is served.
+    #[must_use]
+    pub fn get(&self, version: &str) -> Option<&CompiledSchema> {
+        self.versions
+            .iter()
+            .find(|(name, _)| name == version)
+            .map(|(_, compiled)| compiled)
+    }
+
+    /// The compiled schema for `object`, picked by its `apiVersion`.
+    pub fn for_object(&self, object: &serde_json::Value) -> Result<&CompiledSchema, CrdError> {
+        let version = object_version(object, &self.group)?;
+        self.get(version).ok_or_else(|| CrdError::UnknownVersion {
+            version: version.to_string(),
+        })
+    }
+}
+
+/// The version name of `object`, checked against the CRD's `group`.
+fn object_version<'o>(object: &'o serde_json::Value, group: &str) -> Result<&'o str, CrdError> {
+    let api_version = object.get("apiVersion").and_then(|v| v.as_str());
+    let (found, version) =
+        api_version
+            .and_then(|v| v.split_once('/'))
+            .ok_or_else(|| CrdError::InvalidApiVersion {
+                api_version: api_version.map(str::to_string),
+            })?;
+    if found != group {
+        return Err(CrdError::GroupMismatch {
+            expected: group.to_string(),
+            found: found.to_string(),
+        });
+    }
+    Ok(version)
+}
+
+/// A [`Validator`] bound to a CRD, created by
+/// [`Validator::for_crd`](crate::validation::Validator::for_crd).
+#[derive(Clone, Debug)]
+pub struct CrdValidator {
+    validator: Validator,
+    schemas: CrdSchemas,
+}
+
+impl CrdValidator {
+    pub(crate) fn new(validator: Validator, crd: &serde_json::Value) -> Result<Self, CrdError> {
+        Ok(Self {
+            validator,
+            schemas: CrdSchemas::from_crd(crd)?,
+        })
+    }
+
+    /// The CRD's API group.
+    #[must_use]
+    pub fn group(&self) -> &str {
+        &self.schemas.group
+    }
+
+    /// The kind of the CRD's objects (`spec.names.kind`), e.g. `Widget`.
+    #[must_use]
+    pub fn kind(&self) -> &str {
+        &self.schemas.kind
+    }
+
+    /// The CRD's plural resource name (`spec.names.plural`), e.g. `widgets`.
+    #[must_use]
+    pub fn plural(&self) -> &str {
+        &self.schemas.plural
+    }
+
+    /// The CRD's version names, in declaration order.
+    pub fn versions(&self) -> impl Iterator<Item = &str> {
+        self.schemas.versions.iter().map(|v| v.name.as_str())
+    }
+
+    /// The `openAPIV3Schema` of `version`, if the CRD has it.
+    #[must_use]
+    pub fn schema(&self, version: &str) -> Option<&serde_json::Value> {
+        self.schemas.schema(version).ok()
+    }
+
+    /// Validate `object` against the schema of the version named in its
+    /// `apiVersion`.
+    ///
+    /// Returns an error, without validating, if the object's group is not
+    /// the CRD's or the CRD has no such version.
+    pub fn validate(
+        &self,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> Result<Vec<ValidationError>, CrdError> {
+        let schema = self
+            .schemas
+            .schema(object_version(object, &self.schemas.group)?)?;
+        Ok(self.validator.validate(schema, object, old_object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::ErrorKind;
+    use serde_json::json;
+
+    fn crd() -> serde_json::Value {
+        let schema = |rule: &str| {
+            json!({"openAPIV3Schema": {
+                "type": "object",
+                "x-kubernetes-validations": [{"rule": rule}],
+                "properties": {"spec": {"type": "object", "properties": {"replicas": {"type": "integer"}}}}
+            }})
+        };
+        json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": {"name": "widgets.example.com"},
+            "spec": {
+                "group": "example.com",
+                "names": {"kind": "Widget", "plural": "widgets"},
+                "scope": "Namespaced",
+                "versions": [
+                    {"name": "v1alpha1", "served": true, "storage": false, "schema": schema("self.spec.replicas >= 0")},
+                    {"name": "v1", "served": true, "storage": true, "schema": schema("self.spec.replicas >= 1")}
+                ]
+            }
+        })
+    }
+
+    fn widget(version: &str, replicas: i64) -> serde_json::Value {
+        json!({"apiVersion": format!("example.com/{version}"), "kind": "Widget", "spec": {"replicas": replicas}})
+    }
+
+    #[test]
+    fn selects_version_by_api_version() {
+        let v = Validator::new().for_crd(&crd()).unwrap();
+        assert_eq!(v.group(), "example.com");
+        assert_eq!(v.versions().collect::<Vec<_>>(), ["v1alpha1", "v1"]);
+        assert!(v.validate(&widget("v1alpha1", 0), None).unwrap().is_empty());
+        assert_eq!(v.validate(&widget("v1", 0), None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn metadata_is_restricted_by_default() {
+        let crd = json!({
+            "kind": "CustomResourceDefinition",
+            "spec": {
+                "group": "example.com",
+                "versions": [{"name": "v1", "schema": {"openAPIV3Schema": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "!('app' in self.metadata.labels)"}],
+                    "properties": {"metadata": {"type": "object"}}
+                }}}]
+            }
+        });
+        let object = json!({
+            "apiVersion": "example.com/v1",
+            "metadata": {"name": "w", "labels": {"app": "web"}}
+        });
+        let errors = Validator::new()
+            .for_crd(&crd)
+            .unwrap()
+            .validate(&object, None)
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::EvaluationError);
+
+        let compiled = CompiledCrd::compile(&crd).unwrap();
+        let errors = Validator::new()
+            .validate_compiled_crd(&compiled, &object, None)
+            .unwrap();
+        assert_eq!(errors[0].kind, ErrorKind::EvaluationError);
+
+        let unrestricted = Validator::builder().with_restricted_metadata(false).build();
+        let errors = unrestricted
+            .for_crd(&crd)
+            .unwrap()
+            .validate(&object, None)
+            .unwrap();
+        assert_eq!(errors[0].kind, ErrorKind::ValidationFailure);
+    }
+
+    #[test]
+    fn rejects_mismatched_objects() {
+        let v = Validator::new().for_crd(&crd()).unwrap();
+        assert_eq!(
+            v.validate(&widget("v2", 1), None),
+            Err(CrdError::UnknownVersion {
+                version: "v2".into()
+            })
+        );
+        let other = json!({"apiVersion": "other.io/v1", "spec": {}});
+        assert!(matches!(
+            v.validate(&other, None),
+            Err(CrdError::GroupMismatch { .. })
+        ));
+        assert_eq!(
+            v.validate(&json!({"apiVersion": "v1"}), None)
+                .unwrap_err()
+                .to_string(),
+            "apiVersion \"v1\" is not <group>/<version>"
+        );
+    }
+
+    #[test]
+    fn reads_v1beta1_shared_schema() {
+        let crd = json!({
+            "kind": "CustomResourceDefinition",
+            "spec": {
+                "group": "example.com",
+                "versions": [{"name": "v1beta1"}],
+                "validation": {"openAPIV3Schema": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "false"}]
+                }}
+            }
+        });
+        let v = Validator::new().for_crd(&crd).unwrap();
+        assert_eq!(v.validate(&widget("v1beta1", 1), None).unwrap().len(), 1);
+
+        assert_eq!(
+            Validator::new()
+                .for_crd(&json!({"kind": "Deployment"}))
+                .unwrap_err(),
+            CrdError::NotACrd
+        );
+        let missing = json!({
+            "kind": "CustomResourceDefinition",
+            "spec": {"group": "example.com", "versions": [{"name": "v1"}]}
+        });
+        assert_eq!(
+            Validator::new().for_crd(&missing).unwrap_err(),
+            CrdError::MissingSchema {
+                version: "v1".into()
+            }
+        );
+    }
+
+    #[test]
+    fn compiled_crd_serves_each_version() {
+        let mut crd = crd();
+        crd["spec"]["versions"]
+            .as_array_mut()
+            .unwrap()
+            .push(json!({"name": "v0", "served": false, "schema": {"openAPIV3Schema": {"type": "object"}}}));
+        let compiled = CompiledCrd::compile(&crd).unwrap();
+        assert_eq!(compiled.group(), "example.com");
+        assert_eq!(compiled.versions().collect::<Vec<_>>(), ["v1alpha1", "v1"]);
+        assert!(compiled.get("v0").is_none());
+
+        let v = Validator::new();
+        assert!(
+            v.validate_compiled_crd(&compiled, &widget("v1alpha1", 0), None)
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            v.validate_compiled_crd(&compiled, &widget("v1", 0), None)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            v.validate_compiled_crd(&compiled, &widget("v0", 0), None),
+            Err(CrdError::UnknownVersion {
+                version: "v0".into()
+            })
+        );
+    }
+}
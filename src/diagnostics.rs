@@ -0,0 +1,372 @@
+//! Structured findings about a schema's validation rules, for editors and CI.
+//!
+//! [`diagnostics`] gathers in one list what
+//! [`compile_schema`](crate::compilation::compile_schema) and
+//! [`lint_rules`] report: compilation errors, high estimated costs, and the
+//! rule definition checks kube-apiserver applies. Each [`Diagnostic`] has a
+//! stable [`code`](Diagnostic::code) and, where the problem is in part of a
+//! rule, the byte range of that part, ready to map onto an LSP diagnostic or
+//! a CI annotation.
+//!
+//! ```
+//! use kube_cel::compilation::Severity;
+//! use kube_cel::diagnostics::diagnostics;
+//! use serde_json::json;
+//!
+//! let schema = json!({
+//!     "type": "object",
+//!     "x-kubernetes-validations": [{"rule": "self.name.matches('a(')"}],
+//!     "properties": {"name": {"type": "string"}}
+//! });
+//! let found = diagnostics(&schema);
+//! assert_eq!(found[0].severity, Severity::Error);
+//! assert_eq!(found[0].code, "invalid-regex");
+//! assert_eq!(found[0].schema_path, "x-kubernetes-validations[0]");
+//! assert_eq!(found[0].range, Some(19..21));
+//! ```
+
+use std::ops::Range;
+
+use crate::compilation::{
+    CompilationError, CompilationWarning, CompileOptions, CompiledSchema, Severity,
+    compile_schema_with_options, join_location,
+};
+use crate::lint::{LintIssue, lint_rules};
+
+/// One finding about a rule or schema node.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostic {
+    /// [`Severity::Error`] if kube-apiserver would reject the CRD,
+    /// [`Severity::Warning`] otherwise.
+    pub severity: Severity,
+    /// What kind of problem this is:
+    ///
+    /// - compilation errors: `parse`, `invalid-rule`, `cost-limit`,
+    ///   `invalid-definition`, `undeclared-function`, `invalid-regex`,
+    ///   `type-check`, `uncorrelatable-transition-rule`, `depth-limit`, and
+    ///   `invalid-ref`;
+    /// - the `high-cost` warning;
+    /// - lint findings: `invalid-definition` for the rule itself, and
+    ///   `invalid-message`, `invalid-message-expression`, `invalid-reason`,
+    ///   `invalid-field-path`, and `invalid-optional-old-self`.
+    pub code: &'static str,
+    /// Location of the rule, or of the node for node problems, e.g.
+    /// `properties[spec].x-kubernetes-validations[0]`.
+    pub schema_path: String,
+    /// The rule's CEL expression (empty for node problems, or if the rule
+    /// could not be read).
+    pub rule: String,
+    /// The byte range in [`rule`](Self::rule) the problem is at, or `None`
+    /// if it concerns the whole rule or node.
+    pub range: Option<Range<usize>>,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.schema_path.is_empty() {
+            write!(f, "{}: ", self.schema_path)?;
+        }
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{severity}[{}]: {}", self.code, self.message)
+    }
+}
+
+/// The diagnostics of every rule in `schema`: those of compiling it with
+/// [`CompileOptions::check_functions`], then those of [`lint_rules`] that
+/// compilation does not already report.
+#[must_use]
+pub fn diagnostics(schema: &serde_json::Value) -> Vec<Diagnostic> {
+    let options = CompileOptions {
+        check_functions: true,
+        ..CompileOptions::default()
+    };
+    diagnostics_with_options(schema, &options)
+}
+
+/// Like [`diagnostics`], compiling `schema` with `options`.
+#[must_use]
+pub fn diagnostics_with_options(
+    schema: &serde_json::Value,
+    options: &CompileOptions,
+) -> Vec<Diagnostic> {
+    let compiled = compile_schema_with_options(schema, options);
+    let mut diagnostics = compiled_diagnostics(&compiled);
+    let lint = lint_rules(schema)
+        .into_iter()
+        .filter(|issue| !reported(&diagnostics, issue))
+        .map(lint_diagnostic)
+        .collect::<Vec<_>>();
+    diagnostics.extend(lint);
+    diagnostics
+}
+
+/// The errors and `high-cost` warnings of a compiled schema, in schema
+/// order.
+#[must_use]
+pub fn compiled_diagnostics(compiled: &CompiledSchema) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    compiled.for_each_node("", &mut |location, node| {
+        for (i, result) in node.validations.iter().enumerate() {
+            match result {
+                Ok(compiled) => {
+                    for warning in &compiled.warnings {
+                        if let CompilationWarning::HighEstimatedCost { .. } = warning {
+                            diagnostics.push(Diagnostic {
+                                severity: Severity::Warning,
+                                code: "high-cost",
+                                schema_path: join_location(
+                                    location,
+                                    &format!("x-kubernetes-validations[{i}]"),
+                                ),
+                                rule: compiled.rule.rule.clone(),
+                                range: None,
+                                message: warning.to_string(),
+                            });
+                        }
+                    }
+                }
+                Err(error) => error_diagnostics(error, &mut diagnostics),
+            }
+        }
+    });
+    diagnostics
+}
+
+/// Append the diagnostics of a compilation error: one per parse error,
+/// one otherwise.
+fn error_diagnostics(error: &CompilationError, diagnostics: &mut Vec<Diagnostic>) {
+    let schema_path = error.schema_path().to_string();
+    let rule = match error {
+        CompilationError::Parse { rule, .. }
+        | CompilationError::CostLimitExceeded { rule, .. }
+        | CompilationError::InvalidDefinition { rule, .. }
+        | CompilationError::UndeclaredFunction { rule, .. }
+        | CompilationError::InvalidRegex { rule, .. }
+        | CompilationError::TypeCheck { rule, .. }
+        | CompilationError::UncorrelatableTransitionRule { rule, .. } => rule.clone(),
+        CompilationError::InvalidRule { .. }
+        | CompilationError::DepthLimitExceeded { .. }
+        | CompilationError::InvalidRef { .. } => String::new(),
+    };
+    let (code, range) = match error {
+        CompilationError::Parse { .. } => {
+            diagnostics.extend(
+                error
+                    .parse_diagnostics()
+                    .into_iter()
+                    .map(|parse| Diagnostic {
+                        severity: Severity::Error,
+                        code: "parse",
+                        schema_path: schema_path.clone(),
+                        range: parse.span.map(|span| {
+                            let len = rule[span.offset..].chars().next().map_or(0, char::len_utf8);
+                            span.offset..span.offset + len
+                        }),
+                        rule: rule.clone(),
+                        message: parse.message,
+                    }),
+            );
+            return;
+        }
+        CompilationError::InvalidRule { .. } => ("invalid-rule", None),
+        CompilationError::CostLimitExceeded { .. } => ("cost-limit", None),
+        CompilationError::InvalidDefinition { .. } => ("invalid-definition", None),
+        CompilationError::UndeclaredFunction { function, .. } => (
+            "undeclared-function",
+            find(&rule, &format!("{function}(")).map(|range| range.start..range.end - 1),
+        ),
+        CompilationError::InvalidRegex { pattern, .. } => ("invalid-regex", find(&rule, pattern)),
+        CompilationError::TypeCheck { .. } => ("type-check", None),
+        CompilationError::UncorrelatableTransitionRule { .. } => {
+            ("uncorrelatable-transition-rule", None)
+        }
+        CompilationError::DepthLimitExceeded { .. } => ("depth-limit", None),
+        CompilationError::InvalidRef { .. } => ("invalid-ref", None),
+    };
+    let message = error.to_string();
+    let message = match schema_path.as_str() {
+        "" => message,
+        location => message
+            .strip_prefix(&format!("{location}: "))
+            .map_or(message.clone(), str::to_string),
+    };
+    diagnostics.push(Diagnostic {
+        severity: Severity::Error,
+        code,
+        schema_path,
+        rule,
+        range,
+        message,
+    });
+}
+
+/// The byte range of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &str, needle: &str) -> Option<Range<usize>> {
+    haystack
+        .find(needle)
+        .map(|start| start..start + needle.len())
+}
+
+/// Whether a compilation diagnostic already reports a lint issue: the
+/// issues lint raises about the expression of a rule that failed to
+/// compile restate the compilation error, as do those about unresolvable
+/// `$ref`s.
+fn reported(diagnostics: &[Diagnostic], issue: &LintIssue) -> bool {
+    match issue.property {
+        "$ref" => true,
+        "rule" => diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error && d.schema_path == issue.location),
+        _ => diagnostics
+            .iter()
+            .any(|d| d.schema_path == issue.location && d.message.ends_with(&issue.message)),
+    }
+}
+
+/// The diagnostic of a lint issue. Lint issues are all reasons for
+/// kube-apiserver to reject the CRD.
+fn lint_diagnostic(issue: LintIssue) -> Diagnostic {
+    let code = match issue.property {
+        "message" => "invalid-message",
+        "messageExpression" => "invalid-message-expression",
+        "reason" => "invalid-reason",
+        "fieldPath" => "invalid-field-path",
+        "optionalOldSelf" => "invalid-optional-old-self",
+        _ if issue.message.starts_with("oldSelf cannot be used") => {
+            "uncorrelatable-transition-rule"
+        }
+        _ if issue.message.starts_with("estimated rule cost") => "cost-limit",
+        _ => "invalid-definition",
+    };
+    Diagnostic {
+        severity: Severity::Error,
+        code,
+        schema_path: issue.location,
+        rule: issue.rule,
+        range: None,
+        message: issue.message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn codes(schema: &serde_json::Value) -> Vec<(String, &'static str, Option<Range<usize>>)> {
+        diagnostics(schema)
+            .into_iter()
+            .map(|d| (d.schema_path, d.code, d.range))
+            .collect()
+    }
+
+    #[test]
+    fn reports_compilation_errors_with_ranges() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"spec": {
+                "type": "object",
+                "x-kubernetes-validations": [
+                    {"rule": "self.name >"},
+                    {"rule": "self.name.trimm() != ''"},
+                    {"rule": "math.ceill(1.5) == 2.0"},
+                    {"rule": "self.name.matches('[')"}
+                ],
+                "properties": {"name": {"type": "string", "maxLength": 10}}
+            }}
+        });
+        let spec = |i: usize| format!("properties[spec].x-kubernetes-validations[{i}]");
+        assert_eq!(
+            codes(&schema),
+            [
+                (spec(0), "parse", Some(11..11)),
+                (spec(1), "undeclared-function", Some(10..15)),
+                (spec(2), "undeclared-function", Some(0..10)),
+                (spec(3), "invalid-regex", Some(19..20)),
+            ]
+        );
+        let found = diagnostics(&schema);
+        assert_eq!(found[1].rule, "self.name.trimm() != ''");
+        assert_eq!(
+            found[1].to_string(),
+            format!(
+                "{}: error[undeclared-function]: rule \"self.name.trimm() != ''\" calls \
+                 undeclared function 'trimm'; did you mean 'trim'?",
+                spec(1)
+            )
+        );
+    }
+
+    #[test]
+    fn includes_lint_findings_once() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.x >", "reason": "Custom"},
+                {"rule": "self.x > 0", "message": " ", "fieldPath": ".missing"}
+            ],
+            "properties": {"x": {"type": "integer"}}
+        });
+        let found = diagnostics(&schema);
+        let codes: Vec<_> = found
+            .iter()
+            .map(|d| (d.schema_path.as_str(), d.code))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("x-kubernetes-validations[0]", "parse"),
+                ("x-kubernetes-validations[0]", "invalid-reason"),
+                ("x-kubernetes-validations[1]", "invalid-message"),
+                ("x-kubernetes-validations[1]", "invalid-field-path"),
+            ]
+        );
+        assert!(found.iter().all(|d| d.severity == Severity::Error));
+    }
+
+    #[test]
+    fn reports_high_costs_as_warnings() {
+        let schema = json!({
+            "type": "array",
+            "maxItems": 10,
+            "items": {"type": "string", "maxLength": 10},
+            "x-kubernetes-validations": [{"rule": "self.all(x, x != '')"}]
+        });
+        let options = CompileOptions {
+            cost_warning_threshold: 0,
+            ..CompileOptions::default()
+        };
+        let found = diagnostics_with_options(&schema, &options);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].severity, Severity::Warning);
+        assert_eq!(found[0].code, "high-cost");
+        assert_eq!(found[0].schema_path, "x-kubernetes-validations[0]");
+        assert!(
+            found[0]
+                .to_string()
+                .starts_with("x-kubernetes-validations[0]: warning[high-cost]: ")
+        );
+    }
+
+    #[test]
+    fn reports_node_errors() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"spec": {"$ref": "#/definitions/Missing"}}
+        });
+        let found = diagnostics(&schema);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].code, "invalid-ref");
+        assert_eq!(found[0].schema_path, "properties[spec]");
+        assert_eq!(
+            found[0].message,
+            "invalid $ref \"#/definitions/Missing\": not found in the schema document"
+        );
+    }
+}
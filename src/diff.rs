@@ -0,0 +1,288 @@
+//! Differences between the validation rules of two schemas.
+//!
+//! Useful in CI when reviewing a CRD upgrade: [`diff_schemas`] lists the
+//! rules each schema node gained, lost, or changed, and
+//! [`RuleDiff::is_breaking`] flags those that may reject objects the old
+//! version accepted.
+//!
+//! Rules are matched by their CEL expression within a node, so reordering
+//! rules is not a change, and editing an expression is a removal plus an
+//! addition. Rules that fail to compile are not compared; see
+//! [`CompiledSchema::compilation_errors`].
+//!
+//! ```
+//! use kube_cel::diff::diff_schemas;
+//! use serde_json::json;
+//!
+//! let old = json!({"type": "object", "properties": {"replicas": {"type": "integer"}}});
+//! let new = json!({"type": "object", "properties": {"replicas": {
+//!     "type": "integer",
+//!     "x-kubernetes-validations": [{"rule": "self >= 0"}]
+//! }}});
+//! let diff = diff_schemas(&old, &new);
+//! assert_eq!(diff[0].to_string(), r#"properties[replicas]: added rule "self >= 0""#);
+//! assert!(diff[0].is_breaking());
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::compilation::{CompiledSchema, Rule, Severity, compile_schema};
+
+/// A rule added, removed, or changed at one schema node.
+#[derive(Clone, Debug)]
+pub enum RuleDiff {
+    /// The new schema has a rule the old one does not.
+    Added {
+        /// Location of the node, e.g. `properties[spec]`.
+        schema_path: String,
+        /// The new rule.
+        rule: Rule,
+    },
+    /// The old schema has a rule the new one does not.
+    Removed {
+        /// Location of the node.
+        schema_path: String,
+        /// The old rule.
+        rule: Rule,
+    },
+    /// Both schemas have the rule, with different settings.
+    Changed {
+        /// Location of the node.
+        schema_path: String,
+        /// The rule in the old schema.
+        old: Rule,
+        /// The rule in the new schema.
+        new: Rule,
+        /// The changed settings, by their CRD names (`message`,
+        /// `fieldPath`, ...).
+        fields: Vec<&'static str>,
+    },
+}
+
+impl RuleDiff {
+    /// Location of the node the rule is declared on.
+    #[must_use]
+    pub fn schema_path(&self) -> &str {
+        match self {
+            RuleDiff::Added { schema_path, .. }
+            | RuleDiff::Removed { schema_path, .. }
+            | RuleDiff::Changed { schema_path, .. } => schema_path,
+        }
+    }
+
+    /// Whether the change may reject objects the old schema accepted: an
+    /// added rule, a rule raised from a warning to an error, or a
+    /// transition rule that now also runs on create. Warning rules never
+    /// reject objects.
+    #[must_use]
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            RuleDiff::Added { rule, .. } => rule.severity == Severity::Error,
+            RuleDiff::Removed { .. } => false,
+            RuleDiff::Changed { old, new, .. } => {
+                new.severity == Severity::Error
+                    && (old.severity != Severity::Error
+                        || (new.optional_old_self.unwrap_or(false)
+                            && !old.optional_old_self.unwrap_or(false)))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RuleDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.schema_path().is_empty() {
+            write!(f, "{}: ", self.schema_path())?;
+        }
+        match self {
+            RuleDiff::Added { rule, .. } => write!(f, "added rule \"{}\"", rule.rule),
+            RuleDiff::Removed { rule, .. } => write!(f, "removed rule \"{}\"", rule.rule),
+            RuleDiff::Changed { new, fields, .. } => {
+                write!(f, "changed rule \"{}\": {}", new.rule, fields.join(", "))
+            }
+        }
+    }
+}
+
+/// Compile two schemas and compare their rules. See [`diff_compiled`].
+#[must_use]
+pub fn diff_schemas(old: &serde_json::Value, new: &serde_json::Value) -> Vec<RuleDiff> {
+    diff_compiled(&compile_schema(old), &compile_schema(new))
+}
+
+/// Compare the rules of two compiled schemas, node by node in order of
+/// location. Within a node, removed and changed rules come first, in the
+/// old order, then added rules in the new order.
+#[must_use]
+pub fn diff_compiled(old: &CompiledSchema, new: &CompiledSchema) -> Vec<RuleDiff> {
+    let mut nodes: BTreeMap<String, (Vec<&Rule>, Vec<&Rule>)> = BTreeMap::new();
+    old.for_each_node("", &mut |location, node| {
+        nodes.entry(location.to_string()).or_default().0 = rules(node);
+    });
+    new.for_each_node("", &mut |location, node| {
+        nodes.entry(location.to_string()).or_default().1 = rules(node);
+    });
+
+    let mut diffs = Vec::new();
+    for (schema_path, (old, mut new)) in nodes {
+        for old in old {
+            let Some(i) = new.iter().position(|new| new.rule == old.rule) else {
+                diffs.push(RuleDiff::Removed {
+                    schema_path: schema_path.clone(),
+                    rule: old.clone(),
+                });
+                continue;
+            };
+            let new = new.remove(i);
+            let fields = changed_fields(old, new);
+            if !fields.is_empty() {
+                diffs.push(RuleDiff::Changed {
+                    schema_path: schema_path.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                    fields,
+                });
+            }
+        }
+        diffs.extend(new.into_iter().map(|rule| RuleDiff::Added {
+            schema_path: schema_path.clone(),
+            rule: rule.clone(),
+        }));
+    }
+    diffs
+}
+
+/// The compiled rules of a node.
+fn rules(node: &CompiledSchema) -> Vec<&Rule> {
+    node.validations
+        .iter()
+        .filter_map(|result| result.as_ref().ok())
+        .map(|compiled| &compiled.rule)
+        .collect()
+}
+
+/// The settings that differ between two versions of a rule.
+fn changed_fields(old: &Rule, new: &Rule) -> Vec<&'static str> {
+    [
+        ("message", old.message != new.message),
+        (
+            "messageExpression",
+            old.message_expression != new.message_expression,
+        ),
+        ("reason", old.reason != new.reason),
+        ("fieldPath", old.field_path != new.field_path),
+        (
+            "optionalOldSelf",
+            old.optional_old_self.unwrap_or(false) != new.optional_old_self.unwrap_or(false),
+        ),
+        ("x-kube-cel-severity", old.severity != new.severity),
+    ]
+    .into_iter()
+    .filter_map(|(field, changed)| changed.then_some(field))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema(spec_rules: serde_json::Value) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "x-kubernetes-validations": spec_rules,
+                    "properties": {"replicas": {"type": "integer"}}
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn identical_and_reordered_rules_have_no_diff() {
+        let old = schema(json!([{"rule": "1 == 1"}, {"rule": "2 == 2"}]));
+        let new = schema(json!([{"rule": "2 == 2"}, {"rule": "1 == 1"}]));
+        assert!(diff_schemas(&old, &old).is_empty());
+        assert!(diff_schemas(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_rules() {
+        let old = schema(json!([
+            {"rule": "has(self.replicas)", "message": "replicas required"},
+            {"rule": "self.replicas >= 0"},
+            {"rule": "self.replicas < 100", "x-kube-cel-severity": "warning"}
+        ]));
+        let new = schema(json!([
+            {"rule": "has(self.replicas)", "message": "set replicas", "reason": "FieldValueRequired"},
+            {"rule": "self.replicas < 100"},
+            {"rule": "self.replicas < 10", "x-kube-cel-severity": "warning"}
+        ]));
+        let diff: Vec<_> = diff_schemas(&old, &new)
+            .into_iter()
+            .map(|d| (d.to_string(), d.is_breaking()))
+            .collect();
+        assert_eq!(
+            diff,
+            [
+                (
+                    r#"properties[spec]: changed rule "has(self.replicas)": message, reason"#
+                        .to_string(),
+                    false
+                ),
+                (
+                    r#"properties[spec]: removed rule "self.replicas >= 0""#.to_string(),
+                    false
+                ),
+                (
+                    r#"properties[spec]: changed rule "self.replicas < 100": x-kube-cel-severity"#
+                        .to_string(),
+                    true
+                ),
+                (
+                    r#"properties[spec]: added rule "self.replicas < 10""#.to_string(),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn compares_nodes_present_in_one_schema_only() {
+        let old = json!({"type": "object", "x-kubernetes-validations": [{"rule": "1 == 1"}]});
+        let new = schema(json!([{"rule": "1 == 1"}]));
+        let diff = diff_schemas(&old, &new);
+        assert_eq!(diff.len(), 2);
+        assert!(
+            matches!(&diff[0], RuleDiff::Removed { schema_path, .. } if schema_path.is_empty())
+        );
+        assert_eq!(diff[0].to_string(), r#"removed rule "1 == 1""#);
+        assert_eq!(diff[1].schema_path(), "properties[spec]");
+        assert!(diff[1].is_breaking());
+    }
+
+    #[test]
+    fn duplicate_rules_are_matched_once() {
+        let old = schema(json!([{"rule": "1 == 1"}]));
+        let new = schema(json!([{"rule": "1 == 1"}, {"rule": "1 == 1"}]));
+        let diff = diff_schemas(&old, &new);
+        assert_eq!(diff.len(), 1);
+        assert!(matches!(diff[0], RuleDiff::Added { .. }));
+        assert!(diff_schemas(&new, &old)[0].to_string().contains("removed"));
+    }
+
+    #[test]
+    fn optional_old_self_breaks_transition_rules() {
+        let old = schema(json!([{"rule": "self == oldSelf"}]));
+        let new = schema(json!([{"rule": "self == oldSelf", "optionalOldSelf": true}]));
+        let diff = diff_schemas(&old, &new);
+        assert_eq!(
+            diff[0].to_string(),
+            r#"properties[spec]: changed rule "self == oldSelf": optionalOldSelf"#
+        );
+        assert!(diff[0].is_breaking());
+        assert!(!diff_schemas(&new, &old)[0].is_breaking());
+    }
+}
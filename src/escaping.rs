@@ -68,6 +68,44 @@ pub fn escape_field_name(name: &str) -> String {
     name.to_string()
 }
 
+/// Reverse [`escape_field_name`], recovering the JSON field name.
+///
+/// `__keyword__` becomes `keyword` for reserved words; otherwise `__dot__`,
+/// `__dash__`, `__slash__` and `__` are substituted left to right. Names
+/// that were never escaped come back unchanged, unless they contain `__`.
+#[must_use]
+pub fn unescape_field_name(escaped: &str) -> String {
+    if let Some(word) = escaped
+        .strip_prefix("__")
+        .and_then(|rest| rest.strip_suffix("__"))
+        && CEL_RESERVED_WORDS.contains(&word)
+    {
+        return word.to_string();
+    }
+    if !escaped.contains("__") {
+        return escaped.to_string();
+    }
+    let mut name = String::with_capacity(escaped.len());
+    let mut rest = escaped;
+    while let Some(i) = rest.find("__") {
+        name.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let (ch, len) = if rest.starts_with("__dot__") {
+            ('.', 7)
+        } else if rest.starts_with("__dash__") {
+            ('-', 8)
+        } else if rest.starts_with("__slash__") {
+            ('/', 9)
+        } else {
+            ('_', 2)
+        };
+        name.push(ch);
+        rest = &rest[len..];
+    }
+    name.push_str(rest);
+    name
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -150,4 +188,20 @@ mod tests {
             "app__dot__kubernetes__dot__io__slash__name"
         );
     }
+
+    #[test]
+    fn unescape_round_trips() {
+        for name in [
+            "namespace",
+            "in",
+            "replicas",
+            "my_field",
+            "_private",
+            "a-b_c.d",
+            "app.kubernetes.io/name",
+            "",
+        ] {
+            assert_eq!(unescape_field_name(&escape_field_name(name)), name);
+        }
+    }
 }
@@ -0,0 +1,672 @@
+//! Explanations of why a rule passed or failed for a value.
+//!
+//! [`Validator::explain`] evaluates a compiled rule against the value of
+//! its node and records the value of each sub-expression, so the question
+//! "why did this rule fail for this object?" can be answered without
+//! editing the CRD:
+//!
+//! ```
+//! use kube_cel::compilation::compile_schema;
+//! use kube_cel::explain::explain;
+//! use serde_json::json;
+//!
+//! let schema = json!({
+//!     "type": "object",
+//!     "x-kubernetes-validations": [{"rule": "self.replicas <= self.maxReplicas * 2"}],
+//!     "properties": {
+//!         "replicas": {"type": "integer"},
+//!         "maxReplicas": {"type": "integer"}
+//!     }
+//! });
+//! let compiled = compile_schema(&schema);
+//! let rule = compiled.validations[0].as_ref().unwrap();
+//! let explanation = explain(rule, &schema, &json!({"replicas": 7, "maxReplicas": 3}), None);
+//! assert_eq!(
+//!     explanation.to_string(),
+//!     "self.replicas <= self.maxReplicas * 2 => false\n  \
+//!      self.replicas = 7\n  \
+//!      self.maxReplicas = 3\n  \
+//!      self.maxReplicas * 2 = 6"
+//! );
+//! ```
+//!
+//! Sub-expressions are evaluated on their own, so an operand the rule
+//! short-circuits past is still listed, possibly with an error (such as
+//! `self.x` behind `has(self.x) &&`). Sub-expressions that read a
+//! comprehension variable (`p` in `self.ports.all(p, p.port > 0)`) have no
+//! value of their own and are not listed; constant ones are left out too.
+
+use cel::common::ast::{ComprehensionExpr, EntryExpr, Expr, operators};
+use cel::common::value::CelVal;
+use cel::objects::Key;
+use cel::parser::Expression;
+use cel::{Context, Value};
+
+use crate::compilation::CompilationResult;
+use crate::validation::Validator;
+
+/// The result of a rule and of its sub-expressions.
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    /// The rule's CEL expression.
+    pub rule: String,
+    /// What the rule evaluated to, or the evaluation error.
+    pub result: Result<Value, String>,
+    /// The sub-expressions in evaluation order, each listed once.
+    pub steps: Vec<Step>,
+}
+
+/// A sub-expression of a rule and its value.
+#[derive(Clone, Debug)]
+pub struct Step {
+    /// The sub-expression, as CEL source. Macros are shown as written
+    /// (`self.ports.all(p, p.port > 0)`), other expressions in a
+    /// normalized form.
+    pub expression: String,
+    /// What the sub-expression evaluated to, or the evaluation error.
+    pub value: Result<Value, String>,
+}
+
+impl std::fmt::Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} => {}", self.rule, Outcome(&self.result))?;
+        for step in &self.steps {
+            write!(f, "\n  {} = {}", step.expression, Outcome(&step.value))?;
+        }
+        Ok(())
+    }
+}
+
+/// A value in CEL literal syntax, or an evaluation error.
+struct Outcome<'a>(&'a Result<Value, String>);
+
+impl std::fmt::Display for Outcome<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Ok(value) => f.write_str(&render_value(value)),
+            Err(e) => write!(f, "error: {e}"),
+        }
+    }
+}
+
+/// Explain `rule` for `value`, the value of the node the rule is declared
+/// on, with a default [`Validator`]. See [`Validator::explain`].
+#[must_use]
+pub fn explain(
+    rule: &CompilationResult,
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    old_value: Option<&serde_json::Value>,
+) -> Explanation {
+    Validator::new().explain(rule, schema, value, old_value)
+}
+
+/// Evaluate `rule` and its sub-expressions in `ctx`.
+pub(crate) fn explain_in(rule: &CompilationResult, ctx: &Context<'_>) -> Explanation {
+    let mut tracer = Tracer {
+        ctx,
+        bound: Vec::new(),
+        steps: Vec::new(),
+    };
+    tracer.children(rule.program.expression());
+    Explanation {
+        rule: rule.rule.rule.clone(),
+        result: rule.program.execute(ctx).map_err(|e| e.to_string()),
+        steps: tracer.steps,
+    }
+}
+
+/// State for explaining one rule.
+struct Tracer<'a, 'c> {
+    ctx: &'a Context<'c>,
+    /// Comprehension variables in scope.
+    bound: Vec<String>,
+    steps: Vec<Step>,
+}
+
+impl Tracer<'_, '_> {
+    /// Record `expr` after its sub-expressions.
+    fn visit(&mut self, expr: &Expression) {
+        self.children(expr);
+        match &expr.expr {
+            Expr::Literal(_) | Expr::Ident(_) | Expr::Unspecified => return,
+            Expr::Call(call) if call.func_name == operators::NOT_STRICTLY_FALSE => return,
+            _ => {}
+        }
+        let mut free = Vec::new();
+        free_variables(expr, &mut Vec::new(), &mut free);
+        if free.is_empty() || free.iter().any(|name| self.bound.contains(name)) {
+            return;
+        }
+        let expression = render(expr);
+        if self.steps.iter().any(|step| step.expression == expression) {
+            return;
+        }
+        self.steps.push(Step {
+            expression,
+            value: Value::resolve(expr, self.ctx).map_err(|e| e.to_string()),
+        });
+    }
+
+    fn children(&mut self, expr: &Expression) {
+        match &expr.expr {
+            Expr::Call(call) => {
+                if let Some(target) = &call.target {
+                    self.visit(target);
+                }
+                call.args.iter().for_each(|arg| self.visit(arg));
+            }
+            Expr::Select(select) => self.visit(&select.operand),
+            Expr::List(list) => list.elements.iter().for_each(|e| self.visit(e)),
+            Expr::Map(map) => map.entries.iter().for_each(|e| self.entry(&e.expr)),
+            Expr::Struct(st) => st.entries.iter().for_each(|e| self.entry(&e.expr)),
+            Expr::Comprehension(comp) => {
+                self.visit(&comp.iter_range);
+                let scope = self.bound.len();
+                self.bound.push(comp.iter_var.clone());
+                self.bound.extend(comp.iter_var2.clone());
+                self.bound.push(comp.accu_var.clone());
+                self.visit(&comp.loop_step);
+                self.bound.truncate(scope);
+            }
+            Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => {}
+        }
+    }
+
+    fn entry(&mut self, entry: &EntryExpr) {
+        match entry {
+            EntryExpr::MapEntry(e) => {
+                self.visit(&e.key);
+                self.visit(&e.value);
+            }
+            EntryExpr::StructField(field) => self.visit(&field.value),
+        }
+    }
+}
+
+/// Collect the variables `expr` reads that `bound` does not bind.
+fn free_variables(expr: &Expression, bound: &mut Vec<String>, free: &mut Vec<String>) {
+    match &expr.expr {
+        Expr::Ident(name) => {
+            if !bound.contains(name) && !free.contains(name) {
+                free.push(name.clone());
+            }
+        }
+        Expr::Call(call) => {
+            if let Some(target) = &call.target {
+                free_variables(target, bound, free);
+            }
+            for arg in &call.args {
+                free_variables(arg, bound, free);
+            }
+        }
+        Expr::Select(select) => free_variables(&select.operand, bound, free),
+        Expr::List(list) => {
+            for element in &list.elements {
+                free_variables(element, bound, free);
+            }
+        }
+        Expr::Map(map) => {
+            for entry in &map.entries {
+                free_entry_variables(&entry.expr, bound, free);
+            }
+        }
+        Expr::Struct(st) => {
+            for entry in &st.entries {
+                free_entry_variables(&entry.expr, bound, free);
+            }
+        }
+        Expr::Comprehension(comp) => {
+            free_variables(&comp.iter_range, bound, free);
+            free_variables(&comp.accu_init, bound, free);
+            let scope = bound.len();
+            bound.push(comp.iter_var.clone());
+            bound.extend(comp.iter_var2.clone());
+            bound.push(comp.accu_var.clone());
+            free_variables(&comp.loop_cond, bound, free);
+            free_variables(&comp.loop_step, bound, free);
+            free_variables(&comp.result, bound, free);
+            bound.truncate(scope);
+        }
+        Expr::Literal(_) | Expr::Unspecified => {}
+    }
+}
+
+fn free_entry_variables(entry: &EntryExpr, bound: &mut Vec<String>, free: &mut Vec<String>) {
+    match entry {
+        EntryExpr::MapEntry(e) => {
+            free_variables(&e.key, bound, free);
+            free_variables(&e.value, bound, free);
+        }
+        EntryExpr::StructField(field) => free_variables(&field.value, bound, free),
+    }
+}
+
+/// How tightly an expression binds, from the conditional (1) to atoms and
+/// member expressions (8).
+fn precedence(expr: &Expression) -> u8 {
+    let Expr::Call(call) = &expr.expr else {
+        return 8;
+    };
+    match call.func_name.as_str() {
+        operators::CONDITIONAL => 1,
+        operators::LOGICAL_OR => 2,
+        operators::LOGICAL_AND => 3,
+        operators::EQUALS
+        | operators::NOT_EQUALS
+        | operators::LESS
+        | operators::LESS_EQUALS
+        | operators::GREATER
+        | operators::GREATER_EQUALS
+        | operators::IN => 4,
+        operators::ADD | operators::SUBSTRACT => 5,
+        operators::MULTIPLY | operators::DIVIDE | operators::MODULO => 6,
+        operators::LOGICAL_NOT | operators::NEGATE => 7,
+        operators::NOT_STRICTLY_FALSE => call.args.first().map_or(8, precedence),
+        _ => 8,
+    }
+}
+
+/// `expr` as CEL source, parenthesized if it binds looser than `min`.
+fn render_operand(expr: &Expression, min: u8) -> String {
+    if precedence(expr) < min {
+        format!("({})", render(expr))
+    } else {
+        render(expr)
+    }
+}
+
+/// `expr` as CEL source.
+fn render(expr: &Expression) -> String {
+    match &expr.expr {
+        Expr::Literal(literal) => render_literal(literal),
+        Expr::Ident(name) => name.clone(),
+        Expr::Select(select) if select.test => {
+            format!(
+                "has({}.{})",
+                render_operand(&select.operand, 8),
+                select.field
+            )
+        }
+        Expr::Select(select) => format!("{}.{}", render_operand(&select.operand, 8), select.field),
+        Expr::Call(call) => {
+            let binary = |op: &str| {
+                let p = precedence(expr);
+                format!(
+                    "{} {op} {}",
+                    render_operand(&call.args[0], p),
+                    render_operand(&call.args[1], p + 1)
+                )
+            };
+            match (call.func_name.as_str(), call.args.as_slice()) {
+                (operators::CONDITIONAL, [cond, then, otherwise]) => format!(
+                    "{} ? {} : {}",
+                    render_operand(cond, 2),
+                    render_operand(then, 2),
+                    render_operand(otherwise, 1)
+                ),
+                (operators::LOGICAL_OR, [_, _]) => binary("||"),
+                (operators::LOGICAL_AND, [_, _]) => binary("&&"),
+                (operators::EQUALS, [_, _]) => binary("=="),
+                (operators::NOT_EQUALS, [_, _]) => binary("!="),
+                (operators::LESS, [_, _]) => binary("<"),
+                (operators::LESS_EQUALS, [_, _]) => binary("<="),
+                (operators::GREATER, [_, _]) => binary(">"),
+                (operators::GREATER_EQUALS, [_, _]) => binary(">="),
+                (operators::IN, [_, _]) => binary("in"),
+                (operators::ADD, [_, _]) => binary("+"),
+                (operators::SUBSTRACT, [_, _]) => binary("-"),
+                (operators::MULTIPLY, [_, _]) => binary("*"),
+                (operators::DIVIDE, [_, _]) => binary("/"),
+                (operators::MODULO, [_, _]) => binary("%"),
+                (operators::LOGICAL_NOT, [operand]) => format!("!{}", render_operand(operand, 7)),
+                (operators::NEGATE, [operand]) => format!("-{}", render_operand(operand, 7)),
+                (operators::NOT_STRICTLY_FALSE, [operand]) => render(operand),
+                (operators::INDEX, [container, index]) => {
+                    format!("{}[{}]", render_operand(container, 8), render(index))
+                }
+                (operators::OPT_INDEX, [container, index]) => {
+                    format!("{}[?{}]", render_operand(container, 8), render(index))
+                }
+                (operators::OPT_SELECT, [operand, field]) => match &field.expr {
+                    Expr::Literal(CelVal::String(field)) => {
+                        format!("{}.?{field}", render_operand(operand, 8))
+                    }
+                    _ => format!("{}.?{}", render_operand(operand, 8), render(field)),
+                },
+                (name, args) => {
+                    let args: Vec<_> = args.iter().map(render).collect();
+                    match &call.target {
+                        Some(target) => {
+                            format!("{}.{name}({})", render_operand(target, 8), args.join(", "))
+                        }
+                        None => format!("{name}({})", args.join(", ")),
+                    }
+                }
+            }
+        }
+        Expr::List(list) => {
+            let elements: Vec<_> = list
+                .elements
+                .iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    let optional = if list.optional_indices.contains(&i) {
+                        "?"
+                    } else {
+                        ""
+                    };
+                    format!("{optional}{}", render(e))
+                })
+                .collect();
+            format!("[{}]", elements.join(", "))
+        }
+        Expr::Map(map) => {
+            let entries: Vec<_> = map.entries.iter().map(|e| render_entry(&e.expr)).collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Expr::Struct(st) => {
+            let entries: Vec<_> = st.entries.iter().map(|e| render_entry(&e.expr)).collect();
+            format!("{}{{{}}}", st.type_name, entries.join(", "))
+        }
+        Expr::Comprehension(comp) => render_comprehension(comp),
+        Expr::Unspecified => String::new(),
+    }
+}
+
+fn render_entry(entry: &EntryExpr) -> String {
+    match entry {
+        EntryExpr::MapEntry(e) => {
+            let optional = if e.optional { "?" } else { "" };
+            format!("{optional}{}: {}", render(&e.key), render(&e.value))
+        }
+        EntryExpr::StructField(field) => {
+            let optional = if field.optional { "?" } else { "" };
+            format!("{optional}{}: {}", field.field, render(&field.value))
+        }
+    }
+}
+
+/// A comprehension as the macro call it was expanded from.
+fn render_comprehension(comp: &ComprehensionExpr) -> String {
+    let is_accu =
+        |expr: &Expression| matches!(&expr.expr, Expr::Ident(name) if *name == comp.accu_var);
+    // `@result + [e]`: the element the step appends, if it is one.
+    let appended = |expr: &Expression| match &expr.expr {
+        Expr::Call(call) if call.func_name == operators::ADD && is_accu(&call.args[0]) => {
+            match &call.args[1].expr {
+                Expr::List(list) if list.elements.len() == 1 => Some(render(&list.elements[0])),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+    let (name, body) = match &comp.loop_step.expr {
+        Expr::Call(call) => match (call.func_name.as_str(), call.args.as_slice()) {
+            (operators::LOGICAL_AND, [accu, predicate]) if is_accu(accu) => {
+                (operators::ALL, render(predicate))
+            }
+            (operators::LOGICAL_OR, [accu, predicate]) if is_accu(accu) => {
+                (operators::EXISTS, render(predicate))
+            }
+            (operators::ADD, [accu, _]) if is_accu(accu) => (
+                operators::MAP,
+                appended(&comp.loop_step).unwrap_or_default(),
+            ),
+            (operators::CONDITIONAL, [predicate, step, otherwise]) if is_accu(otherwise) => {
+                match (&step.expr, appended(step)) {
+                    (_, Some(element)) if element == comp.iter_var => {
+                        (operators::FILTER, render(predicate))
+                    }
+                    (_, Some(element)) => {
+                        (operators::MAP, format!("{}, {element}", render(predicate)))
+                    }
+                    (Expr::Call(add), None) if add.func_name == operators::ADD => {
+                        (operators::EXISTS_ONE, render(predicate))
+                    }
+                    _ => ("comprehension", render(&comp.loop_step)),
+                }
+            }
+            _ => ("comprehension", render(&comp.loop_step)),
+        },
+        _ => ("comprehension", render(&comp.loop_step)),
+    };
+    let vars = match &comp.iter_var2 {
+        Some(var2) => format!("{}, {var2}", comp.iter_var),
+        None => comp.iter_var.clone(),
+    };
+    format!(
+        "{}.{name}({vars}, {body})",
+        render_operand(&comp.iter_range, 8)
+    )
+}
+
+fn render_literal(literal: &CelVal) -> String {
+    match literal {
+        CelVal::Int(i) => i.to_string(),
+        CelVal::UInt(u) => format!("{u}u"),
+        CelVal::Double(d) => render_double(*d),
+        CelVal::String(s) => quote(s),
+        CelVal::Bytes(b) => format!("b{}", quote(&String::from_utf8_lossy(b))),
+        CelVal::Boolean(b) => b.to_string(),
+        CelVal::Null => "null".into(),
+        other => format!("{other:?}"),
+    }
+}
+
+/// A double that reads back as one: `1.0`, not `1`.
+fn render_double(d: f64) -> String {
+    format!("{d:?}")
+}
+
+/// A single-quoted CEL string literal.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('\'');
+    for c in s.chars() {
+        match c {
+            '\'' => quoted.push_str("\\'"),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// A value in CEL literal syntax, with map keys sorted.
+fn render_value(value: &Value) -> String {
+    match value {
+        Value::Int(i) => i.to_string(),
+        Value::UInt(u) => format!("{u}u"),
+        Value::Float(d) => render_double(*d),
+        Value::String(s) => quote(s),
+        Value::Bytes(b) => format!("b{}", quote(&String::from_utf8_lossy(b))),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".into(),
+        Value::Duration(d) => format!("duration('{}s')", render_seconds(d)),
+        Value::Timestamp(t) => format!("timestamp('{}')", t.to_rfc3339()),
+        Value::List(items) => {
+            let items: Vec<_> = items.iter().map(render_value).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Map(map) => {
+            let mut entries: Vec<_> = map.map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let entries: Vec<_> = entries
+                .into_iter()
+                .map(|(key, value)| format!("{}: {}", render_key(key), render_value(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Function(name, _) => name.to_string(),
+        Value::Opaque(_) => format!("{value:?}"),
+    }
+}
+
+/// A duration in seconds, with the fraction only if there is one.
+fn render_seconds(d: &chrono::Duration) -> String {
+    let seconds = d.num_seconds();
+    let nanos = d.subsec_nanos().unsigned_abs();
+    if nanos == 0 {
+        seconds.to_string()
+    } else {
+        let sign = if seconds == 0 && d.subsec_nanos() < 0 {
+            "-"
+        } else {
+            ""
+        };
+        let fraction = format!("{nanos:09}");
+        format!("{sign}{seconds}.{}", fraction.trim_end_matches('0'))
+    }
+}
+
+fn render_key(key: &Key) -> String {
+    match key {
+        Key::Int(i) => i.to_string(),
+        Key::Uint(u) => format!("{u}u"),
+        Key::Bool(b) => b.to_string(),
+        Key::String(s) => quote(s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use cel::Program;
+    use serde_json::json;
+
+    fn schema(rule: &str) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": rule}],
+            "properties": {
+                "x": {"type": "integer"},
+                "max": {"type": "integer"},
+                "ports": {"type": "array", "items": {
+                    "type": "object",
+                    "properties": {"port": {"type": "integer"}}
+                }}
+            }
+        })
+    }
+
+    fn explain_rule(
+        rule: &str,
+        value: serde_json::Value,
+        old: Option<serde_json::Value>,
+    ) -> Explanation {
+        let schema = schema(rule);
+        let compiled = compile_schema(&schema);
+        explain(
+            compiled.validations[0].as_ref().unwrap(),
+            &schema,
+            &value,
+            old.as_ref(),
+        )
+    }
+
+    fn steps(explanation: &Explanation) -> Vec<String> {
+        explanation
+            .to_string()
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn macros_are_listed_with_their_outer_operands() {
+        let explanation = explain_rule(
+            "self.ports.all(p, p.port > 0 && p.port < self.max) || self.x == 0",
+            json!({"max": 80, "x": 1, "ports": [{"port": 443}]}),
+            None,
+        );
+        assert_eq!(explanation.result, Ok(Value::Bool(false)));
+        assert_eq!(
+            steps(&explanation),
+            [
+                "self.ports = [{'port': 443}]",
+                "self.max = 80",
+                "self.ports.all(p, p.port > 0 && p.port < self.max) = false",
+                "self.x = 1",
+                "self.x == 0 = false",
+            ]
+        );
+    }
+
+    #[test]
+    fn short_circuited_operands_show_their_errors() {
+        let explanation = explain_rule("!has(self.x) || self.x > 0", json!({}), None);
+        assert_eq!(explanation.result, Ok(Value::Bool(true)));
+        let steps = steps(&explanation);
+        assert_eq!(steps[0], "has(self.x) = false");
+        assert_eq!(steps[1], "!has(self.x) = true");
+        assert!(steps[2].starts_with("self.x = error: "), "{steps:?}");
+    }
+
+    #[test]
+    fn binds_old_self() {
+        let explanation = explain_rule(
+            "self.x >= oldSelf.x",
+            json!({"x": 1}),
+            Some(json!({"x": 2})),
+        );
+        assert_eq!(explanation.result, Ok(Value::Bool(false)));
+        assert_eq!(steps(&explanation), ["self.x = 1", "oldSelf.x = 2"]);
+
+        let explanation = explain_rule("self.x >= oldSelf.x", json!({"x": 1}), None);
+        assert!(explanation.result.is_err());
+    }
+
+    #[test]
+    fn renders_expressions_as_cel() {
+        for source in [
+            "(a + b) * c",
+            "a - (b - c)",
+            "!(a && b) || c",
+            "a ? b : c ? d : e",
+            "(a ? b : c) ? d : e",
+            "-(1 + x)",
+            "x[0].y['k']",
+            "{'a': 1, 'b': [1u, 2.5, b'c', null]}",
+            "m.f.orValue('it\\'s\\n')",
+            "x in [1, 2] && has(m.f)",
+            "math.ceil(x.size()) == 2.0",
+            "l.exists(x, x == 1)",
+            "l.exists_one(x, x == 1)",
+            "l.map(x, x * 2)",
+            "l.map(x, x > 0, x * 2)",
+            "l.filter(x, x > 0)",
+        ] {
+            let rendered = render(Program::compile(source).unwrap().expression());
+            assert_eq!(rendered, source);
+        }
+    }
+
+    #[test]
+    fn renders_values_as_cel() {
+        let explanation = explain_rule(
+            "duration('1m30s') > duration('0s') && timestamp('2024-01-01T00:00:00Z') != null \
+             && {'b': self.x, 'a': 1.0} != {}",
+            json!({"x": 1}),
+            None,
+        );
+        assert_eq!(
+            steps(&explanation),
+            [
+                "self.x = 1",
+                "{'b': self.x, 'a': 1.0} = {'a': 1.0, 'b': 1}",
+                "{'b': self.x, 'a': 1.0} != {} = true"
+            ]
+        );
+        assert_eq!(
+            render_value(&Value::Duration(chrono::Duration::milliseconds(90_500))),
+            "duration('90.5s')"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Stable fingerprints of schemas.
+//!
+//! A fingerprint is a canonical hash of the validation-relevant content of
+//! a schema: object key order and documentation keywords (`description`,
+//! `title`, `example`, ...) do not change it. It is stable across processes
+//! and platforms, so it can key a cache of compiled artifacts (see
+//! [`crate::snapshot`]) or tell whether an update to a watched CRD actually
+//! changed its validation.
+//!
+//! ```
+//! use kube_cel::fingerprint::schema_fingerprint;
+//! use serde_json::json;
+//!
+//! let before = json!({"type": "string", "maxLength": 8, "description": "The name."});
+//! let after = json!({"description": "The object's name.", "maxLength": 8, "type": "string"});
+//! assert_eq!(schema_fingerprint(&before), schema_fingerprint(&after));
+//! assert_ne!(schema_fingerprint(&before), schema_fingerprint(&json!({"type": "string"})));
+//! ```
+
+use crate::compilation::CompiledSchema;
+use crate::snapshot::SnapshotNode;
+
+/// Keywords that only document a schema node.
+const DOCUMENTATION_KEYWORDS: [&str; 6] = [
+    "description",
+    "title",
+    "example",
+    "examples",
+    "externalDocs",
+    "$comment",
+];
+
+/// The fingerprint of a schema's validation-relevant content.
+#[must_use]
+pub fn schema_fingerprint(schema: &serde_json::Value) -> String {
+    let mut hasher = Fnv::default();
+    hasher.write_schema(schema);
+    hasher.hex()
+}
+
+/// The fingerprint of a compiled schema: its rules, compilation errors,
+/// and node metadata. Equal for schemas that compile to the same tree,
+/// even if they are written differently (e.g. with `$ref`s).
+#[must_use]
+pub fn compiled_fingerprint(compiled: &CompiledSchema) -> String {
+    let mut hasher = Fnv::default();
+    // Serializing a snapshot node is infallible: every key is a string.
+    let node = serde_json::to_value(SnapshotNode::new(compiled)).unwrap_or_default();
+    hasher.write_value(&node);
+    hasher.hex()
+}
+
+/// The 64-bit FNV-1a hash, which unlike the standard library's hashers is
+/// stable across processes and platforms.
+pub(crate) struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    pub(crate) fn write_str(&mut self, s: &str) {
+        self.write_u64(s.len() as u64);
+        self.write(s.as_bytes());
+    }
+
+    /// The hash as 16 hex digits.
+    pub(crate) fn hex(&self) -> String {
+        format!("{:016x}", self.0)
+    }
+
+    /// Hash a JSON value with its object keys sorted, so key order does not
+    /// change the hash.
+    pub(crate) fn write_value(&mut self, value: &serde_json::Value) {
+        self.write_object_with(value, &[], |hasher, _, value| hasher.write_value(value));
+    }
+
+    /// Hash a schema node like [`write_value`](Self::write_value), skipping
+    /// documentation keywords of this node and its subschemas.
+    pub(crate) fn write_schema(&mut self, schema: &serde_json::Value) {
+        self.write_object_with(schema, &DOCUMENTATION_KEYWORDS, |hasher, keyword, value| {
+            match keyword {
+                "properties" | "patternProperties" | "definitions" | "$defs" => {
+                    hasher.write_object_with(value, &[], |hasher, _, schema| {
+                        hasher.write_schema(schema);
+                    });
+                }
+                "items"
+                | "additionalProperties"
+                | "additionalItems"
+                | "not"
+                | "allOf"
+                | "anyOf"
+                | "oneOf" => hasher.write_schemas(value),
+                _ => hasher.write_value(value),
+            }
+        });
+    }
+
+    /// Hash a subschema, or an array of them.
+    fn write_schemas(&mut self, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Array(schemas) => {
+                self.write(b"[");
+                self.write_u64(schemas.len() as u64);
+                for schema in schemas {
+                    self.write_schema(schema);
+                }
+            }
+            schema => self.write_schema(schema),
+        }
+    }
+
+    /// Hash `value`, hashing each entry of an object with `entry` in key
+    /// order and leaving out the `skip`ped keys.
+    fn write_object_with(
+        &mut self,
+        value: &serde_json::Value,
+        skip: &[&str],
+        mut entry: impl FnMut(&mut Self, &str, &serde_json::Value),
+    ) {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map
+                    .iter()
+                    .filter(|(key, _)| !skip.contains(&key.as_str()))
+                    .collect();
+                entries.sort_by_key(|(key, _)| *key);
+                self.write(b"{");
+                self.write_u64(entries.len() as u64);
+                for (key, value) in entries {
+                    self.write_str(key);
+                    entry(self, key, value);
+                }
+            }
+            serde_json::Value::Array(items) => {
+                self.write(b"[");
+                self.write_u64(items.len() as u64);
+                for item in items {
+                    self.write_value(item);
+                }
+            }
+            serde_json::Value::String(s) => {
+                self.write(b"\"");
+                self.write_str(s);
+            }
+            scalar => self.write_str(&scalar.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use serde_json::json;
+
+    #[test]
+    fn schema_fingerprint_ignores_documentation_and_key_order() {
+        let a: serde_json::Value = serde_json::from_str(
+            r#"{"type": "object", "description": "a", "properties": {
+                "spec": {"type": "string", "title": "Spec", "maxLength": 3}
+            }}"#,
+        )
+        .unwrap();
+        let b: serde_json::Value = serde_json::from_str(
+            r#"{"properties": {"spec": {"maxLength": 3, "type": "string"}}, "type": "object"}"#,
+        )
+        .unwrap();
+        assert_eq!(schema_fingerprint(&a), schema_fingerprint(&b));
+        assert_eq!(schema_fingerprint(&a).len(), 16);
+
+        for changed in [
+            json!({"type": "object", "properties": {"spec": {"type": "string", "maxLength": 4}}}),
+            json!({"type": "object", "properties": {"other": {"type": "string", "maxLength": 3}}}),
+            json!({"type": "object", "properties": {"spec": {"type": "string", "maxLength": 3}},
+                "x-kubernetes-validations": [{"rule": "has(self.spec)"}]}),
+        ] {
+            assert_ne!(schema_fingerprint(&a), schema_fingerprint(&changed));
+        }
+        assert_ne!(
+            schema_fingerprint(&json!({"enum": ["1"]})),
+            schema_fingerprint(&json!({"enum": [1]}))
+        );
+    }
+
+    #[test]
+    fn documentation_keywords_only_skip_in_schema_positions() {
+        // A property named `description` is not documentation.
+        assert_ne!(
+            schema_fingerprint(&json!({"properties": {"description": {"type": "string"}}})),
+            schema_fingerprint(&json!({"properties": {}}))
+        );
+        // Nor is a `description` key in a default value.
+        assert_ne!(
+            schema_fingerprint(&json!({"default": {"description": "x"}})),
+            schema_fingerprint(&json!({"default": {}}))
+        );
+        assert_eq!(
+            schema_fingerprint(&json!({"items": {"type": "string", "description": "x"}})),
+            schema_fingerprint(&json!({"items": {"type": "string"}}))
+        );
+        assert_eq!(
+            schema_fingerprint(&json!({"allOf": [{"description": "x"}]})),
+            schema_fingerprint(&json!({"allOf": [{}]}))
+        );
+    }
+
+    #[test]
+    fn compiled_fingerprint_follows_the_compiled_tree() {
+        let inline = json!({"type": "object", "properties": {
+            "name": {"type": "string", "x-kubernetes-validations": [{"rule": "self != ''"}]}
+        }});
+        let referenced = json!({
+            "type": "object",
+            "properties": {"name": {"$ref": "#/definitions/name"}},
+            "definitions": {
+                "name": {"type": "string", "x-kubernetes-validations": [{"rule": "self != ''"}]}
+            }
+        });
+        assert_ne!(schema_fingerprint(&inline), schema_fingerprint(&referenced));
+        assert_eq!(
+            compiled_fingerprint(&compile_schema(&inline)),
+            compiled_fingerprint(&compile_schema(&referenced))
+        );
+        assert_ne!(
+            compiled_fingerprint(&compile_schema(&inline)),
+            compiled_fingerprint(&compile_schema(&json!({"type": "object"})))
+        );
+    }
+}
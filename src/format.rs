@@ -126,9 +126,13 @@ fn format_s(val: &Value, out: &mut String) {
             out.push(']');
         }
         Value::Map(map) => {
+            // Entries are sorted by key, as in cel-go, rather than left in
+            // hash order, so the output is reproducible.
+            let mut entries: Vec<_> = map.map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
             out.push('{');
             let mut first = true;
-            for (key, value) in map.map.iter() {
+            for (key, value) in entries {
                 if !first {
                     out.push_str(", ");
                 }
@@ -383,6 +387,14 @@ mod tests {
     #[test]
     fn test_format_list() {
         assert_eq!(eval_str("'val: %s'.format([[1, 2, 3]])"), "val: [1, 2, 3]");
+        assert_eq!(
+            eval_str("'%s'.format([{'b': 1, 'a': [2], 'c': 'x'}])"),
+            r#"{"a": [2], "b": 1, "c": "x"}"#
+        );
+        assert_eq!(
+            eval_str("'%s'.format([{2: 'b', 1: 'a'}])"),
+            r#"{1: "a", 2: "b"}"#
+        );
     }
 
     #[test]
@@ -0,0 +1,495 @@
+//! Compile-time detection of calls to undeclared functions.
+//!
+//! CEL programs are compiled without a function environment, so a rule
+//! calling `self.name.trimm()` compiles and only fails when evaluated.
+//! [`check_functions`] looks every call up in the functions
+//! [`register_all`](crate::register_all) declares, and suggests the closest
+//! declared name for a typo.
+//!
+//! Functions added with
+//! [`ValidatorOptions::with_functions`](crate::validation::ValidatorOptions::with_functions)
+//! are not known here.
+
+use std::collections::BTreeSet;
+
+use cel::ExecutionError;
+use cel::common::ast::{CallExpr, EntryExpr, Expr, SelectExpr};
+use cel::parser::Expression;
+
+use crate::validation::base_context;
+
+/// A call to a function that is not declared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UndeclaredFunction {
+    /// The function name as called, qualified by its namespace for calls
+    /// like `math.ceill(x)`.
+    pub name: String,
+    /// The closest declared function name, if one is close enough.
+    pub suggestion: Option<&'static str>,
+}
+
+impl std::fmt::Display for UndeclaredFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "undeclared reference to '{}'", self.name)?;
+        if let Some(suggestion) = self.suggestion {
+            write!(f, "; did you mean '{suggestion}'?")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for UndeclaredFunction {}
+
+/// Every function name the crate and the CEL standard library declare.
+/// Only used for suggestions and [`Declarations`](crate::typecheck::Declarations):
+/// whether a name is declared is asked of the registered functions
+/// themselves.
+const KNOWN_FUNCTIONS: &[&str] = &[
+    // CEL standard library
+    "bytes",
+    "contains",
+    "double",
+    "duration",
+    "endsWith",
+    "getDate",
+    "getDayOfMonth",
+    "getDayOfWeek",
+    "getDayOfYear",
+    "getFullYear",
+    "getHours",
+    "getMilliseconds",
+    "getMinutes",
+    "getMonth",
+    "getSeconds",
+    "hasValue",
+    "int",
+    "matches",
+    "max",
+    "min",
+    "optional.none",
+    "optional.of",
+    "optional.ofNonZeroValue",
+    "or",
+    "orValue",
+    "size",
+    "startsWith",
+    "string",
+    "timestamp",
+    "type",
+    "uint",
+    "value",
+    // strings
+    "charAt",
+    "indexOf",
+    "join",
+    "lastIndexOf",
+    "lowerAscii",
+    "replace",
+    "split",
+    "strings.quote",
+    "substring",
+    "trim",
+    "upperAscii",
+    // lists
+    "distinct",
+    "first",
+    "flatten",
+    "isSorted",
+    "last",
+    "lists.range",
+    "reverse",
+    "slice",
+    "sort",
+    "sum",
+    // sets
+    "sets.contains",
+    "sets.equivalent",
+    "sets.intersects",
+    // regex
+    "find",
+    "findAll",
+    // urls
+    "getEscapedPath",
+    "getHost",
+    "getHostname",
+    "getPort",
+    "getQuery",
+    "getScheme",
+    "isURL",
+    "url",
+    // ip and cidr
+    "cidr",
+    "containsCIDR",
+    "containsIP",
+    "family",
+    "ip",
+    "ip.isCanonical",
+    "isCIDR",
+    "isGlobalUnicast",
+    "isIP",
+    "isLinkLocalMulticast",
+    "isLinkLocalUnicast",
+    "isLoopback",
+    "isUnspecified",
+    "masked",
+    "prefixLength",
+    // semver
+    "isSemver",
+    "major",
+    "minor",
+    "semver",
+    // format
+    "format",
+    "format.byte",
+    "format.date",
+    "format.datetime",
+    "format.labelValue",
+    "format.named",
+    "format.qualifiedName",
+    "format.uri",
+    "format.uuid",
+    "validate",
+    // quantity
+    "add",
+    "asApproximateFloat",
+    "asInteger",
+    "compareTo",
+    "isGreaterThan",
+    "isInteger",
+    "isLessThan",
+    "isQuantity",
+    "quantity",
+    "sign",
+    "sub",
+    // jsonpatch
+    "jsonpatch.escapeKey",
+    // math
+    "math.abs",
+    "math.bitAnd",
+    "math.bitNot",
+    "math.bitOr",
+    "math.bitShiftLeft",
+    "math.bitShiftRight",
+    "math.bitXor",
+    "math.ceil",
+    "math.floor",
+    "math.greatest",
+    "math.isFinite",
+    "math.isInf",
+    "math.isNaN",
+    "math.least",
+    "math.round",
+    "math.sign",
+    "math.sqrt",
+    "math.trunc",
+    // encoders
+    "base64.decode",
+    "base64.encode",
+    // authz
+    "allowed",
+    "check",
+    "error",
+    "errored",
+    "group",
+    "name",
+    "namespace",
+    "path",
+    "reason",
+    "resource",
+    "serviceAccount",
+    "subresource",
+];
+
+/// The names of the catalogued functions that are declared, with their
+/// namespace for namespaced functions like `math.ceil`.
+pub(crate) fn declared_functions() -> impl Iterator<Item = &'static str> {
+    KNOWN_FUNCTIONS
+        .iter()
+        .copied()
+        .filter(|name| is_declared(name))
+}
+
+/// Whether a function called `name` is declared, without calling it.
+///
+/// Operators, whose names are not identifiers, are always declared.
+#[must_use]
+pub fn is_declared(name: &str) -> bool {
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return true;
+    }
+    // The interpreter looks the function up before resolving its target,
+    // and this target fails to resolve, so the function never runs.
+    let probe = Expression {
+        id: 0,
+        expr: Expr::Call(CallExpr {
+            func_name: name.to_string(),
+            target: Some(Box::new(Expression {
+                id: 0,
+                expr: Expr::Select(SelectExpr {
+                    operand: Box::new(Expression {
+                        id: 0,
+                        expr: Expr::Ident(PROBE.to_string()),
+                    }),
+                    field: PROBE.to_string(),
+                    test: false,
+                }),
+            })),
+            args: Vec::new(),
+        }),
+    };
+    match cel::Value::resolve(&probe, base_context()) {
+        Err(ExecutionError::UndeclaredReference(reference)) => reference.as_str() == PROBE,
+        _ => true,
+    }
+}
+
+/// An identifier no context binds.
+const PROBE: &str = "__kube_cel_probe__";
+
+/// Check that every function `expr` calls is declared.
+///
+/// Returns the first undeclared call, in the order the calls appear.
+pub fn check_functions(expr: &Expression) -> Result<(), UndeclaredFunction> {
+    match &expr.expr {
+        Expr::Call(call) => {
+            if let Some(target) = &call.target {
+                check_functions(target)?;
+            }
+            for arg in &call.args {
+                check_functions(arg)?;
+            }
+            let namespace = match call.target.as_deref().map(|t| &t.expr) {
+                Some(Expr::Ident(namespace)) => Some(namespace.as_str()),
+                _ => None,
+            };
+            let qualified = namespace.map(|ns| format!("{ns}.{}", call.func_name));
+            if qualified.as_deref().is_some_and(is_declared) || is_declared(&call.func_name) {
+                return Ok(());
+            }
+            // `math.ceill(x)` is reported as such, `self.trimm()` as `trimm`.
+            let name = match qualified {
+                Some(qualified) if namespace.is_some_and(is_namespace) => qualified,
+                _ => call.func_name.clone(),
+            };
+            let suggestion = suggest(&name);
+            Err(UndeclaredFunction { name, suggestion })
+        }
+        Expr::Select(select) => check_functions(&select.operand),
+        Expr::List(list) => list.elements.iter().try_for_each(check_functions),
+        Expr::Map(map) => map.entries.iter().try_for_each(|e| check_entry(&e.expr)),
+        Expr::Struct(st) => st.entries.iter().try_for_each(|e| check_entry(&e.expr)),
+        Expr::Comprehension(comp) => {
+            check_functions(&comp.iter_range)?;
+            check_functions(&comp.accu_init)?;
+            check_functions(&comp.loop_cond)?;
+            check_functions(&comp.loop_step)?;
+            check_functions(&comp.result)
+        }
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => Ok(()),
+    }
+}
+
+/// The free variables and the functions an expression references.
+///
+/// Comprehension variables are not free, and operators are not listed.
+/// Namespaced calls like `math.ceil(x)` are listed under their qualified
+/// name, without the namespace as a variable.
+#[derive(Default)]
+pub(crate) struct References {
+    pub(crate) variables: BTreeSet<String>,
+    pub(crate) functions: BTreeSet<String>,
+    /// Comprehension variables in scope.
+    bound: Vec<String>,
+}
+
+impl References {
+    pub(crate) fn walk(&mut self, expr: &Expression) {
+        match &expr.expr {
+            Expr::Ident(name) => {
+                if !name.starts_with('@') && !self.bound.contains(name) {
+                    self.variables.insert(name.clone());
+                }
+            }
+            Expr::Call(call) => {
+                let qualified = match call.target.as_deref().map(|t| &t.expr) {
+                    Some(Expr::Ident(ns)) if !self.bound.contains(ns) => {
+                        Some(format!("{ns}.{}", call.func_name)).filter(|q| is_declared(q))
+                    }
+                    _ => None,
+                };
+                match qualified {
+                    Some(qualified) => {
+                        self.functions.insert(qualified);
+                    }
+                    None => {
+                        if let Some(target) = &call.target {
+                            self.walk(target);
+                        }
+                        if call
+                            .func_name
+                            .starts_with(|c: char| c.is_ascii_alphabetic())
+                        {
+                            self.functions.insert(call.func_name.clone());
+                        }
+                    }
+                }
+                call.args.iter().for_each(|arg| self.walk(arg));
+            }
+            Expr::Select(select) => self.walk(&select.operand),
+            Expr::List(list) => list.elements.iter().for_each(|e| self.walk(e)),
+            Expr::Map(map) => map.entries.iter().for_each(|e| self.walk_entry(&e.expr)),
+            Expr::Struct(st) => st.entries.iter().for_each(|e| self.walk_entry(&e.expr)),
+            Expr::Comprehension(comp) => {
+                self.walk(&comp.iter_range);
+                self.walk(&comp.accu_init);
+                let scope = self.bound.len();
+                self.bound.push(comp.iter_var.clone());
+                self.bound.extend(comp.iter_var2.clone());
+                self.bound.push(comp.accu_var.clone());
+                self.walk(&comp.loop_cond);
+                self.walk(&comp.loop_step);
+                self.walk(&comp.result);
+                self.bound.truncate(scope);
+            }
+            Expr::Literal(_) | Expr::Unspecified => {}
+        }
+    }
+
+    fn walk_entry(&mut self, entry: &EntryExpr) {
+        match entry {
+            EntryExpr::MapEntry(e) => {
+                self.walk(&e.key);
+                self.walk(&e.value);
+            }
+            EntryExpr::StructField(field) => self.walk(&field.value),
+        }
+    }
+}
+
+fn check_entry(entry: &EntryExpr) -> Result<(), UndeclaredFunction> {
+    match entry {
+        EntryExpr::MapEntry(e) => {
+            check_functions(&e.key)?;
+            check_functions(&e.value)
+        }
+        EntryExpr::StructField(field) => check_functions(&field.value),
+    }
+}
+
+/// Whether `ident` is the namespace of a known function, like `math`.
+fn is_namespace(ident: &str) -> bool {
+    KNOWN_FUNCTIONS
+        .iter()
+        .any(|known| known.split_once('.').is_some_and(|(ns, _)| ns == ident))
+}
+
+/// The declared function closest to `name`, if it is within a third of
+/// the name's length in edits.
+fn suggest(name: &str) -> Option<&'static str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    KNOWN_FUNCTIONS
+        .iter()
+        .map(|known| (edit_distance(name, known), *known))
+        .filter(|(distance, known)| *distance <= max_distance && is_declared(known))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cel::Program;
+
+    fn check(source: &str) -> Result<(), UndeclaredFunction> {
+        check_functions(Program::compile(source).unwrap().expression())
+    }
+
+    #[test]
+    fn declared_functions_pass() {
+        for source in [
+            "self.name.trim().size() > 0 && self.items.all(i, i.startsWith('a'))",
+            "has(self.x) ? int(self.x) + 1 : 0",
+            "self.list.map(x, x * 2).filter(x, x > 1).exists_one(x, x == 4)",
+            "optional.of(1).hasValue() && type(self) == map",
+        ] {
+            assert_eq!(check(source), Ok(()), "{source}");
+        }
+    }
+
+    #[cfg(all(feature = "strings", feature = "math"))]
+    #[test]
+    fn undeclared_functions_get_suggestions() {
+        let err = check("self.name.trimm() == 'x'").unwrap_err();
+        assert_eq!(
+            err,
+            UndeclaredFunction {
+                name: "trimm".into(),
+                suggestion: Some("trim"),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "undeclared reference to 'trimm'; did you mean 'trim'?"
+        );
+        assert_eq!(
+            check("math.ceill(self.x) > 1").unwrap_err().to_string(),
+            "undeclared reference to 'math.ceill'; did you mean 'math.ceil'?"
+        );
+        assert_eq!(
+            check("self.x.frobnicate()").unwrap_err().to_string(),
+            "undeclared reference to 'frobnicate'"
+        );
+        assert!(check("math.ceil(1.5) == 2.0 && 'a'.lowerAscii() == 'a'").is_ok());
+    }
+
+    #[test]
+    fn catalog_names_are_declared() {
+        for name in ["size", "contains", "matches", "duration", "optional.of"] {
+            assert!(is_declared(name), "{name}");
+        }
+        #[cfg(all(
+            feature = "strings",
+            feature = "lists",
+            feature = "sets",
+            feature = "regex_funcs",
+            feature = "urls",
+            feature = "ip",
+            feature = "semver_funcs",
+            feature = "format",
+            feature = "quantity",
+            feature = "jsonpatch",
+            feature = "named_format",
+            feature = "math",
+            feature = "encoders",
+            feature = "authz"
+        ))]
+        for name in KNOWN_FUNCTIONS {
+            assert!(is_declared(name), "{name}");
+        }
+        assert!(is_declared("_==_"));
+        assert!(!is_declared("trimm"));
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("trimm", "trim"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+}
@@ -0,0 +1,173 @@
+//! Async validation handle for tokio-based controllers.
+//!
+//! CEL evaluation is CPU-bound and synchronous. Running it directly inside a
+//! kube-rs reconciler blocks the async runtime, so [`ValidatorHandle`] moves
+//! each validation onto tokio's blocking pool and bounds how many run at once.
+//!
+//! ```rust,ignore
+//! use kube_cel::compilation::compile_schema;
+//! use kube_cel::handle::ValidatorHandle;
+//!
+//! let handle = ValidatorHandle::new(compile_schema(&schema)).with_concurrency(4);
+//!
+//! // Inside a reconciler:
+//! let errors = handle.validate(object, None).await?;
+//! ```
+
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+use tokio::task::JoinError;
+
+use crate::compilation::CompiledSchema;
+use crate::validation::{ValidationError, Validator};
+
+/// Default number of validations allowed to run concurrently on the blocking pool.
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Owns a [`CompiledSchema`] and runs validations on tokio's blocking pool.
+///
+/// Cloning a handle is cheap: clones share the compiled schema and the
+/// concurrency limit. When the limit is reached, [`validate`](Self::validate)
+/// waits for a free slot instead of queueing unbounded work on the pool.
+#[derive(Clone, Debug)]
+pub struct ValidatorHandle {
+    validator: Arc<Validator>,
+    compiled: Arc<CompiledSchema>,
+    permits: Arc<Semaphore>,
+}
+
+impl ValidatorHandle {
+    /// Create a handle for a compiled schema with [`DEFAULT_CONCURRENCY`].
+    pub fn new(compiled: CompiledSchema) -> Self {
+        Self::from_shared(Arc::new(compiled))
+    }
+
+    /// Create a handle from an already shared compiled schema.
+    pub fn from_shared(compiled: Arc<CompiledSchema>) -> Self {
+        Self {
+            validator: Arc::new(Validator::new()),
+            compiled,
+            permits: Arc::new(Semaphore::new(DEFAULT_CONCURRENCY)),
+        }
+    }
+
+    /// Use a custom [`Validator`] for evaluation.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = Arc::new(validator);
+        self
+    }
+
+    /// Limit the number of validations running at once.
+    ///
+    /// A value of `0` is treated as `1`. The limit applies to this handle and
+    /// every clone created from it afterwards.
+    #[must_use]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(concurrency.max(1)));
+        self
+    }
+
+    /// The compiled schema this handle validates against.
+    #[must_use]
+    pub fn compiled(&self) -> &Arc<CompiledSchema> {
+        &self.compiled
+    }
+
+    /// Number of validation slots currently free.
+    #[must_use]
+    pub fn available_permits(&self) -> usize {
+        self.permits.available_permits()
+    }
+
+    /// Validate an object on the blocking pool.
+    ///
+    /// Waits for a concurrency slot, then runs
+    /// [`Validator::validate_compiled`] via [`tokio::task::spawn_blocking`].
+    /// Returns a [`JoinError`] only if the validation task panicked or the
+    /// runtime is shutting down.
+    pub async fn validate(
+        &self,
+        object: serde_json::Value,
+        old_object: Option<serde_json::Value>,
+    ) -> Result<Vec<ValidationError>, JoinError> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("validator semaphore is never closed");
+        let validator = Arc::clone(&self.validator);
+        let compiled = Arc::clone(&self.compiled);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            validator.validate_compiled(&compiled, &object, old_object.as_ref())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use serde_json::json;
+
+    fn handle() -> ValidatorHandle {
+        let schema = json!({
+            "type": "object",
+            "properties": {"replicas": {"type": "integer"}},
+            "x-kubernetes-validations": [
+                {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"},
+                {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+            ]
+        });
+        ValidatorHandle::new(compile_schema(&schema))
+    }
+
+    #[tokio::test]
+    async fn validate_on_blocking_pool() {
+        let h = handle();
+        let errors = h.validate(json!({"replicas": -1}), None).await.unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "must be non-negative");
+    }
+
+    #[tokio::test]
+    async fn validate_with_old_object() {
+        let h = handle();
+        let errors = h
+            .validate(json!({"replicas": 1}), Some(json!({"replicas": 3})))
+            .await
+            .unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cannot scale down");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_validations_share_permits() {
+        let h = handle().with_concurrency(2);
+        assert_eq!(h.available_permits(), 2);
+
+        let tasks: Vec<_> = (0..16)
+            .map(|i| {
+                let h = h.clone();
+                tokio::spawn(async move { h.validate(json!({"replicas": i - 8}), None).await })
+            })
+            .collect();
+
+        let mut failures = 0;
+        for task in tasks {
+            failures += task.await.unwrap().unwrap().len();
+        }
+        assert_eq!(failures, 8);
+        assert_eq!(h.available_permits(), 2);
+    }
+
+    #[test]
+    fn zero_concurrency_clamped() {
+        let h = handle().with_concurrency(0);
+        assert_eq!(h.available_permits(), 1);
+    }
+}
@@ -0,0 +1,308 @@
+//! Interop with the `kube-core` and `k8s-openapi` types.
+//!
+//! Converts between [`Rule`] and the rule types of
+//! [`kube_core::Rule`] (the `#[x_kube(validation = ...)]` attribute of
+//! `CustomResource` derives) and
+//! [`ValidationRule`] (`x-kubernetes-validations` in a
+//! [`JSONSchemaProps`]), and compiles [`JSONSchemaProps`] and
+//! [`CustomResourceDefinition`]s. Both are serialized to JSON internally
+//! before compiling.
+//!
+//! ```
+//! use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+//!     JSONSchemaProps, ValidationRule,
+//! };
+//! use kube_cel::compilation::Rule;
+//! use kube_cel::kube::compile_schema_props;
+//!
+//! let props = JSONSchemaProps {
+//!     type_: Some("integer".into()),
+//!     x_kubernetes_validations: Some(vec![ValidationRule {
+//!         rule: "self >= 0".into(),
+//!         ..Default::default()
+//!     }]),
+//!     ..Default::default()
+//! };
+//! let compiled = compile_schema_props(&props);
+//! assert!(compiled.validations[0].is_ok());
+//!
+//! let rule: Rule = kube_core::Rule::new("self == oldSelf").message("is immutable").into();
+//! assert_eq!(rule.message.as_deref(), Some("is immutable"));
+//! ```
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+    CustomResourceDefinition, JSONSchemaProps, ValidationRule,
+};
+
+use crate::compilation::{CompiledSchema, Reason, Rule, Severity, compile_schema_from};
+use crate::crd::{CompiledCrd, CrdError};
+
+/// Why a [`Rule`] cannot be converted to a [`kube_core::Rule`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleConversionError {
+    /// The rule has both a `message` and a `messageExpression`;
+    /// `kube_core::Rule` holds one or the other.
+    BothMessages,
+    /// The rule sets `optionalOldSelf`, which `kube_core::Rule` lacks.
+    OptionalOldSelf,
+    /// The rule is a warning, which `kube_core::Rule` cannot express.
+    WarningSeverity,
+    /// The rule's `reason` is not one of the four Kubernetes reasons.
+    UnknownReason(String),
+}
+
+impl std::fmt::Display for RuleConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleConversionError::BothMessages => {
+                write!(f, "rule has both a message and a messageExpression")
+            }
+            RuleConversionError::OptionalOldSelf => write!(f, "rule sets optionalOldSelf"),
+            RuleConversionError::WarningSeverity => write!(f, "rule has warning severity"),
+            RuleConversionError::UnknownReason(reason) => {
+                write!(f, "unknown rule reason: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleConversionError {}
+
+impl From<kube_core::Rule> for Rule {
+    fn from(rule: kube_core::Rule) -> Self {
+        let mut builder = Rule::builder(rule.rule);
+        match rule.message {
+            Some(kube_core::Message::Message(message)) => builder = builder.with_message(message),
+            Some(kube_core::Message::Expression(expression)) => {
+                builder = builder.with_message_expression(expression);
+            }
+            None => {}
+        }
+        if let Some(reason) = rule.reason {
+            builder = builder.with_reason(match reason {
+                kube_core::Reason::FieldValueInvalid => Reason::FieldValueInvalid,
+                kube_core::Reason::FieldValueForbidden => Reason::FieldValueForbidden,
+                kube_core::Reason::FieldValueRequired => Reason::FieldValueRequired,
+                kube_core::Reason::FieldValueDuplicate => Reason::FieldValueDuplicate,
+            });
+        }
+        if let Some(field_path) = rule.field_path {
+            builder = builder.with_field_path(field_path);
+        }
+        builder.build()
+    }
+}
+
+impl TryFrom<Rule> for kube_core::Rule {
+    type Error = RuleConversionError;
+
+    fn try_from(rule: Rule) -> Result<Self, Self::Error> {
+        if rule.optional_old_self.is_some() {
+            return Err(RuleConversionError::OptionalOldSelf);
+        }
+        if rule.severity == Severity::Warning {
+            return Err(RuleConversionError::WarningSeverity);
+        }
+        let message = match (rule.message, rule.message_expression) {
+            (Some(_), Some(_)) => return Err(RuleConversionError::BothMessages),
+            (Some(message), None) => Some(kube_core::Message::Message(message)),
+            (None, Some(expression)) => Some(kube_core::Message::Expression(expression)),
+            (None, None) => None,
+        };
+        let reason = rule
+            .reason
+            .map(|reason| match reason {
+                Reason::FieldValueInvalid => Ok(kube_core::Reason::FieldValueInvalid),
+                Reason::FieldValueForbidden => Ok(kube_core::Reason::FieldValueForbidden),
+                Reason::FieldValueRequired => Ok(kube_core::Reason::FieldValueRequired),
+                Reason::FieldValueDuplicate => Ok(kube_core::Reason::FieldValueDuplicate),
+                Reason::Unknown(reason) => Err(RuleConversionError::UnknownReason(reason)),
+            })
+            .transpose()?;
+        Ok(kube_core::Rule {
+            rule: rule.rule,
+            message,
+            field_path: rule.field_path,
+            reason,
+        })
+    }
+}
+
+impl From<ValidationRule> for Rule {
+    fn from(rule: ValidationRule) -> Self {
+        Rule {
+            rule: rule.rule,
+            message: rule.message,
+            message_expression: rule.message_expression,
+            reason: rule.reason.map(Reason::from),
+            field_path: rule.field_path,
+            optional_old_self: rule.optional_old_self,
+            severity: Severity::Error,
+        }
+    }
+}
+
+/// The rule as it appears in a CRD. The `x-kube-cel-severity` extension
+/// has no place in [`ValidationRule`] and is dropped.
+impl From<Rule> for ValidationRule {
+    fn from(rule: Rule) -> Self {
+        ValidationRule {
+            rule: rule.rule,
+            message: rule.message,
+            message_expression: rule.message_expression,
+            reason: rule.reason.map(String::from),
+            field_path: rule.field_path,
+            optional_old_self: rule.optional_old_self,
+        }
+    }
+}
+
+/// Compile a [`JSONSchemaProps`] tree, like [`compile_schema_from`].
+///
+/// The tree is serialized to JSON with `serde_json` and compiled from
+/// that; the typed structs are not read directly.
+#[must_use]
+pub fn compile_schema_props(props: &JSONSchemaProps) -> CompiledSchema {
+    compile_schema_from(props)
+}
+
+/// Compile the schema of every served version of a
+/// [`CustomResourceDefinition`], like [`CompiledCrd::compile`].
+pub fn compile_crd(crd: &CustomResourceDefinition) -> Result<CompiledCrd, CrdError> {
+    CompiledCrd::compile(&to_value(crd))
+}
+
+/// The JSON form of a CRD. Serializing one cannot fail: every map in it
+/// is keyed by strings.
+fn to_value(crd: &CustomResourceDefinition) -> serde_json::Value {
+    serde_json::to_value(crd).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+        CustomResourceDefinitionNames, CustomResourceDefinitionSpec,
+        CustomResourceDefinitionVersion, CustomResourceValidation,
+    };
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn kube_core_rules_convert_both_ways() {
+        let rule: Rule = kube_core::Rule::new("self == oldSelf")
+            .message(kube_core::Message::Expression("'immutable'".into()))
+            .reason(kube_core::Reason::FieldValueForbidden)
+            .field_path(".name")
+            .into();
+        assert_eq!(rule.message, None);
+        assert_eq!(rule.message_expression.as_deref(), Some("'immutable'"));
+        assert_eq!(rule.reason, Some(Reason::FieldValueForbidden));
+        assert_eq!(rule.field_path.as_deref(), Some(".name"));
+
+        let back = kube_core::Rule::try_from(rule).unwrap();
+        assert_eq!(
+            back.message,
+            Some(kube_core::Message::Expression("'immutable'".into()))
+        );
+        assert_eq!(back.reason, Some(kube_core::Reason::FieldValueForbidden));
+    }
+
+    #[test]
+    fn rules_kube_core_cannot_express_fail_to_convert() {
+        let cases = [
+            (
+                Rule::builder("true")
+                    .with_message("a")
+                    .with_message_expression("'b'"),
+                RuleConversionError::BothMessages,
+            ),
+            (
+                Rule::builder("true").with_optional_old_self(true),
+                RuleConversionError::OptionalOldSelf,
+            ),
+            (
+                Rule::builder("true").with_severity(Severity::Warning),
+                RuleConversionError::WarningSeverity,
+            ),
+            (
+                Rule::builder("true").with_reason("Custom"),
+                RuleConversionError::UnknownReason("Custom".into()),
+            ),
+        ];
+        for (rule, error) in cases {
+            assert_eq!(kube_core::Rule::try_from(rule.build()).unwrap_err(), error);
+        }
+    }
+
+    #[test]
+    fn validation_rules_convert_both_ways() {
+        let rule = ValidationRule {
+            rule: "self.x > 0".into(),
+            message_expression: Some("'x: ' + string(self.x)".into()),
+            reason: Some("FieldValueRequired".into()),
+            optional_old_self: Some(true),
+            ..Default::default()
+        };
+        let converted = Rule::from(rule.clone());
+        assert_eq!(converted.reason, Some(Reason::FieldValueRequired));
+        assert_eq!(ValidationRule::from(converted), rule);
+    }
+
+    fn props() -> JSONSchemaProps {
+        JSONSchemaProps {
+            type_: Some("object".into()),
+            properties: Some(BTreeMap::from([(
+                "replicas".to_string(),
+                JSONSchemaProps {
+                    type_: Some("integer".into()),
+                    x_kubernetes_validations: Some(vec![ValidationRule {
+                        rule: "self >= 0".into(),
+                        message: Some("must not be negative".into()),
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                },
+            )])),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compiles_schema_props() {
+        let compiled = compile_schema_props(&props());
+        let errors =
+            crate::validation::validate_compiled(&compiled, &json!({"replicas": -1}), None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "must not be negative");
+    }
+
+    #[test]
+    fn compiles_crds() {
+        let crd = CustomResourceDefinition {
+            spec: CustomResourceDefinitionSpec {
+                group: "example.com".into(),
+                names: CustomResourceDefinitionNames {
+                    kind: "Widget".into(),
+                    plural: "widgets".into(),
+                    ..Default::default()
+                },
+                scope: "Namespaced".into(),
+                versions: vec![CustomResourceDefinitionVersion {
+                    name: "v1".into(),
+                    served: true,
+                    storage: true,
+                    schema: Some(CustomResourceValidation {
+                        open_api_v3_schema: Some(props()),
+                    }),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let compiled = compile_crd(&crd).unwrap();
+        assert_eq!(compiled.group(), "example.com");
+        assert!(compiled.get("v1").is_some());
+    }
+}
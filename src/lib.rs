@@ -41,6 +41,27 @@
 //!
 //! For repeated validation against the same schema, pre-compile with
 //! [`compilation::compile_schema`] and use [`validation::Validator::validate_compiled`].
+//!
+//! With the `tokio` feature, `handle::ValidatorHandle` runs compiled
+//! validations on tokio's blocking pool for use inside async controllers.
+//!
+//! The `policy` module evaluates `ValidatingAdmissionPolicy` expressions
+//! against admission requests.
+//! The `mutation` module applies `MutatingAdmissionPolicy` mutations to
+//! the object of an admission request.
+//!
+//! With the `tower` feature, `handle::ValidatorHandle` is a `tower::Service`
+//! of `service::ValidationRequest`s.
+//!
+//! With the `webhook` feature, `webhook::AdmissionWebhook` answers
+//! `AdmissionReview` requests as an axum validating admission webhook.
+//!
+//! With the `yaml` feature, the `yaml` module loads CRDs and objects from
+//! YAML.
+//!
+//! With the `kube` feature, the `kube` module converts rules from and to
+//! the `kube-core` and `k8s-openapi` types and compiles `JSONSchemaProps`
+//! and `CustomResourceDefinition`s directly.
 
 #[cfg(feature = "strings")]
 pub mod strings;
@@ -81,6 +102,9 @@ pub mod math;
 #[cfg(feature = "encoders")]
 pub mod encoders;
 
+#[cfg(feature = "authz")]
+pub mod authz;
+
 #[cfg(feature = "validation")]
 pub mod escaping;
 
@@ -90,9 +114,75 @@ pub mod values;
 #[cfg(feature = "validation")]
 pub mod compilation;
 
+#[cfg(feature = "validation")]
+pub mod cost;
+
+#[cfg(feature = "validation")]
+pub mod typecheck;
+
+#[cfg(feature = "validation")]
+pub mod functions;
+
+#[cfg(feature = "validation")]
+pub mod snapshot;
+
+#[cfg(feature = "validation")]
+pub mod fingerprint;
+
+#[cfg(feature = "validation")]
+pub mod diff;
+
 #[cfg(feature = "validation")]
 pub mod validation;
 
+#[cfg(feature = "validation")]
+pub mod explain;
+
+#[cfg(feature = "validation")]
+pub mod status;
+
+#[cfg(feature = "validation")]
+pub mod report;
+
+#[cfg(feature = "validation")]
+pub mod structural;
+
+#[cfg(feature = "validation")]
+pub mod pruning;
+
+#[cfg(feature = "validation")]
+pub mod lint;
+
+#[cfg(feature = "validation")]
+pub mod diagnostics;
+
+#[cfg(feature = "validation")]
+pub mod crd;
+
+#[cfg(feature = "kube")]
+pub mod kube;
+
+#[cfg(feature = "validation")]
+pub mod policy;
+
+#[cfg(feature = "validation")]
+pub mod matching;
+
+#[cfg(feature = "validation")]
+pub mod mutation;
+
+#[cfg(feature = "tokio")]
+pub mod handle;
+
+#[cfg(feature = "tower")]
+pub mod service;
+
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
 mod dispatch;
 mod value_ops;
 
@@ -137,6 +227,9 @@ pub fn register_all(ctx: &mut cel::Context<'_>) {
     #[cfg(feature = "encoders")]
     encoders::register(ctx);
 
+    #[cfg(feature = "authz")]
+    authz::register(ctx);
+
     // Dispatch: registers functions with name collisions (indexOf, reverse,
     // min/max, string, ip, isGreaterThan, etc.). Order-independent since
     // individual modules no longer register these conflicting names.
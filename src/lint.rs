@@ -0,0 +1,415 @@
+//! CRD admission-time checks of `x-kubernetes-validations` rule definitions.
+//!
+//! kube-apiserver refuses a CRD whose validation rules are malformed, even
+//! when every expression compiles. [`lint_rules`] reproduces those checks so
+//! they can run in CI, before the CRD is applied:
+//!
+//! - the rule compiles, calls only declared functions, has a message when it
+//!   spans several lines, and stays within the length and estimated cost
+//!   limits;
+//! - `message` is not blank and has no line breaks;
+//! - `messageExpression` is not blank, compiles, and is not a non-string literal;
+//! - `reason` is one of the four `FieldValue*` reasons;
+//! - `fieldPath` is a simple path to a field declared in the schema;
+//! - `oldSelf` is only used where the node can be correlated with the old
+//!   object, and `optionalOldSelf` only on rules that use `oldSelf`.
+//!
+//! `messageExpression` results are not type-checked beyond literals, and
+//! rules are not type-checked against the schema; see [`crate::typecheck`]
+//! for that.
+
+use cel::Program;
+use cel::common::ast::Expr;
+use cel::common::value::CelVal;
+
+use crate::compilation::{
+    CompilationError, CompileOptions, ListType, MapType, Reason, Rule,
+    additional_properties_schema, compile_schema_validations, expand_node, join_location as join,
+    resolve_refs,
+};
+use crate::cost::STATIC_ESTIMATED_COST_LIMIT;
+use crate::functions::check_functions;
+use crate::validation::{PathSegment, resolve_field_path};
+
+/// A problem with one rule definition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintIssue {
+    /// Location of the rule in the schema, e.g.
+    /// `properties[spec].x-kubernetes-validations[0]`.
+    pub location: String,
+    /// The rule's CEL expression (empty if the rule could not be read).
+    pub rule: String,
+    /// The rule property at fault: `rule`, `message`, `messageExpression`,
+    /// `reason`, `fieldPath`, or `optionalOldSelf`.
+    pub property: &'static str,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}: {}", self.location, self.property, self.message)
+    }
+}
+
+/// Check every `x-kubernetes-validations` rule in `schema` the way
+/// kube-apiserver does when the CRD is created or updated.
+///
+/// Returns one issue per problem found, in schema order; an empty list
+/// means the apiserver would accept the rules. Local `$ref`s are resolved
+/// against `schema`.
+#[must_use]
+pub fn lint_rules(schema: &serde_json::Value) -> Vec<LintIssue> {
+    let mut linter = Linter {
+        root: schema,
+        refs: Vec::new(),
+        issues: Vec::new(),
+    };
+    linter.node(schema, "", true);
+    linter.issues
+}
+
+/// State for linting one schema document.
+struct Linter<'a> {
+    root: &'a serde_json::Value,
+    /// The `$ref`s being expanded on the path from the root.
+    refs: Vec<String>,
+    issues: Vec<LintIssue>,
+}
+
+impl Linter<'_> {
+    /// Lint the rules of `schema` at `location` and of its children.
+    /// `correlatable` is whether the node can have an `oldSelf`.
+    fn node(&mut self, schema: &serde_json::Value, location: &str, correlatable: bool) {
+        let refs = self.refs.len();
+        match resolve_refs(schema, self.root, &mut self.refs) {
+            Ok(resolved) => self.resolved(&expand_node(&resolved), location, correlatable),
+            Err(CompilationError::InvalidRef {
+                reference, message, ..
+            }) => {
+                self.issues.push(LintIssue {
+                    location: location.to_string(),
+                    rule: String::new(),
+                    property: "$ref",
+                    message: format!("\"{reference}\": {message}"),
+                });
+            }
+            Err(_) => {}
+        }
+        self.refs.truncate(refs);
+    }
+
+    fn resolved(&mut self, schema: &serde_json::Value, location: &str, correlatable: bool) {
+        self.rules(schema, location, correlatable);
+
+        let fields_correlatable = correlatable && MapType::from_schema(schema).correlates_fields();
+        if let Some(props) = schema.get("properties").and_then(|p| p.as_object()) {
+            for (name, prop) in props {
+                let child = join(location, &format!("properties[{name}]"));
+                self.node(prop, &child, fields_correlatable);
+            }
+        }
+        if let Some(additional) = additional_properties_schema(schema) {
+            let child = join(location, "additionalProperties");
+            self.node(additional, &child, fields_correlatable);
+        }
+        if let Some(items) = schema.get("items") {
+            let items_correlatable =
+                correlatable && ListType::from_schema(schema).correlates_items();
+            self.node(items, &join(location, "items"), items_correlatable);
+        }
+    }
+
+    fn rules(&mut self, schema: &serde_json::Value, location: &str, correlatable: bool) {
+        let Some(raw_rules) = schema
+            .get("x-kubernetes-validations")
+            .and_then(|v| v.as_array())
+        else {
+            return;
+        };
+        let compiled = compile_schema_validations(schema);
+        for (i, (raw, result)) in raw_rules.iter().zip(compiled).enumerate() {
+            let location = join(location, &format!("x-kubernetes-validations[{i}]"));
+            let mut issue = |rule: &str, property, message: String| {
+                self.issues.push(LintIssue {
+                    location: location.clone(),
+                    rule: rule.to_string(),
+                    property,
+                    message,
+                });
+            };
+            let rule: Rule = match serde_json::from_value(raw.clone()) {
+                Ok(rule) => rule,
+                Err(e) => {
+                    issue("", "rule", format!("invalid rule definition: {e}"));
+                    continue;
+                }
+            };
+            let expr = rule.rule.as_str();
+
+            match result {
+                Ok(cr) => {
+                    if let Err(undeclared) = check_functions(cr.program.expression()) {
+                        issue(expr, "rule", format!("compilation failed: {undeclared}"));
+                    }
+                    if cr.is_transition_rule && !correlatable {
+                        issue(
+                            expr,
+                            "rule",
+                            "oldSelf cannot be used on the uncorrelatable portion of the \
+                             schema within a list item or an atomic map"
+                                .into(),
+                        );
+                    }
+                    if !cr.is_transition_rule && rule.optional_old_self.is_some() {
+                        issue(
+                            expr,
+                            "optionalOldSelf",
+                            "may not be set if the rule does not use oldSelf".into(),
+                        );
+                    }
+                    if cr.estimated_cost > STATIC_ESTIMATED_COST_LIMIT {
+                        issue(
+                            expr,
+                            "rule",
+                            format!(
+                                "estimated rule cost {} exceeds budget of {STATIC_ESTIMATED_COST_LIMIT}",
+                                cr.estimated_cost
+                            ),
+                        );
+                    }
+                }
+                Err(CompilationError::Parse { source, .. }) => {
+                    issue(expr, "rule", format!("compilation failed: {source}"));
+                }
+                Err(e) => issue(expr, "rule", e.to_string()),
+            }
+
+            if let Err(message) = CompileOptions::default().check_definition(&rule) {
+                let property = if message.starts_with("message") {
+                    "message"
+                } else {
+                    "rule"
+                };
+                issue(expr, property, message);
+            }
+
+            if let Some(message_expression) = &rule.message_expression
+                && let Err(message) = check_message_expression(message_expression)
+            {
+                issue(expr, "messageExpression", message);
+            }
+
+            if let Some(Reason::Unknown(reason)) = &rule.reason {
+                issue(
+                    expr,
+                    "reason",
+                    format!(
+                        "Unsupported value: \"{reason}\": supported values: \
+                         \"FieldValueDuplicate\", \"FieldValueForbidden\", \
+                         \"FieldValueInvalid\", \"FieldValueRequired\""
+                    ),
+                );
+            }
+
+            if let Some(field_path) = &rule.field_path
+                && let Err(message) = check_field_path(schema, field_path)
+            {
+                issue(expr, "fieldPath", message);
+            }
+        }
+    }
+}
+
+/// Check that a `messageExpression` is usable: non-blank, compiles, and is
+/// not a literal of another type than string.
+fn check_message_expression(expression: &str) -> Result<(), String> {
+    if expression.trim().is_empty() {
+        return Err("must be non-empty if specified".into());
+    }
+    let program = Program::compile(expression).map_err(|e| format!("compilation failed: {e}"))?;
+    match &program.expression().expr {
+        Expr::Literal(CelVal::String(_)) | Expr::Literal(CelVal::Unspecified) => Ok(()),
+        Expr::Literal(_) | Expr::List(_) | Expr::Map(_) => Err("must evaluate to a string".into()),
+        _ => Ok(()),
+    }
+}
+
+/// Check that `field_path` is a well-formed path to a field declared below
+/// `schema`.
+fn check_field_path(schema: &serde_json::Value, field_path: &str) -> Result<(), String> {
+    let invalid = |why: &str| format!("Invalid value: \"{field_path}\": {why}");
+    let segments =
+        resolve_field_path(&[], field_path).ok_or_else(|| invalid("not a valid field path"))?;
+    let mut node = expand_node(schema).into_owned();
+    for segment in &segments {
+        let child = match segment {
+            PathSegment::Field(name) | PathSegment::Key(name) => node
+                .get("properties")
+                .and_then(|p| p.get(name))
+                .or_else(|| additional_properties_schema(&node)),
+            PathSegment::Index(_) => node.get("items"),
+        };
+        node = match child {
+            Some(child) => expand_node(child).into_owned(),
+            None => return Err(invalid("does not refer to a field declared in the schema")),
+        };
+    }
+    Ok(())
+}
+
+/// Append `segment` to a schema location.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn issues(schema: serde_json::Value) -> Vec<(String, &'static str, String)> {
+        lint_rules(&schema)
+            .into_iter()
+            .map(|i| (i.location, i.property, i.message))
+            .collect()
+    }
+
+    #[test]
+    fn valid_rules_have_no_issues() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{
+                "rule": "self.replicas >= oldSelf.replicas",
+                "message": "cannot scale down",
+                "messageExpression": "'replicas: ' + string(self.replicas)",
+                "reason": "FieldValueForbidden",
+                "fieldPath": ".replicas"
+            }],
+            "properties": {
+                "replicas": {"type": "integer"},
+                "ports": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "map",
+                    "x-kubernetes-list-map-keys": ["name"],
+                    "items": {
+                        "type": "object",
+                        "x-kubernetes-validations": [{"rule": "self.name == oldSelf.name"}],
+                        "properties": {"name": {"type": "string"}}
+                    }
+                }
+            }
+        });
+        assert_eq!(issues(schema), []);
+    }
+
+    #[test]
+    fn reports_rule_metadata_issues() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "true", "reason": "Bogus"},
+                {"rule": "true", "messageExpression": "42"},
+                {"rule": "true", "messageExpression": "'a' +"},
+                {"rule": "true", "fieldPath": ".missing"},
+                {"rule": "true", "fieldPath": "..x"},
+                {"rule": "true", "message": "a\nb"},
+                {"rule": "true", "optionalOldSelf": true},
+                {"rule": "self.x >"}
+            ],
+            "properties": {"x": {"type": "integer"}}
+        });
+        let found: Vec<_> = issues(schema)
+            .into_iter()
+            .map(|(location, property, _)| (location, property))
+            .collect();
+        let expected: Vec<_> = [
+            (0, "reason"),
+            (1, "messageExpression"),
+            (2, "messageExpression"),
+            (3, "fieldPath"),
+            (4, "fieldPath"),
+            (5, "message"),
+            (6, "optionalOldSelf"),
+            (7, "rule"),
+        ]
+        .into_iter()
+        .map(|(i, property)| (format!("x-kubernetes-validations[{i}]"), property))
+        .collect();
+        assert_eq!(found, expected);
+    }
+
+    #[cfg(feature = "strings")]
+    #[test]
+    fn undeclared_functions_are_reported() {
+        let schema = json!({
+            "type": "string",
+            "x-kubernetes-validations": [{"rule": "self.lowerAscii() == self.trimm()"}]
+        });
+        assert_eq!(
+            issues(schema),
+            [(
+                "x-kubernetes-validations[0]".to_string(),
+                "rule",
+                "compilation failed: undeclared reference to 'trimm'; did you mean 'trim'?"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn transition_rules_must_be_correlatable() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "tags": {
+                    "type": "array",
+                    "items": {
+                        "type": "string",
+                        "x-kubernetes-validations": [{"rule": "self == oldSelf"}]
+                    }
+                },
+                "config": {
+                    "type": "object",
+                    "x-kubernetes-map-type": "atomic",
+                    "x-kubernetes-validations": [{"rule": "self == oldSelf"}],
+                    "properties": {
+                        "mode": {
+                            "type": "string",
+                            "x-kubernetes-validations": [{"rule": "self == oldSelf"}]
+                        }
+                    }
+                }
+            }
+        });
+        let mut locations: Vec<_> = lint_rules(&schema)
+            .into_iter()
+            .map(|i| i.location)
+            .collect();
+        locations.sort();
+        assert_eq!(
+            locations,
+            [
+                "properties[config].properties[mode].x-kubernetes-validations[0]",
+                "properties[tags].items.x-kubernetes-validations[0]",
+            ]
+        );
+    }
+
+    #[test]
+    fn display_and_refs() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"a": {"$ref": "#/definitions/missing"}},
+            "x-kubernetes-validations": [{"rule": "true", "message": " "}]
+        });
+        let mut rendered: Vec<_> = lint_rules(&schema)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        rendered.sort();
+        assert_eq!(
+            rendered,
+            [
+                "properties[a].$ref: \"#/definitions/missing\": not found in the schema document",
+                "x-kubernetes-validations[0].message: message must be non-empty if specified",
+            ]
+        );
+    }
+}
@@ -0,0 +1,388 @@
+//! Matching of admission requests against a policy's `matchConstraints`.
+//!
+//! [`MatchResources`] decides whether a policy applies to a request before
+//! any CEL is evaluated, from the request's resource, operation, and the
+//! labels of the object and its namespace:
+//!
+//! ```
+//! use kube_cel::matching::MatchResources;
+//! use kube_cel::policy::PolicyRequest;
+//! use serde_json::json;
+//!
+//! let constraints: MatchResources = serde_json::from_value(json!({
+//!     "resourceRules": [{
+//!         "apiGroups": ["apps"],
+//!         "apiVersions": ["v1"],
+//!         "operations": ["CREATE", "UPDATE"],
+//!         "resources": ["deployments"]
+//!     }],
+//!     "objectSelector": {"matchLabels": {"team": "core"}}
+//! }))
+//! .unwrap();
+//! let request = json!({
+//!     "operation": "CREATE",
+//!     "resource": {"group": "apps", "version": "v1", "resource": "deployments"},
+//!     "namespace": "default"
+//! });
+//! let object = json!({"metadata": {"labels": {"team": "core"}}});
+//! let request = PolicyRequest {
+//!     object: Some(&object),
+//!     request: Some(&request),
+//!     ..Default::default()
+//! };
+//! assert!(constraints.matches(&request));
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::policy::PolicyRequest;
+
+/// The `spec.matchConstraints` of a policy.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchResources {
+    /// Selects requests by the labels of the object's namespace.
+    #[serde(default)]
+    pub namespace_selector: Option<LabelSelector>,
+    /// Selects requests by the labels of the object or the old object.
+    #[serde(default)]
+    pub object_selector: Option<LabelSelector>,
+    /// The requests the policy applies to; a request must match one.
+    #[serde(default)]
+    pub resource_rules: Vec<NamedRuleWithOperations>,
+    /// Requests the policy never applies to, even if a resource rule
+    /// matches.
+    #[serde(default)]
+    pub exclude_resource_rules: Vec<NamedRuleWithOperations>,
+    /// How requests for other versions of a matched resource are treated.
+    #[serde(default)]
+    pub match_policy: MatchPolicy,
+}
+
+/// The `matchPolicy` of [`MatchResources`].
+///
+/// Matching here compares the request's own group, version, and resource,
+/// so `Equivalent` behaves like `Exact`: the apiserver's conversion to
+/// equivalent versions is not available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum MatchPolicy {
+    /// Match only the exact rules.
+    Exact,
+    /// Also match requests for equivalent versions (the default).
+    #[default]
+    Equivalent,
+}
+
+/// A resource rule of [`MatchResources`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamedRuleWithOperations {
+    /// API groups, `""` for the core group, or `*` for all.
+    #[serde(default)]
+    pub api_groups: Vec<String>,
+    /// API versions, or `*` for all.
+    #[serde(default)]
+    pub api_versions: Vec<String>,
+    /// Resources, optionally with a subresource (`pods/status`); `*` matches
+    /// any resource and `pods/*` any subresource.
+    #[serde(default)]
+    pub resources: Vec<String>,
+    /// `CREATE`, `UPDATE`, `DELETE`, `CONNECT`, or `*` for all.
+    #[serde(default)]
+    pub operations: Vec<String>,
+    /// `Cluster`, `Namespaced`, or `*` (the default).
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// Object names the rule is limited to; empty matches every name.
+    #[serde(default)]
+    pub resource_names: Vec<String>,
+}
+
+/// A Kubernetes label selector.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelSelector {
+    /// Labels that must be present with these values.
+    #[serde(default)]
+    pub match_labels: BTreeMap<String, String>,
+    /// Further requirements, all of which must hold.
+    #[serde(default)]
+    pub match_expressions: Vec<LabelSelectorRequirement>,
+}
+
+/// A requirement of a [`LabelSelector`].
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct LabelSelectorRequirement {
+    /// The label key.
+    pub key: String,
+    /// `In`, `NotIn`, `Exists`, or `DoesNotExist`.
+    pub operator: String,
+    /// The values of `In` and `NotIn`.
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl LabelSelector {
+    /// Whether `labels` satisfy the selector. An empty selector matches
+    /// everything; an unknown operator matches nothing.
+    #[must_use]
+    pub fn matches(&self, labels: &BTreeMap<String, String>) -> bool {
+        self.match_labels
+            .iter()
+            .all(|(key, value)| labels.get(key) == Some(value))
+            && self.match_expressions.iter().all(|requirement| {
+                let value = labels.get(&requirement.key);
+                match requirement.operator.as_str() {
+                    "In" => value.is_some_and(|v| requirement.values.contains(v)),
+                    "NotIn" => value.is_none_or(|v| !requirement.values.contains(v)),
+                    "Exists" => value.is_some(),
+                    "DoesNotExist" => value.is_none(),
+                    _ => false,
+                }
+            })
+    }
+}
+
+impl MatchResources {
+    /// Whether the policy applies to `request`.
+    ///
+    /// Resource rules are matched against the `resource`, `subResource`,
+    /// `operation`, `namespace`, and `name` of [`PolicyRequest::request`].
+    /// The object selector matches if either `object` or `old_object` has
+    /// matching labels. The namespace selector is skipped for cluster-scoped
+    /// requests, and matches a `Namespace` object by its own labels.
+    #[must_use]
+    pub fn matches(&self, request: &PolicyRequest<'_>) -> bool {
+        let attributes = RequestAttributes::new(request.request);
+        if !self.resource_rules.iter().any(|r| r.matches(&attributes))
+            || self
+                .exclude_resource_rules
+                .iter()
+                .any(|r| r.matches(&attributes))
+        {
+            return false;
+        }
+        if let Some(selector) = &self.object_selector {
+            let object = labels(request.object);
+            let old_object = labels(request.old_object);
+            if !selector.matches(&object) && !selector.matches(&old_object) {
+                return false;
+            }
+        }
+        if let Some(selector) = &self.namespace_selector {
+            let namespace = if attributes.group.is_empty() && attributes.resource == "namespaces" {
+                Some(labels(request.object.or(request.old_object)))
+            } else if !attributes.namespace.is_empty() {
+                Some(labels(request.namespace_object))
+            } else {
+                None
+            };
+            if namespace.is_some_and(|labels| !selector.matches(&labels)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The attributes of an `AdmissionRequest` that resource rules match.
+#[derive(Default)]
+struct RequestAttributes<'a> {
+    group: &'a str,
+    version: &'a str,
+    resource: &'a str,
+    subresource: &'a str,
+    operation: &'a str,
+    namespace: &'a str,
+    name: &'a str,
+}
+
+impl<'a> RequestAttributes<'a> {
+    fn new(request: Option<&'a serde_json::Value>) -> Self {
+        let Some(request) = request else {
+            return Self::default();
+        };
+        let field = |pointer: &str| {
+            request
+                .pointer(pointer)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+        };
+        Self {
+            group: field("/resource/group"),
+            version: field("/resource/version"),
+            resource: field("/resource/resource"),
+            subresource: field("/subResource"),
+            operation: field("/operation"),
+            namespace: field("/namespace"),
+            name: field("/name"),
+        }
+    }
+}
+
+impl NamedRuleWithOperations {
+    fn matches(&self, attributes: &RequestAttributes<'_>) -> bool {
+        let listed = |values: &[String], value: &str| values.iter().any(|v| v == "*" || v == value);
+        listed(&self.operations, attributes.operation)
+            && listed(&self.api_groups, attributes.group)
+            && listed(&self.api_versions, attributes.version)
+            && self.resources.iter().any(|r| {
+                let (resource, subresource) = r.split_once('/').unwrap_or((r, ""));
+                (resource == "*" || resource == attributes.resource)
+                    && (subresource == "*" || subresource == attributes.subresource)
+            })
+            && match self.scope.as_deref() {
+                Some("Cluster") => attributes.namespace.is_empty(),
+                Some("Namespaced") => !attributes.namespace.is_empty(),
+                _ => true,
+            }
+            && (self.resource_names.is_empty()
+                || self.resource_names.iter().any(|n| n == attributes.name))
+    }
+}
+
+/// The `metadata.labels` of `object`.
+fn labels(object: Option<&serde_json::Value>) -> BTreeMap<String, String> {
+    object
+        .and_then(|o| o.pointer("/metadata/labels"))
+        .and_then(|l| l.as_object())
+        .map(|labels| {
+            labels
+                .iter()
+                .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn constraints(value: serde_json::Value) -> MatchResources {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn request(operation: &str, resource: &str, namespace: &str) -> serde_json::Value {
+        let (resource, subresource) = resource.split_once('/').unwrap_or((resource, ""));
+        json!({
+            "operation": operation,
+            "resource": {"group": "apps", "version": "v1", "resource": resource},
+            "subResource": subresource,
+            "namespace": namespace,
+            "name": "web"
+        })
+    }
+
+    fn matches(constraints: &MatchResources, request: &serde_json::Value) -> bool {
+        constraints.matches(&PolicyRequest {
+            request: Some(request),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn resource_rules() {
+        let c = constraints(json!({
+            "resourceRules": [{
+                "apiGroups": ["apps"],
+                "apiVersions": ["*"],
+                "operations": ["CREATE", "UPDATE"],
+                "resources": ["deployments", "*/scale"],
+                "scope": "Namespaced"
+            }],
+            "excludeResourceRules": [{
+                "apiGroups": ["*"],
+                "apiVersions": ["*"],
+                "operations": ["*"],
+                "resources": ["deployments"],
+                "resourceNames": ["legacy"]
+            }]
+        }));
+        assert!(matches(&c, &request("CREATE", "deployments", "default")));
+        assert!(matches(
+            &c,
+            &request("UPDATE", "statefulsets/scale", "default")
+        ));
+        assert!(!matches(&c, &request("DELETE", "deployments", "default")));
+        assert!(!matches(
+            &c,
+            &request("CREATE", "deployments/status", "default")
+        ));
+        assert!(!matches(&c, &request("CREATE", "deployments", "")));
+
+        let mut legacy = request("CREATE", "deployments", "default");
+        legacy["name"] = json!("legacy");
+        assert!(!matches(&c, &legacy));
+        assert!(!matches(&MatchResources::default(), &legacy));
+    }
+
+    #[test]
+    fn label_selectors() {
+        let selector: LabelSelector = serde_json::from_value(json!({
+            "matchLabels": {"team": "core"},
+            "matchExpressions": [
+                {"key": "tier", "operator": "In", "values": ["web", "api"]},
+                {"key": "legacy", "operator": "DoesNotExist"},
+                {"key": "env", "operator": "NotIn", "values": ["dev"]}
+            ]
+        }))
+        .unwrap();
+        let labels = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        assert!(selector.matches(&labels(&[("team", "core"), ("tier", "web")])));
+        assert!(!selector.matches(&labels(&[("team", "core"), ("tier", "db")])));
+        assert!(!selector.matches(&labels(&[
+            ("team", "core"),
+            ("tier", "web"),
+            ("env", "dev")
+        ])));
+        assert!(!selector.matches(&labels(&[
+            ("team", "core"),
+            ("tier", "web"),
+            ("legacy", "")
+        ])));
+        assert!(LabelSelector::default().matches(&BTreeMap::new()));
+    }
+
+    #[test]
+    fn object_and_namespace_selectors() {
+        let c = constraints(json!({
+            "resourceRules": [{"apiGroups": ["*"], "apiVersions": ["*"], "operations": ["*"], "resources": ["*"]}],
+            "objectSelector": {"matchLabels": {"managed": "true"}},
+            "namespaceSelector": {"matchExpressions": [{"key": "env", "operator": "In", "values": ["prod"]}]}
+        }));
+        let managed = json!({"metadata": {"labels": {"managed": "true"}}});
+        let unmanaged = json!({"metadata": {}});
+        let prod = json!({"metadata": {"labels": {"env": "prod"}}});
+        let req = request("UPDATE", "deployments", "shop");
+        let matches = |object, old_object, namespace_object| {
+            c.matches(&PolicyRequest {
+                object,
+                old_object,
+                request: Some(&req),
+                namespace_object,
+                ..Default::default()
+            })
+        };
+        assert!(matches(Some(&managed), None, Some(&prod)));
+        assert!(matches(Some(&unmanaged), Some(&managed), Some(&prod)));
+        assert!(!matches(Some(&unmanaged), None, Some(&prod)));
+        assert!(!matches(Some(&managed), None, Some(&unmanaged)));
+
+        // Cluster-scoped requests skip the namespace selector.
+        let cluster = request("CREATE", "clusterroles", "");
+        assert!(c.matches(&PolicyRequest {
+            object: Some(&managed),
+            request: Some(&cluster),
+            ..Default::default()
+        }));
+    }
+}
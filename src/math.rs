@@ -317,6 +317,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_abs_float() {
         assert_eq!(eval("math.abs(-3.14)"), Value::Float(3.14));
         assert_eq!(eval("math.abs(3.14)"), Value::Float(3.14));
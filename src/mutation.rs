@@ -0,0 +1,932 @@
+//! Evaluation of `MutatingAdmissionPolicy` mutations.
+//!
+//! A [`CompiledMutatingPolicy`] holds the compiled `spec` of a
+//! `MutatingAdmissionPolicy`; [`Validator::mutate_policy`] applies its
+//! mutations to the object of one admission request:
+//!
+//! ```
+//! use kube_cel::mutation::CompiledMutatingPolicy;
+//! use kube_cel::policy::PolicyRequest;
+//! use kube_cel::validation::Validator;
+//! use serde_json::json;
+//!
+//! let policy = CompiledMutatingPolicy::from_manifest(&json!({
+//!     "apiVersion": "admissionregistration.k8s.io/v1beta1",
+//!     "kind": "MutatingAdmissionPolicy",
+//!     "spec": {
+//!         "mutations": [{
+//!             "patchType": "ApplyConfiguration",
+//!             "applyConfiguration": {
+//!                 "expression": "Object{metadata: Object.metadata{labels: {'team': 'core'}}}"
+//!             }
+//!         }]
+//!     }
+//! }))
+//! .unwrap();
+//! let object = json!({"metadata": {"name": "web", "labels": {"app": "web"}}});
+//! let request = PolicyRequest { object: Some(&object), ..Default::default() };
+//! let result = Validator::new().mutate_policy(&policy, &request);
+//! assert_eq!(result.object["metadata"]["labels"], json!({"app": "web", "team": "core"}));
+//! ```
+//!
+//! [`Validator::mutate_policy`]: crate::validation::Validator::mutate_policy
+
+use cel::Context;
+use serde::Deserialize;
+
+use crate::compilation::{ListType, additional_properties_schema};
+use crate::matching::MatchResources;
+use crate::policy::{
+    CompiledExpression, CompiledVariable, FailurePolicy, MatchCondition, ParamKind, PolicyError,
+    PolicyRequest, PolicyViolation, RequestScope, Variable, compile_expression,
+    compile_match_conditions, compile_variables,
+};
+use crate::validation::ErrorKind;
+use crate::values::try_cel_to_json;
+
+/// The `spec` of a `MutatingAdmissionPolicy`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MutatingPolicySpec {
+    /// Named sub-expressions, available to later variables and to
+    /// mutations as `variables.<name>`.
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+    /// The changes applied to a matching object, in order.
+    #[serde(default)]
+    pub mutations: Vec<Mutation>,
+    /// Conditions a request must meet for the policy to apply at all.
+    #[serde(default)]
+    pub match_conditions: Vec<MatchCondition>,
+    /// How expression errors are handled.
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// The kind of the parameter resources bindings refer to, if the policy
+    /// is parameterized.
+    #[serde(default)]
+    pub param_kind: Option<ParamKind>,
+    /// The requests the policy applies to, by resource, operation, and
+    /// labels. Without constraints the policy applies to every request.
+    #[serde(default)]
+    pub match_constraints: Option<MatchResources>,
+}
+
+/// A single change from `spec.mutations`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mutation {
+    /// How the expression's result is applied to the object.
+    pub patch_type: PatchType,
+    /// The expression of an `ApplyConfiguration` mutation.
+    #[serde(default)]
+    pub apply_configuration: Option<ApplyConfiguration>,
+    /// The expression of a `JSONPatch` mutation.
+    #[serde(default)]
+    pub json_patch: Option<JsonPatch>,
+}
+
+/// The `patchType` of a mutation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum PatchType {
+    /// The expression returns a partial object that is merged into the
+    /// object, see [`merge_apply_configuration`].
+    ApplyConfiguration,
+    /// The expression returns a list of `JSONPatch{op, path, from, value}`
+    /// operations applied to the object, see [`apply_json_patch`].
+    #[serde(rename = "JSONPatch")]
+    JsonPatch,
+}
+
+/// The `applyConfiguration` of a mutation.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ApplyConfiguration {
+    /// A CEL expression returning the partial object to apply.
+    pub expression: String,
+}
+
+/// The `jsonPatch` of a mutation.
+#[derive(Clone, Debug, Deserialize)]
+pub struct JsonPatch {
+    /// A CEL expression returning a list of `JSONPatch` operations.
+    pub expression: String,
+}
+
+/// A mutating policy with every expression compiled, ready to mutate many
+/// objects.
+#[derive(Debug)]
+pub struct CompiledMutatingPolicy {
+    variables: Vec<CompiledVariable>,
+    mutations: Vec<CompiledMutation>,
+    match_conditions: Vec<(String, CompiledExpression)>,
+    failure_policy: FailurePolicy,
+    param_kind: Option<ParamKind>,
+    match_constraints: Option<MatchResources>,
+    schema: Option<serde_json::Value>,
+}
+
+#[derive(Debug)]
+enum CompiledMutation {
+    ApplyConfiguration(CompiledExpression),
+    JsonPatch(CompiledExpression),
+}
+
+impl CompiledMutatingPolicy {
+    /// Compile a `MutatingAdmissionPolicy` manifest, or just its `spec`.
+    pub fn from_manifest(policy: &serde_json::Value) -> Result<Self, PolicyError> {
+        let spec = policy.get("spec").unwrap_or(policy);
+        let spec = MutatingPolicySpec::deserialize(spec).map_err(|e| PolicyError::InvalidSpec {
+            message: e.to_string(),
+        })?;
+        Self::compile(&spec)
+    }
+
+    /// Compile every expression of `spec`.
+    pub fn compile(spec: &MutatingPolicySpec) -> Result<Self, PolicyError> {
+        let variables = compile_variables(&spec.variables)?;
+        let mutations =
+            spec.mutations
+                .iter()
+                .enumerate()
+                .map(|(i, mutation)| {
+                    let missing = |field: &str, patch_type: &str| PolicyError::InvalidSpec {
+                        message: format!(
+                            "mutations[{i}]: {field} is required for patchType {patch_type}"
+                        ),
+                    };
+                    match mutation.patch_type {
+                        PatchType::ApplyConfiguration => {
+                            let apply = mutation.apply_configuration.as_ref().ok_or_else(|| {
+                                missing("applyConfiguration", "ApplyConfiguration")
+                            })?;
+                            let expression = compile_expression(&apply.expression, &variables)?;
+                            Ok(CompiledMutation::ApplyConfiguration(expression))
+                        }
+                        PatchType::JsonPatch => {
+                            let patch = mutation
+                                .json_patch
+                                .as_ref()
+                                .ok_or_else(|| missing("jsonPatch", "JSONPatch"))?;
+                            let expression = compile_expression(&patch.expression, &variables)?;
+                            Ok(CompiledMutation::JsonPatch(expression))
+                        }
+                    }
+                })
+                .collect::<Result<_, PolicyError>>()?;
+        Ok(Self {
+            variables,
+            mutations,
+            match_conditions: compile_match_conditions(&spec.match_conditions)?,
+            failure_policy: spec.failure_policy,
+            param_kind: spec.param_kind.clone(),
+            match_constraints: spec.match_constraints.clone(),
+            schema: None,
+        })
+    }
+
+    /// Use the OpenAPI schema of the mutated kind when merging apply
+    /// configurations, so that `x-kubernetes-list-type: map` and `set`
+    /// lists are merged item by item rather than replaced.
+    ///
+    /// The fields set by `Object` constructors are checked against the
+    /// schema; a field it does not declare is a [`PolicyError::Compile`].
+    pub fn with_schema(mut self, schema: serde_json::Value) -> Result<Self, PolicyError> {
+        self.variables
+            .iter()
+            .map(|v| &v.expression)
+            .chain(self.mutations.iter().map(|mutation| match mutation {
+                CompiledMutation::ApplyConfiguration(expression)
+                | CompiledMutation::JsonPatch(expression) => expression,
+            }))
+            .chain(
+                self.match_conditions
+                    .iter()
+                    .map(|(_, expression)| expression),
+            )
+            .try_for_each(|expression| expression.check_schema(&schema))?;
+        self.schema = Some(schema);
+        Ok(self)
+    }
+
+    /// The declared variable names, in order.
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables.iter().map(|v| v.name.as_str())
+    }
+
+    /// The kind of parameter resource the policy expects as
+    /// [`PolicyRequest::params`], if it is parameterized.
+    #[must_use]
+    pub fn param_kind(&self) -> Option<&ParamKind> {
+        self.param_kind.as_ref()
+    }
+
+    /// The policy's `matchConstraints`, if any.
+    #[must_use]
+    pub fn match_constraints(&self) -> Option<&MatchResources> {
+        self.match_constraints.as_ref()
+    }
+
+    /// The policy's failure policy.
+    #[must_use]
+    pub fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    /// Check the match constraints and conditions against `request`, then
+    /// apply each mutation in turn. Every mutation sees the object as
+    /// changed by the ones before it.
+    pub(crate) fn evaluate(
+        &self,
+        base: &Context<'_>,
+        request: &PolicyRequest<'_>,
+    ) -> MutationResult {
+        let mut object = request.object.cloned().unwrap_or_default();
+        if self
+            .match_constraints
+            .as_ref()
+            .is_some_and(|c| !c.matches(request))
+        {
+            return MutationResult {
+                matched: false,
+                object,
+                violations: Vec::new(),
+            };
+        }
+        {
+            let mut ctx = base.new_inner_scope();
+            request.bind(&mut ctx);
+            if let Err(failure) =
+                RequestScope::new(&self.variables, &ctx).matches(&self.match_conditions)
+            {
+                let violations = match (failure, self.failure_policy) {
+                    (Some(error), FailurePolicy::Fail) => vec![error],
+                    _ => Vec::new(),
+                };
+                return MutationResult {
+                    matched: false,
+                    object,
+                    violations,
+                };
+            }
+        }
+        let mut violations = Vec::new();
+        for mutation in &self.mutations {
+            let mut ctx = base.new_inner_scope();
+            PolicyRequest {
+                object: Some(&object),
+                ..*request
+            }
+            .bind(&mut ctx);
+            let scope = RequestScope::new(&self.variables, &ctx);
+            let outcome = match mutation {
+                CompiledMutation::ApplyConfiguration(expression) => {
+                    apply_configuration(&scope, expression).map(|patch| {
+                        merge_apply_configuration(&mut object, patch, self.schema.as_ref());
+                    })
+                }
+                CompiledMutation::JsonPatch(expression) => {
+                    json_patch(&scope, expression).and_then(|patch| {
+                        apply_json_patch(&mut object, &patch).map_err(|e| PolicyViolation {
+                            expression: expression.source.clone(),
+                            message: format!("jsonPatch '{}' {e}", expression.source),
+                            reason: "Invalid".to_string(),
+                            kind: ErrorKind::EvaluationError,
+                        })
+                    })
+                }
+            };
+            match outcome {
+                Ok(()) => {}
+                Err(error) if self.failure_policy == FailurePolicy::Fail => {
+                    violations.push(error);
+                    break;
+                }
+                Err(_) => {}
+            }
+        }
+        MutationResult {
+            matched: true,
+            object,
+            violations,
+        }
+    }
+}
+
+/// Evaluate an `ApplyConfiguration` expression to the object it applies.
+fn apply_configuration(
+    scope: &RequestScope<'_>,
+    expression: &CompiledExpression,
+) -> Result<serde_json::Value, PolicyViolation> {
+    let (message, kind) = match scope.execute(expression) {
+        Ok(value @ cel::Value::Map(_)) => match try_cel_to_json(&value) {
+            Ok(patch) => return Ok(patch),
+            Err(e) => (
+                format!(
+                    "applyConfiguration '{}' is not valid JSON: {e}",
+                    expression.source
+                ),
+                ErrorKind::InvalidResult,
+            ),
+        },
+        Ok(_) => (
+            format!(
+                "applyConfiguration '{}' did not evaluate to an object",
+                expression.source
+            ),
+            ErrorKind::InvalidResult,
+        ),
+        Err(e) => (
+            format!(
+                "applyConfiguration '{}' resulted in error: {e}",
+                expression.source
+            ),
+            ErrorKind::EvaluationError,
+        ),
+    };
+    Err(PolicyViolation {
+        expression: expression.source.clone(),
+        message,
+        reason: "Invalid".to_string(),
+        kind,
+    })
+}
+
+/// Evaluate a `JSONPatch` expression to the operations it applies.
+fn json_patch(
+    scope: &RequestScope<'_>,
+    expression: &CompiledExpression,
+) -> Result<Vec<serde_json::Value>, PolicyViolation> {
+    let (message, kind) = match scope.execute(expression) {
+        Ok(value @ cel::Value::List(_)) => match try_cel_to_json(&value) {
+            Ok(serde_json::Value::Array(patch)) => return Ok(patch),
+            Ok(_) => unreachable!("lists convert to arrays"),
+            Err(e) => (
+                format!("jsonPatch '{}' is not valid JSON: {e}", expression.source),
+                ErrorKind::InvalidResult,
+            ),
+        },
+        Ok(_) => (
+            format!(
+                "jsonPatch '{}' did not evaluate to a list of JSONPatch",
+                expression.source
+            ),
+            ErrorKind::InvalidResult,
+        ),
+        Err(e) => (
+            format!("jsonPatch '{}' resulted in error: {e}", expression.source),
+            ErrorKind::EvaluationError,
+        ),
+    };
+    Err(PolicyViolation {
+        expression: expression.source.clone(),
+        message,
+        reason: "Invalid".to_string(),
+        kind,
+    })
+}
+
+/// Merge an apply configuration into `object`, following server-side apply.
+///
+/// Objects are merged field by field and scalars, including `null`, are
+/// replaced. Lists are replaced as a whole unless `schema` declares them
+/// `x-kubernetes-list-type: map`, whose items are merged by their
+/// `x-kubernetes-list-map-keys`, or `set`, which gains the missing items.
+pub fn merge_apply_configuration(
+    object: &mut serde_json::Value,
+    patch: serde_json::Value,
+    schema: Option<&serde_json::Value>,
+) {
+    let list_type = schema.map(ListType::from_schema).unwrap_or_default();
+    match (object, patch) {
+        (serde_json::Value::Object(object), serde_json::Value::Object(patch)) => {
+            for (key, value) in patch {
+                let field_schema = schema.and_then(|s| {
+                    s.get("properties")
+                        .and_then(|p| p.get(&key))
+                        .or_else(|| additional_properties_schema(s))
+                });
+                match object.get_mut(&key) {
+                    Some(existing) => merge_apply_configuration(existing, value, field_schema),
+                    None => {
+                        object.insert(key, value);
+                    }
+                }
+            }
+        }
+        (serde_json::Value::Array(items), serde_json::Value::Array(patch))
+            if list_type != ListType::Unspecified =>
+        {
+            let item_schema = schema.and_then(|s| s.get("items"));
+            match list_type {
+                ListType::Map { keys } => {
+                    for item in patch {
+                        let same_key = |existing: &&mut serde_json::Value| {
+                            keys.iter().all(|k| existing.get(k) == item.get(k))
+                        };
+                        match items.iter_mut().find(same_key) {
+                            Some(existing) => {
+                                merge_apply_configuration(existing, item, item_schema);
+                            }
+                            None => items.push(item),
+                        }
+                    }
+                }
+                ListType::Set => {
+                    for item in patch {
+                        if !items.contains(&item) {
+                            items.push(item);
+                        }
+                    }
+                }
+                ListType::Atomic | ListType::Unspecified => *items = patch,
+            }
+        }
+        (object, patch) => *object = patch,
+    }
+}
+
+/// An RFC 6902 JSON Patch operation that could not be applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPatchError {
+    /// The index of the failing operation in the patch.
+    pub index: usize,
+    /// Why it failed.
+    pub message: String,
+}
+
+impl std::fmt::Display for JsonPatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation {} failed: {}", self.index, self.message)
+    }
+}
+
+impl std::error::Error for JsonPatchError {}
+
+/// Apply RFC 6902 JSON Patch operations to `object`.
+///
+/// Each operation is an object with an `op` (`add`, `remove`, `replace`,
+/// `move`, `copy` or `test`), a JSON Pointer `path`, and a `value` or
+/// `from` as the op requires. The patch is atomic: if any operation fails,
+/// `object` is left unchanged.
+pub fn apply_json_patch(
+    object: &mut serde_json::Value,
+    patch: &[serde_json::Value],
+) -> Result<(), JsonPatchError> {
+    let mut patched = object.clone();
+    for (index, operation) in patch.iter().enumerate() {
+        apply_operation(&mut patched, operation)
+            .map_err(|message| JsonPatchError { index, message })?;
+    }
+    *object = patched;
+    Ok(())
+}
+
+fn apply_operation(
+    object: &mut serde_json::Value,
+    operation: &serde_json::Value,
+) -> Result<(), String> {
+    let field = |name: &str| {
+        operation
+            .get(name)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| format!("missing string field '{name}'"))
+    };
+    let value = || {
+        operation
+            .get("value")
+            .cloned()
+            .ok_or_else(|| "missing field 'value'".to_string())
+    };
+    let path = parse_pointer(field("path")?)?;
+    match field("op")? {
+        "add" => add(object, &path, value()?),
+        "remove" => remove(object, &path).map(drop),
+        "replace" => {
+            let target = resolve_mut(object, &path)?;
+            *target = value()?;
+            Ok(())
+        }
+        "move" => {
+            let from = parse_pointer(field("from")?)?;
+            if path.len() > from.len() && path.starts_with(&from) {
+                return Err("cannot move a value into one of its children".to_string());
+            }
+            let moved = remove(object, &from)?;
+            add(object, &path, moved)
+        }
+        "copy" => {
+            let from = parse_pointer(field("from")?)?;
+            let copied = resolve_mut(object, &from)?.clone();
+            add(object, &path, copied)
+        }
+        "test" => {
+            if *resolve_mut(object, &path)? == value()? {
+                Ok(())
+            } else {
+                Err(format!("test of {} failed", field("path")?))
+            }
+        }
+        op => Err(format!("unknown op '{op}'")),
+    }
+}
+
+/// Split an RFC 6901 JSON Pointer into its unescaped reference tokens.
+fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rest = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| format!("path {pointer:?} must start with '/'"))?;
+    Ok(rest
+        .split('/')
+        .map(|token| token.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn resolve_mut<'a>(
+    mut value: &'a mut serde_json::Value,
+    path: &[String],
+) -> Result<&'a mut serde_json::Value, String> {
+    for token in path {
+        value = match value {
+            serde_json::Value::Object(map) => map.get_mut(token),
+            serde_json::Value::Array(items) => {
+                token.parse::<usize>().ok().and_then(|i| items.get_mut(i))
+            }
+            _ => None,
+        }
+        .ok_or_else(|| format!("path /{} does not exist", path.join("/")))?;
+    }
+    Ok(value)
+}
+
+fn add(
+    object: &mut serde_json::Value,
+    path: &[String],
+    value: serde_json::Value,
+) -> Result<(), String> {
+    let Some((last, parent)) = path.split_last() else {
+        *object = value;
+        return Ok(());
+    };
+    match resolve_mut(object, parent)? {
+        serde_json::Value::Object(map) => {
+            map.insert(last.clone(), value);
+        }
+        serde_json::Value::Array(items) if last == "-" => items.push(value),
+        serde_json::Value::Array(items) => match last.parse::<usize>() {
+            Ok(i) if i <= items.len() => items.insert(i, value),
+            _ => return Err(format!("invalid array index {last:?}")),
+        },
+        _ => return Err(format!("cannot add to /{}", parent.join("/"))),
+    }
+    Ok(())
+}
+
+fn remove(object: &mut serde_json::Value, path: &[String]) -> Result<serde_json::Value, String> {
+    let (last, parent) = path
+        .split_last()
+        .ok_or_else(|| "cannot remove the whole object".to_string())?;
+    let removed = match resolve_mut(object, parent)? {
+        serde_json::Value::Object(map) => map.remove(last),
+        serde_json::Value::Array(items) => last
+            .parse::<usize>()
+            .ok()
+            .filter(|&i| i < items.len())
+            .map(|i| items.remove(i)),
+        _ => None,
+    };
+    removed.ok_or_else(|| format!("path /{} does not exist", path.join("/")))
+}
+
+/// The outcome of applying a mutating policy to one request.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MutationResult {
+    /// Whether every match condition held, so the mutations ran.
+    pub matched: bool,
+    /// The mutated object; unchanged if the policy did not match. If a
+    /// mutation failed, the object as changed by the mutations before it.
+    pub object: serde_json::Value,
+    /// The failed mutation or the match condition error that rejects the
+    /// request. Empty if the request is admitted.
+    pub violations: Vec<PolicyViolation>,
+}
+
+impl MutationResult {
+    /// Whether the request is admitted.
+    #[must_use]
+    pub fn allowed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Validator;
+    use serde_json::json;
+
+    fn mutate(spec: serde_json::Value, object: serde_json::Value) -> MutationResult {
+        let policy = CompiledMutatingPolicy::from_manifest(&spec).unwrap();
+        let request = PolicyRequest {
+            object: Some(&object),
+            ..Default::default()
+        };
+        Validator::new().mutate_policy(&policy, &request)
+    }
+
+    fn apply(expression: &str) -> serde_json::Value {
+        json!({"patchType": "ApplyConfiguration", "applyConfiguration": {"expression": expression}})
+    }
+
+    #[test]
+    fn mutations_apply_in_order() {
+        let spec = json!({
+            "variables": [{"name": "team", "expression": "'core'"}],
+            "mutations": [
+                apply("{'metadata': {'labels': {'team': variables.team}}}"),
+                apply("{'spec': {'replicas': object.metadata.labels.team == 'core' ? 3 : 1}}"),
+                apply("{'metadata': {'annotations': object.metadata.labels}}")
+            ]
+        });
+        let object = json!({
+            "metadata": {"name": "web", "labels": {"app.kubernetes.io/name": "web"}},
+            "spec": {"replicas": 1, "paused": false}
+        });
+        let result = mutate(spec, object);
+        assert!(result.matched);
+        assert!(result.allowed());
+        let labels = json!({"app.kubernetes.io/name": "web", "team": "core"});
+        assert_eq!(
+            result.object,
+            json!({
+                "metadata": {"name": "web", "labels": labels, "annotations": labels},
+                "spec": {"replicas": 3, "paused": false}
+            })
+        );
+    }
+
+    #[test]
+    fn object_constructors_are_applied() {
+        let spec = json!({
+            "mutations": [apply(
+                "Object{metadata: Object.metadata{labels: {'app.kubernetes.io/part-of': 'shop'}}, \
+                 spec: Object.spec{template: Object.spec.template{spec: Object.spec.template.spec{priority: 10}}}}"
+            )]
+        });
+        let object = json!({"metadata": {"name": "web"}, "spec": {"replicas": 1}});
+        let result = mutate(spec, object);
+        assert!(result.allowed(), "{:?}", result.violations);
+        assert_eq!(
+            result.object,
+            json!({
+                "metadata": {"name": "web", "labels": {"app.kubernetes.io/part-of": "shop"}},
+                "spec": {"replicas": 1, "template": {"spec": {"priority": 10}}}
+            })
+        );
+    }
+
+    #[test]
+    fn schema_list_types_merge_items() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "containers": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "map",
+                    "x-kubernetes-list-map-keys": ["name"],
+                    "items": {"type": "object"}
+                },
+                "finalizers": {"type": "array", "x-kubernetes-list-type": "set"},
+                "args": {"type": "array"}
+            }
+        });
+        let mut object = json!({
+            "containers": [{"name": "app", "image": "app:1"}, {"name": "proxy", "image": "proxy:1"}],
+            "finalizers": ["a"],
+            "args": ["--x"]
+        });
+        merge_apply_configuration(
+            &mut object,
+            json!({
+                "containers": [{"name": "proxy", "image": "proxy:2"}, {"name": "log", "image": "log:1"}],
+                "finalizers": ["a", "b"],
+                "args": ["--y"]
+            }),
+            Some(&schema),
+        );
+        assert_eq!(
+            object,
+            json!({
+                "containers": [
+                    {"name": "app", "image": "app:1"},
+                    {"name": "proxy", "image": "proxy:2"},
+                    {"name": "log", "image": "log:1"}
+                ],
+                "finalizers": ["a", "b"],
+                "args": ["--y"]
+            })
+        );
+    }
+
+    #[test]
+    fn failed_mutations_follow_the_failure_policy() {
+        let mutations = json!([
+            apply("{'spec': {'replicas': 2}}"),
+            apply("'not an object'"),
+            apply("{'spec': {'paused': true}}")
+        ]);
+        let object = json!({"spec": {"replicas": 1}});
+
+        let result = mutate(json!({"mutations": mutations}), object.clone());
+        assert_eq!(result.violations.len(), 1);
+        assert_eq!(result.violations[0].kind, ErrorKind::InvalidResult);
+        assert_eq!(result.object, json!({"spec": {"replicas": 2}}));
+
+        let result = mutate(
+            json!({"failurePolicy": "Ignore", "mutations": mutations}),
+            object,
+        );
+        assert!(result.allowed());
+        assert_eq!(
+            result.object,
+            json!({"spec": {"replicas": 2, "paused": true}})
+        );
+    }
+
+    #[test]
+    fn unmatched_policies_leave_the_object_alone() {
+        let spec = json!({
+            "matchConditions": [{"name": "is-deployment", "expression": "object.kind == 'Deployment'"}],
+            "mutations": [apply("{'spec': {'replicas': 2}}")]
+        });
+        let object = json!({"kind": "Pod", "spec": {}});
+        let result = mutate(spec, object.clone());
+        assert!(!result.matched);
+        assert_eq!(result.object, object);
+    }
+
+    #[test]
+    fn json_patch_mutations() {
+        let spec = json!({
+            "mutations": [{
+                "patchType": "JSONPatch",
+                "jsonPatch": {"expression": r#"[
+                    JSONPatch{op: "add", path: "/metadata/labels/example.com~1team", value: "core"},
+                    JSONPatch{op: "replace", path: "/spec/replicas", value: object.spec.replicas + 1},
+                    JSONPatch{op: "add", path: "/spec/args/-", value: "--verbose"},
+                    JSONPatch{op: "remove", path: "/spec/paused"}
+                ]"#}
+            }]
+        });
+        let object = json!({
+            "metadata": {"labels": {"app": "web"}},
+            "spec": {"replicas": 1, "paused": true, "args": ["--x"]}
+        });
+        let result = mutate(spec, object);
+        assert!(result.allowed(), "{:?}", result.violations);
+        assert_eq!(
+            result.object,
+            json!({
+                "metadata": {"labels": {"app": "web", "example.com/team": "core"}},
+                "spec": {"replicas": 2, "args": ["--x", "--verbose"]}
+            })
+        );
+
+        let spec = json!({
+            "mutations": [{
+                "patchType": "JSONPatch",
+                "jsonPatch": {"expression": "[JSONPatch{op: 'test', path: '/spec/replicas', value: 5}]"}
+            }]
+        });
+        let result = mutate(spec, json!({"spec": {"replicas": 1}}));
+        assert_eq!(result.violations[0].kind, ErrorKind::EvaluationError);
+        assert!(
+            result.violations[0]
+                .message
+                .ends_with("operation 0 failed: test of /spec/replicas failed")
+        );
+    }
+
+    #[test]
+    fn json_patch_operations() {
+        let mut object = json!({"a": {"b": [1, 2]}, "c~d": 1});
+        let patch = json!([
+            {"op": "copy", "from": "/a/b/0", "path": "/a/b/0"},
+            {"op": "move", "from": "/c~0d", "path": "/a/e"},
+            {"op": "add", "path": "/a/f~1g", "value": null},
+            {"op": "test", "path": "/a/b", "value": [1, 1, 2]}
+        ]);
+        apply_json_patch(&mut object, patch.as_array().unwrap()).unwrap();
+        assert_eq!(object, json!({"a": {"b": [1, 1, 2], "e": 1, "f/g": null}}));
+
+        let before = object.clone();
+        let error = apply_json_patch(
+            &mut object,
+            &[
+                json!({"op": "remove", "path": "/a/e"}),
+                json!({"op": "remove", "path": "/a/missing"}),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(error.index, 1);
+        assert_eq!(object, before);
+        assert!(
+            apply_json_patch(
+                &mut object,
+                &[json!({"op": "move", "from": "/a", "path": "/a/x"})]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn unknown_constructors_fail_to_compile() {
+        for expression in ["[Patch{op: 'add'}]", "[JSONPatch{op: 'add', paths: '/x'}]"] {
+            let spec = json!({
+                "mutations": [{"patchType": "JSONPatch", "jsonPatch": {"expression": expression}}]
+            });
+            assert!(matches!(
+                CompiledMutatingPolicy::from_manifest(&spec),
+                Err(PolicyError::Compile { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn object_constructors_are_checked_against_the_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "apiVersion": {"type": "string"},
+                "metadata": {"type": "object"},
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {"type": "integer"},
+                        "containers": {
+                            "type": "array",
+                            "items": {"type": "object", "properties": {"name": {"type": "string"}}}
+                        },
+                        "config": {"type": "object", "x-kubernetes-preserve-unknown-fields": true}
+                    }
+                }
+            }
+        });
+        let compile = |expression: &str| {
+            CompiledMutatingPolicy::from_manifest(&json!({"mutations": [apply(expression)]}))
+                .unwrap()
+                .with_schema(schema.clone())
+                .map(|_| ())
+        };
+        assert!(
+            compile(
+                "Object{metadata: Object.metadata{labels: {'a': 'b'}}, \
+                 spec: Object.spec{replicas: 3, containers: [Object.spec.containers{name: 'c'}], \
+                 config: Object.spec.config{anything: Object.spec.config.anything{at: 'all'}}}}"
+            )
+            .is_ok()
+        );
+        for (expression, message) in [
+            (
+                "Object{spec: Object.spec{replica: 3}}",
+                "type 'Object.spec' has no field 'replica'",
+            ),
+            (
+                "Object{spec: Object.spec{containers: [Object.spec.containers{image: 'x'}]}}",
+                "type 'Object.spec.containers' has no field 'image'",
+            ),
+            (
+                "Object{spec: Object.spec{replicas: Object.spec.replicas{}}}",
+                "type 'Object.spec.replicas' is not an object",
+            ),
+            (
+                "Object{status: Object.status{}}",
+                "type 'Object' has no field 'status'",
+            ),
+        ] {
+            assert_eq!(
+                compile(expression),
+                Err(PolicyError::Compile {
+                    expression: expression.into(),
+                    message: message.into()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn apply_configuration_is_required() {
+        let error = CompiledMutatingPolicy::from_manifest(&json!({
+            "mutations": [{"patchType": "ApplyConfiguration"}]
+        }))
+        .unwrap_err();
+        assert!(matches!(error, PolicyError::InvalidSpec { .. }), "{error}");
+        assert!(matches!(
+            CompiledMutatingPolicy::from_manifest(&json!({
+                "mutations": [{"patchType": "StrategicMerge"}]
+            })),
+            Err(PolicyError::InvalidSpec { .. })
+        ));
+    }
+}
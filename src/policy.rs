@@ -0,0 +1,1477 @@
+//! Evaluation of `ValidatingAdmissionPolicy` expressions.
+//!
+//! A [`CompiledPolicy`] holds the compiled `spec` of a
+//! `ValidatingAdmissionPolicy`; [`Validator::validate_policy`] evaluates it
+//! against one admission request:
+//!
+//! ```
+//! use kube_cel::policy::{CompiledPolicy, PolicyRequest};
+//! use kube_cel::validation::Validator;
+//! use serde_json::json;
+//!
+//! let policy = CompiledPolicy::from_manifest(&json!({
+//!     "apiVersion": "admissionregistration.k8s.io/v1",
+//!     "kind": "ValidatingAdmissionPolicy",
+//!     "spec": {
+//!         "variables": [{"name": "replicas", "expression": "object.spec.replicas"}],
+//!         "validations": [{"expression": "variables.replicas <= 5", "message": "too many replicas"}]
+//!     }
+//! }))
+//! .unwrap();
+//! let object = json!({"spec": {"replicas": 10}});
+//! let request = PolicyRequest { object: Some(&object), ..Default::default() };
+//! let result = Validator::new().validate_policy(&policy, &request);
+//! assert_eq!(result.violations[0].message, "too many replicas");
+//! ```
+//!
+//! [`Validator::validate_policy`]: crate::validation::Validator::validate_policy
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, OnceLock};
+
+use cel::common::ast::{EntryExpr, Expr, IdedEntryExpr, MapEntryExpr, MapExpr, operators};
+use cel::common::value::CelVal;
+use cel::context::VariableResolver;
+use cel::objects::{Key, Map};
+use cel::parser::Expression;
+use cel::{Context, ExecutionError, Program};
+use serde::Deserialize;
+
+use crate::compilation::{additional_properties_schema, preserves_unknown_fields};
+use crate::matching::MatchResources;
+use crate::validation::{ErrorKind, check_evaluated_message};
+use crate::values::json_to_cel;
+
+/// The CEL variable holding the policy's `variables`.
+const VARIABLES: &str = "variables";
+
+/// The type of the patch operations a `JSONPatch` mutation returns.
+const JSON_PATCH: &str = "JSONPatch";
+
+/// The type of the request object; `Object.<field>...` names the types of
+/// its nested objects.
+const OBJECT: &str = "Object";
+
+/// The longest audit annotation value, in bytes, matching the apiserver.
+/// Longer values are truncated.
+pub const MAX_AUDIT_ANNOTATION_VALUE_LENGTH: usize = 10 * 1024;
+
+/// The `spec` of a `ValidatingAdmissionPolicy`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicySpec {
+    /// Named sub-expressions, available to later variables and to
+    /// validations as `variables.<name>`.
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+    /// The checks an admitted object must pass.
+    #[serde(default)]
+    pub validations: Vec<PolicyValidation>,
+    /// Conditions a request must meet for the policy to apply at all.
+    #[serde(default)]
+    pub match_conditions: Vec<MatchCondition>,
+    /// How expression errors are handled.
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// Annotations recorded in the audit event of matching requests.
+    #[serde(default)]
+    pub audit_annotations: Vec<AuditAnnotation>,
+    /// The kind of the parameter resources bindings refer to, if the policy
+    /// is parameterized.
+    #[serde(default)]
+    pub param_kind: Option<ParamKind>,
+    /// The requests the policy applies to, by resource, operation, and
+    /// labels. Without constraints the policy applies to every request.
+    #[serde(default)]
+    pub match_constraints: Option<MatchResources>,
+}
+
+/// The `spec.paramKind` of a parameterized policy.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParamKind {
+    /// The `apiVersion` of the parameter resources, e.g. `example.com/v1`.
+    pub api_version: String,
+    /// The `kind` of the parameter resources.
+    pub kind: String,
+}
+
+/// An annotation from `spec.auditAnnotations`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditAnnotation {
+    /// The annotation key, unique within the policy.
+    pub key: String,
+    /// A CEL expression returning the value as a `string`, or `null` to
+    /// omit the annotation.
+    pub value_expression: String,
+}
+
+/// A condition from `spec.matchConditions`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct MatchCondition {
+    /// Identifies the condition in errors.
+    pub name: String,
+    /// A CEL expression returning `bool`. Policy variables are not
+    /// available here.
+    pub expression: String,
+}
+
+/// What to do when an expression cannot be evaluated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum FailurePolicy {
+    /// Reject the request (the default).
+    #[default]
+    Fail,
+    /// Ignore the error: an erroring match condition makes the policy not
+    /// apply, and an erroring validation is dropped.
+    Ignore,
+}
+
+/// A named expression from `spec.variables`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Variable {
+    /// The name, referenced as `variables.<name>`.
+    pub name: String,
+    /// The CEL expression computing the value.
+    pub expression: String,
+}
+
+/// A single check from `spec.validations`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PolicyValidation {
+    /// The CEL expression; the request is rejected if it returns `false`.
+    pub expression: String,
+    /// Static message returned when the check fails.
+    #[serde(default)]
+    pub message: Option<String>,
+    /// CEL expression that produces the failure message.
+    #[serde(default)]
+    pub message_expression: Option<String>,
+    /// The `metav1.StatusReason` of a failure, e.g. `Forbidden`. Defaults
+    /// to `Invalid`.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Errors reading or compiling a policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The document has no `spec`, or its `spec` is malformed.
+    InvalidSpec {
+        /// Why the spec could not be read.
+        message: String,
+    },
+    /// Two variables share a name.
+    DuplicateVariable {
+        /// The repeated name.
+        name: String,
+    },
+    /// An expression failed to parse.
+    Compile {
+        /// The expression.
+        expression: String,
+        /// The parse error.
+        message: String,
+    },
+    /// An expression references a variable that is not declared before it.
+    UndeclaredVariable {
+        /// The expression.
+        expression: String,
+        /// The variable name.
+        name: String,
+    },
+    /// Two audit annotations share a key.
+    DuplicateAuditAnnotation {
+        /// The repeated key.
+        key: String,
+    },
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyError::InvalidSpec { message } => write!(f, "invalid policy spec: {message}"),
+            PolicyError::DuplicateVariable { name } => {
+                write!(f, "variable \"{name}\" is declared more than once")
+            }
+            PolicyError::Compile {
+                expression,
+                message,
+            } => write!(f, "failed to compile \"{expression}\": {message}"),
+            PolicyError::UndeclaredVariable { expression, name } => write!(
+                f,
+                "\"{expression}\" references undeclared variable \"{name}\""
+            ),
+            PolicyError::DuplicateAuditAnnotation { key } => {
+                write!(f, "audit annotation \"{key}\" is declared more than once")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// A policy with every expression compiled, ready to evaluate many requests.
+#[derive(Debug)]
+pub struct CompiledPolicy {
+    variables: Vec<CompiledVariable>,
+    validations: Vec<CompiledValidation>,
+    match_conditions: Vec<(String, CompiledExpression)>,
+    failure_policy: FailurePolicy,
+    audit_annotations: Vec<(String, CompiledExpression)>,
+    param_kind: Option<ParamKind>,
+    match_constraints: Option<MatchResources>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CompiledVariable {
+    pub(crate) name: String,
+    pub(crate) expression: CompiledExpression,
+}
+
+#[derive(Debug)]
+struct CompiledValidation {
+    validation: PolicyValidation,
+    expression: CompiledExpression,
+    message_expression: Option<CompiledExpression>,
+}
+
+/// A compiled expression and the indices of the variables it references.
+///
+/// The expression is kept as an AST rather than a [`Program`] because
+/// typed object constructors and `variables.<name>` selections are
+/// rewritten after parsing.
+#[derive(Debug)]
+pub(crate) struct CompiledExpression {
+    pub(crate) source: String,
+    expression: Expression,
+    variables: Vec<usize>,
+    /// `variables` is used as a whole, so every variable is bound up front
+    /// instead of on access.
+    bind_all: bool,
+    /// The `Object` constructors of the source, checked against a schema by
+    /// [`CompiledExpression::check_schema`].
+    constructors: Vec<ObjectConstructor>,
+}
+
+/// An `Object.<path>` constructor and the fields it sets.
+#[derive(Debug)]
+struct ObjectConstructor {
+    type_name: String,
+    fields: Vec<String>,
+}
+
+impl CompiledExpression {
+    /// Check the fields of every `Object` constructor against `schema`, the
+    /// schema of the object the policy applies to.
+    pub(crate) fn check_schema(&self, schema: &serde_json::Value) -> Result<(), PolicyError> {
+        // Constructors are collected innermost first; check the outer ones
+        // first so that an undeclared field is reported where it is set.
+        self.constructors
+            .iter()
+            .rev()
+            .try_for_each(|constructor| check_constructor(constructor, schema))
+            .map_err(|message| PolicyError::Compile {
+                expression: self.source.clone(),
+                message,
+            })
+    }
+}
+
+impl CompiledPolicy {
+    /// Compile a `ValidatingAdmissionPolicy` manifest, or just its `spec`.
+    pub fn from_manifest(policy: &serde_json::Value) -> Result<Self, PolicyError> {
+        let spec = policy.get("spec").unwrap_or(policy);
+        let spec = PolicySpec::deserialize(spec).map_err(|e| PolicyError::InvalidSpec {
+            message: e.to_string(),
+        })?;
+        Self::compile(&spec)
+    }
+
+    /// Compile every expression of `spec`.
+    ///
+    /// A variable may only reference variables declared before it.
+    pub fn compile(spec: &PolicySpec) -> Result<Self, PolicyError> {
+        let variables = compile_variables(&spec.variables)?;
+        let validations = spec
+            .validations
+            .iter()
+            .map(|validation| {
+                Ok(CompiledValidation {
+                    expression: compile_expression(&validation.expression, &variables)?,
+                    message_expression: validation
+                        .message_expression
+                        .as_deref()
+                        .map(|expr| compile_expression(expr, &variables))
+                        .transpose()?,
+                    validation: validation.clone(),
+                })
+            })
+            .collect::<Result<_, PolicyError>>()?;
+        let match_conditions = compile_match_conditions(&spec.match_conditions)?;
+        let mut audit_annotations: Vec<(String, CompiledExpression)> = Vec::new();
+        for annotation in &spec.audit_annotations {
+            if audit_annotations
+                .iter()
+                .any(|(key, _)| *key == annotation.key)
+            {
+                return Err(PolicyError::DuplicateAuditAnnotation {
+                    key: annotation.key.clone(),
+                });
+            }
+            let expression = compile_expression(&annotation.value_expression, &variables)?;
+            audit_annotations.push((annotation.key.clone(), expression));
+        }
+        Ok(Self {
+            variables,
+            validations,
+            match_conditions,
+            failure_policy: spec.failure_policy,
+            audit_annotations,
+            param_kind: spec.param_kind.clone(),
+            match_constraints: spec.match_constraints.clone(),
+        })
+    }
+
+    /// The declared variable names, in order.
+    pub fn variable_names(&self) -> impl Iterator<Item = &str> {
+        self.variables.iter().map(|v| v.name.as_str())
+    }
+
+    /// The kind of parameter resource the policy expects as
+    /// [`PolicyRequest::params`], if it is parameterized.
+    #[must_use]
+    pub fn param_kind(&self) -> Option<&ParamKind> {
+        self.param_kind.as_ref()
+    }
+
+    /// The policy's `matchConstraints`, if any.
+    #[must_use]
+    pub fn match_constraints(&self) -> Option<&MatchResources> {
+        self.match_constraints.as_ref()
+    }
+
+    /// The policy's failure policy.
+    #[must_use]
+    pub fn failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    /// Check the match constraints, then evaluate the match conditions and
+    /// every validation and audit annotation against `request`.
+    pub(crate) fn evaluate(&self, base: &Context<'_>, request: &PolicyRequest<'_>) -> PolicyResult {
+        if self
+            .match_constraints
+            .as_ref()
+            .is_some_and(|c| !c.matches(request))
+        {
+            return PolicyResult::default();
+        }
+        let mut ctx = base.new_inner_scope();
+        request.bind(&mut ctx);
+        let scope = RequestScope::new(&self.variables, &ctx);
+        if let Err(failure) = scope.matches(&self.match_conditions) {
+            let violations = match (failure, self.failure_policy) {
+                (Some(error), FailurePolicy::Fail) => vec![error],
+                _ => Vec::new(),
+            };
+            return PolicyResult {
+                matched: false,
+                violations,
+                audit_annotations: BTreeMap::new(),
+            };
+        }
+        let mut violations: Vec<_> = self
+            .validations
+            .iter()
+            .filter_map(|v| scope.validate(v))
+            .collect();
+        let mut audit_annotations = BTreeMap::new();
+        for (key, expression) in &self.audit_annotations {
+            match scope.audit_annotation(key, expression) {
+                Ok(Some(value)) => {
+                    audit_annotations.insert(key.clone(), value);
+                }
+                Ok(None) => {}
+                Err(error) => violations.push(error),
+            }
+        }
+        if self.failure_policy == FailurePolicy::Ignore {
+            violations.retain(|v| v.kind == ErrorKind::ValidationFailure);
+        }
+        PolicyResult {
+            matched: true,
+            violations,
+            audit_annotations,
+        }
+    }
+}
+
+/// The inputs of one admission request.
+///
+/// Absent values are bound as `null`, as the apiserver does for
+/// `oldObject` on create and `object` on delete.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PolicyRequest<'a> {
+    /// The incoming object, bound as `object`.
+    pub object: Option<&'a serde_json::Value>,
+    /// The existing object, bound as `oldObject`.
+    pub old_object: Option<&'a serde_json::Value>,
+    /// The `AdmissionRequest` attributes (`operation`, `userInfo`, ...),
+    /// bound as `request`.
+    pub request: Option<&'a serde_json::Value>,
+    /// The parameter resource selected by the policy binding's `paramRef`,
+    /// bound as `params`. `None` matches a binding whose
+    /// `parameterNotFoundAction` is `Allow` and found no parameters.
+    pub params: Option<&'a serde_json::Value>,
+    /// The `Namespace` the object belongs to, bound as `namespaceObject`.
+    /// `None` for cluster-scoped objects and `Namespace` objects themselves.
+    pub namespace_object: Option<&'a serde_json::Value>,
+    /// Answers `authorizer` checks, made for the user in
+    /// `request.userInfo`. Without one, `authorizer` is not bound.
+    #[cfg(feature = "authz")]
+    pub authorizer: Option<&'a crate::authz::SharedAuthorizer>,
+}
+
+impl PolicyRequest<'_> {
+    pub(crate) fn bind(&self, ctx: &mut Context<'_>) {
+        for (name, value) in [
+            ("object", self.object),
+            ("oldObject", self.old_object),
+            ("request", self.request),
+            ("params", self.params),
+            ("namespaceObject", self.namespace_object),
+        ] {
+            ctx.add_variable_from_value(name, value.map_or(cel::Value::Null, json_to_cel));
+        }
+        #[cfg(feature = "authz")]
+        if let Some(authorizer) = self.authorizer {
+            let user_info = self.request.and_then(|r| r.get("userInfo"));
+            let user = user_info
+                .and_then(|u| u.get("username"))
+                .and_then(|u| u.as_str())
+                .unwrap_or_default();
+            let groups = user_info
+                .and_then(|u| u.get("groups"))
+                .and_then(|g| g.as_array())
+                .map(|groups| {
+                    groups
+                        .iter()
+                        .filter_map(|g| g.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default();
+            ctx.add_variable_from_value("authorizer", authorizer.value_for(user, groups));
+        }
+    }
+}
+
+/// The outcome of evaluating a policy against one request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PolicyResult {
+    /// Whether every match condition held, so the validations ran.
+    pub matched: bool,
+    /// The failed validations, in declaration order, followed by audit
+    /// annotation errors; or the match condition error that rejected the
+    /// request. Empty if the request is admitted.
+    pub violations: Vec<PolicyViolation>,
+    /// The evaluated audit annotations, by key. Annotations whose
+    /// expression returned `null` or an empty string are omitted.
+    pub audit_annotations: BTreeMap<String, String>,
+}
+
+impl PolicyResult {
+    /// Whether the request is admitted.
+    #[must_use]
+    pub fn allowed(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// A validation that rejected the request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyViolation {
+    /// The validation expression.
+    pub expression: String,
+    /// The failure message.
+    pub message: String,
+    /// The `metav1.StatusReason`, `Invalid` unless the validation sets one.
+    pub reason: String,
+    /// [`ErrorKind::ValidationFailure`] if the expression returned `false`,
+    /// otherwise why it could not be evaluated.
+    pub kind: ErrorKind,
+}
+
+impl std::fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Per-request state: the bound inputs and the lazily evaluated variables.
+///
+/// A variable is evaluated the first time an expression reads it, so
+/// `||`, `&&` and `?:` that short-circuit past a failing variable do not
+/// fail.
+pub(crate) struct RequestScope<'a> {
+    variables: &'a [CompiledVariable],
+    ctx: &'a Context<'a>,
+    /// Each variable's value or evaluation error, computed on first use.
+    values: Vec<OnceLock<Result<cel::Value, String>>>,
+}
+
+impl<'a> RequestScope<'a> {
+    pub(crate) fn new(variables: &'a [CompiledVariable], ctx: &'a Context<'a>) -> Self {
+        Self {
+            variables,
+            ctx,
+            values: (0..variables.len()).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    /// Evaluate the match conditions like the apiserver: any `false` means
+    /// no match (`Err(None)`), regardless of errors in other conditions.
+    /// Otherwise the first error, if any, is returned as `Err(Some(_))`.
+    pub(crate) fn matches(
+        &self,
+        conditions: &[(String, CompiledExpression)],
+    ) -> Result<(), Option<PolicyViolation>> {
+        let mut error = None;
+        for (name, condition) in conditions {
+            let (message, kind) = match self.execute(condition) {
+                Ok(cel::Value::Bool(true)) => continue,
+                Ok(cel::Value::Bool(false)) => return Err(None),
+                Ok(_) => (
+                    format!("matchCondition '{name}' did not evaluate to bool"),
+                    ErrorKind::InvalidResult,
+                ),
+                Err(e) => (
+                    format!("failed to evaluate matchCondition '{name}': {e}"),
+                    ErrorKind::EvaluationError,
+                ),
+            };
+            error.get_or_insert(PolicyViolation {
+                expression: condition.source.clone(),
+                message,
+                reason: "Invalid".to_string(),
+                kind,
+            });
+        }
+        error.map_or(Ok(()), |e| Err(Some(e)))
+    }
+
+    fn validate(&self, validation: &CompiledValidation) -> Option<PolicyViolation> {
+        let expression = &validation.validation.expression;
+        let (message, kind) = match self.execute(&validation.expression) {
+            Ok(cel::Value::Bool(true)) => return None,
+            Ok(cel::Value::Bool(false)) => (self.message(validation), ErrorKind::ValidationFailure),
+            Ok(_) => (
+                format!("expression '{expression}' did not evaluate to bool"),
+                ErrorKind::InvalidResult,
+            ),
+            Err(e) => (
+                format!("expression '{expression}' resulted in error: {e}"),
+                ErrorKind::EvaluationError,
+            ),
+        };
+        Some(PolicyViolation {
+            expression: expression.clone(),
+            message,
+            reason: validation
+                .validation
+                .reason
+                .clone()
+                .unwrap_or_else(|| "Invalid".to_string()),
+            kind,
+        })
+    }
+
+    /// The value of an audit annotation, `None` if it is omitted.
+    fn audit_annotation(
+        &self,
+        key: &str,
+        expression: &CompiledExpression,
+    ) -> Result<Option<String>, PolicyViolation> {
+        let (message, kind) = match self.execute(expression) {
+            Ok(cel::Value::Null) => return Ok(None),
+            Ok(cel::Value::String(s)) if s.is_empty() => return Ok(None),
+            Ok(cel::Value::String(s)) => {
+                let mut value = (*s).clone();
+                if value.len() > MAX_AUDIT_ANNOTATION_VALUE_LENGTH {
+                    let end = value.floor_char_boundary(MAX_AUDIT_ANNOTATION_VALUE_LENGTH);
+                    value.truncate(end);
+                }
+                return Ok(Some(value));
+            }
+            Ok(_) => (
+                format!(
+                    "valueExpression of audit annotation '{key}' must evaluate to a string or null"
+                ),
+                ErrorKind::InvalidResult,
+            ),
+            Err(e) => (
+                format!("valueExpression of audit annotation '{key}' resulted in error: {e}"),
+                ErrorKind::EvaluationError,
+            ),
+        };
+        Err(PolicyViolation {
+            expression: expression.source.clone(),
+            message,
+            reason: "Invalid".to_string(),
+            kind,
+        })
+    }
+
+    /// The message of a failed validation: its `messageExpression` if that
+    /// yields a usable message, else `message`, else a default.
+    fn message(&self, validation: &CompiledValidation) -> String {
+        let evaluated =
+            validation
+                .message_expression
+                .as_ref()
+                .and_then(|expr| match self.execute(expr) {
+                    Ok(cel::Value::String(s)) if check_evaluated_message(&s).is_none() => {
+                        Some((*s).clone())
+                    }
+                    _ => None,
+                });
+        evaluated
+            .or_else(|| validation.validation.message.clone())
+            .unwrap_or_else(|| format!("failed expression: {}", validation.validation.expression))
+    }
+
+    /// Run `expression` with the variables it references bound.
+    pub(crate) fn execute(&self, expression: &CompiledExpression) -> Result<cel::Value, String> {
+        if expression.variables.is_empty() {
+            return cel::Value::resolve(&expression.expression, self.ctx)
+                .map_err(|e| e.to_string());
+        }
+        let mut ctx = self.ctx.new_inner_scope();
+        if expression.bind_all {
+            let mut variables = HashMap::with_capacity(expression.variables.len());
+            for &i in &expression.variables {
+                let value = self.variable(i).map_err(|e| self.variable_error(i, e))?;
+                variables.insert(Key::from(self.variables[i].name.as_str()), value.clone());
+            }
+            ctx.add_variable_from_value(
+                VARIABLES,
+                cel::Value::Map(Map {
+                    map: Arc::new(variables),
+                }),
+            );
+        } else {
+            ctx.set_variable_resolver(self);
+        }
+        cel::Value::resolve(&expression.expression, &ctx).map_err(|e| match &e {
+            // The resolver cannot return errors, so a failed variable
+            // surfaces as an undeclared reference to its identifier.
+            ExecutionError::UndeclaredReference(name) => match self.variable_index(name) {
+                Some(i) => self.variable_error(i, self.variable(i).unwrap_err()),
+                None => e.to_string(),
+            },
+            _ => e.to_string(),
+        })
+    }
+
+    /// The value of variable `i`, evaluated on first use.
+    fn variable(&self, i: usize) -> Result<&cel::Value, &str> {
+        self.values[i]
+            .get_or_init(|| self.execute(&self.variables[i].expression))
+            .as_ref()
+            .map_err(String::as_str)
+    }
+
+    fn variable_error(&self, i: usize, error: &str) -> String {
+        format!("variable '{}' failed: {error}", self.variables[i].name)
+    }
+
+    /// The index of the variable bound to `ident` by [`bind_variables`].
+    fn variable_index(&self, ident: &str) -> Option<usize> {
+        let name = ident.strip_prefix(VARIABLES)?.strip_prefix('.')?;
+        self.variables.iter().position(|v| v.name == name)
+    }
+}
+
+impl VariableResolver for RequestScope<'_> {
+    fn resolve(&self, ident: &str) -> Option<cel::Value> {
+        let i = self.variable_index(ident)?;
+        self.variable(i).ok().cloned()
+    }
+}
+
+/// Compile `variables` in order; each may reference those before it.
+pub(crate) fn compile_variables(
+    variables: &[Variable],
+) -> Result<Vec<CompiledVariable>, PolicyError> {
+    let mut compiled: Vec<CompiledVariable> = Vec::with_capacity(variables.len());
+    for variable in variables {
+        if compiled.iter().any(|v| v.name == variable.name) {
+            return Err(PolicyError::DuplicateVariable {
+                name: variable.name.clone(),
+            });
+        }
+        let expression = compile_expression(&variable.expression, &compiled)?;
+        compiled.push(CompiledVariable {
+            name: variable.name.clone(),
+            expression,
+        });
+    }
+    Ok(compiled)
+}
+
+/// Compile `conditions`, which cannot reference variables.
+pub(crate) fn compile_match_conditions(
+    conditions: &[MatchCondition],
+) -> Result<Vec<(String, CompiledExpression)>, PolicyError> {
+    conditions
+        .iter()
+        .map(|condition| {
+            let expression = compile_expression(&condition.expression, &[])?;
+            Ok((condition.name.clone(), expression))
+        })
+        .collect()
+}
+
+/// Compile `source`, resolving its `variables.<name>` references against
+/// the variables declared so far.
+pub(crate) fn compile_expression(
+    source: &str,
+    declared: &[CompiledVariable],
+) -> Result<CompiledExpression, PolicyError> {
+    let compile_error = |message: String| PolicyError::Compile {
+        expression: source.to_string(),
+        message,
+    };
+    let program = Program::compile(source).map_err(|e| compile_error(e.to_string()))?;
+    let mut expression = program.expression().clone();
+    let mut constructors = Vec::new();
+    resolve_constructors(&mut expression, None, &mut constructors).map_err(compile_error)?;
+    let mut names = BTreeSet::new();
+    let all = referenced_variables(&expression, &mut names);
+    if !all {
+        bind_variables(&mut expression);
+    }
+    let variables = if all {
+        (0..declared.len()).collect()
+    } else {
+        names
+            .into_iter()
+            .map(|name| {
+                declared.iter().position(|v| v.name == name).ok_or_else(|| {
+                    PolicyError::UndeclaredVariable {
+                        expression: source.to_string(),
+                        name,
+                    }
+                })
+            })
+            .collect::<Result<_, _>>()?
+    };
+    Ok(CompiledExpression {
+        source: source.to_string(),
+        expression,
+        variables,
+        bind_all: all,
+        constructors,
+    })
+}
+
+/// Rewrite the typed object constructors of `expr`, which the interpreter
+/// cannot evaluate, into map literals: `JSONPatch{op: 'add', path: '/x'}`
+/// becomes `{'op': 'add', 'path': '/x'}`, and
+/// `Object{spec: Object.spec{replicas: 3}}` becomes
+/// `{'spec': {'replicas': 3}}`.
+///
+/// The value of a field `f` of an `Object.<path>` constructor, or each item
+/// of a list literal there, must be constructed as `Object.<path>.f`; the
+/// `Object` constructors are collected into `constructors` so that their
+/// fields can be checked once a schema is known. Unknown types, `JSONPatch`
+/// fields, and mistyped nested constructors are errors.
+fn resolve_constructors(
+    expr: &mut Expression,
+    expected: Option<&str>,
+    constructors: &mut Vec<ObjectConstructor>,
+) -> Result<(), String> {
+    let mut resolve = |expr: &mut Expression| resolve_constructors(expr, None, constructors);
+    match &mut expr.expr {
+        Expr::Struct(st) => {
+            let is_object = st.type_name == OBJECT || st.type_name.starts_with("Object.");
+            let fields: Option<&[&str]> = match st.type_name.as_str() {
+                JSON_PATCH => Some(&["op", "path", "from", "value"]),
+                _ if is_object => None,
+                other => return Err(format!("unknown type '{other}'")),
+            };
+            if let Some(expected) = expected
+                && st.type_name != expected
+            {
+                return Err(format!(
+                    "expected type '{expected}', found '{}'",
+                    st.type_name
+                ));
+            }
+            let mut entries = Vec::with_capacity(st.entries.len());
+            let mut names = Vec::with_capacity(st.entries.len());
+            for entry in std::mem::take(&mut st.entries) {
+                let EntryExpr::StructField(mut field) = entry.expr else {
+                    return Err(format!("{} can only set fields", st.type_name));
+                };
+                if fields.is_some_and(|fields| !fields.contains(&field.field.as_str())) {
+                    return Err(format!("{} has no field '{}'", st.type_name, field.field));
+                }
+                let field_type = is_object.then(|| format!("{}.{}", st.type_name, field.field));
+                resolve_constructors(&mut field.value, field_type.as_deref(), constructors)?;
+                names.push(field.field.clone());
+                entries.push(IdedEntryExpr {
+                    id: entry.id,
+                    expr: EntryExpr::MapEntry(MapEntryExpr {
+                        key: Expression {
+                            id: entry.id,
+                            expr: Expr::Literal(CelVal::String(field.field)),
+                        },
+                        value: field.value,
+                        optional: field.optional,
+                    }),
+                });
+            }
+            if is_object {
+                constructors.push(ObjectConstructor {
+                    type_name: std::mem::take(&mut st.type_name),
+                    fields: names,
+                });
+            }
+            expr.expr = Expr::Map(MapExpr { entries });
+            Ok(())
+        }
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => Ok(()),
+        Expr::Select(select) => resolve(&mut select.operand),
+        Expr::List(list) => list
+            .elements
+            .iter_mut()
+            .try_for_each(|e| resolve_constructors(e, expected, constructors)),
+        Expr::Map(map) => map
+            .entries
+            .iter_mut()
+            .try_for_each(|entry| match &mut entry.expr {
+                EntryExpr::StructField(field) => resolve(&mut field.value),
+                EntryExpr::MapEntry(e) => {
+                    resolve(&mut e.key)?;
+                    resolve(&mut e.value)
+                }
+            }),
+        Expr::Call(call) if call.func_name == operators::CONDITIONAL => {
+            let (condition, branches) = call.args.split_at_mut(1);
+            condition.iter_mut().try_for_each(&mut resolve)?;
+            branches
+                .iter_mut()
+                .try_for_each(|e| resolve_constructors(e, expected, constructors))
+        }
+        Expr::Call(call) => call
+            .target
+            .iter_mut()
+            .map(|t| t.as_mut())
+            .chain(call.args.iter_mut())
+            .try_for_each(resolve),
+        Expr::Comprehension(comp) => [
+            &mut comp.iter_range,
+            &mut comp.accu_init,
+            &mut comp.loop_cond,
+            &mut comp.loop_step,
+            &mut comp.result,
+        ]
+        .into_iter()
+        .try_for_each(resolve),
+    }
+}
+
+/// Check the fields of `constructor` against `schema`, the schema of the
+/// object an `Object` constructor builds. The apiserver types `metadata`
+/// as `ObjectMeta` whatever the schema says, so it and the top-level
+/// `apiVersion` and `kind` are not checked.
+fn check_constructor(
+    constructor: &ObjectConstructor,
+    schema: &serde_json::Value,
+) -> Result<(), String> {
+    let mut node = schema;
+    let mut top_level = true;
+    for segment in constructor.type_name.split('.').skip(1) {
+        if top_level && segment == "metadata" {
+            return Ok(());
+        }
+        top_level = false;
+        let Some(child) = field_schema(node, segment) else {
+            if preserves_unknown_fields(node) {
+                return Ok(());
+            }
+            return Err(format!(
+                "type '{}' is not declared by the schema",
+                constructor.type_name
+            ));
+        };
+        node = child.get("items").unwrap_or(child);
+    }
+    let is_object = node.get("type").is_none_or(|t| t == "object");
+    if !is_object {
+        return Err(format!("type '{}' is not an object", constructor.type_name));
+    }
+    let undeclared = constructor.fields.iter().find(|field| {
+        !(top_level && ["apiVersion", "kind", "metadata"].contains(&field.as_str()))
+            && field_schema(node, field).is_none()
+            && !preserves_unknown_fields(node)
+    });
+    match undeclared {
+        Some(field) => Err(format!(
+            "type '{}' has no field '{field}'",
+            constructor.type_name
+        )),
+        None => Ok(()),
+    }
+}
+
+/// The schema of the field `name` of an object node, declared or allowed
+/// through `additionalProperties`.
+fn field_schema<'s>(node: &'s serde_json::Value, name: &str) -> Option<&'s serde_json::Value> {
+    node.get("properties")
+        .and_then(|p| p.get(name))
+        .or_else(|| additional_properties_schema(node))
+}
+
+/// Rewrite the `variables.<name>` selections of `expr` into identifiers
+/// `variables.<name>`, which no source expression can spell, so that
+/// [`RequestScope`] resolves each variable on access.
+fn bind_variables(expr: &mut Expression) {
+    match &mut expr.expr {
+        Expr::Select(select) => match &select.operand.expr {
+            Expr::Ident(name) if name == VARIABLES && !select.test => {
+                expr.expr = Expr::Ident(format!("{VARIABLES}.{}", select.field));
+            }
+            _ => bind_variables(&mut select.operand),
+        },
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => {}
+        Expr::List(list) => list.elements.iter_mut().for_each(bind_variables),
+        Expr::Map(map) => map
+            .entries
+            .iter_mut()
+            .for_each(|entry| match &mut entry.expr {
+                EntryExpr::StructField(field) => bind_variables(&mut field.value),
+                EntryExpr::MapEntry(e) => {
+                    bind_variables(&mut e.key);
+                    bind_variables(&mut e.value);
+                }
+            }),
+        Expr::Struct(st) => st
+            .entries
+            .iter_mut()
+            .for_each(|entry| match &mut entry.expr {
+                EntryExpr::StructField(field) => bind_variables(&mut field.value),
+                EntryExpr::MapEntry(e) => {
+                    bind_variables(&mut e.key);
+                    bind_variables(&mut e.value);
+                }
+            }),
+        Expr::Call(call) => call
+            .target
+            .iter_mut()
+            .map(|t| t.as_mut())
+            .chain(call.args.iter_mut())
+            .for_each(bind_variables),
+        Expr::Comprehension(comp) => [
+            &mut comp.iter_range,
+            &mut comp.accu_init,
+            &mut comp.loop_cond,
+            &mut comp.loop_step,
+            &mut comp.result,
+        ]
+        .into_iter()
+        .for_each(bind_variables),
+    }
+}
+
+/// Collect the `<name>`s of `variables.<name>` selections in `expr`.
+/// Returns `true` if `variables` is also used as a whole, including in
+/// `has(variables.<name>)`, in which case every variable must be bound.
+fn referenced_variables(expr: &Expression, names: &mut BTreeSet<String>) -> bool {
+    match &expr.expr {
+        Expr::Ident(name) => name == VARIABLES,
+        Expr::Select(select) => {
+            if let Expr::Ident(name) = &select.operand.expr
+                && name == VARIABLES
+            {
+                names.insert(select.field.clone());
+                return select.test;
+            }
+            referenced_variables(&select.operand, names)
+        }
+        Expr::Literal(_) | Expr::Unspecified => false,
+        Expr::List(list) => list
+            .elements
+            .iter()
+            .fold(false, |all, e| referenced_variables(e, names) | all),
+        Expr::Map(map) => map.entries.iter().fold(false, |all, entry| {
+            entry_references(&entry.expr, names) | all
+        }),
+        Expr::Struct(st) => st.entries.iter().fold(false, |all, entry| {
+            entry_references(&entry.expr, names) | all
+        }),
+        Expr::Call(call) => call
+            .target
+            .iter()
+            .map(|t| t.as_ref())
+            .chain(call.args.iter())
+            .fold(false, |all, e| referenced_variables(e, names) | all),
+        Expr::Comprehension(comp) => [
+            &comp.iter_range,
+            &comp.accu_init,
+            &comp.loop_cond,
+            &comp.loop_step,
+            &comp.result,
+        ]
+        .into_iter()
+        .fold(false, |all, e| referenced_variables(e, names) | all),
+    }
+}
+
+fn entry_references(entry: &EntryExpr, names: &mut BTreeSet<String>) -> bool {
+    match entry {
+        EntryExpr::StructField(field) => referenced_variables(&field.value, names),
+        EntryExpr::MapEntry(e) => {
+            referenced_variables(&e.key, names) | referenced_variables(&e.value, names)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::Validator;
+    use serde_json::json;
+
+    fn evaluate(spec: serde_json::Value, object: serde_json::Value) -> Vec<PolicyViolation> {
+        let policy = CompiledPolicy::from_manifest(&spec).unwrap();
+        let request = PolicyRequest {
+            object: Some(&object),
+            ..Default::default()
+        };
+        Validator::new()
+            .validate_policy(&policy, &request)
+            .violations
+    }
+
+    #[test]
+    fn variables_are_visible_to_validations_and_later_variables() {
+        let spec = json!({
+            "variables": [
+                {"name": "containers", "expression": "object.spec.containers"},
+                {"name": "names", "expression": "variables.containers.map(c, c.name)"}
+            ],
+            "validations": [
+                {"expression": "variables.names.all(n, n.startsWith('app-'))"},
+                {
+                    "expression": "size(variables.containers) <= 1",
+                    "messageExpression": "'too many containers: ' + string(size(variables.names))",
+                    "reason": "Forbidden"
+                }
+            ]
+        });
+        let object = json!({"spec": {"containers": [{"name": "app-a"}, {"name": "sidecar"}]}});
+        let violations = evaluate(spec, object);
+        assert_eq!(violations.len(), 2);
+        assert_eq!(
+            violations[0].message,
+            "failed expression: variables.names.all(n, n.startsWith('app-'))"
+        );
+        assert_eq!(violations[0].reason, "Invalid");
+        assert_eq!(violations[1].message, "too many containers: 2");
+        assert_eq!(violations[1].reason, "Forbidden");
+    }
+
+    #[test]
+    fn variables_are_evaluated_lazily() {
+        let spec = json!({
+            "variables": [
+                {"name": "broken", "expression": "object.missing.field"},
+                {"name": "replicas", "expression": "object.spec.replicas"}
+            ],
+            "validations": [
+                {"expression": "variables.replicas < 5"},
+                {"expression": "variables.broken == 1"}
+            ]
+        });
+        let violations = evaluate(spec, json!({"spec": {"replicas": 1}}));
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ErrorKind::EvaluationError);
+        assert!(
+            violations[0].message.contains("variable 'broken' failed"),
+            "{}",
+            violations[0].message
+        );
+    }
+
+    #[test]
+    fn variables_short_circuit() {
+        let spec = json!({
+            "variables": [{"name": "bad", "expression": "object.spec.missing.x"}],
+            "validations": [
+                {"expression": "!has(object.spec.missing) || variables.bad == 1"},
+                {"expression": "has(object.spec.missing) && variables.bad == 1 ? false : true"}
+            ]
+        });
+        assert!(evaluate(spec, json!({"spec": {}})).is_empty());
+    }
+
+    #[test]
+    fn referenced_variables_are_collected() {
+        let program = Program::compile("variables.a + [variables.b].size() > 0").unwrap();
+        let mut names = BTreeSet::new();
+        assert!(!referenced_variables(program.expression(), &mut names));
+        assert_eq!(names.into_iter().collect::<Vec<_>>(), ["a", "b"]);
+
+        let program = Program::compile("size(variables) > 0").unwrap();
+        assert!(referenced_variables(
+            program.expression(),
+            &mut BTreeSet::new()
+        ));
+    }
+
+    #[test]
+    fn object_constructors_build_maps() {
+        let spec = json!({
+            "validations": [{
+                "expression": "Object{spec: Object.spec{replicas: 3}}.spec == object.spec",
+                "messageExpression": "string(Object{spec: Object.spec{replicas: 3}}.spec.replicas) + ' replicas required'"
+            }]
+        });
+        assert!(evaluate(spec.clone(), json!({"spec": {"replicas": 3}})).is_empty());
+        let violations = evaluate(spec, json!({"spec": {"replicas": 2}}));
+        assert_eq!(violations[0].message, "3 replicas required");
+    }
+
+    #[test]
+    fn object_constructors_follow_the_nesting() {
+        let compile = |expression: &str| compile_expression(expression, &[]).map(|_| ());
+        assert!(compile("Object{spec: Object.spec{replicas: 3}}").is_ok());
+        assert!(
+            compile("Object{spec: Object.spec{containers: [Object.spec.containers{name: 'a'}]}}")
+                .is_ok()
+        );
+        assert!(
+            compile("Object{spec: object.spec.paused ? Object.spec{replicas: 0} : Object.spec{}}")
+                .is_ok()
+        );
+        for (expression, message) in [
+            (
+                "Object{spec: Object.metadata{name: 'x'}}",
+                "expected type 'Object.spec', found 'Object.metadata'",
+            ),
+            (
+                "Object{spec: Object.spec{containers: [Object.spec.container{}]}}",
+                "expected type 'Object.spec.containers', found 'Object.spec.container'",
+            ),
+            (
+                "Object{spec: true ? Object.spec{} : Object.status{}}",
+                "expected type 'Object.spec', found 'Object.status'",
+            ),
+        ] {
+            assert_eq!(
+                compile(expression),
+                Err(PolicyError::Compile {
+                    expression: expression.into(),
+                    message: message.into()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn match_constraints_gate_evaluation() {
+        let policy = CompiledPolicy::from_manifest(&json!({
+            "matchConstraints": {
+                "resourceRules": [{
+                    "apiGroups": ["apps"],
+                    "apiVersions": ["v1"],
+                    "operations": ["CREATE"],
+                    "resources": ["deployments"]
+                }]
+            },
+            "validations": [{"expression": "object.spec.replicas <= 3"}]
+        }))
+        .unwrap();
+        assert!(policy.match_constraints().is_some());
+        let object = json!({"spec": {"replicas": 5}});
+        let run = |operation: &str| {
+            let request = json!({
+                "operation": operation,
+                "resource": {"group": "apps", "version": "v1", "resource": "deployments"}
+            });
+            Validator::new().validate_policy(
+                &policy,
+                &PolicyRequest {
+                    object: Some(&object),
+                    request: Some(&request),
+                    ..Default::default()
+                },
+            )
+        };
+        let result = run("CREATE");
+        assert!(result.matched);
+        assert!(!result.allowed());
+        let result = run("UPDATE");
+        assert!(!result.matched);
+        assert!(result.allowed());
+    }
+
+    #[test]
+    fn compile_errors() {
+        let undeclared = CompiledPolicy::from_manifest(&json!({
+            "variables": [{"name": "a", "expression": "variables.b"}, {"name": "b", "expression": "1"}]
+        }))
+        .unwrap_err();
+        assert_eq!(
+            undeclared,
+            PolicyError::UndeclaredVariable {
+                expression: "variables.b".into(),
+                name: "b".into()
+            }
+        );
+        let duplicate = CompiledPolicy::from_manifest(&json!({
+            "variables": [{"name": "a", "expression": "1"}, {"name": "a", "expression": "2"}]
+        }))
+        .unwrap_err();
+        assert_eq!(
+            duplicate.to_string(),
+            "variable \"a\" is declared more than once"
+        );
+        assert!(matches!(
+            CompiledPolicy::from_manifest(&json!({"validations": [{"expression": "1 +"}]})),
+            Err(PolicyError::Compile { .. })
+        ));
+        assert!(matches!(
+            CompiledPolicy::from_manifest(&json!({"validations": "nope"})),
+            Err(PolicyError::InvalidSpec { .. })
+        ));
+    }
+
+    #[test]
+    fn match_conditions_gate_validations() {
+        let spec = json!({
+            "matchConditions": [
+                {"name": "is-deployment", "expression": "object.kind == 'Deployment'"},
+                {"name": "has-spec", "expression": "object.spec.replicas > 0"}
+            ],
+            "validations": [{"expression": "object.spec.replicas <= 3"}]
+        });
+        let policy = CompiledPolicy::from_manifest(&spec).unwrap();
+        let run = |object: serde_json::Value| {
+            let request = PolicyRequest {
+                object: Some(&object),
+                ..Default::default()
+            };
+            Validator::new().validate_policy(&policy, &request)
+        };
+
+        let result = run(json!({"kind": "Deployment", "spec": {"replicas": 5}}));
+        assert!(result.matched);
+        assert!(!result.allowed());
+
+        // A false condition wins over an erroring one.
+        let result = run(json!({"kind": "Pod"}));
+        assert!(!result.matched);
+        assert!(result.allowed());
+
+        let result = run(json!({"kind": "Deployment"}));
+        assert!(!result.matched);
+        assert_eq!(result.violations[0].kind, ErrorKind::EvaluationError);
+        assert!(
+            result.violations[0]
+                .message
+                .starts_with("failed to evaluate matchCondition 'has-spec'")
+        );
+    }
+
+    #[test]
+    fn failure_policy_ignore_drops_errors() {
+        let spec = json!({
+            "failurePolicy": "Ignore",
+            "matchConditions": [{"name": "labelled", "expression": "object.metadata.labels.team != ''"}],
+            "validations": [
+                {"expression": "object.spec.missing == 1"},
+                {"expression": "object.spec.replicas <= 3"}
+            ]
+        });
+        let policy = CompiledPolicy::from_manifest(&spec).unwrap();
+        assert_eq!(policy.failure_policy(), FailurePolicy::Ignore);
+
+        let object = json!({"metadata": {"labels": {"team": "a"}}, "spec": {"replicas": 5}});
+        let request = PolicyRequest {
+            object: Some(&object),
+            ..Default::default()
+        };
+        let violations = Validator::new()
+            .validate_policy(&policy, &request)
+            .violations;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ErrorKind::ValidationFailure);
+
+        let object = json!({"spec": {"replicas": 5}});
+        let request = PolicyRequest {
+            object: Some(&object),
+            ..Default::default()
+        };
+        let result = Validator::new().validate_policy(&policy, &request);
+        assert!(!result.matched);
+        assert!(result.allowed());
+    }
+
+    #[test]
+    fn match_conditions_cannot_use_variables() {
+        let spec = json!({
+            "variables": [{"name": "a", "expression": "1"}],
+            "matchConditions": [{"name": "c", "expression": "variables.a == 1"}]
+        });
+        assert!(matches!(
+            CompiledPolicy::from_manifest(&spec),
+            Err(PolicyError::UndeclaredVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn audit_annotations_are_evaluated() {
+        let spec = json!({
+            "variables": [{"name": "replicas", "expression": "object.spec.replicas"}],
+            "auditAnnotations": [
+                {"key": "replicas", "valueExpression": "'requested ' + string(variables.replicas)"},
+                {"key": "high", "valueExpression": "variables.replicas > 10 ? 'yes' : null"},
+                {"key": "long", "valueExpression": "object.spec.name"},
+                {"key": "bad", "valueExpression": "variables.replicas"}
+            ]
+        });
+        let policy = CompiledPolicy::from_manifest(&spec).unwrap();
+        let name = "é".repeat(MAX_AUDIT_ANNOTATION_VALUE_LENGTH);
+        let object = json!({"spec": {"replicas": 3, "name": name}});
+        let request = PolicyRequest {
+            object: Some(&object),
+            ..Default::default()
+        };
+        let result = Validator::new().validate_policy(&policy, &request);
+        assert_eq!(result.audit_annotations["replicas"], "requested 3");
+        assert!(!result.audit_annotations.contains_key("high"));
+        assert_eq!(
+            result.audit_annotations["long"].len(),
+            MAX_AUDIT_ANNOTATION_VALUE_LENGTH
+        );
+        assert_eq!(result.violations.len(), 1);
+        assert_eq!(result.violations[0].kind, ErrorKind::InvalidResult);
+
+        let duplicate = CompiledPolicy::from_manifest(&json!({
+            "auditAnnotations": [
+                {"key": "a", "valueExpression": "'x'"},
+                {"key": "a", "valueExpression": "'y'"}
+            ]
+        }));
+        assert_eq!(
+            duplicate.unwrap_err(),
+            PolicyError::DuplicateAuditAnnotation { key: "a".into() }
+        );
+    }
+
+    #[test]
+    fn params_are_bound() {
+        let policy = CompiledPolicy::from_manifest(&json!({
+            "spec": {
+                "paramKind": {"apiVersion": "example.com/v1", "kind": "ReplicaLimit"},
+                "validations": [{
+                    "expression": "params == null || object.spec.replicas <= params.maxReplicas",
+                    "messageExpression": "'at most ' + string(params.maxReplicas) + ' replicas'"
+                }]
+            }
+        }))
+        .unwrap();
+        assert_eq!(policy.param_kind().unwrap().kind, "ReplicaLimit");
+
+        let object = json!({"spec": {"replicas": 5}});
+        let params = json!({"maxReplicas": 3});
+        let mut request = PolicyRequest {
+            object: Some(&object),
+            params: Some(&params),
+            ..Default::default()
+        };
+        let violations = Validator::new()
+            .validate_policy(&policy, &request)
+            .violations;
+        assert_eq!(violations[0].message, "at most 3 replicas");
+
+        request.params = None;
+        assert!(
+            Validator::new()
+                .validate_policy(&policy, &request)
+                .allowed()
+        );
+    }
+
+    #[test]
+    fn namespace_object_is_bound() {
+        let policy = CompiledPolicy::from_manifest(&json!({
+            "validations": [{
+                "expression": "namespaceObject == null || !('restricted' in namespaceObject.metadata.labels) || object.spec.replicas <= 1"
+            }]
+        }))
+        .unwrap();
+        let object = json!({"spec": {"replicas": 2}});
+        let namespace = json!({"metadata": {"name": "prod", "labels": {"restricted": "true"}}});
+        let mut request = PolicyRequest {
+            object: Some(&object),
+            namespace_object: Some(&namespace),
+            ..Default::default()
+        };
+        assert!(
+            !Validator::new()
+                .validate_policy(&policy, &request)
+                .allowed()
+        );
+
+        request.namespace_object = None;
+        assert!(
+            Validator::new()
+                .validate_policy(&policy, &request)
+                .allowed()
+        );
+    }
+
+    #[cfg(feature = "authz")]
+    #[test]
+    fn authorizer_checks_use_the_requesting_user() {
+        use crate::authz::{Attributes, Decision, SharedAuthorizer};
+
+        let policy = CompiledPolicy::from_manifest(&json!({
+            "validations": [{
+                "expression": "authorizer.group('').resource('pods').namespace(object.metadata.name).check('delete').allowed()",
+                "message": "may not delete pods here"
+            }]
+        }))
+        .unwrap();
+        let authorizer = SharedAuthorizer::new(|a: &Attributes| {
+            if a.groups.iter().any(|g| g == "admins") {
+                Decision::allow("admin")
+            } else {
+                Decision::no_opinion("")
+            }
+        });
+        let object = json!({"metadata": {"name": "prod"}});
+        let admin = json!({"userInfo": {"username": "alice", "groups": ["admins"]}});
+        let user = json!({"userInfo": {"username": "bob", "groups": []}});
+        let mut request = PolicyRequest {
+            object: Some(&object),
+            request: Some(&admin),
+            authorizer: Some(&authorizer),
+            ..Default::default()
+        };
+        assert!(
+            Validator::new()
+                .validate_policy(&policy, &request)
+                .allowed()
+        );
+        request.request = Some(&user);
+        let result = Validator::new().validate_policy(&policy, &request);
+        assert_eq!(result.violations[0].message, "may not delete pods here");
+    }
+}
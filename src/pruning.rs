@@ -0,0 +1,337 @@
+//! Pruning of fields not declared in a structural schema.
+//!
+//! The apiserver drops unknown fields from custom resources before
+//! validating them. [`prune`] and [`prune_compiled`] do the same, so a
+//! client-side validation sees exactly the object the apiserver would store:
+//!
+//! - undeclared object fields are removed, unless the node sets
+//!   `x-kubernetes-preserve-unknown-fields: true` or has an
+//!   `additionalProperties` schema;
+//! - `apiVersion`, `kind`, and `metadata` are never pruned at the root or on
+//!   `x-kubernetes-embedded-resource` nodes.
+//!
+//! See [`Validator::validate_pruned`](crate::validation::Validator::validate_pruned)
+//! to prune and validate in one step.
+
+use std::borrow::Cow;
+
+use crate::compilation::{
+    CompiledSchema, additional_properties_schema, expand_node, preserves_unknown_fields,
+    resolve_refs,
+};
+use crate::validation::{PathSegment, render_path};
+
+/// Fields the apiserver keeps at the root of every object and of every
+/// embedded resource.
+const RESOURCE_FIELDS: [&str; 3] = ["apiVersion", "kind", "metadata"];
+
+/// Remove fields of `object` that `schema` does not declare.
+///
+/// Returns the paths of the removed fields (e.g. `spec.unknown`) in the
+/// order they were removed.
+pub fn prune(object: &mut serde_json::Value, schema: &serde_json::Value) -> Vec<String> {
+    let mut pruned = Vec::new();
+    let mut walk = SchemaWalk {
+        document: schema,
+        refs: Vec::new(),
+        path: Vec::new(),
+        pruned: &mut pruned,
+    };
+    walk.prune(object, schema, true);
+    pruned
+}
+
+/// Like [`prune`], using a pre-compiled schema tree.
+pub fn prune_compiled(object: &mut serde_json::Value, compiled: &CompiledSchema) -> Vec<String> {
+    let mut pruned = Vec::new();
+    prune_tree(object, compiled, true, &mut Vec::new(), &mut pruned);
+    pruned
+}
+
+fn is_embedded_resource(schema: &serde_json::Value) -> bool {
+    schema
+        .get("x-kubernetes-embedded-resource")
+        .and_then(|v| v.as_bool())
+        == Some(true)
+}
+
+/// State for pruning against an uncompiled schema document.
+struct SchemaWalk<'a> {
+    /// The document `$ref`s are resolved against.
+    document: &'a serde_json::Value,
+    /// The `$ref`s expanded on the way to the current node.
+    refs: Vec<String>,
+    path: Vec<PathSegment>,
+    pruned: &'a mut Vec<String>,
+}
+
+impl SchemaWalk<'_> {
+    fn prune(&mut self, value: &mut serde_json::Value, schema: &serde_json::Value, root: bool) {
+        let refs = self.refs.len();
+        // A `$ref` that cannot be resolved declares no fields, as in the
+        // compiled tree.
+        let resolved = resolve_refs(schema, self.document, &mut self.refs)
+            .unwrap_or_else(|_| Cow::Owned(serde_json::Value::Object(serde_json::Map::new())));
+        self.prune_resolved(value, &resolved, root);
+        self.refs.truncate(refs);
+    }
+
+    fn prune_resolved(
+        &mut self,
+        value: &mut serde_json::Value,
+        schema: &serde_json::Value,
+        root: bool,
+    ) {
+        let keep_resource_fields = root || is_embedded_resource(schema);
+        let schema = &*expand_node(schema);
+        match value {
+            serde_json::Value::Object(obj) => {
+                let properties = schema.get("properties").and_then(|p| p.as_object());
+                let additional = additional_properties_schema(schema);
+                let preserve = preserves_unknown_fields(schema);
+                obj.retain(|key, child| {
+                    if keep_resource_fields && RESOURCE_FIELDS.contains(&key.as_str()) {
+                        return true;
+                    }
+                    let (child_schema, segment) = match properties.and_then(|p| p.get(key)) {
+                        Some(prop) => (prop, PathSegment::Field(key.clone())),
+                        None => match additional {
+                            Some(additional) => (additional, PathSegment::Key(key.clone())),
+                            None if preserve => return true,
+                            None => {
+                                self.path.push(PathSegment::Field(key.clone()));
+                                self.pruned.push(render_path(&self.path));
+                                self.path.pop();
+                                return false;
+                            }
+                        },
+                    };
+                    self.path.push(segment);
+                    self.prune(child, child_schema, false);
+                    self.path.pop();
+                    true
+                });
+            }
+            serde_json::Value::Array(items) => {
+                if let Some(items_schema) = schema.get("items") {
+                    for (i, item) in items.iter_mut().enumerate() {
+                        self.path.push(PathSegment::Index(i));
+                        self.prune(item, items_schema, false);
+                        self.path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn prune_tree(
+    value: &mut serde_json::Value,
+    compiled: &CompiledSchema,
+    root: bool,
+    path: &mut Vec<PathSegment>,
+    pruned: &mut Vec<String>,
+) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            // Embedded resources always declare the resource fields after compilation.
+            let keep_resource_fields = root
+                || RESOURCE_FIELDS
+                    .iter()
+                    .all(|f| compiled.properties.contains_key(*f));
+            obj.retain(|key, child| {
+                if keep_resource_fields && RESOURCE_FIELDS.contains(&key.as_str()) {
+                    return true;
+                }
+                let (child_compiled, segment) = match compiled.properties.get(key) {
+                    Some(prop) => (prop, PathSegment::Field(key.clone())),
+                    None => match &compiled.additional_properties {
+                        Some(additional) => (&**additional, PathSegment::Key(key.clone())),
+                        None if compiled.preserve_unknown_fields => return true,
+                        None => {
+                            path.push(PathSegment::Field(key.clone()));
+                            pruned.push(render_path(path));
+                            path.pop();
+                            return false;
+                        }
+                    },
+                };
+                path.push(segment);
+                prune_tree(child, child_compiled, false, path, pruned);
+                path.pop();
+                true
+            });
+        }
+        serde_json::Value::Array(items) => {
+            if let Some(items_compiled) = &compiled.items {
+                for (i, item) in items.iter_mut().enumerate() {
+                    path.push(PathSegment::Index(i));
+                    prune_tree(item, items_compiled, false, path, pruned);
+                    path.pop();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use serde_json::json;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {"type": "integer"},
+                        "labels": {
+                            "type": "object",
+                            "additionalProperties": {"type": "string"}
+                        },
+                        "extra": {
+                            "type": "object",
+                            "x-kubernetes-preserve-unknown-fields": true
+                        },
+                        "containers": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": {"name": {"type": "string"}}
+                            }
+                        },
+                        "template": {
+                            "type": "object",
+                            "x-kubernetes-embedded-resource": true,
+                            "properties": {"spec": {"type": "object"}}
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn object() -> serde_json::Value {
+        json!({
+            "apiVersion": "example.com/v1",
+            "kind": "Widget",
+            "metadata": {"name": "demo", "uid": "1"},
+            "status": {"ready": true},
+            "spec": {
+                "replicas": 1,
+                "unknown": true,
+                "labels": {"app": "demo"},
+                "extra": {"anything": {"goes": 1}},
+                "containers": [{"name": "a", "image": "nginx"}],
+                "template": {
+                    "kind": "Pod",
+                    "metadata": {"labels": {"a": "b"}, "uid": "2"},
+                    "spec": {"x": 1},
+                    "bogus": 1
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn prunes_undeclared_fields() {
+        let mut obj = object();
+        let mut pruned = prune(&mut obj, &schema());
+        pruned.sort();
+        assert_eq!(
+            pruned,
+            [
+                "spec.containers[0].image",
+                "spec.template.bogus",
+                "spec.template.spec.x",
+                "spec.unknown",
+                "status"
+            ]
+        );
+        assert_eq!(
+            obj["spec"],
+            json!({
+                "replicas": 1,
+                "labels": {"app": "demo"},
+                "extra": {"anything": {"goes": 1}},
+                "containers": [{"name": "a"}],
+                "template": {
+                    "kind": "Pod",
+                    "metadata": {"labels": {"a": "b"}, "uid": "2"},
+                    "spec": {}
+                }
+            })
+        );
+        assert_eq!(obj["metadata"], json!({"name": "demo", "uid": "1"}));
+        assert!(obj.get("status").is_none());
+    }
+
+    #[test]
+    fn boolean_additional_properties() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "open": {"type": "object", "additionalProperties": true},
+                "closed": {"type": "object", "additionalProperties": false}
+            }
+        });
+        let mut obj = json!({"open": {"a": {"b": 1}}, "closed": {"a": 1}});
+        assert_eq!(prune(&mut obj, &schema), ["closed.a"]);
+        assert_eq!(obj, json!({"open": {"a": {"b": 1}}, "closed": {}}));
+        let mut obj = json!({"open": {"a": {"b": 1}}, "closed": {"a": 1}});
+        assert_eq!(
+            prune_compiled(&mut obj, &compile_schema(&schema)),
+            ["closed.a"]
+        );
+    }
+
+    #[test]
+    fn compiled_pruning_matches() {
+        let mut from_schema = object();
+        let mut from_compiled = object();
+        let mut a = prune(&mut from_schema, &schema());
+        let mut b = prune_compiled(&mut from_compiled, &compile_schema(&schema()));
+        a.sort();
+        b.sort();
+        assert_eq!(a, b);
+        assert_eq!(from_schema, from_compiled);
+    }
+
+    #[test]
+    fn refs_are_followed() {
+        let schema = json!({
+            "definitions": {
+                "Spec": {
+                    "type": "object",
+                    "properties": {"replicas": {"type": "integer"}}
+                }
+            },
+            "type": "object",
+            "properties": {
+                "spec": {"$ref": "#/definitions/Spec"},
+                "missing": {"$ref": "#/definitions/Missing"}
+            }
+        });
+        let object = json!({"spec": {"replicas": -1, "extra": 1}, "missing": {"a": 1}});
+        let mut from_schema = object.clone();
+        let mut from_compiled = object;
+        assert_eq!(
+            prune(&mut from_schema, &schema),
+            ["missing.a", "spec.extra"]
+        );
+        assert_eq!(
+            prune_compiled(&mut from_compiled, &compile_schema(&schema)),
+            ["missing.a", "spec.extra"]
+        );
+        assert_eq!(
+            from_schema,
+            json!({"spec": {"replicas": -1}, "missing": {}})
+        );
+        assert_eq!(from_schema, from_compiled);
+    }
+}
@@ -0,0 +1,462 @@
+//! Grouped views over validation results.
+//!
+//! [`RuleReport`], returned by
+//! [`Validator::validate_with_report`](crate::validation::Validator::validate_with_report),
+//! lists the outcome of every rule alongside the errors.
+//!
+//! [`ValidationReport`] groups a flat list of [`ValidationError`]s by field
+//! path, so CLIs and UIs can print one block per field:
+//!
+//! ```text
+//! spec.replicas:
+//!   - must be non-negative
+//!   - must be at most 10
+//! spec.image:
+//!   - must be pinned by digest
+//! ```
+
+use std::time::Duration;
+
+use crate::validation::ValidationError;
+
+/// Why a rule was not evaluated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A transition rule had no `oldSelf` because there is no old object
+    /// (a create).
+    NoOldObject,
+    /// A transition rule had no `oldSelf` because this node could not be
+    /// correlated with the old object: the field or list item is new, or it
+    /// sits below an atomic map or list.
+    Uncorrelated,
+    /// The rule exceeded the per-rule cost limit or the remaining budget.
+    CostLimitExceeded,
+    /// The rule failed to compile.
+    CompilationFailed,
+    /// Validation had already stopped (cost budget exhausted or `max_errors` reached).
+    Halted,
+    /// Ratcheting skipped the rule because the value is unchanged from the
+    /// old object.
+    Ratcheted,
+}
+
+/// A problem the validator worked around without reporting an error,
+/// delivered to the hook set with
+/// [`ValidatorOptions::with_diagnostics`](crate::validation::ValidatorOptions::with_diagnostics).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// A failing rule's `messageExpression` could not produce a message, so
+    /// the static `message` (or the default) was reported instead.
+    MessageExpressionFallback {
+        /// The rule that failed.
+        rule: String,
+        /// Path of the node the rule is declared on.
+        field_path: String,
+        /// Why the expression could not be used.
+        cause: String,
+    },
+    /// A `date-time`, `duration`, `byte` or custom-format string did not
+    /// parse and was bound to rules as a plain CEL `string`.
+    FormatFallback {
+        /// Path of the value.
+        field_path: String,
+        /// The schema format, e.g. `"date-time"`.
+        format: String,
+        /// The unparseable value.
+        value: String,
+    },
+    /// A transition rule was skipped because it had no `oldSelf`.
+    TransitionRuleSkipped {
+        /// The transition rule.
+        rule: String,
+        /// Path of the node the rule is declared on.
+        field_path: String,
+        /// [`SkipReason::NoOldObject`] or [`SkipReason::Uncorrelated`].
+        reason: SkipReason,
+    },
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::MessageExpressionFallback {
+                rule,
+                field_path,
+                cause,
+            } => write!(
+                f,
+                "{}: messageExpression of \"{rule}\" not used: {cause}",
+                display_path(field_path)
+            ),
+            Diagnostic::FormatFallback {
+                field_path,
+                format,
+                value,
+            } => write!(
+                f,
+                "{}: {value:?} is not a valid {format}; bound as a string",
+                display_path(field_path)
+            ),
+            Diagnostic::TransitionRuleSkipped {
+                rule,
+                field_path,
+                reason,
+            } => {
+                let why = match reason {
+                    SkipReason::NoOldObject => "no old object",
+                    _ => "no correlated old value",
+                };
+                write!(
+                    f,
+                    "{}: transition rule \"{rule}\" skipped: {why}",
+                    display_path(field_path)
+                )
+            }
+        }
+    }
+}
+
+/// What happened to a single rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleStatus {
+    /// The rule evaluated to `true`.
+    Passed,
+    /// The rule produced an error (returned `false`, a non-bool, or failed to evaluate).
+    Failed,
+    /// The rule was not evaluated.
+    Skipped(SkipReason),
+}
+
+/// The outcome of one rule at one position in the object.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleOutcome {
+    /// The CEL expression.
+    pub rule: String,
+    /// Path of the node the rule is declared on.
+    pub field_path: String,
+    /// Whether the rule passed, failed, or was skipped.
+    pub status: RuleStatus,
+    /// Time spent evaluating the rule (zero for skipped rules).
+    pub duration: Duration,
+}
+
+/// Errors plus per-rule outcomes from a single validation.
+///
+/// Rules below nodes that were never walked (e.g. absent fields) do not
+/// appear in `outcomes`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RuleReport {
+    /// The validation errors, as returned by `validate`.
+    pub errors: Vec<ValidationError>,
+    /// Failures of warning-severity rules (`x-kube-cel-severity: warning`),
+    /// and [`ErrorKind::MessageExpressionFailure`](crate::validation::ErrorKind::MessageExpressionFailure)
+    /// warnings for unusable `messageExpression` results. These never
+    /// appear in `errors`.
+    pub warnings: Vec<ValidationError>,
+    /// One entry per rule evaluation, in walk order.
+    pub outcomes: Vec<RuleOutcome>,
+}
+
+impl RuleReport {
+    /// Outcomes with the given status.
+    pub fn with_status<'a>(
+        &'a self,
+        status: &'a RuleStatus,
+    ) -> impl Iterator<Item = &'a RuleOutcome> + 'a {
+        self.outcomes.iter().filter(move |o| &o.status == status)
+    }
+
+    /// Transition rules that were skipped for lack of `oldSelf`.
+    pub fn skipped_transition_rules(&self) -> impl Iterator<Item = &RuleOutcome> {
+        self.outcomes.iter().filter(|o| {
+            matches!(
+                o.status,
+                RuleStatus::Skipped(SkipReason::NoOldObject | SkipReason::Uncorrelated)
+            )
+        })
+    }
+
+    /// One line per skipped transition rule explaining why it did not run,
+    /// e.g. `spec: "self.replicas >= oldSelf.replicas" skipped: no old object (create)`.
+    #[must_use]
+    pub fn explain_skipped_transitions(&self) -> Vec<String> {
+        self.skipped_transition_rules()
+            .map(|o| {
+                let why = match o.status {
+                    RuleStatus::Skipped(SkipReason::NoOldObject) => "no old object (create)",
+                    _ => "no correlated old value (new field or item, or below an atomic map/list)",
+                };
+                format!(
+                    "{}: \"{}\" skipped: {why}",
+                    display_path(&o.field_path),
+                    o.rule
+                )
+            })
+            .collect()
+    }
+
+    /// Total time spent evaluating rules.
+    #[must_use]
+    pub fn total_duration(&self) -> Duration {
+        self.outcomes.iter().map(|o| o.duration).sum()
+    }
+}
+
+/// All errors reported for a single field path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FieldReport {
+    /// The field path shared by every error (empty for the root).
+    pub field_path: String,
+    /// The errors for this field, in the order they were reported.
+    pub errors: Vec<ValidationError>,
+}
+
+impl FieldReport {
+    /// One-line summary, e.g. `spec.replicas: 2 errors (must be non-negative; too large)`.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let messages: Vec<&str> = self.errors.iter().map(|e| e.message.as_str()).collect();
+        let count = match self.errors.len() {
+            1 => "1 error".to_string(),
+            n => format!("{n} errors"),
+        };
+        format!(
+            "{}: {count} ({})",
+            display_path(&self.field_path),
+            messages.join("; ")
+        )
+    }
+}
+
+/// Validation errors grouped by field path.
+///
+/// Fields appear in the order their first error was reported.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    fields: Vec<FieldReport>,
+}
+
+impl ValidationReport {
+    /// Group `errors` by field path.
+    #[must_use]
+    pub fn new(errors: Vec<ValidationError>) -> Self {
+        let mut fields: Vec<FieldReport> = Vec::new();
+        for error in errors {
+            match fields.iter_mut().find(|f| f.field_path == error.field_path) {
+                Some(field) => field.errors.push(error),
+                None => fields.push(FieldReport {
+                    field_path: error.field_path.clone(),
+                    errors: vec![error],
+                }),
+            }
+        }
+        Self { fields }
+    }
+
+    /// The per-field groups.
+    #[must_use]
+    pub fn fields(&self) -> &[FieldReport] {
+        &self.fields
+    }
+
+    /// The group for `field_path`, if it has errors.
+    #[must_use]
+    pub fn field(&self, field_path: &str) -> Option<&FieldReport> {
+        self.fields.iter().find(|f| f.field_path == field_path)
+    }
+
+    /// Returns `true` if there are no errors.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Total number of errors across all fields.
+    #[must_use]
+    pub fn error_count(&self) -> usize {
+        self.fields.iter().map(|f| f.errors.len()).sum()
+    }
+
+    /// All errors, flattened back into a list grouped by field.
+    #[must_use]
+    pub fn into_errors(self) -> Vec<ValidationError> {
+        self.fields.into_iter().flat_map(|f| f.errors).collect()
+    }
+}
+
+impl From<Vec<ValidationError>> for ValidationReport {
+    fn from(errors: Vec<ValidationError>) -> Self {
+        Self::new(errors)
+    }
+}
+
+impl std::fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for field in &self.fields {
+            writeln!(f, "{}:", display_path(&field.field_path))?;
+            for error in &field.errors {
+                writeln!(f, "  - {}", error.message)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn display_path(field_path: &str) -> &str {
+    if field_path.is_empty() {
+        "<root>"
+    } else {
+        field_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::validate;
+    use serde_json::json;
+
+    fn errors() -> Vec<ValidationError> {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "has(self.spec)", "message": "spec required"}],
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {
+                            "type": "integer",
+                            "x-kubernetes-validations": [
+                                {"rule": "self >= 0", "message": "must be non-negative"},
+                                {"rule": "self % 2 == 0", "message": "must be even"}
+                            ]
+                        },
+                        "image": {
+                            "type": "string",
+                            "x-kubernetes-validations": [
+                                {"rule": "self.contains('@')", "message": "must be pinned"}
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+        validate(
+            &schema,
+            &json!({"spec": {"replicas": -1, "image": "nginx"}}),
+            None,
+        )
+    }
+
+    #[test]
+    fn groups_by_field_path() {
+        let report = ValidationReport::new(errors());
+        assert_eq!(report.error_count(), 3);
+        assert_eq!(report.fields().len(), 2);
+        let replicas = report.field("spec.replicas").unwrap();
+        assert_eq!(replicas.errors.len(), 2);
+        assert_eq!(
+            replicas.summary(),
+            "spec.replicas: 2 errors (must be non-negative; must be even)"
+        );
+        assert!(report.field("spec").is_none());
+    }
+
+    #[test]
+    fn display_prints_blocks() {
+        let report = ValidationReport::from(errors());
+        let text = report.to_string();
+        assert!(text.contains("spec.replicas:\n  - must be non-negative\n  - must be even\n"));
+        assert!(text.contains("spec.image:\n  - must be pinned\n"));
+    }
+
+    #[test]
+    fn rule_report_outcomes() {
+        use crate::validation::Validator;
+
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.replicas >= 0"},
+                {"rule": "self.replicas < 10"},
+                {"rule": "self.replicas >= oldSelf.replicas"},
+                {"rule": "self.replicas >="}
+            ],
+            "properties": {"replicas": {"type": "integer"}}
+        });
+        let report = Validator::new().validate_with_report(&schema, &json!({"replicas": 20}), None);
+        let statuses: Vec<_> = report.outcomes.iter().map(|o| o.status.clone()).collect();
+        assert_eq!(
+            statuses,
+            [
+                RuleStatus::Passed,
+                RuleStatus::Failed,
+                RuleStatus::Skipped(SkipReason::NoOldObject),
+                RuleStatus::Skipped(SkipReason::CompilationFailed),
+            ]
+        );
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.with_status(&RuleStatus::Passed).count(), 1);
+        assert!(report.total_duration() >= report.outcomes[0].duration);
+
+        let compiled = crate::compilation::compile_schema(&schema);
+        let compiled_report = Validator::new().validate_compiled_with_report(
+            &compiled,
+            &json!({"replicas": 20}),
+            None,
+        );
+        let compiled_statuses: Vec<_> = compiled_report
+            .outcomes
+            .iter()
+            .map(|o| o.status.clone())
+            .collect();
+        assert_eq!(compiled_statuses, statuses);
+    }
+
+    #[test]
+    fn explains_skipped_transition_rules() {
+        use crate::validation::Validator;
+
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "self.replicas >= oldSelf.replicas"}],
+            "properties": {
+                "replicas": {"type": "integer"},
+                "spec": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "self == oldSelf"}]
+                }
+            }
+        });
+        let v = Validator::new();
+        let created = v.validate_with_report(&schema, &json!({"replicas": 1, "spec": {}}), None);
+        assert_eq!(
+            created.explain_skipped_transitions(),
+            [
+                "<root>: \"self.replicas >= oldSelf.replicas\" skipped: no old object (create)",
+                "spec: \"self == oldSelf\" skipped: no old object (create)",
+            ]
+        );
+
+        let updated = v.validate_with_report(
+            &schema,
+            &json!({"replicas": 1, "spec": {}}),
+            Some(&json!({"replicas": 1})),
+        );
+        let skipped: Vec<_> = updated.skipped_transition_rules().collect();
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].field_path, "spec");
+        assert_eq!(
+            skipped[0].status,
+            RuleStatus::Skipped(SkipReason::Uncorrelated)
+        );
+    }
+
+    #[test]
+    fn empty_report() {
+        let report = ValidationReport::new(Vec::new());
+        assert!(report.is_empty());
+        assert_eq!(report.to_string(), "");
+        assert!(report.into_errors().is_empty());
+    }
+}
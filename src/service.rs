@@ -0,0 +1,124 @@
+//! A [`tower::Service`] adapter for validation.
+//!
+//! [`ValidatorHandle`] implements `Service<ValidationRequest>`, so
+//! validation composes with tower middleware such as timeouts, retries, and
+//! metrics layers:
+//!
+//! ```rust,ignore
+//! use std::time::Duration;
+//! use kube_cel::handle::ValidatorHandle;
+//! use kube_cel::service::ValidationRequest;
+//! use tower::{ServiceBuilder, ServiceExt};
+//!
+//! let service = ServiceBuilder::new()
+//!     .timeout(Duration::from_secs(1))
+//!     .service(ValidatorHandle::new(compile_schema(&schema)));
+//! let errors = service.oneshot(ValidationRequest::new(object)).await?;
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::task::JoinError;
+
+use crate::handle::ValidatorHandle;
+use crate::validation::ValidationError;
+
+/// An object to validate, with the existing object for transition rules.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationRequest {
+    /// The object to validate.
+    pub object: serde_json::Value,
+    /// The previous revision, bound as `oldSelf` in transition rules.
+    pub old_object: Option<serde_json::Value>,
+}
+
+impl ValidationRequest {
+    /// A request to validate a newly created `object`.
+    pub fn new(object: serde_json::Value) -> Self {
+        Self {
+            object,
+            old_object: None,
+        }
+    }
+
+    /// A request to validate an update from `old_object` to `object`.
+    pub fn update(object: serde_json::Value, old_object: serde_json::Value) -> Self {
+        Self {
+            object,
+            old_object: Some(old_object),
+        }
+    }
+}
+
+/// The future returned by [`ValidatorHandle`]'s `Service::call`.
+pub type ValidationFuture =
+    Pin<Box<dyn Future<Output = Result<Vec<ValidationError>, JoinError>> + Send>>;
+
+/// Always ready: [`ValidatorHandle::validate`] waits for a concurrency slot
+/// itself, so back-pressure applies inside the returned future.
+impl tower::Service<ValidationRequest> for ValidatorHandle {
+    type Response = Vec<ValidationError>;
+    type Error = JoinError;
+    type Future = ValidationFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ValidationRequest) -> Self::Future {
+        let handle = self.clone();
+        Box::pin(async move { handle.validate(request.object, request.old_object).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use serde_json::json;
+    use std::time::Duration;
+    use tower::{ServiceBuilder, ServiceExt};
+
+    fn handle() -> ValidatorHandle {
+        ValidatorHandle::new(compile_schema(&json!({
+            "type": "object",
+            "properties": {"replicas": {"type": "integer"}},
+            "x-kubernetes-validations": [
+                {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"},
+                {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+            ]
+        })))
+    }
+
+    #[tokio::test]
+    async fn handle_is_a_service() {
+        let errors = handle()
+            .oneshot(ValidationRequest::new(json!({"replicas": -1})))
+            .await
+            .unwrap();
+        assert_eq!(errors[0].message, "must be non-negative");
+
+        let errors = handle()
+            .oneshot(ValidationRequest::update(
+                json!({"replicas": 1}),
+                json!({"replicas": 2}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(errors[0].message, "cannot scale down");
+    }
+
+    #[tokio::test]
+    async fn composes_with_layers() {
+        let service = ServiceBuilder::new()
+            .timeout(Duration::from_secs(5))
+            .service(handle());
+        let errors = service
+            .oneshot(ValidationRequest::new(json!({"replicas": 3})))
+            .await
+            .unwrap();
+        assert!(errors.is_empty());
+    }
+}
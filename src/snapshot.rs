@@ -0,0 +1,589 @@
+//! Serializable snapshots of compiled schemas.
+//!
+//! Compiling a large CRD walks the whole schema, resolves `$ref`s, and
+//! estimates the cost of every rule. A [`SchemaSnapshot`] stores the result
+//! — the rules, their estimated costs and warnings, compilation errors, and
+//! the metadata of every node — so a process can [`restore`] it after a
+//! restart instead of compiling again. CEL programs cannot be serialized;
+//! they are parsed again on restore, which is the cheap part.
+//!
+//! A snapshot is keyed by the [`fingerprint`] of the schema and options it
+//! was taken with, and only restores for the same schema, options, and
+//! crate version.
+//!
+//! ```
+//! use kube_cel::compilation::{CompileOptions, compile_schema_with_options};
+//! use kube_cel::snapshot::SchemaSnapshot;
+//! use serde_json::json;
+//!
+//! let schema = json!({"type": "string", "x-kubernetes-validations": [{"rule": "self != ''"}]});
+//! let options = CompileOptions::default();
+//! let compiled = compile_schema_with_options(&schema, &options);
+//! let stored = serde_json::to_string(&SchemaSnapshot::new(&schema, &options, &compiled)).unwrap();
+//!
+//! // After a restart:
+//! let snapshot: SchemaSnapshot = serde_json::from_str(&stored).unwrap();
+//! let compiled = snapshot
+//!     .restore(&schema, &options)
+//!     .unwrap_or_else(|| compile_schema_with_options(&schema, &options));
+//! assert!(compiled.validations[0].is_ok());
+//! ```
+//!
+//! [`restore`]: SchemaSnapshot::restore
+
+use std::collections::BTreeMap;
+
+use cel::Program;
+
+use crate::compilation::{
+    CompilationError, CompilationResult, CompilationWarning, CompileOptions, CompiledSchema,
+    ListType, MapType, Programs, Rule,
+};
+use crate::fingerprint::Fnv;
+use crate::structural::StructuralSchema;
+use crate::values::SchemaFormat;
+
+/// A fingerprint of a schema and the options it is compiled with, like
+/// [`schema_fingerprint`](crate::fingerprint::schema_fingerprint). It also
+/// changes with the crate version, since compilation may change with it.
+#[must_use]
+pub fn fingerprint(schema: &serde_json::Value, options: &CompileOptions) -> String {
+    let mut hasher = Fnv::default();
+    hasher.write_str(env!("CARGO_PKG_NAME"));
+    hasher.write_str(env!("CARGO_PKG_VERSION"));
+    hasher.write_schema(schema);
+    for value in [
+        options.max_estimated_cost,
+        options.max_depth as u64,
+        options.max_rule_length as u64,
+        options.max_message_length as u64,
+        options.cost_warning_threshold,
+    ] {
+        hasher.write_u64(value);
+    }
+    for flag in [
+        options.strict,
+        options.reject_invalid_message_expressions,
+        options.typecheck,
+        options.check_functions,
+    ] {
+        hasher.write(&[u8::from(flag)]);
+    }
+    hasher.hex()
+}
+
+/// A serializable form of a [`CompiledSchema`].
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct SchemaSnapshot {
+    /// The [`fingerprint`] of the schema and options the snapshot was
+    /// taken with.
+    pub fingerprint: String,
+    root: SnapshotNode,
+}
+
+impl SchemaSnapshot {
+    /// Take a snapshot of `compiled`, the result of compiling `schema` with
+    /// `options`.
+    #[must_use]
+    pub fn new(
+        schema: &serde_json::Value,
+        options: &CompileOptions,
+        compiled: &CompiledSchema,
+    ) -> Self {
+        Self {
+            fingerprint: fingerprint(schema, options),
+            root: SnapshotNode::new(compiled),
+        }
+    }
+
+    /// Rebuild the compiled schema, parsing its programs again.
+    ///
+    /// Returns `None` if the snapshot was taken from a different schema,
+    /// with different options, or by another version of this crate; the
+    /// schema should then be compiled again.
+    #[must_use]
+    pub fn restore(
+        &self,
+        schema: &serde_json::Value,
+        options: &CompileOptions,
+    ) -> Option<CompiledSchema> {
+        if self.fingerprint != fingerprint(schema, options) {
+            return None;
+        }
+        self.root.restore(&mut Programs::new(options))
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct SnapshotNode {
+    validations: Vec<SnapshotRule>,
+    properties: BTreeMap<String, SnapshotNode>,
+    items: Option<Box<SnapshotNode>>,
+    additional_properties: Option<Box<SnapshotNode>>,
+    format: SchemaFormat,
+    list_type: ListType,
+    map_type: MapType,
+    preserve_unknown_fields: bool,
+    structural: StructuralSchema,
+}
+
+impl SnapshotNode {
+    pub(crate) fn new(compiled: &CompiledSchema) -> Self {
+        let child = |node: &Option<Box<CompiledSchema>>| {
+            node.as_deref().map(|node| Box::new(Self::new(node)))
+        };
+        Self {
+            validations: compiled.validations.iter().map(SnapshotRule::new).collect(),
+            properties: compiled
+                .properties
+                .iter()
+                .map(|(name, node)| (name.clone(), Self::new(node)))
+                .collect(),
+            items: child(&compiled.items),
+            additional_properties: child(&compiled.additional_properties),
+            format: compiled.format.clone(),
+            list_type: compiled.list_type.clone(),
+            map_type: compiled.map_type.clone(),
+            preserve_unknown_fields: compiled.preserve_unknown_fields,
+            structural: compiled.structural.clone(),
+        }
+    }
+
+    fn restore(&self, programs: &mut Programs<'_>) -> Option<CompiledSchema> {
+        let mut child = |node: &Option<Box<SnapshotNode>>| match node {
+            Some(node) => node.restore(programs).map(|node| Some(Box::new(node))),
+            None => Some(None),
+        };
+        let items = child(&self.items)?;
+        let additional_properties = child(&self.additional_properties)?;
+        Some(CompiledSchema {
+            validations: self
+                .validations
+                .iter()
+                .map(|rule| rule.restore(programs))
+                .collect::<Option<_>>()?,
+            properties: self
+                .properties
+                .iter()
+                .map(|(name, node)| Some((name.clone(), node.restore(programs)?)))
+                .collect::<Option<BTreeMap<_, _>>>()?,
+            items,
+            additional_properties,
+            format: self.format.clone(),
+            list_type: self.list_type.clone(),
+            map_type: self.map_type.clone(),
+            preserve_unknown_fields: self.preserve_unknown_fields,
+            structural: self.structural.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+enum SnapshotRule {
+    Compiled {
+        rule: Rule,
+        is_transition_rule: bool,
+        estimated_cost: u64,
+        warnings: Vec<CompilationWarning>,
+    },
+    Failed(SnapshotError),
+}
+
+impl SnapshotRule {
+    fn new(result: &Result<CompilationResult, CompilationError>) -> Self {
+        match result {
+            Ok(compiled) => SnapshotRule::Compiled {
+                rule: compiled.rule.clone(),
+                is_transition_rule: compiled.is_transition_rule,
+                estimated_cost: compiled.estimated_cost,
+                warnings: compiled.warnings.clone(),
+            },
+            Err(error) => SnapshotRule::Failed(SnapshotError::new(error)),
+        }
+    }
+
+    /// Parse the rule's programs again, or `None` if they no longer parse
+    /// the way they did when the snapshot was taken.
+    fn restore(
+        &self,
+        programs: &mut Programs<'_>,
+    ) -> Option<Result<CompilationResult, CompilationError>> {
+        match self {
+            SnapshotRule::Compiled {
+                rule,
+                is_transition_rule,
+                estimated_cost,
+                warnings,
+            } => {
+                let message_program = match &rule.message_expression {
+                    Some(expression) => {
+                        let invalid = warnings.iter().any(|warning| {
+                            matches!(warning, CompilationWarning::InvalidMessageExpression { .. })
+                        });
+                        let program = programs.compile(expression).ok();
+                        if program.is_none() != invalid {
+                            return None;
+                        }
+                        program
+                    }
+                    None => None,
+                };
+                Some(Ok(CompilationResult {
+                    program: programs.compile(&rule.rule).ok()?,
+                    rule: rule.clone(),
+                    is_transition_rule: *is_transition_rule,
+                    message_program,
+                    estimated_cost: *estimated_cost,
+                    warnings: warnings.clone(),
+                }))
+            }
+            SnapshotRule::Failed(error) => Some(Err(error.restore()?)),
+        }
+    }
+}
+
+/// A [`CompilationError`] without its unserializable sources, which are
+/// recreated on restore.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+enum SnapshotError {
+    Parse {
+        rule: String,
+        schema_path: String,
+    },
+    InvalidRule {
+        message: String,
+        schema_path: String,
+    },
+    CostLimitExceeded {
+        rule: String,
+        estimated_cost: u64,
+        limit: u64,
+        schema_path: String,
+    },
+    InvalidDefinition {
+        rule: String,
+        message: String,
+        schema_path: String,
+    },
+    UndeclaredFunction {
+        rule: String,
+        function: String,
+        suggestion: Option<String>,
+        schema_path: String,
+    },
+    InvalidRegex {
+        rule: String,
+        pattern: String,
+        message: String,
+        schema_path: String,
+    },
+    TypeCheck {
+        rule: String,
+        message: String,
+        schema_path: String,
+    },
+    UncorrelatableTransitionRule {
+        rule: String,
+        schema_path: String,
+    },
+    DepthLimitExceeded {
+        limit: usize,
+        schema_path: String,
+    },
+    InvalidRef {
+        reference: String,
+        message: String,
+        schema_path: String,
+    },
+}
+
+impl SnapshotError {
+    fn new(error: &CompilationError) -> Self {
+        let schema_path = error.schema_path().to_string();
+        match error {
+            CompilationError::Parse { rule, .. } => SnapshotError::Parse {
+                rule: rule.clone(),
+                schema_path,
+            },
+            CompilationError::InvalidRule { source, .. } => SnapshotError::InvalidRule {
+                message: source.to_string(),
+                schema_path,
+            },
+            CompilationError::CostLimitExceeded {
+                rule,
+                estimated_cost,
+                limit,
+                ..
+            } => SnapshotError::CostLimitExceeded {
+                rule: rule.clone(),
+                estimated_cost: *estimated_cost,
+                limit: *limit,
+                schema_path,
+            },
+            CompilationError::InvalidDefinition { rule, message, .. } => {
+                SnapshotError::InvalidDefinition {
+                    rule: rule.clone(),
+                    message: message.clone(),
+                    schema_path,
+                }
+            }
+            CompilationError::UndeclaredFunction {
+                rule,
+                function,
+                suggestion,
+                ..
+            } => SnapshotError::UndeclaredFunction {
+                rule: rule.clone(),
+                function: function.clone(),
+                suggestion: suggestion.clone(),
+                schema_path,
+            },
+            CompilationError::InvalidRegex {
+                rule,
+                pattern,
+                message,
+                ..
+            } => SnapshotError::InvalidRegex {
+                rule: rule.clone(),
+                pattern: pattern.clone(),
+                message: message.clone(),
+                schema_path,
+            },
+            CompilationError::TypeCheck { rule, message, .. } => SnapshotError::TypeCheck {
+                rule: rule.clone(),
+                message: message.clone(),
+                schema_path,
+            },
+            CompilationError::UncorrelatableTransitionRule { rule, .. } => {
+                SnapshotError::UncorrelatableTransitionRule {
+                    rule: rule.clone(),
+                    schema_path,
+                }
+            }
+            CompilationError::DepthLimitExceeded { limit, .. } => {
+                SnapshotError::DepthLimitExceeded {
+                    limit: *limit,
+                    schema_path,
+                }
+            }
+            CompilationError::InvalidRef {
+                reference, message, ..
+            } => SnapshotError::InvalidRef {
+                reference: reference.clone(),
+                message: message.clone(),
+                schema_path,
+            },
+        }
+    }
+
+    /// The error, or `None` if a rule that failed to parse now parses.
+    fn restore(&self) -> Option<CompilationError> {
+        let error = self.clone();
+        Some(match error {
+            SnapshotError::Parse { rule, schema_path } => CompilationError::Parse {
+                source: Program::compile(&rule).err()?,
+                rule,
+                schema_path,
+            },
+            SnapshotError::InvalidRule {
+                message,
+                schema_path,
+            } => CompilationError::InvalidRule {
+                source: serde::de::Error::custom(message),
+                schema_path,
+            },
+            SnapshotError::CostLimitExceeded {
+                rule,
+                estimated_cost,
+                limit,
+                schema_path,
+            } => CompilationError::CostLimitExceeded {
+                rule,
+                estimated_cost,
+                limit,
+                schema_path,
+            },
+            SnapshotError::InvalidDefinition {
+                rule,
+                message,
+                schema_path,
+            } => CompilationError::InvalidDefinition {
+                rule,
+                message,
+                schema_path,
+            },
+            SnapshotError::UndeclaredFunction {
+                rule,
+                function,
+                suggestion,
+                schema_path,
+            } => CompilationError::UndeclaredFunction {
+                rule,
+                function,
+                suggestion,
+                schema_path,
+            },
+            SnapshotError::InvalidRegex {
+                rule,
+                pattern,
+                message,
+                schema_path,
+            } => CompilationError::InvalidRegex {
+                rule,
+                pattern,
+                message,
+                schema_path,
+            },
+            SnapshotError::TypeCheck {
+                rule,
+                message,
+                schema_path,
+            } => CompilationError::TypeCheck {
+                rule,
+                message,
+                schema_path,
+            },
+            SnapshotError::UncorrelatableTransitionRule { rule, schema_path } => {
+                CompilationError::UncorrelatableTransitionRule { rule, schema_path }
+            }
+            SnapshotError::DepthLimitExceeded { limit, schema_path } => {
+                CompilationError::DepthLimitExceeded { limit, schema_path }
+            }
+            SnapshotError::InvalidRef {
+                reference,
+                message,
+                schema_path,
+            } => CompilationError::InvalidRef {
+                reference,
+                message,
+                schema_path,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema_with_options;
+    use crate::validation::validate_compiled;
+    use serde_json::json;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.name.size() > 0", "messageExpression": "'bad name ' + self.name"},
+                {"rule": "self.name ==", "message": "broken"},
+                {"message": "no rule"},
+                {"rule": "self.items.all(i, i.all(j, j.all(k, k.size() < 10)))"}
+            ],
+            "properties": {
+                "name": {"type": "string", "pattern": "^[a-z]+$"},
+                "items": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "atomic",
+                    "items": {"type": "array", "items": {"type": "array", "items": {"type": "string"}}}
+                },
+                "created": {"type": "string", "format": "date-time", "maxLength": 32,
+                    "x-kubernetes-validations": [{"rule": "self != oldSelf", "messageExpression": "'x' +"}]}
+            }
+        })
+    }
+
+    fn restore(schema: &serde_json::Value, options: &CompileOptions) -> CompiledSchema {
+        let compiled = compile_schema_with_options(schema, options);
+        let stored =
+            serde_json::to_string(&SchemaSnapshot::new(schema, options, &compiled)).unwrap();
+        let snapshot: SchemaSnapshot = serde_json::from_str(&stored).unwrap();
+        snapshot.restore(schema, options).unwrap()
+    }
+
+    fn errors(compiled: &CompiledSchema) -> Vec<String> {
+        compiled
+            .compilation_errors()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn restored_schema_matches_the_compiled_one() {
+        let schema = schema();
+        let options = CompileOptions {
+            max_estimated_cost: 1000,
+            ..CompileOptions::default()
+        };
+        let compiled = compile_schema_with_options(&schema, &options);
+        let restored = restore(&schema, &options);
+
+        assert_eq!(errors(&restored), errors(&compiled));
+        assert_eq!(errors(&restored).len(), 3);
+        let created = |s: &CompiledSchema| {
+            let rule = s.properties["created"].validations[0].as_ref().unwrap();
+            (
+                rule.is_transition_rule,
+                rule.warnings.clone(),
+                rule.message_program.is_none(),
+            )
+        };
+        assert_eq!(created(&restored), created(&compiled));
+        assert_eq!(
+            restored.properties["created"].format,
+            SchemaFormat::DateTime
+        );
+        assert_eq!(restored.properties["items"].list_type, ListType::Atomic);
+
+        for (object, old) in [
+            (json!({"name": ""}), None),
+            (json!({"name": "Bad"}), None),
+            (
+                json!({"name": "a", "created": "2024-01-01T00:00:00Z"}),
+                Some(json!({"name": "a", "created": "2024-01-01T00:00:00Z"})),
+            ),
+        ] {
+            assert_eq!(
+                validate_compiled(&restored, &object, old.as_ref()),
+                validate_compiled(&compiled, &object, old.as_ref()),
+            );
+        }
+    }
+
+    #[test]
+    fn restore_requires_the_same_schema_and_options() {
+        let schema = schema();
+        let options = CompileOptions::default();
+        let compiled = compile_schema_with_options(&schema, &options);
+        let snapshot = SchemaSnapshot::new(&schema, &options, &compiled);
+
+        assert!(snapshot.restore(&schema, &options).is_some());
+        assert!(
+            snapshot
+                .restore(&json!({"type": "object"}), &options)
+                .is_none()
+        );
+        let strict = CompileOptions {
+            strict: true,
+            ..CompileOptions::default()
+        };
+        assert!(snapshot.restore(&schema, &strict).is_none());
+    }
+
+    #[test]
+    fn fingerprint_ignores_key_order() {
+        let options = CompileOptions::default();
+        let a: serde_json::Value =
+            serde_json::from_str(r#"{"type": "string", "maxLength": 3}"#).unwrap();
+        let b: serde_json::Value =
+            serde_json::from_str(r#"{"maxLength": 3, "type": "string"}"#).unwrap();
+        assert_eq!(fingerprint(&a, &options), fingerprint(&b, &options));
+        assert_ne!(
+            fingerprint(&a, &options),
+            fingerprint(&json!({"type": "string", "maxLength": 4}), &options)
+        );
+        assert_ne!(
+            fingerprint(&json!("1"), &options),
+            fingerprint(&json!(1), &options)
+        );
+    }
+}
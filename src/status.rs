@@ -0,0 +1,174 @@
+//! Conversion of validation errors into Kubernetes `Status` responses.
+//!
+//! Admission webhooks and controllers can return the result of
+//! [`to_status`] as-is to produce the same `422 Invalid` payload the
+//! apiserver returns when a CRD validation rule fails:
+//!
+//! ```json
+//! {
+//!   "kind": "Status",
+//!   "apiVersion": "v1",
+//!   "status": "Failure",
+//!   "reason": "Invalid",
+//!   "code": 422,
+//!   "message": "Widget \"demo\" is invalid: spec.replicas: Invalid value: must be non-negative",
+//!   "details": {
+//!     "name": "demo",
+//!     "kind": "Widget",
+//!     "causes": [
+//!       {"reason": "FieldValueInvalid", "message": "Invalid value: must be non-negative", "field": "spec.replicas"}
+//!     ]
+//!   }
+//! }
+//! ```
+
+use serde_json::json;
+
+use crate::compilation::Reason;
+use crate::validation::ValidationError;
+
+/// HTTP status code of an `Invalid` status.
+const STATUS_CODE_INVALID: u16 = 422;
+
+/// Build a `metav1.Status` JSON body for failed validation of `kind`/`name`.
+///
+/// Each error becomes a `details.causes[]` entry. The cause `reason` defaults
+/// to `FieldValueInvalid` when the rule did not set one. Unlike the apiserver,
+/// `Invalid value` messages do not include the offending value's type.
+///
+/// An empty error list produces a `Success` status.
+#[must_use]
+pub fn to_status(errors: &[ValidationError], kind: &str, name: &str) -> serde_json::Value {
+    if errors.is_empty() {
+        return json!({
+            "kind": "Status",
+            "apiVersion": "v1",
+            "metadata": {},
+            "status": "Success",
+            "code": 200
+        });
+    }
+
+    let causes: Vec<_> = errors
+        .iter()
+        .map(|e| {
+            json!({
+                "reason": reason_of(e).as_str(),
+                "message": cause_message(e),
+                "field": e.field_path,
+            })
+        })
+        .collect();
+
+    let details: Vec<String> = errors
+        .iter()
+        .map(|e| {
+            if e.field_path.is_empty() {
+                cause_message(e)
+            } else {
+                format!("{}: {}", e.field_path, cause_message(e))
+            }
+        })
+        .collect();
+    let summary = match details.as_slice() {
+        [single] => single.clone(),
+        many => format!("[{}]", many.join(", ")),
+    };
+
+    json!({
+        "kind": "Status",
+        "apiVersion": "v1",
+        "metadata": {},
+        "status": "Failure",
+        "message": format!("{kind} \"{name}\" is invalid: {summary}"),
+        "reason": "Invalid",
+        "details": {
+            "name": name,
+            "kind": kind,
+            "causes": causes,
+        },
+        "code": STATUS_CODE_INVALID,
+    })
+}
+
+fn reason_of(error: &ValidationError) -> Reason {
+    error.reason.clone().unwrap_or(Reason::FieldValueInvalid)
+}
+
+/// The cause message, prefixed like `field.Error` in the apiserver.
+fn cause_message(error: &ValidationError) -> String {
+    let prefix = match reason_of(error) {
+        Reason::FieldValueInvalid | Reason::Unknown(_) => "Invalid value",
+        Reason::FieldValueForbidden => "Forbidden",
+        Reason::FieldValueRequired => "Required value",
+        Reason::FieldValueDuplicate => "Duplicate value",
+    };
+    format!("{prefix}: {}", error.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::validation::{ErrorKind, PathSegment};
+
+    fn error(field_path: &str, message: &str, reason: Option<Reason>) -> ValidationError {
+        ValidationError {
+            rule: "false".into(),
+            message: message.into(),
+            field_path: field_path.into(),
+            path: vec![PathSegment::Field(field_path.into())],
+            reason,
+            kind: ErrorKind::ValidationFailure,
+        }
+    }
+
+    #[test]
+    fn single_error() {
+        let status = to_status(
+            &[error("spec.replicas", "must be non-negative", None)],
+            "Widget",
+            "demo",
+        );
+        assert_eq!(status["code"], 422);
+        assert_eq!(status["reason"], "Invalid");
+        assert_eq!(
+            status["message"],
+            "Widget \"demo\" is invalid: spec.replicas: Invalid value: must be non-negative"
+        );
+        assert_eq!(
+            status["details"]["causes"],
+            json!([{
+                "reason": "FieldValueInvalid",
+                "message": "Invalid value: must be non-negative",
+                "field": "spec.replicas"
+            }])
+        );
+    }
+
+    #[test]
+    fn multiple_errors_with_reasons() {
+        let status = to_status(
+            &[
+                error("spec.a", "immutable", Some(Reason::FieldValueForbidden)),
+                error("", "name required", Some(Reason::FieldValueRequired)),
+            ],
+            "Widget",
+            "demo",
+        );
+        assert_eq!(
+            status["message"],
+            "Widget \"demo\" is invalid: [spec.a: Forbidden: immutable, Required value: name required]"
+        );
+        assert_eq!(
+            status["details"]["causes"][0]["reason"],
+            "FieldValueForbidden"
+        );
+        assert_eq!(status["details"]["causes"][1]["field"], "");
+    }
+
+    #[test]
+    fn no_errors_is_success() {
+        let status = to_status(&[], "Widget", "demo");
+        assert_eq!(status["status"], "Success");
+    }
+}
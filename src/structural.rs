@@ -0,0 +1,451 @@
+//! Structural (non-CEL) OpenAPI schema validation.
+//!
+//! Checks the built-in OpenAPI constraints of a schema node — `type`,
+//! `required`, `enum`, `pattern`, `minimum`/`maximum`,
+//! `minLength`/`maxLength`, `minItems`/`maxItems`, and `uniqueItems` — the
+//! way the apiserver does before evaluating `x-kubernetes-validations`.
+//!
+//! Enable it on a [`Validator`](crate::validation::Validator) with
+//! [`ValidatorOptions::with_structural_validation`](crate::validation::ValidatorOptions::with_structural_validation)
+//! to check these constraints during the same walk as the CEL rules.
+
+use regex::Regex;
+
+use crate::compilation::{Reason, forbids_additional_properties};
+use crate::validation::{ErrorKind, PathSegment, ValidationError, render_path};
+
+/// The structural constraints of a single schema node.
+///
+/// Constraints of child nodes are not included; `required` only checks that
+/// the named properties are present on this node's object.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct StructuralSchema {
+    schema_type: Option<String>,
+    nullable: bool,
+    int_or_string: bool,
+    required: Vec<String>,
+    /// The declared property names, if `additionalProperties: false`.
+    closed_properties: Option<Vec<String>>,
+    enum_values: Option<Vec<serde_json::Value>>,
+    #[serde(with = "pattern")]
+    pattern: Option<(String, Result<Regex, String>)>,
+    minimum: Option<f64>,
+    exclusive_minimum: bool,
+    maximum: Option<f64>,
+    exclusive_maximum: bool,
+    min_length: Option<u64>,
+    max_length: Option<u64>,
+    min_items: Option<u64>,
+    max_items: Option<u64>,
+    unique_items: bool,
+}
+
+impl StructuralSchema {
+    /// Read the structural constraints of a schema node. The `pattern` is
+    /// compiled once here.
+    #[must_use]
+    pub fn from_schema(schema: &serde_json::Value) -> Self {
+        let flag = |key: &str| schema.get(key).and_then(|v| v.as_bool()) == Some(true);
+        Self {
+            schema_type: schema
+                .get("type")
+                .and_then(|t| t.as_str())
+                .map(str::to_string),
+            nullable: flag("nullable"),
+            int_or_string: flag("x-kubernetes-int-or-string"),
+            required: schema
+                .get("required")
+                .and_then(|r| r.as_array())
+                .map(|r| {
+                    r.iter()
+                        .filter_map(|v| v.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            closed_properties: forbids_additional_properties(schema).then(|| {
+                schema
+                    .get("properties")
+                    .and_then(|p| p.as_object())
+                    .map(|p| p.keys().cloned().collect())
+                    .unwrap_or_default()
+            }),
+            enum_values: schema.get("enum").and_then(|e| e.as_array()).cloned(),
+            pattern: schema
+                .get("pattern")
+                .and_then(|p| p.as_str())
+                .map(compile_pattern),
+            minimum: schema.get("minimum").and_then(|v| v.as_f64()),
+            exclusive_minimum: flag("exclusiveMinimum"),
+            maximum: schema.get("maximum").and_then(|v| v.as_f64()),
+            exclusive_maximum: flag("exclusiveMaximum"),
+            min_length: schema.get("minLength").and_then(|v| v.as_u64()),
+            max_length: schema.get("maxLength").and_then(|v| v.as_u64()),
+            min_items: schema.get("minItems").and_then(|v| v.as_u64()),
+            max_items: schema.get("maxItems").and_then(|v| v.as_u64()),
+            unique_items: flag("uniqueItems"),
+        }
+    }
+
+    /// Returns `true` if the node has no structural constraints.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.schema_type.is_none()
+            && self.required.is_empty()
+            && self.closed_properties.is_none()
+            && self.enum_values.is_none()
+            && self.pattern.is_none()
+            && self.minimum.is_none()
+            && self.maximum.is_none()
+            && self.min_length.is_none()
+            && self.max_length.is_none()
+            && self.min_items.is_none()
+            && self.max_items.is_none()
+            && !self.unique_items
+    }
+
+    /// Check `value` against this node's constraints. Errors are reported
+    /// at `path`, except missing required fields and fields forbidden by
+    /// `additionalProperties: false`, which are reported at the field's path.
+    ///
+    /// If the value has the wrong type, only the type error is reported.
+    #[must_use]
+    pub fn validate(
+        &self,
+        value: &serde_json::Value,
+        path: &[PathSegment],
+    ) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        let mut fail = |keyword: &str, message: String, reason: Reason| {
+            errors.push(structural_error(path, keyword, message, reason));
+        };
+
+        if value.is_null() && self.nullable {
+            return errors;
+        }
+        if let Some(expected) = self.type_mismatch(value) {
+            fail(
+                "type",
+                format!("must be of type {expected}"),
+                Reason::FieldValueInvalid,
+            );
+            return errors;
+        }
+
+        if let Some(allowed) = &self.enum_values
+            && !allowed.contains(value)
+        {
+            let supported: Vec<String> = allowed.iter().map(|v| v.to_string()).collect();
+            fail(
+                "enum",
+                format!("supported values: {}", supported.join(", ")),
+                Reason::FieldValueInvalid,
+            );
+        }
+
+        match value {
+            serde_json::Value::Number(n) => {
+                let Some(n) = n.as_f64() else {
+                    return errors;
+                };
+                if let Some(min) = self.minimum {
+                    if self.exclusive_minimum && n <= min {
+                        fail(
+                            "minimum",
+                            format!("should be greater than {min}"),
+                            Reason::FieldValueInvalid,
+                        );
+                    } else if n < min {
+                        fail(
+                            "minimum",
+                            format!("should be greater than or equal to {min}"),
+                            Reason::FieldValueInvalid,
+                        );
+                    }
+                }
+                if let Some(max) = self.maximum {
+                    if self.exclusive_maximum && n >= max {
+                        fail(
+                            "maximum",
+                            format!("should be less than {max}"),
+                            Reason::FieldValueInvalid,
+                        );
+                    } else if n > max {
+                        fail(
+                            "maximum",
+                            format!("should be less than or equal to {max}"),
+                            Reason::FieldValueInvalid,
+                        );
+                    }
+                }
+            }
+            serde_json::Value::String(s) => {
+                let len = s.chars().count() as u64;
+                if let Some(min) = self.min_length
+                    && len < min
+                {
+                    fail(
+                        "minLength",
+                        format!("should be at least {min} chars long"),
+                        Reason::FieldValueInvalid,
+                    );
+                }
+                if let Some(max) = self.max_length
+                    && len > max
+                {
+                    fail(
+                        "maxLength",
+                        format!("may not be longer than {max} chars"),
+                        Reason::FieldValueInvalid,
+                    );
+                }
+                match &self.pattern {
+                    Some((pattern, Ok(regex))) if !regex.is_match(s) => fail(
+                        "pattern",
+                        format!("should match '{pattern}'"),
+                        Reason::FieldValueInvalid,
+                    ),
+                    Some((pattern, Err(e))) => fail(
+                        "pattern",
+                        format!("invalid pattern '{pattern}': {e}"),
+                        Reason::FieldValueInvalid,
+                    ),
+                    _ => {}
+                }
+            }
+            serde_json::Value::Array(items) => {
+                let len = items.len() as u64;
+                if let Some(min) = self.min_items
+                    && len < min
+                {
+                    fail(
+                        "minItems",
+                        format!("should have at least {min} items"),
+                        Reason::FieldValueInvalid,
+                    );
+                }
+                if let Some(max) = self.max_items
+                    && len > max
+                {
+                    fail(
+                        "maxItems",
+                        format!("must have at most {max} items"),
+                        Reason::FieldValueInvalid,
+                    );
+                }
+                if self.unique_items
+                    && items
+                        .iter()
+                        .enumerate()
+                        .any(|(i, item)| items[..i].contains(item))
+                {
+                    fail(
+                        "uniqueItems",
+                        "shouldn't contain duplicates".to_string(),
+                        Reason::FieldValueDuplicate,
+                    );
+                }
+            }
+            serde_json::Value::Object(obj) => {
+                for name in &self.required {
+                    if !obj.contains_key(name) {
+                        let mut field = path.to_vec();
+                        field.push(PathSegment::Field(name.clone()));
+                        errors.push(structural_error(
+                            &field,
+                            "required",
+                            "field is required".to_string(),
+                            Reason::FieldValueRequired,
+                        ));
+                    }
+                }
+                if let Some(declared) = &self.closed_properties {
+                    for name in obj.keys().filter(|k| !declared.contains(k)) {
+                        let mut field = path.to_vec();
+                        field.push(PathSegment::Field(name.clone()));
+                        errors.push(structural_error(
+                            &field,
+                            "additionalProperties",
+                            "field not declared in schema".to_string(),
+                            Reason::FieldValueInvalid,
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+        errors
+    }
+
+    /// The expected type name if `value` does not match `type`.
+    fn type_mismatch(&self, value: &serde_json::Value) -> Option<&str> {
+        if self.int_or_string {
+            let ok = value.is_string() || value.as_f64().is_some_and(|f| f.fract() == 0.0);
+            return (!ok).then_some("integer or string");
+        }
+        let expected = self.schema_type.as_deref()?;
+        let ok = match expected {
+            "object" => value.is_object(),
+            "array" => value.is_array(),
+            "string" => value.is_string(),
+            "boolean" => value.is_boolean(),
+            "number" => value.is_number(),
+            "integer" => value.as_f64().is_some_and(|f| f.fract() == 0.0),
+            _ => true,
+        };
+        (!ok).then_some(expected)
+    }
+}
+
+/// Compile a `pattern`, keeping the error message if it is invalid.
+fn compile_pattern(pattern: &str) -> (String, Result<Regex, String>) {
+    (
+        pattern.to_string(),
+        Regex::new(pattern).map_err(|e| e.to_string()),
+    )
+}
+
+/// (De)serializes a `pattern` as its source, compiling it again on load.
+mod pattern {
+    use regex::Regex;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    type Pattern = Option<(String, Result<Regex, String>)>;
+
+    pub(super) fn serialize<S: Serializer>(pattern: &Pattern, s: S) -> Result<S::Ok, S::Error> {
+        pattern.as_ref().map(|(source, _)| source).serialize(s)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Pattern, D::Error> {
+        Ok(Option::<String>::deserialize(d)?
+            .as_deref()
+            .map(super::compile_pattern))
+    }
+}
+
+fn structural_error(
+    path: &[PathSegment],
+    keyword: &str,
+    message: String,
+    reason: Reason,
+) -> ValidationError {
+    ValidationError {
+        rule: keyword.to_string(),
+        message,
+        field_path: render_path(path),
+        path: path.to_vec(),
+        reason: Some(reason),
+        kind: ErrorKind::SchemaViolation,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn check(schema: serde_json::Value, value: serde_json::Value) -> Vec<String> {
+        StructuralSchema::from_schema(&schema)
+            .validate(&value, &[PathSegment::Field("spec".into())])
+            .into_iter()
+            .map(|e| format!("{}: {}", e.field_path, e.message))
+            .collect()
+    }
+
+    #[test]
+    fn type_mismatch_stops_other_checks() {
+        assert_eq!(
+            check(json!({"type": "integer", "minimum": 5}), json!("x")),
+            ["spec: must be of type integer"]
+        );
+        assert!(check(json!({"type": "integer"}), json!(3.0)).is_empty());
+        assert!(check(json!({"type": "string", "nullable": true}), json!(null)).is_empty());
+        assert!(check(json!({"x-kubernetes-int-or-string": true}), json!("50%")).is_empty());
+    }
+
+    #[test]
+    fn numeric_bounds() {
+        let schema =
+            json!({"type": "integer", "minimum": 1, "maximum": 10, "exclusiveMaximum": true});
+        assert!(check(schema.clone(), json!(1)).is_empty());
+        assert_eq!(
+            check(schema.clone(), json!(0)),
+            ["spec: should be greater than or equal to 1"]
+        );
+        assert_eq!(check(schema, json!(10)), ["spec: should be less than 10"]);
+    }
+
+    #[test]
+    fn string_constraints() {
+        let schema =
+            json!({"type": "string", "minLength": 2, "maxLength": 4, "pattern": "^[a-z]+$"});
+        assert!(check(schema.clone(), json!("abc")).is_empty());
+        assert_eq!(
+            check(schema.clone(), json!("A")),
+            [
+                "spec: should be at least 2 chars long",
+                "spec: should match '^[a-z]+$'"
+            ]
+        );
+        assert_eq!(
+            check(schema, json!("abcde")),
+            ["spec: may not be longer than 4 chars"]
+        );
+        assert_eq!(
+            check(json!({"type": "string", "pattern": "("}), json!("x")).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn enum_and_required() {
+        assert_eq!(
+            check(json!({"enum": ["a", "b"]}), json!("c")),
+            ["spec: supported values: \"a\", \"b\""]
+        );
+        let errors =
+            StructuralSchema::from_schema(&json!({"type": "object", "required": ["name", "size"]}))
+                .validate(&json!({"size": 1}), &[]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "name");
+        assert_eq!(errors[0].reason, Some(Reason::FieldValueRequired));
+    }
+
+    #[test]
+    fn closed_objects_reject_unknown_fields() {
+        let closed = json!({
+            "type": "object",
+            "properties": {"a": {"type": "string"}},
+            "additionalProperties": false
+        });
+        assert!(check(closed.clone(), json!({"a": "x"})).is_empty());
+        assert_eq!(
+            check(closed, json!({"a": "x", "b": 1})),
+            ["spec.b: field not declared in schema"]
+        );
+        let open = json!({"type": "object", "additionalProperties": true});
+        assert!(check(open, json!({"b": 1})).is_empty());
+    }
+
+    #[test]
+    fn list_constraints() {
+        let schema = json!({"type": "array", "minItems": 1, "maxItems": 2, "uniqueItems": true});
+        assert!(check(schema.clone(), json!([1, 2])).is_empty());
+        assert_eq!(
+            check(schema.clone(), json!([])),
+            ["spec: should have at least 1 items"]
+        );
+        assert_eq!(
+            check(schema, json!([1, 1, 1])),
+            [
+                "spec: must have at most 2 items",
+                "spec: shouldn't contain duplicates"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_schema_has_no_constraints() {
+        assert!(StructuralSchema::from_schema(&json!({"description": "x"})).is_empty());
+        assert!(!StructuralSchema::from_schema(&json!({"type": "object"})).is_empty());
+    }
+}
@@ -0,0 +1,731 @@
+//! Schema-aware type checking of validation rules.
+//!
+//! kube-apiserver compiles `x-kubernetes-validations` rules in an environment
+//! where `self` and `oldSelf` are declared with types derived from the
+//! schema, so a rule that selects an undeclared field or compares values of
+//! different types is rejected when the CRD is applied. [`typecheck`]
+//! reproduces the most common of these checks:
+//!
+//! - field selections (including `has()`) on an object must name a declared
+//!   property, unless the object allows additional or unknown fields;
+//! - field selections are only allowed on objects and maps;
+//! - `==`, `!=`, `<`, `<=`, `>` and `>=` must compare values of the same
+//!   type, and ordering is only defined on scalars.
+//!
+//! The checker is deliberately lenient: anything it cannot type, such as a
+//! function result or a `$ref` node, is `dyn` and matches everything.
+//! Numeric types compare with each other, as number fields may bind as
+//! either `int` or `double`.
+//!
+//! [`Declarations`] exposes the same environment to tools that do their own
+//! checking or completion, such as linters and editor plugins:
+//!
+//! ```
+//! use kube_cel::typecheck::{DeclType, Declarations};
+//! use serde_json::json;
+//!
+//! let schema = json!({"type": "object", "properties": {
+//!     "replicas": {"type": "integer"},
+//!     "max-surge": {"type": "string"}
+//! }});
+//! let declarations = Declarations::new(&schema);
+//! let self_type = &declarations.variables["self"];
+//! assert_eq!(self_type.field("replicas"), Some(&DeclType::Int));
+//! assert_eq!(self_type.field("max__dash__surge"), Some(&DeclType::String));
+//! assert!(declarations.function("startsWith").is_some());
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use cel::common::ast::{EntryExpr, Expr, operators};
+use cel::common::value::CelVal;
+use cel::parser::Expression;
+
+use crate::compilation::{CompilationResult, Rule, additional_properties_schema, expand_node};
+use crate::escaping::escape_field_name;
+use crate::values::SchemaFormat;
+
+/// Check the rule of `result`, declared on `schema`, against the types the
+/// schema gives `self` and `oldSelf`.
+///
+/// Returns the first problem found, in cel-go's wording. `oldSelf` is not
+/// typed for rules with `optionalOldSelf`, as it is then an optional.
+pub fn typecheck(result: &CompilationResult, schema: &serde_json::Value) -> Result<(), String> {
+    let declared = Type::from_schema(schema);
+    let old_self = if result.rule.optional_old_self == Some(true) {
+        Type::Dyn
+    } else {
+        declared.clone()
+    };
+    let scope = [
+        ("self".to_string(), declared),
+        ("oldSelf".to_string(), old_self),
+    ]
+    .into_iter()
+    .collect();
+    check(result.program.expression(), &scope).map(|_| ())
+}
+
+/// The variables and functions a rule on a schema node is compiled with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declarations {
+    /// The declared variables by name: `self` and `oldSelf`.
+    pub variables: BTreeMap<String, DeclType>,
+    /// The declared functions, sorted by name.
+    pub functions: Vec<FunctionDecl>,
+}
+
+impl Declarations {
+    /// The declarations of a rule on `schema`.
+    #[must_use]
+    pub fn new(schema: &serde_json::Value) -> Self {
+        let declared = DeclType::from_type(&Type::from_schema(schema));
+        Declarations {
+            variables: [
+                ("self".to_string(), declared.clone()),
+                ("oldSelf".to_string(), declared),
+            ]
+            .into_iter()
+            .collect(),
+            functions: functions(),
+        }
+    }
+
+    /// The declarations of `rule` on `schema`. `oldSelf` is `dyn` for
+    /// rules with `optionalOldSelf`, as it is then an optional.
+    #[must_use]
+    pub fn for_rule(rule: &Rule, schema: &serde_json::Value) -> Self {
+        let mut declarations = Declarations::new(schema);
+        if rule.optional_old_self == Some(true) {
+            declarations
+                .variables
+                .insert("oldSelf".to_string(), DeclType::Dyn);
+        }
+        declarations
+    }
+
+    /// The declared function called `name`, qualified by its namespace for
+    /// functions like `math.ceil`.
+    #[must_use]
+    pub fn function(&self, name: &str) -> Option<&FunctionDecl> {
+        self.functions
+            .binary_search_by(|function| function.name.cmp(name))
+            .ok()
+            .map(|i| &self.functions[i])
+    }
+}
+
+/// A declared function.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FunctionDecl {
+    /// The function name, qualified by its namespace for functions like
+    /// `math.ceil`.
+    pub name: &'static str,
+    /// The type of the function's result where the checker knows it, `dyn`
+    /// otherwise.
+    pub result: DeclType,
+}
+
+/// The declared functions, sorted by name.
+fn functions() -> Vec<FunctionDecl> {
+    let mut functions: Vec<_> = crate::functions::declared_functions()
+        .map(|name| FunctionDecl {
+            name,
+            result: DeclType::from_type(
+                &check_call(name, Some(Type::Dyn), &[]).unwrap_or(Type::Dyn),
+            ),
+        })
+        .collect();
+    functions.sort_by_key(|function| function.name);
+    functions
+}
+
+/// The declared type of a variable or field.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeclType {
+    Int,
+    Uint,
+    Double,
+    String,
+    Bytes,
+    Bool,
+    Null,
+    Duration,
+    Timestamp,
+    List(Box<DeclType>),
+    Map(Box<DeclType>, Box<DeclType>),
+    /// An object with declared properties.
+    Object {
+        /// The declared properties by their escaped names, as selected in
+        /// CEL (`max__dash__surge` for `max-surge`).
+        fields: BTreeMap<String, DeclType>,
+        /// The type of fields the object does not declare, if it allows
+        /// additional (or, as `dyn`, unknown) fields.
+        other_fields: Option<Box<DeclType>>,
+    },
+    /// Unknown: compatible with every type.
+    Dyn,
+}
+
+impl DeclType {
+    /// The type of field `name` (escaped, as written in CEL) of this type,
+    /// or `None` if it has no such field.
+    #[must_use]
+    pub fn field(&self, name: &str) -> Option<&DeclType> {
+        match self {
+            DeclType::Dyn => Some(&DeclType::Dyn),
+            DeclType::Map(_, values) => Some(values),
+            DeclType::Object {
+                fields,
+                other_fields,
+            } => fields.get(name).or(other_fields.as_deref()),
+            _ => None,
+        }
+    }
+
+    /// The fully expanded form of a checker type.
+    fn from_type(ty: &Type) -> DeclType {
+        match ty {
+            Type::Int => DeclType::Int,
+            Type::Uint => DeclType::Uint,
+            Type::Double => DeclType::Double,
+            Type::String => DeclType::String,
+            Type::Bytes => DeclType::Bytes,
+            Type::Bool => DeclType::Bool,
+            Type::Null => DeclType::Null,
+            Type::Duration => DeclType::Duration,
+            Type::Timestamp => DeclType::Timestamp,
+            Type::List(elements) => DeclType::List(Box::new(DeclType::from_type(elements))),
+            Type::Map(keys, values) => DeclType::Map(
+                Box::new(DeclType::from_type(keys)),
+                Box::new(DeclType::from_type(values)),
+            ),
+            Type::Object(schema) => DeclType::Object {
+                fields: schema
+                    .get("properties")
+                    .and_then(|p| p.as_object())
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, property)| {
+                        (
+                            escape_field_name(name),
+                            DeclType::from_type(&Type::from_schema(property)),
+                        )
+                    })
+                    .collect(),
+                other_fields: match additional_properties_schema(schema) {
+                    Some(values) => Some(Box::new(DeclType::from_type(&Type::from_schema(values)))),
+                    None if allows_unknown_fields(schema) => Some(Box::new(DeclType::Dyn)),
+                    None => None,
+                },
+            },
+            Type::Dyn => DeclType::Dyn,
+        }
+    }
+}
+
+/// In cel-go's type names, like [`typecheck`] errors.
+impl std::fmt::Display for DeclType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeclType::Int => write!(f, "int"),
+            DeclType::Uint => write!(f, "uint"),
+            DeclType::Double => write!(f, "double"),
+            DeclType::String => write!(f, "string"),
+            DeclType::Bytes => write!(f, "bytes"),
+            DeclType::Bool => write!(f, "bool"),
+            DeclType::Null => write!(f, "null_type"),
+            DeclType::Duration => write!(f, "google.protobuf.Duration"),
+            DeclType::Timestamp => write!(f, "google.protobuf.Timestamp"),
+            DeclType::List(elements) => write!(f, "list({elements})"),
+            DeclType::Map(keys, values) => write!(f, "map({keys}, {values})"),
+            DeclType::Object { .. } => write!(f, "object"),
+            DeclType::Dyn => write!(f, "dyn"),
+        }
+    }
+}
+
+/// The static type of an expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Int,
+    Uint,
+    Double,
+    String,
+    Bytes,
+    Bool,
+    Null,
+    Duration,
+    Timestamp,
+    List(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    /// An object with declared properties. Holds the expanded schema node.
+    Object(serde_json::Value),
+    /// Unknown: compatible with every type.
+    Dyn,
+}
+
+impl Type {
+    /// The type a value described by `schema` binds as.
+    fn from_schema(schema: &serde_json::Value) -> Type {
+        if schema.get("$ref").is_some() {
+            return Type::Dyn;
+        }
+        let schema = expand_node(schema);
+        if SchemaFormat::from_schema(&schema) == SchemaFormat::IntOrString {
+            return Type::Dyn;
+        }
+        match schema.get("type").and_then(|t| t.as_str()) {
+            Some("integer") => Type::Int,
+            Some("number") => Type::Double,
+            Some("boolean") => Type::Bool,
+            Some("string") => match SchemaFormat::from_schema(&schema) {
+                SchemaFormat::DateTime => Type::Timestamp,
+                SchemaFormat::Duration => Type::Duration,
+                SchemaFormat::Byte => Type::Bytes,
+                _ => Type::String,
+            },
+            Some("array") => Type::List(Box::new(
+                schema.get("items").map_or(Type::Dyn, Type::from_schema),
+            )),
+            Some("object") if schema.get("properties").is_some() => {
+                Type::Object(schema.into_owned())
+            }
+            Some("object") => match additional_properties_schema(&schema) {
+                Some(values) => {
+                    Type::Map(Box::new(Type::String), Box::new(Type::from_schema(values)))
+                }
+                None => Type::Dyn,
+            },
+            _ => Type::Dyn,
+        }
+    }
+
+    /// The type of field `name` (escaped, as written in CEL) of this type.
+    fn field(&self, name: &str) -> Result<Type, String> {
+        match self {
+            Type::Dyn => Ok(Type::Dyn),
+            Type::Map(_, values) => Ok((**values).clone()),
+            Type::Object(schema) => {
+                let declared = schema
+                    .get("properties")
+                    .and_then(|p| p.as_object())
+                    .and_then(|props| props.iter().find(|(k, _)| escape_field_name(k) == name));
+                match declared {
+                    Some((_, property)) => Ok(Type::from_schema(property)),
+                    None => match additional_properties_schema(schema) {
+                        Some(values) => Ok(Type::from_schema(values)),
+                        None if allows_unknown_fields(schema) => Ok(Type::Dyn),
+                        None => Err(format!("undefined field '{name}'")),
+                    },
+                }
+            }
+            other => Err(format!("type '{other}' does not support field selection")),
+        }
+    }
+
+    /// The type of the elements of a list, or of the values of a map.
+    fn elements(&self) -> Type {
+        match self {
+            Type::List(elements) => (**elements).clone(),
+            Type::Map(_, values) => (**values).clone(),
+            _ => Type::Dyn,
+        }
+    }
+
+    /// The type of the variable a comprehension over this type binds:
+    /// list elements, or map keys.
+    fn iter_var(&self) -> Type {
+        match self {
+            Type::List(elements) => (**elements).clone(),
+            Type::Map(keys, _) => (**keys).clone(),
+            Type::Object(_) => Type::String,
+            _ => Type::Dyn,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Uint | Type::Double)
+    }
+
+    /// Whether values of the two types can be tested for equality.
+    fn comparable(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Dyn | Type::Null, _) | (_, Type::Dyn | Type::Null) => true,
+            (a, b) if a.is_numeric() && b.is_numeric() => true,
+            (Type::List(a), Type::List(b)) => a.comparable(b),
+            (Type::Map(ka, va), Type::Map(kb, vb)) => ka.comparable(kb) && va.comparable(vb),
+            (Type::Object(_) | Type::Map(..), Type::Object(_) | Type::Map(..)) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    /// Whether values of the two types can be ordered with `<` and friends.
+    fn orderable(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Dyn, Type::Dyn) => true,
+            (Type::Dyn, t) | (t, Type::Dyn) => t.is_scalar(),
+            (a, b) if a.is_numeric() && b.is_numeric() => true,
+            (a, b) => a == b && a.is_scalar(),
+        }
+    }
+
+    fn is_scalar(&self) -> bool {
+        !matches!(
+            self,
+            Type::List(_) | Type::Map(..) | Type::Object(_) | Type::Null
+        )
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Uint => write!(f, "uint"),
+            Type::Double => write!(f, "double"),
+            Type::String => write!(f, "string"),
+            Type::Bytes => write!(f, "bytes"),
+            Type::Bool => write!(f, "bool"),
+            Type::Null => write!(f, "null_type"),
+            Type::Duration => write!(f, "google.protobuf.Duration"),
+            Type::Timestamp => write!(f, "google.protobuf.Timestamp"),
+            Type::List(elements) => write!(f, "list({elements})"),
+            Type::Map(keys, values) => write!(f, "map({keys}, {values})"),
+            Type::Object(_) => write!(f, "object"),
+            Type::Dyn => write!(f, "dyn"),
+        }
+    }
+}
+
+/// Whether an object node accepts fields it does not declare.
+fn allows_unknown_fields(schema: &serde_json::Value) -> bool {
+    schema
+        .get("x-kubernetes-preserve-unknown-fields")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+type Scope = HashMap<String, Type>;
+
+/// Type `expr`, failing on the first ill-typed subexpression.
+fn check(expr: &Expression, scope: &Scope) -> Result<Type, String> {
+    match &expr.expr {
+        Expr::Literal(literal) => Ok(match literal {
+            CelVal::Int(_) => Type::Int,
+            CelVal::UInt(_) => Type::Uint,
+            CelVal::Double(_) => Type::Double,
+            CelVal::String(_) => Type::String,
+            CelVal::Bytes(_) => Type::Bytes,
+            CelVal::Boolean(_) => Type::Bool,
+            CelVal::Null => Type::Null,
+            _ => Type::Dyn,
+        }),
+        Expr::Ident(name) => Ok(scope.get(name).cloned().unwrap_or(Type::Dyn)),
+        Expr::Select(select) => {
+            let field = check(&select.operand, scope)?.field(&select.field)?;
+            Ok(if select.test { Type::Bool } else { field })
+        }
+        Expr::List(list) => {
+            let mut elements = list
+                .elements
+                .iter()
+                .map(|e| check(e, scope))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter();
+            let first = elements.next().unwrap_or(Type::Dyn);
+            let uniform = elements.all(|t| t == first);
+            Ok(Type::List(Box::new(if uniform {
+                first
+            } else {
+                Type::Dyn
+            })))
+        }
+        Expr::Map(map) => {
+            for entry in &map.entries {
+                check_entry(&entry.expr, scope)?;
+            }
+            Ok(Type::Map(Box::new(Type::Dyn), Box::new(Type::Dyn)))
+        }
+        Expr::Struct(st) => {
+            for entry in &st.entries {
+                check_entry(&entry.expr, scope)?;
+            }
+            Ok(Type::Dyn)
+        }
+        Expr::Call(call) => {
+            let target = call
+                .target
+                .as_deref()
+                .map(|t| check(t, scope))
+                .transpose()?;
+            let args = call
+                .args
+                .iter()
+                .map(|a| check(a, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            check_call(&call.func_name, target, &args)
+        }
+        Expr::Comprehension(comp) => {
+            let range = check(&comp.iter_range, scope)?;
+            check(&comp.accu_init, scope)?;
+            let mut inner = scope.clone();
+            match &comp.iter_var2 {
+                Some(value_var) => {
+                    let key = match &range {
+                        Type::List(_) => Type::Int,
+                        _ => range.iter_var(),
+                    };
+                    inner.insert(comp.iter_var.clone(), key);
+                    inner.insert(value_var.clone(), range.elements());
+                }
+                None => {
+                    inner.insert(comp.iter_var.clone(), range.iter_var());
+                }
+            }
+            inner.insert(comp.accu_var.clone(), Type::Dyn);
+            check(&comp.loop_cond, &inner)?;
+            check(&comp.loop_step, &inner)?;
+            check(&comp.result, &inner)
+        }
+        Expr::Unspecified => Ok(Type::Dyn),
+    }
+}
+
+fn check_entry(entry: &EntryExpr, scope: &Scope) -> Result<(), String> {
+    match entry {
+        EntryExpr::StructField(field) => check(&field.value, scope).map(|_| ()),
+        EntryExpr::MapEntry(e) => {
+            check(&e.key, scope)?;
+            check(&e.value, scope).map(|_| ())
+        }
+    }
+}
+
+/// The result type of a call whose operands have the given types.
+fn check_call(name: &str, target: Option<Type>, args: &[Type]) -> Result<Type, String> {
+    let no_overload = || {
+        let operands: Vec<_> = args.iter().map(Type::to_string).collect();
+        format!(
+            "found no matching overload for '{name}' applied to '({})'",
+            operands.join(", ")
+        )
+    };
+    match (name, args) {
+        (operators::EQUALS | operators::NOT_EQUALS, [a, b]) => {
+            if a.comparable(b) {
+                Ok(Type::Bool)
+            } else {
+                Err(no_overload())
+            }
+        }
+        (
+            operators::LESS
+            | operators::LESS_EQUALS
+            | operators::GREATER
+            | operators::GREATER_EQUALS,
+            [a, b],
+        ) => {
+            if a.orderable(b) {
+                Ok(Type::Bool)
+            } else {
+                Err(no_overload())
+            }
+        }
+        (
+            operators::LOGICAL_AND
+            | operators::LOGICAL_OR
+            | operators::LOGICAL_NOT
+            | operators::IN
+            | operators::NOT_STRICTLY_FALSE,
+            _,
+        ) => Ok(Type::Bool),
+        (operators::CONDITIONAL, [_, a, b]) if a == b => Ok(a.clone()),
+        (operators::INDEX, [container, _]) => Ok(container.elements()),
+        (
+            operators::ADD
+            | operators::SUBSTRACT
+            | operators::MULTIPLY
+            | operators::DIVIDE
+            | operators::MODULO,
+            [a, b],
+        ) if a == b => Ok(a.clone()),
+        (operators::NEGATE, [a]) => Ok(a.clone()),
+        ("size", _) => Ok(Type::Int),
+        ("int", _) => Ok(Type::Int),
+        ("uint", _) => Ok(Type::Uint),
+        ("double", _) => Ok(Type::Double),
+        ("string", _) => Ok(Type::String),
+        ("bool", _) => Ok(Type::Bool),
+        ("bytes", _) => Ok(Type::Bytes),
+        ("duration", _) => Ok(Type::Duration),
+        ("timestamp", _) => Ok(Type::Timestamp),
+        ("contains" | "startsWith" | "endsWith" | "matches", _) if target.is_some() => {
+            Ok(Type::Bool)
+        }
+        ("lowerAscii" | "upperAscii" | "trim", _) if target.is_some() => Ok(Type::String),
+        _ => Ok(Type::Dyn),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_rule;
+    use serde_json::json;
+
+    fn schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "replicas": {"type": "integer"},
+                "name": {"type": "string"},
+                "max-surge": {"type": "integer"},
+                "timeout": {"type": "string", "format": "duration"},
+                "ports": {"type": "array", "items": {
+                    "type": "object",
+                    "properties": {"port": {"type": "integer"}}
+                }},
+                "labels": {"type": "object", "additionalProperties": {"type": "string"}},
+                "extra": {"type": "object", "x-kubernetes-preserve-unknown-fields": true},
+                "template": {
+                    "type": "object",
+                    "x-kubernetes-embedded-resource": true,
+                    "properties": {"spec": {"type": "object"}}
+                },
+                "port": {"x-kubernetes-int-or-string": true}
+            }
+        })
+    }
+
+    fn check_rule(rule: &str) -> Result<(), String> {
+        let rule: Rule = serde_json::from_value(json!({"rule": rule})).unwrap();
+        typecheck(&compile_rule(&rule).unwrap(), &schema())
+    }
+
+    #[test]
+    fn well_typed_rules_pass() {
+        for rule in [
+            "self.replicas >= 0 && self.name != ''",
+            "self.max__dash__surge <= self.replicas",
+            "has(self.name) && self.name.startsWith('a')",
+            "self.timeout < duration('1h')",
+            "self.ports.all(p, p.port > 0 && p.port < 65536)",
+            "self.labels.all(k, self.labels[k] != 'x' && k.size() < 64)",
+            "self.extra.anything == 1",
+            "self.template.kind == 'Pod' && self.template.metadata.name != ''",
+            "self.port == 80 || self.port == 'http'",
+            "self.replicas == 1.0",
+            "self.name == oldSelf.name",
+            "self.ports.size() == 0 || self.ports[0].port == 80",
+            "size(self.name) > 0 ? self.name != 'x' : true",
+        ] {
+            assert_eq!(check_rule(rule), Ok(()), "{rule}");
+        }
+    }
+
+    #[test]
+    fn undeclared_fields_are_rejected() {
+        assert_eq!(
+            check_rule("self.replica > 0"),
+            Err("undefined field 'replica'".into())
+        );
+        assert_eq!(
+            check_rule("has(self.nmae)"),
+            Err("undefined field 'nmae'".into())
+        );
+        assert_eq!(
+            check_rule("self.ports.exists(p, p.number == 80)"),
+            Err("undefined field 'number'".into())
+        );
+        assert_eq!(
+            check_rule("self.replicas.value > 0"),
+            Err("type 'int' does not support field selection".into())
+        );
+    }
+
+    #[test]
+    fn mismatched_comparisons_are_rejected() {
+        assert_eq!(
+            check_rule("self.replicas == '3'"),
+            Err("found no matching overload for '_==_' applied to '(int, string)'".into())
+        );
+        assert_eq!(
+            check_rule("self.name > 5"),
+            Err("found no matching overload for '_>_' applied to '(string, int)'".into())
+        );
+        assert_eq!(
+            check_rule("self.timeout < 60"),
+            Err(
+                "found no matching overload for '_<_' applied to '(google.protobuf.Duration, int)'"
+                    .into()
+            )
+        );
+        assert_eq!(
+            check_rule("self.ports < []"),
+            Err(
+                "found no matching overload for '_<_' applied to '(list(object), list(dyn))'"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn declarations_expand_the_schema() {
+        let declarations = Declarations::new(&schema());
+        let declared = &declarations.variables["self"];
+        assert_eq!(declarations.variables["oldSelf"], *declared);
+        assert_eq!(declared.field("max__dash__surge"), Some(&DeclType::Int));
+        assert_eq!(declared.field("timeout"), Some(&DeclType::Duration));
+        assert_eq!(declared.field("replica"), None);
+        assert_eq!(
+            declared.field("ports").map(ToString::to_string).as_deref(),
+            Some("list(object)")
+        );
+        let labels = declared.field("labels").unwrap();
+        assert_eq!(labels.field("anything"), Some(&DeclType::String));
+        let extra = declared.field("extra").unwrap();
+        assert_eq!(extra.field("anything"), Some(&DeclType::Dyn));
+        let template = declared.field("template").unwrap();
+        assert_eq!(template.field("kind"), Some(&DeclType::String));
+        assert_eq!(declared.field("port"), Some(&DeclType::Dyn));
+
+        let rule: Rule = serde_json::from_value(json!({
+            "rule": "true",
+            "optionalOldSelf": true
+        }))
+        .unwrap();
+        let declarations = Declarations::for_rule(&rule, &schema());
+        assert_eq!(declarations.variables["oldSelf"], DeclType::Dyn);
+    }
+
+    #[test]
+    fn declarations_list_functions() {
+        let declarations = Declarations::new(&json!({"type": "string"}));
+        assert_eq!(
+            declarations.function("size").map(|f| &f.result),
+            Some(&DeclType::Int)
+        );
+        assert_eq!(
+            declarations.function("startsWith").map(|f| &f.result),
+            Some(&DeclType::Bool)
+        );
+        assert!(declarations.function("trimm").is_none());
+        assert!(
+            declarations
+                .functions
+                .windows(2)
+                .all(|w| w[0].name < w[1].name)
+        );
+    }
+
+    #[test]
+    fn optional_old_self_is_not_typed() {
+        let rule: Rule = serde_json::from_value(json!({
+            "rule": "oldSelf.hasValue() && oldSelf.value().replicas <= self.replicas",
+            "optionalOldSelf": true
+        }))
+        .unwrap();
+        assert_eq!(typecheck(&compile_rule(&rule).unwrap(), &schema()), Ok(()));
+    }
+}
@@ -5,13 +5,30 @@
 //! and collects [`ValidationError`]s.
 
 use crate::compilation::{
-    CompilationError, CompilationResult, CompiledSchema, compile_schema_validations,
+    CompilationError, CompilationResult, CompiledSchema, DEFAULT_MAX_DEPTH, ListType, MapType,
+    Reason, SchemaSource, Severity, additional_properties_schema, compile_schema_validations,
+    expand_node, join_location, resolve_refs,
+};
+use crate::cost::runtime_cost;
+use crate::escaping::escape_field_name;
+use crate::explain::{Explanation, explain_in};
+use crate::report::{Diagnostic, RuleOutcome, RuleReport, RuleStatus, SkipReason};
+use crate::structural::StructuralSchema;
+use crate::values::{
+    CustomFormats, Projection, SchemaFormat, format_error, json_to_cel, json_to_cel_in_document,
+    json_to_cel_projected_with_formats, json_to_cel_with_formats, json_to_cel_with_schema_formats,
+    parse_well_known_timestamps,
 };
-use crate::values::{json_to_cel_with_compiled, json_to_cel_with_schema};
 use cel::Context;
+use std::borrow::Cow;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 /// The kind of error that occurred during validation.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum ErrorKind {
     /// CEL expression syntax error.
     CompilationFailure,
@@ -23,10 +40,33 @@ pub enum ErrorKind {
     InvalidResult,
     /// Runtime evaluation error.
     EvaluationError,
+    /// A list exceeded the configured item limits and was only partially validated.
+    ListTruncated,
+    /// A rule exceeded the per-rule cost limit, or the validation exhausted
+    /// its total cost budget.
+    CostBudgetExceeded,
+    /// The validation deadline passed before all rules were evaluated.
+    DeadlineExceeded,
+    /// The validation was cancelled through its [`CancellationToken`].
+    Cancelled,
+    /// The value violates a structural schema constraint such as `type`,
+    /// `required`, or `maxLength` (see [`structural`](crate::structural)).
+    SchemaViolation,
+    /// The schema or the object is nested deeper than the configured
+    /// maximum depth; nodes below the limit were not validated.
+    DepthLimitExceeded,
+    /// A failing rule's `messageExpression` could not be used and the static
+    /// message was reported instead. Only appears in
+    /// [`RuleReport::warnings`], never among the errors.
+    MessageExpressionFailure,
 }
 
 /// An error produced when a CEL validation rule fails.
-#[derive(Clone, Debug, PartialEq, Eq)]
+///
+/// Serializes with camelCase field names (`fieldPath`, ...) for use in
+/// webhook and API responses.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ValidationError {
     /// The CEL expression that failed.
     pub rule: String,
@@ -34,8 +74,12 @@ pub struct ValidationError {
     pub message: String,
     /// JSON path to the field (e.g., "spec.replicas").
     pub field_path: String,
-    /// Machine-readable reason (e.g., "FieldValueInvalid").
-    pub reason: Option<String>,
+    /// The same path as structured segments, e.g. `spec`, `containers`, `[1]`.
+    #[serde(default)]
+    pub path: Vec<PathSegment>,
+    /// Machine-readable reason from the rule, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<Reason>,
     /// Classification of the error.
     pub kind: ErrorKind,
 }
@@ -52,6 +96,513 @@ impl std::fmt::Display for ValidationError {
 
 impl std::error::Error for ValidationError {}
 
+/// One step of a [`ValidationError::path`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PathSegment {
+    /// A declared object property.
+    Field(String),
+    /// A list item.
+    Index(usize),
+    /// A map entry (an `additionalProperties` key or a `['key']` field path).
+    Key(String),
+}
+
+/// How old and new values are paired for transition rules.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransitionCorrelation {
+    /// Follow the schema: `x-kubernetes-list-type` map/set lists are
+    /// correlated by key/value, and nothing below atomic maps or lists is
+    /// correlated (the apiserver behavior).
+    #[default]
+    Schema,
+    /// Pair list items by index and object fields by name, ignoring list and
+    /// map types.
+    Index,
+}
+
+/// The admission operation a validation is made for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// A new object; there is no old object.
+    Create,
+    /// A change to an existing object.
+    Update,
+    /// Removal of an object; there is only the old object.
+    Delete,
+}
+
+impl Operation {
+    /// The operation as named in admission requests, e.g. `CREATE`.
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Create => "CREATE",
+            Operation::Update => "UPDATE",
+            Operation::Delete => "DELETE",
+        }
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The part of a resource an update is made through.
+///
+/// For CRDs with a status subresource, the apiserver ignores changes to
+/// `status` on the main resource and changes to everything else on
+/// `/status`. See [`Validator::validate_subresource`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Subresource {
+    /// An update of the main resource: `status` keeps its old value.
+    Main,
+    /// An update through the `/status` subresource: only `status` changes.
+    Status,
+}
+
+/// A handle for cancelling validations from another thread.
+///
+/// Clones share the same flag. Cancellation is checked before each rule is
+/// evaluated; a rule that is already running completes first.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every validation using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once [`cancel`](Self::cancel) has been called.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Configuration for a [`Validator`].
+///
+/// Build one with [`Validator::builder`] and finish with
+/// [`build`](Self::build):
+///
+/// ```rust,ignore
+/// use kube_cel::validation::Validator;
+///
+/// let validator = Validator::builder()
+///     .with_cost_budget(kube_cel::cost::RUNTIME_COST_BUDGET)
+///     .with_max_errors(10)
+///     .build();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ValidatorOptions {
+    max_items_to_validate: Option<usize>,
+    max_item_failures: Option<usize>,
+    cost_budget: Option<u64>,
+    per_rule_cost_limit: Option<u64>,
+    message_cost_limit: Option<u64>,
+    max_errors: Option<usize>,
+    correlation: TransitionCorrelation,
+    dedup: bool,
+    deadline: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+    skip_unchanged: bool,
+    memoize: bool,
+    field_projection: bool,
+    ratcheting: bool,
+    structural: bool,
+    strict_formats: bool,
+    diagnostics: Option<DiagnosticHook>,
+    max_depth: Option<usize>,
+    max_object_nodes: Option<usize>,
+    /// `None` restricts metadata only in the CRD entry points.
+    restrict_metadata: Option<bool>,
+    well_known_timestamps: bool,
+    formats: CustomFormats,
+    variables: Vec<(String, cel::Value)>,
+    functions: Vec<FunctionRegistrar>,
+    #[cfg(feature = "rayon")]
+    parallel_rules: Option<usize>,
+}
+
+impl ValidatorOptions {
+    /// Create options with every limit disabled, except the nesting depth,
+    /// which defaults to [`DEFAULT_MAX_DEPTH`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Validate at most `max` items of any list; the remaining items are
+    /// skipped and an [`ErrorKind::ListTruncated`] error is reported.
+    ///
+    /// Rules declared on the list node itself still see the whole list.
+    #[must_use]
+    pub fn with_max_items_to_validate(mut self, max: usize) -> Self {
+        self.max_items_to_validate = Some(max);
+        self
+    }
+
+    /// Stop descending into a list's items once `max` items have produced
+    /// errors, reporting an [`ErrorKind::ListTruncated`] error if any items
+    /// were left unvalidated.
+    #[must_use]
+    pub fn with_max_item_failures(mut self, max: usize) -> Self {
+        self.max_item_failures = Some(max);
+        self
+    }
+
+    /// Limit the total CEL cost spent on a single validation.
+    ///
+    /// Each rule's cost is computed from the actual sizes of `self` and
+    /// `oldSelf` before it runs (see [`crate::cost`]). Once the budget is
+    /// exhausted, an [`ErrorKind::CostBudgetExceeded`] error is reported and
+    /// no further rules are evaluated. Kubernetes uses
+    /// [`RUNTIME_COST_BUDGET`](crate::cost::RUNTIME_COST_BUDGET).
+    #[must_use]
+    pub fn with_cost_budget(mut self, budget: u64) -> Self {
+        self.cost_budget = Some(budget);
+        self
+    }
+
+    /// Limit the CEL cost of any single rule evaluation.
+    ///
+    /// Rules whose cost exceeds the limit are not evaluated and produce an
+    /// [`ErrorKind::CostBudgetExceeded`] error. Kubernetes uses
+    /// [`PER_CALL_LIMIT`](crate::cost::PER_CALL_LIMIT).
+    #[must_use]
+    pub fn with_per_rule_cost_limit(mut self, limit: u64) -> Self {
+        self.per_rule_cost_limit = Some(limit);
+        self
+    }
+
+    /// Limit the CEL cost of evaluating a failing rule's `messageExpression`.
+    ///
+    /// A `messageExpression` over the limit is not evaluated; the static
+    /// message is reported instead, with an
+    /// [`ErrorKind::MessageExpressionFailure`] warning. Message costs are
+    /// never charged against the [cost budget](Self::with_cost_budget), so
+    /// an expensive message cannot stop later rules from running.
+    /// Kubernetes uses [`PER_CALL_LIMIT`](crate::cost::PER_CALL_LIMIT).
+    #[must_use]
+    pub fn with_message_expression_cost_limit(mut self, limit: u64) -> Self {
+        self.message_cost_limit = Some(limit);
+        self
+    }
+
+    /// Stop validating once `max` errors have been collected.
+    ///
+    /// The schema walk short-circuits, so no further rules are evaluated and
+    /// at most `max` errors are returned. A value of `0` is treated as `1`.
+    #[must_use]
+    pub fn with_max_errors(mut self, max: usize) -> Self {
+        self.max_errors = Some(max.max(1));
+        self
+    }
+
+    /// Stop validating at the first error. Shorthand for `with_max_errors(1)`.
+    #[must_use]
+    pub fn with_fail_fast(self) -> Self {
+        self.with_max_errors(1)
+    }
+
+    /// Choose how old values are correlated for transition rules.
+    #[must_use]
+    pub fn with_transition_correlation(mut self, correlation: TransitionCorrelation) -> Self {
+        self.correlation = correlation;
+        self
+    }
+
+    /// Collapse errors with the same message and field path into one (see
+    /// [`dedup`]). Useful when overlapping rules, e.g. from merged `allOf`
+    /// schemas, report the same failure.
+    #[must_use]
+    pub fn with_dedup(mut self, dedup: bool) -> Self {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Give each validation at most `deadline` of wall-clock time.
+    ///
+    /// The deadline is checked before each rule; once it has passed, an
+    /// [`ErrorKind::DeadlineExceeded`] error is reported and no further rules
+    /// are evaluated.
+    #[must_use]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop validations when `token` is cancelled, reporting an
+    /// [`ErrorKind::Cancelled`] error.
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// On updates, skip every node whose value equals its correlated old
+    /// value, along with everything below it.
+    ///
+    /// Rules on unchanged data are not re-evaluated, which makes validating
+    /// small edits to large objects much cheaper. Note that this forgives
+    /// existing failures in unchanged data.
+    #[must_use]
+    pub fn with_skip_unchanged(mut self, skip: bool) -> Self {
+        self.skip_unchanged = skip;
+        self
+    }
+
+    /// Reuse rule results within a validation when the same rule sees an
+    /// identical `self` (and `oldSelf`) again, e.g. in many identical list
+    /// items.
+    ///
+    /// Values are compared by hash. Costs are still charged for reused
+    /// results, so budgets behave the same with and without memoization.
+    #[must_use]
+    pub fn with_memoization(mut self, memoize: bool) -> Self {
+        self.memoize = memoize;
+        self
+    }
+
+    /// Convert only the fields a node's rules select from `self` and
+    /// `oldSelf` (see [`Projection`]) when validating against a
+    /// [`CompiledSchema`], instead of the whole value.
+    ///
+    /// This pays off for large objects whose rules read a few fields, but
+    /// subtrees with rules of their own are then converted again for those
+    /// rules. Rules evaluate the same either way. Has no effect with
+    /// ratcheting enabled, which compares whole values.
+    #[must_use]
+    pub fn with_field_projection(mut self, projection: bool) -> Self {
+        self.field_projection = projection;
+        self
+    }
+
+    /// Enable validation ratcheting, matching the apiserver's
+    /// `CRDValidationRatcheting` feature.
+    ///
+    /// On updates, rules that do not reference `oldSelf` are skipped on any
+    /// node whose value equals its correlated old value, so existing
+    /// invalid data does not block unrelated changes. Transition rules
+    /// always run.
+    #[must_use]
+    pub fn with_ratcheting(mut self, ratcheting: bool) -> Self {
+        self.ratcheting = ratcheting;
+        self
+    }
+
+    /// Also check each node's structural OpenAPI constraints (`type`,
+    /// `required`, `enum`, `pattern`, numeric, length and item bounds,
+    /// `uniqueItems`) during the walk, reporting violations as
+    /// [`ErrorKind::SchemaViolation`] before the node's CEL rules.
+    ///
+    /// With ratcheting enabled, unchanged values are not checked.
+    #[must_use]
+    pub fn with_structural_validation(mut self, structural: bool) -> Self {
+        self.structural = structural;
+        self
+    }
+
+    /// Report `format: date-time`, `format: duration` and `format: byte`
+    /// strings that do not parse as [`ErrorKind::SchemaViolation`] errors.
+    ///
+    /// By default such values silently bind as plain CEL strings, so a rule
+    /// like `self < timestamp('2030-01-01T00:00:00Z')` fails with an
+    /// evaluation error, or a rule comparing strings passes unexpectedly.
+    /// The node's rules still run after the error is reported. With
+    /// ratcheting enabled, unchanged values are not checked.
+    #[must_use]
+    pub fn with_strict_formats(mut self, strict: bool) -> Self {
+        self.strict_formats = strict;
+        self
+    }
+
+    /// Call `hook` for each problem the validator works around silently: a
+    /// `messageExpression` that could not be used, a `date-time` or
+    /// `duration` string bound as a plain string, or a transition rule
+    /// skipped for lack of `oldSelf`.
+    ///
+    /// Diagnostics are informational; the returned errors are the same with
+    /// and without a hook. The hook may be called from several threads when
+    /// rules are evaluated in parallel.
+    #[must_use]
+    pub fn with_diagnostics<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Diagnostic) + Send + Sync + 'static,
+    {
+        self.diagnostics = Some(DiagnosticHook(Arc::new(hook)));
+        self
+    }
+
+    /// Validate nodes at most `max` levels below the root (default
+    /// [`DEFAULT_MAX_DEPTH`]). A node at the limit reports an
+    /// [`ErrorKind::DepthLimitExceeded`] error instead of being validated,
+    /// guarding against stack exhaustion on deeply nested objects.
+    #[must_use]
+    pub fn with_max_depth(mut self, max: usize) -> Self {
+        self.max_depth = Some(max);
+        self
+    }
+
+    /// Refuse objects with more than `max` JSON nodes (every object, array,
+    /// and scalar counts as one), checked before any rule is evaluated.
+    ///
+    /// An object or old object over the limit produces a single
+    /// [`ErrorKind::CostBudgetExceeded`] error and is not validated. Use
+    /// this to bound the work spent on untrusted admission payloads.
+    #[must_use]
+    pub fn with_max_object_nodes(mut self, max: usize) -> Self {
+        self.max_object_nodes = Some(max);
+        self
+    }
+
+    /// Expose only `metadata.name` and `metadata.generateName` to rules, as
+    /// the apiserver does for CRDs: `self.metadata` has no other fields at
+    /// the root, so a rule reading e.g. `self.metadata.labels` fails with an
+    /// [`ErrorKind::EvaluationError`].
+    ///
+    /// Unless set, metadata is restricted when validating through a CRD
+    /// ([`Validator::for_crd`], [`Validator::validate_compiled_crd`], and
+    /// the admission webhook) and unrestricted otherwise.
+    #[must_use]
+    pub fn with_restricted_metadata(mut self, restrict: bool) -> Self {
+        self.restrict_metadata = Some(restrict);
+        self
+    }
+
+    /// Bind well-known Kubernetes timestamp fields as CEL timestamps even
+    /// where the schema does not declare `format: date-time`, so rules like
+    /// `self.metadata.creationTimestamp < timestamp('2030-01-01T00:00:00Z')`
+    /// work without a schema change.
+    ///
+    /// The fields are, from the object root, `metadata.creationTimestamp`,
+    /// `metadata.deletionTimestamp`, `metadata.managedFields[].time` and
+    /// `status.conditions[].lastTransitionTime`. Values that do not parse
+    /// as RFC 3339 stay strings.
+    #[must_use]
+    pub fn with_well_known_timestamps(mut self, enabled: bool) -> Self {
+        self.well_known_timestamps = enabled;
+        self
+    }
+
+    /// Convert strings of the custom schema `format` `name`, e.g. `ipv4` or an
+    /// organizational format, with `handler` (see [`CustomFormats`]).
+    ///
+    /// Strings the handler rejects bind as plain CEL strings and are
+    /// reported like unparseable date-times: as errors with
+    /// [`with_strict_formats`](Self::with_strict_formats), otherwise as
+    /// diagnostics.
+    ///
+    /// ```
+    /// use kube_cel::validation::Validator;
+    /// use serde_json::json;
+    ///
+    /// let v = Validator::builder()
+    ///     .with_format("ipv4", |s| match s.parse::<std::net::Ipv4Addr>() {
+    ///         Ok(ip) => Ok(cel::Value::List(std::sync::Arc::new(
+    ///             ip.octets().iter().map(|&o| cel::Value::Int(o.into())).collect(),
+    ///         ))),
+    ///         Err(_) => Err("must be an IPv4 address".to_string()),
+    ///     })
+    ///     .with_strict_formats(true)
+    ///     .build();
+    /// let schema = json!({
+    ///     "type": "string",
+    ///     "format": "ipv4",
+    ///     "x-kubernetes-validations": [{"rule": "self[0] == 10", "message": "must be private"}]
+    /// });
+    /// assert!(v.validate(&schema, &json!("10.0.0.1"), None).is_empty());
+    /// assert_eq!(v.validate(&schema, &json!("192.168.0.1"), None)[0].message, "must be private");
+    /// assert_eq!(
+    ///     v.validate(&schema, &json!("nope"), None)[0].message,
+    ///     "invalid value \"nope\": must be an IPv4 address"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn with_format<F>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(&str) -> Result<cel::Value, String> + Send + Sync + 'static,
+    {
+        self.formats = std::mem::take(&mut self.formats).with_format(name, handler);
+        self
+    }
+
+    /// Register additional CEL functions, e.g. organization-specific helpers
+    /// like `isApprovedRegistry()`, on top of [`register_all`](crate::register_all).
+    ///
+    /// `register` runs once when the [`Validator`] is built. Functions it
+    /// adds replace built-in functions of the same name.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use kube_cel::validation::Validator;
+    /// use serde_json::json;
+    ///
+    /// let v = Validator::builder()
+    ///     .with_functions(|ctx| {
+    ///         ctx.add_function("isApprovedRegistry", |image: Arc<String>| {
+    ///             image.starts_with("registry.example.com/")
+    ///         });
+    ///     })
+    ///     .build();
+    /// let schema = json!({
+    ///     "type": "string",
+    ///     "x-kubernetes-validations": [{"rule": "isApprovedRegistry(self)"}]
+    /// });
+    /// assert_eq!(v.validate(&schema, &json!("docker.io/nginx"), None).len(), 1);
+    /// ```
+    #[must_use]
+    pub fn with_functions<F>(mut self, register: F) -> Self
+    where
+        F: Fn(&mut Context<'static>) + Send + Sync + 'static,
+    {
+        self.functions.push(FunctionRegistrar(Arc::new(register)));
+        self
+    }
+
+    /// Bind `name` to `value` in every rule, e.g. `params` or
+    /// `clusterName`. Setting the same name again replaces the value.
+    ///
+    /// `self` and `oldSelf` always refer to the validated values and
+    /// cannot be overridden.
+    #[must_use]
+    pub fn with_variable(mut self, name: impl Into<String>, value: &serde_json::Value) -> Self {
+        let name = name.into();
+        let value = json_to_cel(value);
+        match self.variables.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.variables.push((name, value)),
+        }
+        self
+    }
+
+    /// Evaluate the rules of a node in parallel on the rayon thread pool when
+    /// it has at least `min_rules` runnable rules.
+    ///
+    /// Errors are reported in the same order, and limits apply the same way,
+    /// as with sequential evaluation. Rules that end up skipped by a limit may
+    /// still have been evaluated.
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn with_parallel_rules(mut self, min_rules: usize) -> Self {
+        self.parallel_rules = Some(min_rules.max(2));
+        self
+    }
+
+    /// Create a [`Validator`] with these options.
+    #[must_use]
+    pub fn build(self) -> Validator {
+        Validator::with_options(self)
+    }
+}
+
 /// Validates Kubernetes objects against CRD schema CEL validation rules.
 ///
 /// Walks the OpenAPI schema tree, compiles `x-kubernetes-validations` rules at
@@ -59,25 +610,140 @@ impl std::error::Error for ValidationError {}
 ///
 /// For repeated validation against the same schema, use [`compile_schema`](crate::compilation::compile_schema) +
 /// [`validate_compiled`](Validator::validate_compiled) to avoid re-compilation.
+/// Use [`Validator::builder`] to configure limits.
 ///
 /// # Thread Safety
 ///
 /// `Validator` is `Send + Sync` and can be shared across threads.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct Validator {
-    _private: (),
+    options: ValidatorOptions,
+    /// The evaluation context with custom functions, if any were registered.
+    context: Option<CustomContext>,
+}
+
+/// A function registration hook passed to [`ValidatorOptions::with_functions`].
+#[derive(Clone)]
+struct FunctionRegistrar(Arc<dyn Fn(&mut Context<'static>) + Send + Sync>);
+
+impl std::fmt::Debug for FunctionRegistrar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FunctionRegistrar(..)")
+    }
+}
+
+/// The hook passed to [`ValidatorOptions::with_diagnostics`].
+#[derive(Clone)]
+struct DiagnosticHook(Arc<dyn Fn(&Diagnostic) + Send + Sync>);
+
+impl std::fmt::Debug for DiagnosticHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DiagnosticHook(..)")
+    }
+}
+
+/// A root evaluation context built for one [`Validator`].
+#[derive(Clone)]
+struct CustomContext(Arc<Context<'static>>);
+
+impl std::fmt::Debug for CustomContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomContext(..)")
+    }
 }
 
 impl Validator {
-    /// Create a new `Validator`.
+    /// Create a new `Validator` with default options.
     pub fn new() -> Self {
-        Self { _private: () }
+        Self::default()
+    }
+
+    /// Start configuring a `Validator`.
+    pub fn builder() -> ValidatorOptions {
+        ValidatorOptions::new()
+    }
+
+    /// Create a `Validator` with the given options.
+    pub fn with_options(options: ValidatorOptions) -> Self {
+        let context = (!options.functions.is_empty()).then(|| {
+            let mut ctx = new_base_context();
+            for FunctionRegistrar(register) in &options.functions {
+                register(&mut ctx);
+            }
+            CustomContext(Arc::new(ctx))
+        });
+        Self { options, context }
+    }
+
+    /// The root context rules are evaluated in.
+    fn base_context(&self) -> &Context<'static> {
+        match &self.context {
+            Some(CustomContext(ctx)) => ctx,
+            None => base_context(),
+        }
+    }
+
+    /// The options this validator was built with.
+    #[must_use]
+    pub fn options(&self) -> &ValidatorOptions {
+        &self.options
+    }
+
+    /// Validate an object against a schema in any [`SchemaSource`] form,
+    /// like [`validate`](Self::validate).
+    #[must_use]
+    pub fn validate_from<S: SchemaSource + ?Sized>(
+        &self,
+        schema: &S,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> Vec<ValidationError> {
+        self.validate(&schema.to_schema_json(), object, old_object)
+    }
+
+    /// Validate a typed object, such as a custom resource struct, like
+    /// [`validate`](Self::validate), serializing it to JSON first.
+    ///
+    /// Fails only if `object` or `old_object` cannot be serialized to JSON,
+    /// e.g. a map with non-string keys.
+    ///
+    /// ```
+    /// use kube_cel::validation::Validator;
+    /// use serde_json::json;
+    ///
+    /// #[derive(serde::Serialize)]
+    /// struct Spec {
+    ///     replicas: i64,
+    /// }
+    ///
+    /// let schema = json!({
+    ///     "type": "object",
+    ///     "properties": {"replicas": {"type": "integer"}},
+    ///     "x-kubernetes-validations": [{"rule": "self.replicas <= 3"}]
+    /// });
+    /// let errors = Validator::new()
+    ///     .validate_typed(&schema, &Spec { replicas: 5 }, None)
+    ///     .unwrap();
+    /// assert_eq!(errors.len(), 1);
+    /// ```
+    pub fn validate_typed<T: serde::Serialize>(
+        &self,
+        schema: &serde_json::Value,
+        object: &T,
+        old_object: Option<&T>,
+    ) -> Result<Vec<ValidationError>, serde_json::Error> {
+        let object = serde_json::to_value(object)?;
+        let old_object = old_object.map(serde_json::to_value).transpose()?;
+        Ok(self.validate(schema, &object, old_object.as_ref()))
     }
 
     /// Validate an object against a CRD schema's CEL validation rules.
     ///
     /// Compiles rules on each call. For repeated validation against the same
     /// schema, prefer [`compile_schema`](crate::compilation::compile_schema) + [`validate_compiled`](Self::validate_compiled).
+    ///
+    /// Failures of warning-severity rules are not returned; use
+    /// [`validate_with_report`](Self::validate_with_report) to see them.
     #[must_use]
     pub fn validate(
         &self,
@@ -85,18 +751,173 @@ impl Validator {
         object: &serde_json::Value,
         old_object: Option<&serde_json::Value>,
     ) -> Vec<ValidationError> {
-        let mut base_ctx = Context::default();
-        crate::register_all(&mut base_ctx);
-        let mut errors = Vec::new();
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object).with_root(schema);
         self.walk_schema(
             schema,
             object,
             old_object,
-            String::new(),
-            &mut errors,
-            &base_ctx,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
         );
-        errors
+        state.finish()
+    }
+
+    /// Evaluate a compiled `ValidatingAdmissionPolicy` against one request.
+    ///
+    /// Expressions can use the functions registered on this validator.
+    #[must_use]
+    pub fn validate_policy(
+        &self,
+        policy: &crate::policy::CompiledPolicy,
+        request: &crate::policy::PolicyRequest<'_>,
+    ) -> crate::policy::PolicyResult {
+        policy.evaluate(self.base_context(), request)
+    }
+
+    /// Apply a compiled `MutatingAdmissionPolicy` to one admission request,
+    /// returning the mutated object.
+    ///
+    /// Mutations run with the same functions as rules, including any
+    /// registered with [`ValidatorOptions::with_functions`].
+    #[must_use]
+    pub fn mutate_policy(
+        &self,
+        policy: &crate::mutation::CompiledMutatingPolicy,
+        request: &crate::policy::PolicyRequest<'_>,
+    ) -> crate::mutation::MutationResult {
+        policy.evaluate(self.base_context(), request)
+    }
+
+    /// Bind this validator to a `CustomResourceDefinition` manifest.
+    ///
+    /// The returned [`CrdValidator`](crate::crd::CrdValidator) validates each
+    /// object against the schema of the version named in its `apiVersion`,
+    /// with metadata restricted as in the apiserver unless configured
+    /// otherwise (see [`ValidatorOptions::with_restricted_metadata`]).
+    pub fn for_crd(
+        &self,
+        crd: &serde_json::Value,
+    ) -> Result<crate::crd::CrdValidator, crate::crd::CrdError> {
+        crate::crd::CrdValidator::new(self.for_crds().into_owned(), crd)
+    }
+
+    /// Validate `object` against the compiled schema of the version named in
+    /// its `apiVersion`, with metadata restricted as in [`for_crd`](Self::for_crd).
+    ///
+    /// Returns an error, without validating, if the object's group is not
+    /// the CRD's or the version is not served.
+    pub fn validate_compiled_crd(
+        &self,
+        crd: &crate::crd::CompiledCrd,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> Result<Vec<ValidationError>, crate::crd::CrdError> {
+        let compiled = crd.for_object(object)?;
+        Ok(self
+            .for_crds()
+            .validate_compiled(compiled, object, old_object))
+    }
+
+    /// This validator with metadata restricted, unless that was configured.
+    fn for_crds(&self) -> Cow<'_, Validator> {
+        if self.options.restrict_metadata.is_some() {
+            return Cow::Borrowed(self);
+        }
+        let mut validator = self.clone();
+        validator.options.restrict_metadata = Some(true);
+        Cow::Owned(validator)
+    }
+
+    /// Validate `object` for `operation`.
+    ///
+    /// `old_object` is ignored on [`Operation::Create`], and a missing one
+    /// on [`Operation::Update`] is treated as a create. The apiserver does not
+    /// run validation rules on [`Operation::Delete`], so it never produces
+    /// errors. Prefer [`validate_create`](Self::validate_create),
+    /// [`validate_update`](Self::validate_update), and
+    /// [`validate_delete`](Self::validate_delete).
+    #[must_use]
+    pub fn validate_operation(
+        &self,
+        schema: &serde_json::Value,
+        operation: Operation,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> Vec<ValidationError> {
+        match operation {
+            Operation::Create => self.validate(schema, object, None),
+            Operation::Update => self.validate(schema, object, old_object),
+            Operation::Delete => Vec::new(),
+        }
+    }
+
+    /// Validate a newly created object. Transition rules are skipped.
+    #[must_use]
+    pub fn validate_create(
+        &self,
+        schema: &serde_json::Value,
+        object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        self.validate_operation(schema, Operation::Create, object, None)
+    }
+
+    /// Validate an update of `old_object` to `object`.
+    #[must_use]
+    pub fn validate_update(
+        &self,
+        schema: &serde_json::Value,
+        object: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        self.validate_operation(schema, Operation::Update, object, Some(old_object))
+    }
+
+    /// Validate the deletion of `old_object`, which has no new object.
+    ///
+    /// Validation rules do not apply to deletes, so this always succeeds.
+    #[must_use]
+    pub fn validate_delete(
+        &self,
+        schema: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        self.validate_operation(
+            schema,
+            Operation::Delete,
+            &serde_json::Value::Null,
+            Some(old_object),
+        )
+    }
+
+    /// Validate an object (and optional old object) written in YAML, like
+    /// [`validate`](Self::validate) (feature = `yaml`).
+    #[cfg(feature = "yaml")]
+    pub fn validate_yaml(
+        &self,
+        schema: &serde_json::Value,
+        object_yaml: &str,
+        old_yaml: Option<&str>,
+    ) -> Result<Vec<ValidationError>, crate::yaml::YamlError> {
+        let object = crate::yaml::parse(object_yaml)?;
+        let old_object = old_yaml.map(crate::yaml::parse).transpose()?;
+        Ok(self.validate(schema, &object, old_object.as_ref()))
+    }
+
+    /// Validate an object (and optional old object) written in YAML, like
+    /// [`validate_compiled`](Self::validate_compiled) (feature = `yaml`).
+    #[cfg(feature = "yaml")]
+    pub fn validate_compiled_yaml(
+        &self,
+        compiled: &CompiledSchema,
+        object_yaml: &str,
+        old_yaml: Option<&str>,
+    ) -> Result<Vec<ValidationError>, crate::yaml::YamlError> {
+        let object = crate::yaml::parse(object_yaml)?;
+        let old_object = old_yaml.map(crate::yaml::parse).transpose()?;
+        Ok(self.validate_compiled(compiled, &object, old_object.as_ref()))
     }
 
     /// Validate an object using a pre-compiled schema tree.
@@ -110,41 +931,508 @@ impl Validator {
         object: &serde_json::Value,
         old_object: Option<&serde_json::Value>,
     ) -> Vec<ValidationError> {
-        let mut base_ctx = Context::default();
-        crate::register_all(&mut base_ctx);
-        let mut errors = Vec::new();
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object);
         self.walk_compiled(
             compiled,
             object,
             old_object,
-            String::new(),
-            &mut errors,
-            &base_ctx,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
         );
-        errors
+        state.finish()
     }
 
-    // ── Schema-based walking (compiles on each call) ────────────────
-
+    /// Evaluate `rule`, declared on `schema`, against `value`, the value of
+    /// its node, and record the value of each of its sub-expressions (see
+    /// [`crate::explain`]). `old_value` binds `oldSelf`, as in validation.
+    ///
+    /// Only the rule is evaluated: ratcheting, cost limits, and the
+    /// skipping of transition rules without `oldSelf` do not apply.
+    #[must_use]
+    pub fn explain(
+        &self,
+        rule: &CompilationResult,
+        schema: &serde_json::Value,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+    ) -> Explanation {
+        let mut ctx = self.base_context().new_inner_scope();
+        for (name, value) in &self.options.variables {
+            ctx.add_variable_from_value(name.as_str(), value.clone());
+        }
+        let convert = |v: &serde_json::Value| {
+            self.bind(v, &[], |v| {
+                json_to_cel_with_schema_formats(v, schema, &self.options.formats)
+            })
+        };
+        ctx.add_variable_from_value("self", convert(value));
+        match old_value {
+            Some(old) => ctx.add_variable_from_value("oldSelf", convert(old)),
+            None if rule.rule.optional_old_self == Some(true) => {
+                ctx.add_variable_from_value("oldSelf", cel::Value::Null);
+            }
+            None => {}
+        }
+        explain_in(rule, &ctx)
+    }
+
+    /// Validate an update whose old object conforms to `old_schema`, e.g. a
+    /// previous CRD version during a storage-version migration.
+    ///
+    /// Rules come from `schema`; `oldSelf` values are converted using the
+    /// `format` and `x-kubernetes-int-or-string` hints of `old_schema`.
+    #[must_use]
+    pub fn validate_with_old_schema(
+        &self,
+        schema: &serde_json::Value,
+        old_schema: &serde_json::Value,
+        object: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, Some(old_object)).with_root(schema);
+        let cel = CelCache::with_old(self.bind(old_object, &[], |v| {
+            json_to_cel_with_schema_formats(v, old_schema, &self.options.formats)
+        }));
+        self.walk_schema(
+            schema,
+            object,
+            Some(old_object),
+            Vec::new(),
+            cel,
+            &mut state,
+            base_ctx,
+        );
+        state.finish()
+    }
+
+    /// Like [`validate_with_old_schema`](Self::validate_with_old_schema),
+    /// using pre-compiled schemas.
+    #[must_use]
+    pub fn validate_compiled_with_old_schema(
+        &self,
+        compiled: &CompiledSchema,
+        old_compiled: &CompiledSchema,
+        object: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, Some(old_object));
+        let cel = CelCache::with_old(self.bind(old_object, &[], |v| {
+            json_to_cel_with_formats(v, old_compiled, &self.options.formats)
+        }));
+        self.walk_compiled(
+            compiled,
+            object,
+            Some(old_object),
+            Vec::new(),
+            cel,
+            &mut state,
+            base_ctx,
+        );
+        state.finish()
+    }
+
+    /// Prune fields the schema does not declare (see [`pruning`](crate::pruning)),
+    /// then validate the pruned object, as the apiserver does.
+    ///
+    /// Returns the pruned object together with the validation errors.
+    #[must_use]
+    pub fn validate_pruned(
+        &self,
+        schema: &serde_json::Value,
+        mut object: serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> (serde_json::Value, Vec<ValidationError>) {
+        crate::pruning::prune(&mut object, schema);
+        let errors = self.validate(schema, &object, old_object);
+        (object, errors)
+    }
+
+    /// Like [`validate_pruned`](Self::validate_pruned), using a pre-compiled schema.
+    #[must_use]
+    pub fn validate_compiled_pruned(
+        &self,
+        compiled: &CompiledSchema,
+        mut object: serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> (serde_json::Value, Vec<ValidationError>) {
+        crate::pruning::prune_compiled(&mut object, compiled);
+        let errors = self.validate_compiled(compiled, &object, old_object);
+        (object, errors)
+    }
+
+    /// Validate each of `objects` (as creates) against one compiled schema.
+    ///
+    /// Returns one error list per object, in the same order. Limits such as
+    /// `max_errors` and the deadline apply to each object separately.
+    #[must_use]
+    pub fn validate_many(
+        &self,
+        compiled: &CompiledSchema,
+        objects: &[serde_json::Value],
+    ) -> Vec<Vec<ValidationError>> {
+        let base_ctx = self.base_context();
+        objects
+            .iter()
+            .map(|object| {
+                let mut state = WalkState::new(self, None);
+                self.walk_compiled(
+                    compiled,
+                    object,
+                    None,
+                    Vec::new(),
+                    CelCache::default(),
+                    &mut state,
+                    base_ctx,
+                );
+                state.finish()
+            })
+            .collect()
+    }
+
+    /// Lazily validate `(object, old_object)` pairs against one compiled schema.
+    ///
+    /// Yields `(index, errors)` for each pair as it is pulled, so results for
+    /// a large batch (e.g. an informer cache) never have to be held at once.
+    ///
+    /// ```
+    /// use kube_cel::compilation::compile_schema;
+    /// use kube_cel::validation::Validator;
+    /// use serde_json::json;
+    ///
+    /// let compiled = compile_schema(&json!({
+    ///     "type": "object",
+    ///     "x-kubernetes-validations": [{"rule": "self.replicas >= 0"}],
+    ///     "properties": {"replicas": {"type": "integer"}}
+    /// }));
+    /// let objects = [json!({"replicas": 1}), json!({"replicas": -1})];
+    /// let failing: Vec<usize> = Validator::new()
+    ///     .validate_iter(&compiled, objects.iter().map(|o| (o, None)))
+    ///     .filter(|(_, errors)| !errors.is_empty())
+    ///     .map(|(i, _)| i)
+    ///     .collect();
+    /// assert_eq!(failing, [1]);
+    /// ```
+    pub fn validate_iter<'a, I>(
+        &'a self,
+        compiled: &'a CompiledSchema,
+        objects: I,
+    ) -> impl Iterator<Item = (usize, Vec<ValidationError>)> + 'a
+    where
+        I: IntoIterator<Item = (&'a serde_json::Value, Option<&'a serde_json::Value>)>,
+        I::IntoIter: 'a,
+    {
+        objects
+            .into_iter()
+            .enumerate()
+            .map(move |(i, (object, old_object))| {
+                (i, self.validate_compiled(compiled, object, old_object))
+            })
+    }
+
+    /// Like [`validate`](Self::validate), but passes each error to `on_error`
+    /// as it is produced instead of collecting them.
+    ///
+    /// Return [`ControlFlow::Break`] from `on_error` to stop validating.
+    /// `max_errors` and de-duplication still apply; errors are never reordered.
+    ///
+    /// ```
+    /// use std::ops::ControlFlow;
+    /// use kube_cel::validation::Validator;
+    /// use serde_json::json;
+    ///
+    /// let schema = json!({
+    ///     "type": "object",
+    ///     "x-kubernetes-validations": [
+    ///         {"rule": "self.replicas >= 0", "message": "must be non-negative"},
+    ///         {"rule": "self.replicas % 2 == 0", "message": "must be even"}
+    ///     ],
+    ///     "properties": {"replicas": {"type": "integer"}}
+    /// });
+    /// let mut first = None;
+    /// Validator::new().validate_with(&schema, &json!({"replicas": -1}), None, |err| {
+    ///     first = Some(err.message);
+    ///     ControlFlow::Break(())
+    /// });
+    /// assert_eq!(first.as_deref(), Some("must be non-negative"));
+    /// ```
+    pub fn validate_with<F>(
+        &self,
+        schema: &serde_json::Value,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+        mut on_error: F,
+    ) where
+        F: FnMut(ValidationError) -> ControlFlow<()>,
+    {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object)
+            .with_sink(&mut on_error)
+            .with_root(schema);
+        self.walk_schema(
+            schema,
+            object,
+            old_object,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
+        );
+    }
+
+    /// Like [`validate_compiled`](Self::validate_compiled), but passes each
+    /// error to `on_error` as it is produced. See [`validate_with`](Self::validate_with).
+    pub fn validate_compiled_with<F>(
+        &self,
+        compiled: &CompiledSchema,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+        mut on_error: F,
+    ) where
+        F: FnMut(ValidationError) -> ControlFlow<()>,
+    {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object).with_sink(&mut on_error);
+        self.walk_compiled(
+            compiled,
+            object,
+            old_object,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
+        );
+    }
+
+    /// Like [`validate`](Self::validate), but also reports the outcome of
+    /// every rule that was reached: passed, failed, or skipped (and why),
+    /// with its evaluation time.
+    #[must_use]
+    pub fn validate_with_report(
+        &self,
+        schema: &serde_json::Value,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> RuleReport {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object)
+            .with_outcomes()
+            .with_root(schema);
+        self.walk_schema(
+            schema,
+            object,
+            old_object,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
+        );
+        state.finish_report()
+    }
+
+    /// Validate an update made through `subresource`, as the apiserver does
+    /// for CRDs with a status subresource.
+    ///
+    /// For [`Subresource::Status`], only rules under `.status` run, against
+    /// the new and old `status`; everything else is treated as unchanged.
+    /// For [`Subresource::Main`], `status` is reset to its old value and the
+    /// rules under `.status` are skipped; all other rules run as usual.
+    #[must_use]
+    pub fn validate_subresource(
+        &self,
+        schema: &serde_json::Value,
+        subresource: Subresource,
+        object: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, Some(old_object)).with_root(schema);
+        match subresource {
+            Subresource::Status => {
+                let root = expand_node(schema);
+                let Some(status_schema) = root.get("properties").and_then(|p| p.get(STATUS)) else {
+                    return Vec::new();
+                };
+                let Some(status) = object.get(STATUS) else {
+                    return Vec::new();
+                };
+                let old_status = self
+                    .correlate_fields(&MapType::from_schema(&root), Some(old_object))
+                    .and_then(|old| old.get(STATUS));
+                self.walk_schema(
+                    status_schema,
+                    status,
+                    old_status,
+                    vec![PathSegment::Field(STATUS.into())],
+                    CelCache::default(),
+                    &mut state,
+                    base_ctx,
+                );
+            }
+            Subresource::Main => {
+                let object = with_old_status(object, old_object);
+                state.excluded = Some(STATUS);
+                self.walk_schema(
+                    schema,
+                    &object,
+                    Some(old_object),
+                    Vec::new(),
+                    CelCache::default(),
+                    &mut state,
+                    base_ctx,
+                );
+            }
+        }
+        state.finish()
+    }
+
+    /// Like [`validate_subresource`](Self::validate_subresource), using a
+    /// pre-compiled schema.
+    #[must_use]
+    pub fn validate_compiled_subresource(
+        &self,
+        compiled: &CompiledSchema,
+        subresource: Subresource,
+        object: &serde_json::Value,
+        old_object: &serde_json::Value,
+    ) -> Vec<ValidationError> {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, Some(old_object));
+        match subresource {
+            Subresource::Status => {
+                let (Some(status_compiled), Some(status)) =
+                    (compiled.properties.get(STATUS), object.get(STATUS))
+                else {
+                    return Vec::new();
+                };
+                let old_status = self
+                    .correlate_fields(&compiled.map_type, Some(old_object))
+                    .and_then(|old| old.get(STATUS));
+                self.walk_compiled(
+                    status_compiled,
+                    status,
+                    old_status,
+                    vec![PathSegment::Field(STATUS.into())],
+                    CelCache::default(),
+                    &mut state,
+                    base_ctx,
+                );
+            }
+            Subresource::Main => {
+                let object = with_old_status(object, old_object);
+                state.excluded = Some(STATUS);
+                self.walk_compiled(
+                    compiled,
+                    &object,
+                    Some(old_object),
+                    Vec::new(),
+                    CelCache::default(),
+                    &mut state,
+                    base_ctx,
+                );
+            }
+        }
+        state.finish()
+    }
+
+    /// Like [`validate_compiled`](Self::validate_compiled), but also reports
+    /// per-rule outcomes. See [`validate_with_report`](Self::validate_with_report).
+    #[must_use]
+    pub fn validate_compiled_with_report(
+        &self,
+        compiled: &CompiledSchema,
+        object: &serde_json::Value,
+        old_object: Option<&serde_json::Value>,
+    ) -> RuleReport {
+        let base_ctx = self.base_context();
+        let mut state = WalkState::new(self, old_object).with_outcomes();
+        self.walk_compiled(
+            compiled,
+            object,
+            old_object,
+            Vec::new(),
+            CelCache::default(),
+            &mut state,
+            base_ctx,
+        );
+        state.finish_report()
+    }
+
+    // ── Schema-based walking (compiles on each call) ────────────────
+
+    #[allow(clippy::too_many_arguments)]
     fn walk_schema(
         &self,
         schema: &serde_json::Value,
         value: &serde_json::Value,
         old_value: Option<&serde_json::Value>,
-        path: String,
-        errors: &mut Vec<ValidationError>,
+        path: Vec<PathSegment>,
+        mut cel: CelCache,
+        state: &mut WalkState,
         base_ctx: &Context<'_>,
     ) {
-        let cel_value = json_to_cel_with_schema(value, schema);
-        let cel_old = old_value.map(|o| json_to_cel_with_schema(o, schema));
-        self.evaluate_validations(
-            schema,
-            &cel_value,
-            cel_old.as_ref(),
+        if state.halted()
+            || self.is_unchanged(value, old_value)
+            || self.too_deep(&path, state)
+            || self.too_large(&path, value, old_value, state)
+            || state.excludes(&path)
+        {
+            return;
+        }
+        // Nodes reached through `$ref`, compositions, or embedded resources
+        // are temporaries, so the location identifies the node, not its
+        // address.
+        let node = hash_location(&state.location);
+        let refs = state.refs.len();
+        let resolved = match state
+            .root
+            .map(|root| resolve_refs(schema, root, &mut state.refs))
+        {
+            None => Cow::Borrowed(schema),
+            Some(Ok(resolved)) => resolved,
+            Some(Err(error)) => {
+                self.report_compilation_error(&error, &path, state);
+                state.refs.truncate(refs);
+                return;
+            }
+        };
+        let schema = &*expand_node(&resolved);
+        if self.checks_structure(value, old_value) {
+            let structural = StructuralSchema::from_schema(schema);
+            for error in structural.validate(value, &path) {
+                state.push_error(error);
+            }
+        }
+        self.check_format(
+            value,
+            old_value,
+            &SchemaFormat::from_schema(schema),
             &path,
-            errors,
-            base_ctx,
+            state,
         );
+        if value.is_null() {
+            state.refs.truncate(refs);
+            return;
+        }
+        let old_value = cel.correlated_old(old_value);
+        let rules = compile_schema_validations(schema);
+        if !rules.is_empty() {
+            let memo = state.memo_key(node, value, old_value);
+            let document = state.root.unwrap_or(schema);
+            let (cel_value, cel_old) = cel.fill(value, old_value, |v| {
+                self.bind(v, &path, |v| {
+                    json_to_cel_in_document(v, schema, document, &state.refs, &self.options.formats)
+                })
+            });
+            self.evaluate_compiled_results(
+                &rules, cel_value, cel_old, &path, memo, state, base_ctx,
+            );
+        }
+        let old_fields = self.correlate_fields(&MapType::from_schema(schema), old_value);
 
         if let (Some(properties), Some(obj)) = (
             schema.get("properties").and_then(|p| p.as_object()),
@@ -152,32 +1440,44 @@ impl Validator {
         ) {
             for (prop_name, prop_schema) in properties {
                 if let Some(child_value) = obj.get(prop_name) {
-                    let child_old = old_value.and_then(|o| o.get(prop_name));
-                    let child_path = join_path(&path, prop_name);
+                    let child_old = old_fields.and_then(|o| o.get(prop_name));
+                    let child_path = join_path(&path, PathSegment::Field(prop_name.clone()));
+                    let parent = state.descend(|| format!("properties[{prop_name}]"));
                     self.walk_schema(
                         prop_schema,
                         child_value,
                         child_old,
                         child_path,
-                        errors,
+                        cel.field(prop_name, child_old.is_some()),
+                        state,
                         base_ctx,
                     );
+                    state.location = parent;
                 }
             }
         }
 
         if let (Some(items_schema), Some(arr)) = (schema.get("items"), value.as_array()) {
-            for (i, item) in arr.iter().enumerate() {
-                let old_item = old_value.and_then(|o| o.as_array()).and_then(|a| a.get(i));
-                let item_path = join_path_index(&path, i);
-                self.walk_schema(items_schema, item, old_item, item_path, errors, base_ctx);
-            }
+            let old_items = self.correlate_items(&ListType::from_schema(schema), arr, old_value);
+            let old_arr = old_value.and_then(|o| o.as_array());
+            self.walk_items(arr, &path, state, |i, item, item_path, state| {
+                let parent = state.descend(|| "items".to_string());
+                self.walk_schema(
+                    items_schema,
+                    item,
+                    old_items[i].and_then(|j| old_arr?.get(j)),
+                    item_path,
+                    cel.item(i, old_items[i]),
+                    state,
+                    base_ctx,
+                );
+                state.location = parent;
+            });
         }
 
-        if let (Some(additional_schema), Some(obj)) = (
-            schema.get("additionalProperties").filter(|a| a.is_object()),
-            value.as_object(),
-        ) {
+        if let (Some(additional_schema), Some(obj)) =
+            (additional_properties_schema(schema), value.as_object())
+        {
             let known: std::collections::HashSet<&str> = schema
                 .get("properties")
                 .and_then(|p| p.as_object())
@@ -188,66 +1488,109 @@ impl Validator {
                 if known.contains(key.as_str()) {
                     continue;
                 }
-                let old_val = old_value.and_then(|o| o.get(key));
-                let child_path = join_path(&path, key);
+                let old_val = old_fields.and_then(|o| o.get(key));
+                let child_path = join_path(&path, PathSegment::Key(key.clone()));
+                let parent = state.descend(|| "additionalProperties".to_string());
                 self.walk_schema(
                     additional_schema,
                     val,
                     old_val,
                     child_path,
-                    errors,
+                    cel.field(key, old_val.is_some()),
+                    state,
                     base_ctx,
                 );
+                state.location = parent;
             }
         }
-    }
-
-    fn evaluate_validations(
-        &self,
-        schema: &serde_json::Value,
-        cel_value: &cel::Value,
-        cel_old: Option<&cel::Value>,
-        path: &str,
-        errors: &mut Vec<ValidationError>,
-        base_ctx: &Context<'_>,
-    ) {
-        let compiled = compile_schema_validations(schema);
-        self.evaluate_compiled_results(&compiled, cel_value, cel_old, path, errors, base_ctx);
+        state.refs.truncate(refs);
     }
 
     // ── CompiledSchema-based walking ────────────────────────────────
 
+    #[allow(clippy::too_many_arguments)]
     fn walk_compiled(
         &self,
         compiled: &CompiledSchema,
         value: &serde_json::Value,
         old_value: Option<&serde_json::Value>,
-        path: String,
-        errors: &mut Vec<ValidationError>,
+        path: Vec<PathSegment>,
+        mut cel: CelCache,
+        state: &mut WalkState,
         base_ctx: &Context<'_>,
     ) {
-        let cel_value = json_to_cel_with_compiled(value, compiled);
-        let cel_old = old_value.map(|o| json_to_cel_with_compiled(o, compiled));
-        self.evaluate_compiled_results(
-            &compiled.validations,
-            &cel_value,
-            cel_old.as_ref(),
-            &path,
-            errors,
-            base_ctx,
-        );
+        if state.halted()
+            || self.is_unchanged(value, old_value)
+            || self.too_deep(&path, state)
+            || self.too_large(&path, value, old_value, state)
+            || state.excludes(&path)
+        {
+            return;
+        }
+        if self.checks_structure(value, old_value) {
+            for error in compiled.structural.validate(value, &path) {
+                state.push_error(error);
+            }
+        }
+        self.check_format(value, old_value, &compiled.format, &path, state);
+        if value.is_null() {
+            return;
+        }
+        let old_value = cel.correlated_old(old_value);
+        if !compiled.validations.is_empty() {
+            let memo = state.memo_key(std::ptr::from_ref(compiled) as u64, value, old_value);
+            let projected;
+            let (cel_value, cel_old) = match self.projections(compiled, &cel) {
+                Some((value_fields, old_fields)) => {
+                    let convert = |v, fields| {
+                        self.bind(v, &path, |v| {
+                            json_to_cel_projected_with_formats(
+                                v,
+                                compiled,
+                                fields,
+                                &self.options.formats,
+                            )
+                        })
+                    };
+                    projected = (
+                        convert(value, &value_fields),
+                        match &cel.old {
+                            Some(old) => Some(old.clone()),
+                            None => old_value.map(|o| convert(o, &old_fields)),
+                        },
+                    );
+                    (&projected.0, projected.1.as_ref())
+                }
+                None => cel.fill(value, old_value, |v| {
+                    self.bind(v, &path, |v| {
+                        json_to_cel_with_formats(v, compiled, &self.options.formats)
+                    })
+                }),
+            };
+            self.evaluate_compiled_results(
+                &compiled.validations,
+                cel_value,
+                cel_old,
+                &path,
+                memo,
+                state,
+                base_ctx,
+            );
+        }
+        let old_fields = self.correlate_fields(&compiled.map_type, old_value);
 
         if let Some(obj) = value.as_object() {
             for (prop_name, child_compiled) in &compiled.properties {
                 if let Some(child_value) = obj.get(prop_name) {
-                    let child_old = old_value.and_then(|o| o.get(prop_name));
-                    let child_path = join_path(&path, prop_name);
+                    let child_old = old_fields.and_then(|o| o.get(prop_name));
+                    let child_path = join_path(&path, PathSegment::Field(prop_name.clone()));
                     self.walk_compiled(
                         child_compiled,
                         child_value,
                         child_old,
                         child_path,
-                        errors,
+                        cel.field(prop_name, child_old.is_some()),
+                        state,
                         base_ctx,
                     );
                 }
@@ -255,11 +1598,19 @@ impl Validator {
         }
 
         if let (Some(items_compiled), Some(arr)) = (&compiled.items, value.as_array()) {
-            for (i, item) in arr.iter().enumerate() {
-                let old_item = old_value.and_then(|o| o.as_array()).and_then(|a| a.get(i));
-                let item_path = join_path_index(&path, i);
-                self.walk_compiled(items_compiled, item, old_item, item_path, errors, base_ctx);
-            }
+            let old_items = self.correlate_items(&compiled.list_type, arr, old_value);
+            let old_arr = old_value.and_then(|o| o.as_array());
+            self.walk_items(arr, &path, state, |i, item, item_path, state| {
+                self.walk_compiled(
+                    items_compiled,
+                    item,
+                    old_items[i].and_then(|j| old_arr?.get(j)),
+                    item_path,
+                    cel.item(i, old_items[i]),
+                    state,
+                    base_ctx,
+                );
+            });
         }
 
         if let (Some(additional_compiled), Some(obj)) =
@@ -269,14 +1620,15 @@ impl Validator {
                 if compiled.properties.contains_key(key) {
                     continue;
                 }
-                let old_val = old_value.and_then(|o| o.get(key));
-                let child_path = join_path(&path, key);
+                let old_val = old_fields.and_then(|o| o.get(key));
+                let child_path = join_path(&path, PathSegment::Key(key.clone()));
                 self.walk_compiled(
                     additional_compiled,
                     val,
                     old_val,
                     child_path,
-                    errors,
+                    cel.field(key, old_val.is_some()),
+                    state,
                     base_ctx,
                 );
             }
@@ -285,132 +1637,670 @@ impl Validator {
 
     // ── Shared evaluation logic ─────────────────────────────────────
 
-    fn evaluate_compiled_results(
+    /// Whether structural constraints should be checked on this node.
+    fn checks_structure(
         &self,
-        results: &[Result<CompilationResult, CompilationError>],
-        cel_value: &cel::Value,
-        cel_old: Option<&cel::Value>,
-        path: &str,
-        errors: &mut Vec<ValidationError>,
-        base_ctx: &Context<'_>,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+    ) -> bool {
+        self.options.structural && !(self.options.ratcheting && old_value == Some(value))
+    }
+
+    /// Report `value` if it does not parse as `format`: as an error in
+    /// strict mode, otherwise as a diagnostic.
+    fn check_format(
+        &self,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+        format: &SchemaFormat,
+        path: &[PathSegment],
+        state: &mut WalkState,
     ) {
-        // Create a node-level scope once with self/oldSelf bound
-        let mut node_ctx = base_ctx.new_inner_scope();
-        node_ctx.add_variable_from_value("self", cel_value.clone());
-        if let Some(old) = cel_old {
-            node_ctx.add_variable_from_value("oldSelf", old.clone());
+        if !self.options.strict_formats && self.options.diagnostics.is_none() {
+            return;
         }
+        let Some(message) = format_error(value, format, &self.options.formats) else {
+            return;
+        };
+        if self.options.strict_formats && !(self.options.ratcheting && old_value == Some(value)) {
+            state.push_error(ValidationError {
+                rule: "format".to_string(),
+                message,
+                field_path: render_path(path),
+                path: path.to_vec(),
+                reason: Some(Reason::FieldValueInvalid),
+                kind: ErrorKind::SchemaViolation,
+            });
+            return;
+        }
+        self.diagnose(|| Diagnostic::FormatFallback {
+            field_path: render_path(path),
+            format: match format {
+                SchemaFormat::DateTime => "date-time".to_string(),
+                SchemaFormat::Byte => "byte".to_string(),
+                SchemaFormat::Custom(name) => name.clone(),
+                _ => "duration".to_string(),
+            },
+            value: value.as_str().unwrap_or_default().to_string(),
+        });
+    }
 
-        for result in results {
-            match result {
-                Ok(cr) => {
-                    self.evaluate_rule(cr, &node_ctx, cel_old, path, errors);
-                }
-                Err(CompilationError::Parse { rule, source }) => {
-                    errors.push(ValidationError {
-                        rule: rule.clone(),
-                        message: format!("failed to compile rule \"{rule}\": {source}"),
-                        field_path: path.to_string(),
-                        reason: None,
-                        kind: ErrorKind::CompilationFailure,
-                    });
-                }
-                Err(CompilationError::InvalidRule(e)) => {
-                    errors.push(ValidationError {
-                        rule: String::new(),
-                        message: format!("invalid rule definition: {e}"),
-                        field_path: path.to_string(),
-                        reason: None,
-                        kind: ErrorKind::InvalidRule,
-                    });
-                }
-            }
+    /// Pass a diagnostic to the configured hook, if any.
+    fn diagnose(&self, diagnostic: impl FnOnce() -> Diagnostic) {
+        if let Some(DiagnosticHook(hook)) = &self.options.diagnostics {
+            hook(&diagnostic());
         }
     }
 
-    fn evaluate_rule(
-        &self,
-        cr: &CompilationResult,
-        node_ctx: &Context<'_>,
-        cel_old: Option<&cel::Value>,
-        path: &str,
-        errors: &mut Vec<ValidationError>,
-    ) {
-        // Handle transition rules
-        if cr.is_transition_rule && cel_old.is_none() && cr.rule.optional_old_self != Some(true) {
-            return; // skip transition rule without old value
+    /// Whether the node at `path` is at the depth limit, reporting it if so.
+    fn too_deep(&self, path: &[PathSegment], state: &mut WalkState) -> bool {
+        let limit = self.options.max_depth.unwrap_or(DEFAULT_MAX_DEPTH);
+        if path.len() < limit {
+            return false;
         }
+        state.push_error(ValidationError {
+            rule: String::new(),
+            message: format!("object is nested deeper than the limit of {limit}"),
+            field_path: render_path(path),
+            path: path.to_vec(),
+            reason: None,
+            kind: ErrorKind::DepthLimitExceeded,
+        });
+        true
+    }
 
-        // optionalOldSelf: true + no old object → child scope with oldSelf = null
-        let use_null_old_self = cel_old.is_none() && cr.rule.optional_old_self == Some(true);
-        let null_scope;
-        let effective_ctx: &Context<'_> = if use_null_old_self {
-            null_scope = {
-                let mut s = node_ctx.new_inner_scope();
-                s.add_variable_from_value("oldSelf", cel::Value::Null);
-                s
-            };
-            &null_scope
+    /// Whether the object or old object has more nodes than allowed,
+    /// reporting it if so. Only checked at the root.
+    fn too_large(
+        &self,
+        path: &[PathSegment],
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+        state: &mut WalkState,
+    ) -> bool {
+        let Some(limit) = self.options.max_object_nodes else {
+            return false;
+        };
+        if !path.is_empty() {
+            return false;
+        }
+        let which = if exceeds_node_count(value, limit) {
+            "object"
+        } else if old_value.is_some_and(|old| exceeds_node_count(old, limit)) {
+            "old object"
         } else {
-            node_ctx
+            return false;
         };
+        state.push_error(ValidationError {
+            rule: String::new(),
+            message: format!("{which} has more than {limit} nodes"),
+            field_path: String::new(),
+            path: Vec::new(),
+            reason: None,
+            kind: ErrorKind::CostBudgetExceeded,
+        });
+        true
+    }
 
-        let result = cr.program.execute(effective_ctx);
-        let error_path = effective_path(path, cr.rule.field_path.as_deref());
+    /// Convert `value`, the node at `path`, with `convert`, as rules see it.
+    fn bind(
+        &self,
+        value: &serde_json::Value,
+        path: &[PathSegment],
+        convert: impl FnOnce(&serde_json::Value) -> cel::Value,
+    ) -> cel::Value {
+        let mut cel = convert(&self.visible(value, path));
+        if self.options.well_known_timestamps {
+            let at: Vec<&str> = path
+                .iter()
+                .map(|segment| match segment {
+                    PathSegment::Field(name) | PathSegment::Key(name) => name.as_str(),
+                    PathSegment::Index(_) => "[]",
+                })
+                .collect();
+            parse_well_known_timestamps(&mut cel, &at);
+        }
+        cel
+    }
+
+    /// The part of the value at `path` that rules may see: with restricted
+    /// metadata, the root's `metadata` keeps only [`ROOT_METADATA_FIELDS`].
+    fn visible<'v>(
+        &self,
+        value: &'v serde_json::Value,
+        path: &[PathSegment],
+    ) -> Cow<'v, serde_json::Value> {
+        if self.options.restrict_metadata != Some(true) || !path.is_empty() {
+            return Cow::Borrowed(value);
+        }
+        let Some(metadata) = value.get("metadata").and_then(|m| m.as_object()) else {
+            return Cow::Borrowed(value);
+        };
+        if metadata
+            .keys()
+            .all(|k| ROOT_METADATA_FIELDS.contains(&k.as_str()))
+        {
+            return Cow::Borrowed(value);
+        }
+        let mut value = value.clone();
+        if let Some(metadata) = value.get_mut("metadata").and_then(|m| m.as_object_mut()) {
+            metadata.retain(|k, _| ROOT_METADATA_FIELDS.contains(&k.as_str()));
+        }
+        Cow::Owned(value)
+    }
+
+    /// The parts of `self` and `oldSelf` the node's rules read, when only
+    /// those are converted. Values the parent already converted are reused
+    /// instead.
+    fn projections(
+        &self,
+        compiled: &CompiledSchema,
+        cel: &CelCache,
+    ) -> Option<(Projection, Projection)> {
+        if !self.options.field_projection || self.options.ratcheting || cel.value.is_some() {
+            return None;
+        }
+        let mut value = Projection::default();
+        let mut old = Projection::default();
+        for cr in compiled.validations.iter().flatten() {
+            for program in std::iter::once(&cr.program).chain(&cr.message_program) {
+                value.merge(Projection::of(program.expression(), "self"));
+                old.merge(Projection::of(program.expression(), "oldSelf"));
+            }
+        }
+        (value != Projection::All).then_some((value, old))
+    }
+
+    /// Whether the node can be skipped because it equals its old value.
+    fn is_unchanged(
+        &self,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+    ) -> bool {
+        self.options.skip_unchanged && old_value == Some(value)
+    }
+
+    /// The old value an object's fields are correlated against.
+    fn correlate_fields<'a>(
+        &self,
+        map_type: &MapType,
+        old: Option<&'a serde_json::Value>,
+    ) -> Option<&'a serde_json::Value> {
+        match self.options.correlation {
+            TransitionCorrelation::Schema => map_type.correlate(old),
+            TransitionCorrelation::Index => old,
+        }
+    }
+
+    /// The old item correlated with each new list item.
+    fn correlate_items(
+        &self,
+        list_type: &ListType,
+        new: &[serde_json::Value],
+        old: Option<&serde_json::Value>,
+    ) -> Vec<Option<usize>> {
+        match self.options.correlation {
+            TransitionCorrelation::Schema => list_type.correlate_indices(new, old),
+            TransitionCorrelation::Index => ListType::Unspecified.correlate_indices(new, old),
+        }
+    }
+
+    /// Walk list items, honoring the item limits. `walk_item` receives the
+    /// index, item, item path, and error sink for each visited item.
+    fn walk_items<F>(
+        &self,
+        arr: &[serde_json::Value],
+        path: &[PathSegment],
+        state: &mut WalkState,
+        mut walk_item: F,
+    ) where
+        F: FnMut(usize, &serde_json::Value, Vec<PathSegment>, &mut WalkState),
+    {
+        let limit = self.options.max_items_to_validate.unwrap_or(usize::MAX);
+        let mut failed_items = 0;
+        for (i, item) in arr.iter().enumerate() {
+            if state.halted() {
+                return;
+            }
+            if i >= limit {
+                state.push_error(truncation_error(
+                    path,
+                    format!(
+                        "list has {} items; only the first {limit} were validated",
+                        arr.len()
+                    ),
+                ));
+                return;
+            }
+            let before = state.error_count();
+            walk_item(i, item, join_path(path, PathSegment::Index(i)), state);
+            if state.error_count() > before {
+                failed_items += 1;
+            }
+            if self
+                .options
+                .max_item_failures
+                .is_some_and(|max| failed_items >= max)
+                && i + 1 < arr.len()
+            {
+                state.push_error(truncation_error(
+                    path,
+                    format!(
+                        "stopped after {failed_items} failing items; {} of {} items were not validated",
+                        arr.len() - i - 1,
+                        arr.len()
+                    ),
+                ));
+                return;
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_compiled_results(
+        &self,
+        results: &[Result<CompilationResult, CompilationError>],
+        cel_value: &cel::Value,
+        cel_old: Option<&cel::Value>,
+        path: &[PathSegment],
+        memo: Option<MemoKey>,
+        state: &mut WalkState,
+        base_ctx: &Context<'_>,
+    ) {
+        // Create a node-level scope once with self/oldSelf bound
+        let mut node_ctx = base_ctx.new_inner_scope();
+        for (name, value) in &self.options.variables {
+            node_ctx.add_variable_from_value(name.as_str(), value.clone());
+        }
+        node_ctx.add_variable_from_value("self", cel_value.clone());
+        if let Some(old) = cel_old {
+            node_ctx.add_variable_from_value("oldSelf", old.clone());
+        }
+
+        let mut precomputed = self.precompute(results, &node_ctx, cel_old);
+
+        for (i, result) in results.iter().enumerate() {
+            if state.halted() {
+                return;
+            }
+            let e = match result {
+                Ok(cr) => {
+                    let memo = memo.map(|key| MemoKey { rule: i, ..key });
+                    let evaluation = precomputed
+                        .get_mut(i)
+                        .and_then(Option::take)
+                        .or_else(|| memo.and_then(|key| state.memoized(&key)));
+                    let executed = self
+                        .evaluate_rule(cr, &node_ctx, cel_value, cel_old, path, state, evaluation);
+                    if let (Some(key), Some(result)) = (memo, executed) {
+                        state.memoize(key, result);
+                    }
+                    continue;
+                }
+                Err(e) => e,
+            };
+            self.report_compilation_error(e, path, state);
+        }
+    }
+
+    /// Report a rule or schema node that failed to compile.
+    fn report_compilation_error(
+        &self,
+        e: &CompilationError,
+        path: &[PathSegment],
+        state: &mut WalkState,
+    ) {
+        let located = |message: String| match e.schema_path() {
+            "" => message,
+            location => format!("{location}: {message}"),
+        };
+        let (rule, message, kind) = match e {
+            CompilationError::Parse { rule, source, .. } => (
+                rule.clone(),
+                located(format!("failed to compile rule \"{rule}\": {source}")),
+                ErrorKind::CompilationFailure,
+            ),
+            CompilationError::InvalidRule { source, .. } => (
+                String::new(),
+                located(format!("invalid rule definition: {source}")),
+                ErrorKind::InvalidRule,
+            ),
+            CompilationError::CostLimitExceeded { rule, .. }
+            | CompilationError::UndeclaredFunction { rule, .. }
+            | CompilationError::InvalidRegex { rule, .. }
+            | CompilationError::TypeCheck { rule, .. } => {
+                (rule.clone(), e.to_string(), ErrorKind::CompilationFailure)
+            }
+            CompilationError::InvalidDefinition { rule, .. }
+            | CompilationError::UncorrelatableTransitionRule { rule, .. } => {
+                (rule.clone(), e.to_string(), ErrorKind::InvalidRule)
+            }
+            CompilationError::DepthLimitExceeded { .. } => {
+                (String::new(), e.to_string(), ErrorKind::DepthLimitExceeded)
+            }
+            CompilationError::InvalidRef { .. } => {
+                (String::new(), e.to_string(), ErrorKind::InvalidRule)
+            }
+        };
+        state.record(
+            &rule,
+            path,
+            RuleStatus::Skipped(SkipReason::CompilationFailed),
+            Duration::ZERO,
+        );
+        state.push_error(ValidationError {
+            rule,
+            message,
+            field_path: render_path(path),
+            path: path.to_vec(),
+            reason: None,
+            kind,
+        });
+    }
+
+    /// Evaluate a node's runnable rules in parallel ahead of the in-order
+    /// pass, if enabled. Limits, costs, and error order are still applied
+    /// sequentially, so the results match sequential evaluation exactly.
+    #[cfg(feature = "rayon")]
+    fn precompute(
+        &self,
+        results: &[Result<CompilationResult, CompilationError>],
+        node_ctx: &Context<'_>,
+        cel_old: Option<&cel::Value>,
+    ) -> Vec<Option<Evaluation>> {
+        use rayon::prelude::*;
+
+        let runnable = results
+            .iter()
+            .filter(|r| r.as_ref().is_ok_and(|cr| !skips_transition(cr, cel_old)))
+            .count();
+        if self.options.parallel_rules.is_none_or(|min| runnable < min) {
+            return Vec::new();
+        }
+        results
+            .par_iter()
+            .map(|r| match r {
+                Ok(cr) if !skips_transition(cr, cel_old) => {
+                    Some(self.execute_rule(cr, node_ctx, cel_old))
+                }
+                _ => None,
+            })
+            .collect()
+    }
 
-        match result {
-            Ok(cel::Value::Bool(true)) => {
-                // Validation passed
+    #[cfg(not(feature = "rayon"))]
+    fn precompute(
+        &self,
+        _results: &[Result<CompilationResult, CompilationError>],
+        _node_ctx: &Context<'_>,
+        _cel_old: Option<&cel::Value>,
+    ) -> Vec<Option<Evaluation>> {
+        Vec::new()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn evaluate_rule(
+        &self,
+        cr: &CompilationResult,
+        node_ctx: &Context<'_>,
+        cel_value: &cel::Value,
+        cel_old: Option<&cel::Value>,
+        path: &[PathSegment],
+        state: &mut WalkState,
+        precomputed: Option<Evaluation>,
+    ) -> Option<RuleResult> {
+        let start = Instant::now();
+        let mut executed = None;
+        let (status, duration) = match self.gate_rule(cr, cel_value, cel_old, path, state) {
+            Some(skipped) => (skipped, start.elapsed()),
+            None => {
+                let evaluation =
+                    precomputed.unwrap_or_else(|| self.execute_rule(cr, node_ctx, cel_old));
+                if state.memo.is_some() {
+                    executed = Some(evaluation.result.clone());
+                }
+                let status = self.apply_result(cr, evaluation.result, path, state);
+                (status, evaluation.duration)
             }
+        };
+        state.record(&cr.rule.rule, path, status, duration);
+        executed
+    }
+
+    /// Decide whether a rule may run. Returns the skip status (after
+    /// recording any error) if it must not be evaluated.
+    fn gate_rule(
+        &self,
+        cr: &CompilationResult,
+        cel_value: &cel::Value,
+        cel_old: Option<&cel::Value>,
+        path: &[PathSegment],
+        state: &mut WalkState,
+    ) -> Option<RuleStatus> {
+        if state.halted() || state.check_interrupted(&cr.rule.rule, path) {
+            return Some(RuleStatus::Skipped(SkipReason::Halted));
+        }
+
+        // Handle transition rules
+        if skips_transition(cr, cel_old) {
+            let reason = if state.has_old_object {
+                SkipReason::Uncorrelated
+            } else {
+                SkipReason::NoOldObject
+            };
+            self.diagnose(|| Diagnostic::TransitionRuleSkipped {
+                rule: cr.rule.rule.clone(),
+                field_path: render_path(path),
+                reason: reason.clone(),
+            });
+            return Some(RuleStatus::Skipped(reason));
+        }
+
+        if self.options.ratcheting && !cr.is_transition_rule && cel_old == Some(cel_value) {
+            return Some(RuleStatus::Skipped(SkipReason::Ratcheted));
+        }
+
+        if !self.charge_cost(cr, cel_value, cel_old, path, state) {
+            return Some(RuleStatus::Skipped(SkipReason::CostLimitExceeded));
+        }
+        None
+    }
+
+    /// Execute a rule's program. Touches no shared state, so it can run on
+    /// any thread.
+    fn execute_rule(
+        &self,
+        cr: &CompilationResult,
+        node_ctx: &Context<'_>,
+        cel_old: Option<&cel::Value>,
+    ) -> Evaluation {
+        let start = Instant::now();
+
+        // optionalOldSelf: true + no old object → child scope with oldSelf = null
+        let use_null_old_self = cel_old.is_none() && cr.rule.optional_old_self == Some(true);
+        let null_scope;
+        let effective_ctx: &Context<'_> = if use_null_old_self {
+            null_scope = {
+                let mut s = node_ctx.new_inner_scope();
+                s.add_variable_from_value("oldSelf", cel::Value::Null);
+                s
+            };
+            &null_scope
+        } else {
+            node_ctx
+        };
+
+        let result = match cr.program.execute(effective_ctx) {
+            Ok(cel::Value::Bool(true)) => RuleResult::Passed,
             Ok(cel::Value::Bool(false)) => {
-                let message = self.resolve_message(cr, effective_ctx);
-                errors.push(ValidationError {
-                    rule: cr.rule.rule.clone(),
-                    message,
-                    field_path: error_path,
-                    reason: cr.rule.reason.clone(),
-                    kind: ErrorKind::ValidationFailure,
-                });
+                let (message, fallback) = self.resolve_message(cr, effective_ctx);
+                RuleResult::Failed { message, fallback }
             }
-            Ok(_) => {
-                errors.push(ValidationError {
-                    rule: cr.rule.rule.clone(),
-                    message: format!("rule \"{}\" did not evaluate to bool", cr.rule.rule),
-                    field_path: error_path,
-                    reason: None,
-                    kind: ErrorKind::InvalidResult,
-                });
+            Ok(_) => RuleResult::NotBool,
+            Err(e) => RuleResult::Error(e.to_string()),
+        };
+        Evaluation {
+            result,
+            duration: start.elapsed(),
+        }
+    }
+
+    /// Record the error (if any) for an executed rule.
+    fn apply_result(
+        &self,
+        cr: &CompilationResult,
+        result: RuleResult,
+        path: &[PathSegment],
+        state: &mut WalkState,
+    ) -> RuleStatus {
+        let (message, reason, kind) = match result {
+            RuleResult::Passed => return RuleStatus::Passed,
+            RuleResult::Failed { message, fallback } => {
+                if let Some(cause) = fallback {
+                    self.diagnose(|| Diagnostic::MessageExpressionFallback {
+                        rule: cr.rule.rule.clone(),
+                        field_path: render_path(path),
+                        cause: cause.clone(),
+                    });
+                    state.warnings.push(ValidationError {
+                        rule: cr.rule.rule.clone(),
+                        message: cause,
+                        field_path: render_path(path),
+                        path: path.to_vec(),
+                        reason: None,
+                        kind: ErrorKind::MessageExpressionFailure,
+                    });
+                }
+                (
+                    message,
+                    cr.rule.reason.clone(),
+                    ErrorKind::ValidationFailure,
+                )
             }
-            Err(e) => {
-                errors.push(ValidationError {
+            RuleResult::NotBool => (
+                format!("rule \"{}\" did not evaluate to bool", cr.rule.rule),
+                None,
+                ErrorKind::InvalidResult,
+            ),
+            RuleResult::Error(e) => (
+                format!("rule evaluation error: {e}"),
+                None,
+                ErrorKind::EvaluationError,
+            ),
+        };
+        let error_path = effective_path(path, cr.rule.field_path.as_deref());
+        state.push_rule_error(
+            cr,
+            ValidationError {
+                rule: cr.rule.rule.clone(),
+                message,
+                field_path: render_path(&error_path),
+                path: error_path,
+                reason,
+                kind,
+            },
+        );
+        RuleStatus::Failed
+    }
+
+    /// Charge the rule's cost against the configured limits. Returns `false`
+    /// (after recording an error) if the rule must not be evaluated.
+    fn charge_cost(
+        &self,
+        cr: &CompilationResult,
+        cel_value: &cel::Value,
+        cel_old: Option<&cel::Value>,
+        path: &[PathSegment],
+        state: &mut WalkState,
+    ) -> bool {
+        if self.options.cost_budget.is_none() && self.options.per_rule_cost_limit.is_none() {
+            return true;
+        }
+
+        let mut vars = vec![("self", cel_value)];
+        if let Some(old) = cel_old {
+            vars.push(("oldSelf", old));
+        }
+        let cost = runtime_cost(cr.program.expression(), &vars);
+
+        if let Some(limit) = self.options.per_rule_cost_limit
+            && cost > limit
+        {
+            state.push_error(ValidationError {
+                rule: cr.rule.rule.clone(),
+                message: format!("rule cost {cost} exceeds the per-rule limit of {limit}"),
+                field_path: render_path(path),
+                path: path.to_vec(),
+                reason: None,
+                kind: ErrorKind::CostBudgetExceeded,
+            });
+            return false;
+        }
+
+        if let Some(remaining) = state.cost_remaining {
+            if cost > remaining {
+                state.budget_exhausted = true;
+                state.push_error(ValidationError {
                     rule: cr.rule.rule.clone(),
-                    message: format!("rule evaluation error: {e}"),
-                    field_path: error_path,
+                    message: format!(
+                        "validation cost budget of {} exceeded",
+                        self.options.cost_budget.unwrap_or_default()
+                    ),
+                    field_path: render_path(path),
+                    path: path.to_vec(),
                     reason: None,
-                    kind: ErrorKind::EvaluationError,
+                    kind: ErrorKind::CostBudgetExceeded,
                 });
+                return false;
             }
+            state.cost_remaining = Some(remaining - cost);
         }
+        true
+    }
+
+    /// Why a `messageExpression` must not run because its cost, with the
+    /// variables bound in `ctx`, is over the configured limit.
+    fn message_cost_exceeded(&self, program: &cel::Program, ctx: &Context<'_>) -> Option<String> {
+        let limit = self.options.message_cost_limit?;
+        let bound: Vec<_> = ["self", "oldSelf"]
+            .into_iter()
+            .filter_map(|name| ctx.get_variable(name).ok().map(|v| (name, v)))
+            .collect();
+        let vars: Vec<_> = bound.iter().map(|(name, v)| (*name, v)).collect();
+        let cost = runtime_cost(program.expression(), &vars);
+        (cost > limit)
+            .then(|| format!("messageExpression cost {cost} exceeds the limit of {limit}"))
     }
 
     /// Resolve the error message: try messageExpression first, fall back to
-    /// static message, then default.
-    fn resolve_message(&self, cr: &CompilationResult, ctx: &Context<'_>) -> String {
-        if let Some(ref msg_prog) = cr.message_program
-            && let Ok(cel::Value::String(s)) = msg_prog.execute(ctx)
-        {
-            return (*s).clone();
-        }
-        cr.rule
+    /// static message, then default. Also returns why the messageExpression
+    /// was not used, if the rule has one.
+    fn resolve_message(
+        &self,
+        cr: &CompilationResult,
+        ctx: &Context<'_>,
+    ) -> (String, Option<String>) {
+        let fallback = match (&cr.message_program, &cr.rule.message_expression) {
+            (_, None) => None,
+            (None, Some(_)) => Some("messageExpression failed to compile".to_string()),
+            (Some(msg_prog), Some(_)) => match self.message_cost_exceeded(msg_prog, ctx) {
+                Some(cause) => Some(cause),
+                None => match msg_prog.execute(ctx) {
+                    Ok(cel::Value::String(s)) => match check_evaluated_message(&s) {
+                        None => return ((*s).clone(), None),
+                        cause => cause,
+                    },
+                    Ok(_) => Some("messageExpression must evaluate to a string".to_string()),
+                    Err(e) => Some(format!("messageExpression evaluation failed due to: {e}")),
+                },
+            },
+        };
+        let message = cr
+            .rule
             .message
             .clone()
-            .unwrap_or_else(|| format!("failed rule: {}", cr.rule.rule))
-    }
-}
-
-impl Default for Validator {
-    fn default() -> Self {
-        Self::new()
+            .unwrap_or_else(|| format!("failed rule: {}", cr.rule.rule));
+        (message, fallback)
     }
 }
 
@@ -426,6 +2316,19 @@ pub fn validate(
     Validator::new().validate(schema, object, old_object)
 }
 
+/// Convenience function to validate against a schema in any
+/// [`SchemaSource`] form, such as a k8s-openapi `JSONSchemaProps`.
+///
+/// See [`Validator::validate_from`] for details.
+#[must_use]
+pub fn validate_from<S: SchemaSource + ?Sized>(
+    schema: &S,
+    object: &serde_json::Value,
+    old_object: Option<&serde_json::Value>,
+) -> Vec<ValidationError> {
+    Validator::new().validate_from(schema, object, old_object)
+}
+
 /// Convenience function to validate using a pre-compiled schema.
 ///
 /// See [`Validator::validate_compiled`] for details.
@@ -438,73 +2341,633 @@ pub fn validate_compiled(
     Validator::new().validate_compiled(compiled, object, old_object)
 }
 
-// ── Path helpers ────────────────────────────────────────────────────
+/// The result of executing one rule's program.
+#[derive(Clone)]
+enum RuleResult {
+    Passed,
+    /// The rule returned `false`; carries the resolved message and, if the
+    /// `messageExpression` could not be used, why.
+    Failed {
+        message: String,
+        fallback: Option<String>,
+    },
+    NotBool,
+    Error(String),
+}
 
-fn effective_path(base_path: &str, rule_field_path: Option<&str>) -> String {
-    match rule_field_path {
-        Some(fp) if fp.starts_with('.') => format!("{base_path}{fp}"),
-        Some(fp) if !base_path.is_empty() => format!("{base_path}.{fp}"),
-        Some(fp) => fp.to_string(),
-        None => base_path.to_string(),
+/// A rule's result and how long it took to compute.
+struct Evaluation {
+    result: RuleResult,
+    duration: Duration,
+}
+
+/// Identifies a rule evaluation for memoization: the schema node (by
+/// address, stable for the duration of a walk), the rule's index on that
+/// node, and hashes of `self` and `oldSelf`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct MemoKey {
+    /// The schema node: its address in a [`CompiledSchema`], or a hash of
+    /// its location in an uncompiled schema.
+    node: u64,
+    rule: usize,
+    value: u64,
+    old: Option<u64>,
+}
+
+/// Hash the location of an uncompiled schema node.
+fn hash_location(location: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    location.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hash a JSON value. Object keys are hashed in sorted order so equal
+/// values hash equally regardless of key order.
+fn hash_json(value: &serde_json::Value) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    fn feed(value: &serde_json::Value, hasher: &mut impl Hasher) {
+        std::mem::discriminant(value).hash(hasher);
+        match value {
+            serde_json::Value::Null => {}
+            serde_json::Value::Bool(b) => b.hash(hasher),
+            serde_json::Value::Number(n) => n.to_string().hash(hasher),
+            serde_json::Value::String(s) => s.hash(hasher),
+            serde_json::Value::Array(items) => {
+                items.len().hash(hasher);
+                for item in items {
+                    feed(item, hasher);
+                }
+            }
+            serde_json::Value::Object(obj) => {
+                let mut entries: Vec<_> = obj.iter().collect();
+                entries.sort_unstable_by_key(|(k, _)| *k);
+                entries.len().hash(hasher);
+                for (k, v) in entries {
+                    k.hash(hasher);
+                    feed(v, hasher);
+                }
+            }
+        }
     }
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    feed(value, &mut hasher);
+    hasher.finish()
 }
 
-fn join_path(base: &str, segment: &str) -> String {
-    if base.is_empty() {
-        segment.to_string()
-    } else {
-        format!("{base}.{segment}")
+/// The field holding a resource's status subresource.
+const STATUS: &str = "status";
+
+/// `object` with its `status` replaced by that of `old_object` (or removed if
+/// the old object has none), as the apiserver does on main-resource updates.
+fn with_old_status<'v>(
+    object: &'v serde_json::Value,
+    old_object: &serde_json::Value,
+) -> Cow<'v, serde_json::Value> {
+    if object.get(STATUS) == old_object.get(STATUS) {
+        return Cow::Borrowed(object);
     }
+    let mut object = object.clone();
+    if let Some(fields) = object.as_object_mut() {
+        match old_object.get(STATUS) {
+            Some(status) => fields.insert(STATUS.into(), status.clone()),
+            None => fields.remove(STATUS),
+        };
+    }
+    Cow::Owned(object)
 }
 
-fn join_path_index(base: &str, index: usize) -> String {
-    if base.is_empty() {
-        format!("[{index}]")
+/// Why an evaluated `messageExpression` result cannot be used as a message,
+/// following the apiserver: it must be non-blank, single-line, and at most
+/// [`MAX_EVALUATED_MESSAGE_EXPRESSION_SIZE`] bytes.
+pub(crate) fn check_evaluated_message(message: &str) -> Option<String> {
+    if message.trim().is_empty() {
+        Some("messageExpression evaluated to an empty string".to_string())
+    } else if message.len() > MAX_EVALUATED_MESSAGE_EXPRESSION_SIZE {
+        Some(format!(
+            "messageExpression beyond allowable length of {MAX_EVALUATED_MESSAGE_EXPRESSION_SIZE}"
+        ))
+    } else if message.contains(['\n', '\r']) {
+        Some("messageExpression should evaluate to a string with no line breaks".to_string())
     } else {
-        format!("{base}[{index}]")
+        None
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::compilation::compile_schema;
-    use serde_json::json;
+/// The `metadata` fields visible to rules when metadata access is restricted
+/// (see [`ValidatorOptions::with_restricted_metadata`]).
+pub const ROOT_METADATA_FIELDS: [&str; 2] = ["name", "generateName"];
 
-    fn make_schema(validations: serde_json::Value) -> serde_json::Value {
-        json!({
-            "type": "object",
-            "properties": {
-                "replicas": {"type": "integer"},
-                "name": {"type": "string"}
-            },
-            "x-kubernetes-validations": validations
-        })
+/// The longest message a `messageExpression` may produce, in bytes, matching
+/// the apiserver's limit. Longer results fall back to the static message.
+pub const MAX_EVALUATED_MESSAGE_EXPRESSION_SIZE: usize = 5 * 1024;
+
+/// Whether `value` has more than `limit` JSON nodes. Stops counting once
+/// the limit is passed.
+fn exceeds_node_count(value: &serde_json::Value, limit: usize) -> bool {
+    let mut remaining = limit;
+    let mut stack = vec![value];
+    while let Some(node) = stack.pop() {
+        let Some(rest) = remaining.checked_sub(1) else {
+            return true;
+        };
+        remaining = rest;
+        match node {
+            serde_json::Value::Array(items) => stack.extend(items),
+            serde_json::Value::Object(fields) => stack.extend(fields.values()),
+            _ => {}
+        }
     }
+    false
+}
 
-    #[test]
-    fn validation_passes() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
-        ]));
-        let obj = json!({"replicas": 3, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty());
+/// Returns `true` if `cr` is a transition rule that cannot run without `oldSelf`.
+fn skips_transition(cr: &CompilationResult, cel_old: Option<&cel::Value>) -> bool {
+    cr.is_transition_rule && cel_old.is_none() && cr.rule.optional_old_self != Some(true)
+}
+
+/// Remove errors whose message and field path repeat an earlier error,
+/// keeping the first occurrence and the original order.
+pub fn dedup(errors: &mut Vec<ValidationError>) {
+    let mut seen = std::collections::HashSet::new();
+    errors.retain(|e| seen.insert((e.message.clone(), e.field_path.clone())));
+}
+
+/// Callback receiving each error as it is produced.
+type ErrorSink<'a> = dyn FnMut(ValidationError) -> ControlFlow<()> + 'a;
+
+/// Mutable state for a single validation run.
+struct WalkState<'a> {
+    errors: Vec<ValidationError>,
+    /// When set, errors are passed here instead of being collected.
+    sink: Option<&'a mut ErrorSink<'a>>,
+    /// Number of errors passed to `sink`.
+    emitted: usize,
+    /// `(message, field_path)` of emitted errors, for de-duplication.
+    emitted_keys: std::collections::HashSet<(String, String)>,
+    /// Set once `sink` asked to stop.
+    stopped: bool,
+    /// Whether the validation has an old object (an update).
+    has_old_object: bool,
+    /// Failures of warning-severity rules.
+    warnings: Vec<ValidationError>,
+    /// Cost left in the budget, if a budget is configured.
+    cost_remaining: Option<u64>,
+    /// Set once the cost budget is exceeded; stops all further evaluation.
+    budget_exhausted: bool,
+    /// When the validation must stop, if a deadline is configured.
+    deadline: Option<Instant>,
+    /// Cancellation token, if configured.
+    cancellation: Option<CancellationToken>,
+    /// Set once the deadline passed or the validation was cancelled.
+    interrupted: bool,
+    /// Stop once this many errors have been collected.
+    max_errors: Option<usize>,
+    /// Collapse duplicate errors when finishing.
+    dedup: bool,
+    /// Per-rule outcomes, collected only when a report was requested.
+    outcomes: Option<Vec<RuleOutcome>>,
+    /// Memoized rule results, if memoization is enabled.
+    memo: Option<std::collections::HashMap<MemoKey, RuleResult>>,
+    /// A top-level field whose subtree is not validated.
+    excluded: Option<&'static str>,
+    /// The document `$ref`s are resolved against, when walking an
+    /// uncompiled schema.
+    root: Option<&'a serde_json::Value>,
+    /// The `$ref`s expanded on the way to the node being walked.
+    refs: Vec<String>,
+    /// Location of the uncompiled schema node being walked, e.g.
+    /// `properties[spec].items`, tracked only for memoization.
+    location: String,
+}
+
+impl<'a> WalkState<'a> {
+    fn new(validator: &Validator, old_object: Option<&serde_json::Value>) -> Self {
+        Self {
+            errors: Vec::new(),
+            sink: None,
+            emitted: 0,
+            emitted_keys: std::collections::HashSet::new(),
+            stopped: false,
+            has_old_object: old_object.is_some(),
+            warnings: Vec::new(),
+            cost_remaining: validator.options.cost_budget,
+            budget_exhausted: false,
+            deadline: validator.options.deadline.map(|d| Instant::now() + d),
+            cancellation: validator.options.cancellation.clone(),
+            interrupted: false,
+            max_errors: validator.options.max_errors,
+            dedup: validator.options.dedup,
+            outcomes: None,
+            memo: validator
+                .options
+                .memoize
+                .then(std::collections::HashMap::new),
+            excluded: None,
+            root: None,
+            refs: Vec::new(),
+            location: String::new(),
+        }
     }
 
-    #[test]
-    fn validation_fails() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
-        ]));
-        let obj = json!({"replicas": -1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].message, "must be non-negative");
-        assert_eq!(errors[0].rule, "self.replicas >= 0");
+    /// Enter the child schema node reached through `segment`, returning the
+    /// parent's location to restore afterwards.
+    fn descend(&mut self, segment: impl FnOnce() -> String) -> String {
+        let parent = std::mem::take(&mut self.location);
+        if self.memo.is_some() {
+            self.location = join_location(&parent, &segment());
+        }
+        parent
     }
 
-    #[test]
+    /// Resolve `$ref`s in an uncompiled schema against `root`.
+    fn with_root(mut self, root: &'a serde_json::Value) -> Self {
+        self.root = Some(root);
+        self
+    }
+
+    /// Whether the node at `path` is the excluded top-level field.
+    fn excludes(&self, path: &[PathSegment]) -> bool {
+        matches!((self.excluded, path), (Some(excluded), [PathSegment::Field(name)]) if name == excluded)
+    }
+
+    /// Start collecting per-rule outcomes.
+    fn with_outcomes(mut self) -> Self {
+        self.outcomes = Some(Vec::new());
+        self
+    }
+
+    /// Pass errors to `sink` as they are produced instead of collecting them.
+    fn with_sink(mut self, sink: &'a mut ErrorSink<'a>) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Collect an error, or pass it to the sink if one is set.
+    fn push_error(&mut self, error: ValidationError) {
+        let Some(sink) = &mut self.sink else {
+            self.errors.push(error);
+            return;
+        };
+        if self.dedup
+            && !self
+                .emitted_keys
+                .insert((error.message.clone(), error.field_path.clone()))
+        {
+            return;
+        }
+        self.emitted += 1;
+        if sink(error).is_break() {
+            self.stopped = true;
+        }
+    }
+
+    /// The memoization key for rules on `node`, if memoization is enabled.
+    fn memo_key(
+        &self,
+        node: u64,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+    ) -> Option<MemoKey> {
+        self.memo.as_ref()?;
+        Some(MemoKey {
+            node,
+            rule: 0,
+            value: hash_json(value),
+            old: old_value.map(hash_json),
+        })
+    }
+
+    /// A previously computed result for `key`.
+    fn memoized(&self, key: &MemoKey) -> Option<Evaluation> {
+        let result = self.memo.as_ref()?.get(key)?.clone();
+        Some(Evaluation {
+            result,
+            duration: Duration::ZERO,
+        })
+    }
+
+    /// Remember the result for `key`.
+    fn memoize(&mut self, key: MemoKey, result: RuleResult) {
+        if let Some(memo) = &mut self.memo {
+            memo.insert(key, result);
+        }
+    }
+
+    /// Number of errors produced so far.
+    fn error_count(&self) -> usize {
+        self.errors.len() + self.emitted
+    }
+
+    /// Record a failure of `cr`, as a warning if the rule is advisory.
+    fn push_rule_error(&mut self, cr: &CompilationResult, error: ValidationError) {
+        match cr.rule.severity {
+            Severity::Error => self.push_error(error),
+            Severity::Warning => self.warnings.push(error),
+        }
+    }
+
+    /// Record a rule outcome if outcomes are being collected.
+    fn record(&mut self, rule: &str, path: &[PathSegment], status: RuleStatus, duration: Duration) {
+        if let Some(outcomes) = &mut self.outcomes {
+            outcomes.push(RuleOutcome {
+                rule: rule.to_string(),
+                field_path: render_path(path),
+                status,
+                duration,
+            });
+        }
+    }
+
+    /// Returns `true` once the walk should stop evaluating anything.
+    fn halted(&self) -> bool {
+        self.budget_exhausted
+            || self.interrupted
+            || self.stopped
+            || self.max_errors.is_some_and(|max| self.error_count() >= max)
+    }
+
+    /// Check the deadline and cancellation token, recording an error and
+    /// halting the walk if either has fired.
+    fn check_interrupted(&mut self, rule: &str, path: &[PathSegment]) -> bool {
+        let (kind, message) = if self.cancellation.as_ref().is_some_and(|c| c.is_cancelled()) {
+            (ErrorKind::Cancelled, "validation was cancelled")
+        } else if self.deadline.is_some_and(|d| Instant::now() >= d) {
+            (ErrorKind::DeadlineExceeded, "validation deadline exceeded")
+        } else {
+            return false;
+        };
+        self.interrupted = true;
+        self.push_error(ValidationError {
+            rule: rule.to_string(),
+            message: message.to_string(),
+            field_path: render_path(path),
+            path: path.to_vec(),
+            reason: None,
+            kind,
+        });
+        true
+    }
+
+    /// The collected errors, de-duplicated if requested and capped at `max_errors`.
+    fn finish(mut self) -> Vec<ValidationError> {
+        if self.dedup {
+            dedup(&mut self.errors);
+        }
+        if let Some(max) = self.max_errors {
+            self.errors.truncate(max);
+        }
+        self.errors
+    }
+
+    /// The collected errors together with the per-rule outcomes.
+    fn finish_report(mut self) -> RuleReport {
+        let outcomes = self.outcomes.take().unwrap_or_default();
+        let mut warnings = std::mem::take(&mut self.warnings);
+        if self.dedup {
+            dedup(&mut warnings);
+        }
+        RuleReport {
+            errors: self.finish(),
+            warnings,
+            outcomes,
+        }
+    }
+}
+
+/// The root context shared by every rule: extension functions plus `type()`.
+///
+/// Registering every function is the most expensive part of setting up an
+/// evaluation, so the root is built once per process and each validation
+/// only adds scopes on top of it.
+pub(crate) fn base_context() -> &'static Context<'static> {
+    static BASE: OnceLock<Context<'static>> = OnceLock::new();
+    BASE.get_or_init(new_base_context)
+}
+
+/// A root context with every Kubernetes function registered.
+fn new_base_context() -> Context<'static> {
+    let mut ctx = Context::default();
+    crate::register_all(&mut ctx);
+    crate::values::register_types(&mut ctx);
+    ctx
+}
+
+fn truncation_error(path: &[PathSegment], message: String) -> ValidationError {
+    ValidationError {
+        rule: String::new(),
+        message,
+        field_path: render_path(path),
+        path: path.to_vec(),
+        reason: None,
+        kind: ErrorKind::ListTruncated,
+    }
+}
+
+// ── Path helpers ────────────────────────────────────────────────────
+
+fn effective_path(base_path: &[PathSegment], rule_field_path: Option<&str>) -> Vec<PathSegment> {
+    match rule_field_path {
+        Some(fp) => resolve_field_path(base_path, fp).unwrap_or_else(|| {
+            // Malformed field paths are appended verbatim.
+            join_path(
+                base_path,
+                PathSegment::Field(fp.strip_prefix('.').unwrap_or(fp).to_string()),
+            )
+        }),
+        None => base_path.to_vec(),
+    }
+}
+
+/// Resolve a rule's `fieldPath` (e.g. `.spec.host`, `.ports[0]`,
+/// `.labels['app.kubernetes.io/name']`) relative to the walk path.
+///
+/// Returns `None` if the field path is malformed.
+pub(crate) fn resolve_field_path(
+    base_path: &[PathSegment],
+    field_path: &str,
+) -> Option<Vec<PathSegment>> {
+    let mut path = base_path.to_vec();
+    let mut rest = field_path.trim();
+    if rest.is_empty() {
+        return None;
+    }
+    if !rest.starts_with(['.', '[']) {
+        let end = rest.find(['.', '[']).unwrap_or(rest.len());
+        path.push(PathSegment::Field(rest[..end].to_string()));
+        rest = &rest[end..];
+    }
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            if end == 0 {
+                return None;
+            }
+            path.push(PathSegment::Field(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let quote = after.chars().next().filter(|c| *c == '\'' || *c == '"');
+            if let Some(q) = quote {
+                let close = after[1..].find(q)? + 1;
+                path.push(PathSegment::Key(after[1..close].to_string()));
+                rest = after[close + 1..].strip_prefix(']')?;
+            } else {
+                let close = after.find(']')?;
+                let index = after[..close].trim().parse::<usize>().ok()?;
+                path.push(PathSegment::Index(index));
+                rest = &after[close + 1..];
+            }
+        } else {
+            return None;
+        }
+    }
+    Some(path)
+}
+
+/// A node's `self`/`oldSelf` CEL values, taken from the parent's conversion
+/// when available so each subtree is converted at most once.
+#[derive(Default)]
+struct CelCache {
+    value: Option<cel::Value>,
+    old: Option<cel::Value>,
+}
+
+impl CelCache {
+    /// A cache whose old value was already converted.
+    fn with_old(old: cel::Value) -> Self {
+        Self {
+            value: None,
+            old: Some(old),
+        }
+    }
+
+    /// The values for field or map key `name`. The old value is kept only
+    /// when the child is correlated with the old object.
+    fn field(&self, name: &str, correlated: bool) -> Self {
+        let key = cel::objects::Key::String(Arc::new(escape_field_name(name)));
+        let lookup = |value: &Option<cel::Value>| match value {
+            Some(cel::Value::Map(map)) => map.map.get(&key).cloned(),
+            _ => None,
+        };
+        Self {
+            value: lookup(&self.value),
+            old: if correlated { lookup(&self.old) } else { None },
+        }
+    }
+
+    /// The values for list item `i`, correlated with old item `old_index`.
+    fn item(&self, i: usize, old_index: Option<usize>) -> Self {
+        let lookup = |value: &Option<cel::Value>, i: Option<usize>| match value {
+            Some(cel::Value::List(items)) => items.get(i?).cloned(),
+            _ => None,
+        };
+        Self {
+            value: lookup(&self.value, Some(i)),
+            old: lookup(&self.old, old_index),
+        }
+    }
+
+    /// The old value to correlate with, dropping a null old value (and its
+    /// cached conversion): like a missing one, it has no `oldSelf`.
+    fn correlated_old<'v>(
+        &mut self,
+        old_value: Option<&'v serde_json::Value>,
+    ) -> Option<&'v serde_json::Value> {
+        let old_value = old_value.filter(|o| !o.is_null());
+        if old_value.is_none() {
+            self.old = None;
+        }
+        old_value
+    }
+
+    /// Fill in whichever values are missing using `convert`.
+    fn fill(
+        &mut self,
+        value: &serde_json::Value,
+        old_value: Option<&serde_json::Value>,
+        convert: impl Fn(&serde_json::Value) -> cel::Value,
+    ) -> (&cel::Value, Option<&cel::Value>) {
+        if self.old.is_none() {
+            self.old = old_value.map(&convert);
+        }
+        let value = self.value.get_or_insert_with(|| convert(value));
+        (value, self.old.as_ref())
+    }
+}
+
+fn join_path(base: &[PathSegment], segment: PathSegment) -> Vec<PathSegment> {
+    let mut path = Vec::with_capacity(base.len() + 1);
+    path.extend_from_slice(base);
+    path.push(segment);
+    path
+}
+
+/// Format a path as a string like `spec.containers[1].name`.
+///
+/// Fields and map keys are joined with `.`; list indices use `[i]`.
+pub(crate) fn render_path(path: &[PathSegment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Field(name) | PathSegment::Key(name) => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(name);
+            }
+            PathSegment::Index(i) => {
+                out.push('[');
+                out.push_str(&i.to_string());
+                out.push(']');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compilation::compile_schema;
+    use serde_json::json;
+
+    fn make_schema(validations: serde_json::Value) -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "replicas": {"type": "integer"},
+                "name": {"type": "string"}
+            },
+            "x-kubernetes-validations": validations
+        })
+    }
+
+    #[test]
+    fn validation_passes() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+        ]));
+        let obj = json!({"replicas": 3, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn validation_fails() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+        ]));
+        let obj = json!({"replicas": -1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "must be non-negative");
+        assert_eq!(errors[0].rule, "self.replicas >= 0");
+    }
+
+    #[test]
     fn default_message_when_none() {
         let schema = make_schema(json!([
             {"rule": "self.replicas >= 0"}
@@ -522,444 +2985,2455 @@ mod tests {
         ]));
         let obj = json!({"replicas": -1, "name": "app"});
         let errors = validate(&schema, &obj, None);
-        assert_eq!(errors[0].reason.as_deref(), Some("FieldValueInvalid"));
+        assert_eq!(errors[0].reason, Some(Reason::FieldValueInvalid));
+    }
+
+    #[test]
+    fn transition_rule_skipped_without_old_object() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
+        ]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn transition_rule_evaluated_with_old_object() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
+        ]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let old = json!({"replicas": 3, "name": "app"});
+        let errors = validate(&schema, &obj, Some(&old));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cannot scale down");
+    }
+
+    #[test]
+    fn transition_rule_passes() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
+        ]));
+        let obj = json!({"replicas": 5, "name": "app"});
+        let old = json!({"replicas": 3, "name": "app"});
+        let errors = validate(&schema, &obj, Some(&old));
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn nested_property_field_path() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {
+                            "type": "integer",
+                            "x-kubernetes-validations": [
+                                {"rule": "self >= 0", "message": "must be non-negative"}
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+        let obj = json!({"spec": {"replicas": -1}});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec.replicas");
+        assert_eq!(errors[0].message, "must be non-negative");
+    }
+
+    #[test]
+    fn array_items_validation() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": {"type": "string"}
+                        },
+                        "x-kubernetes-validations": [
+                            {"rule": "self.name.size() > 0", "message": "name required"}
+                        ]
+                    }
+                }
+            }
+        });
+        let obj = json!({
+            "items": [
+                {"name": "good"},
+                {"name": ""},
+                {"name": "also-good"}
+            ]
+        });
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "items[1]");
+        assert_eq!(errors[0].message, "name required");
+    }
+
+    #[test]
+    fn missing_field_not_validated() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "optional_field": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [
+                        {"rule": "self >= 0", "message": "must be non-negative"}
+                    ]
+                }
+            }
+        });
+        let obj = json!({});
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn multiple_rules_partial_failure() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "non-negative"},
+            {"rule": "self.name.size() > 0", "message": "name required"}
+        ]));
+        let obj = json!({"replicas": -1, "name": ""});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn compilation_error_reported() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >="}
+        ]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("failed to compile"));
+    }
+
+    #[test]
+    fn no_validations_no_errors() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "replicas": {"type": "integer"}
+            }
+        });
+        let obj = json!({"replicas": -1});
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn display_with_field_path() {
+        let err = ValidationError {
+            rule: "self >= 0".into(),
+            message: "must be non-negative".into(),
+            field_path: "spec.replicas".into(),
+            path: vec![
+                PathSegment::Field("spec".into()),
+                PathSegment::Field("replicas".into()),
+            ],
+            reason: None,
+            kind: ErrorKind::ValidationFailure,
+        };
+        assert_eq!(err.to_string(), "spec.replicas: must be non-negative");
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let err = ValidationError {
+            rule: "self >= 0".into(),
+            message: "must be non-negative".into(),
+            field_path: "spec.replicas[0]".into(),
+            path: vec![
+                PathSegment::Field("spec".into()),
+                PathSegment::Field("replicas".into()),
+                PathSegment::Index(0),
+            ],
+            reason: Some(Reason::FieldValueForbidden),
+            kind: ErrorKind::ValidationFailure,
+        };
+        let value = serde_json::to_value(&err).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "rule": "self >= 0",
+                "message": "must be non-negative",
+                "fieldPath": "spec.replicas[0]",
+                "path": [{"field": "spec"}, {"field": "replicas"}, {"index": 0}],
+                "reason": "FieldValueForbidden",
+                "kind": "validationFailure"
+            })
+        );
+        let back: ValidationError = serde_json::from_value(value).unwrap();
+        assert_eq!(back, err);
+
+        let minimal: ValidationError = serde_json::from_value(json!({
+            "rule": "", "message": "m", "fieldPath": "", "kind": "listTruncated"
+        }))
+        .unwrap();
+        assert_eq!(minimal.reason, None);
+        assert!(minimal.path.is_empty());
+    }
+
+    #[test]
+    fn display_without_field_path() {
+        let err = ValidationError {
+            rule: "self >= 0".into(),
+            message: "must be non-negative".into(),
+            field_path: String::new(),
+            path: Vec::new(),
+            reason: None,
+            kind: ErrorKind::ValidationFailure,
+        };
+        assert_eq!(err.to_string(), "must be non-negative");
+    }
+
+    #[test]
+    fn validator_default() {
+        let v = Validator::default();
+        let schema = make_schema(json!([{"rule": "self.replicas >= 0"}]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        assert!(v.validate(&schema, &obj, None).is_empty());
+    }
+
+    #[test]
+    fn additional_properties_walking() {
+        let schema = json!({
+            "type": "object",
+            "additionalProperties": {
+                "type": "integer",
+                "x-kubernetes-validations": [
+                    {"rule": "self >= 0", "message": "must be non-negative"}
+                ]
+            }
+        });
+        let obj = json!({"a": 1, "b": -1, "c": 5});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "b");
+    }
+
+    // ── Phase 5 tests ───────────────────────────────────────────────
+
+    #[test]
+    fn message_expression_produces_dynamic_message() {
+        let schema = make_schema(json!([{
+            "rule": "self.replicas >= 0",
+            "message": "static fallback",
+            "messageExpression": "'replicas is ' + string(self.replicas) + ', must be >= 0'"
+        }]));
+        let obj = json!({"replicas": -5, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "replicas is -5, must be >= 0");
+    }
+
+    #[test]
+    fn message_expression_falls_back_to_static() {
+        let schema = make_schema(json!([{
+            "rule": "self.replicas >= 0",
+            "message": "static message",
+            "messageExpression": "invalid >="
+        }]));
+        let obj = json!({"replicas": -1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        // messageExpression failed to compile → falls back to static message
+        assert_eq!(errors[0].message, "static message");
+    }
+
+    #[test]
+    fn optional_old_self_evaluated_on_create() {
+        let schema = make_schema(json!([{
+            "rule": "oldSelf == null || self.replicas >= oldSelf.replicas",
+            "message": "cannot scale down",
+            "optionalOldSelf": true
+        }]));
+        // Create (no old object): rule is evaluated with oldSelf = null
+        let obj = json!({"replicas": 1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty()); // oldSelf == null → true
+    }
+
+    #[test]
+    fn optional_old_self_with_old_object() {
+        let schema = make_schema(json!([{
+            "rule": "oldSelf == null || self.replicas >= oldSelf.replicas",
+            "message": "cannot scale down",
+            "optionalOldSelf": true
+        }]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let old = json!({"replicas": 3, "name": "app"});
+        let errors = validate(&schema, &obj, Some(&old));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "cannot scale down");
+    }
+
+    #[test]
+    fn optional_old_self_false_still_skips() {
+        let schema = make_schema(json!([{
+            "rule": "self.replicas >= oldSelf.replicas",
+            "message": "cannot scale down",
+            "optionalOldSelf": false
+        }]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        // optionalOldSelf: false → transition rule skipped on create
+        let errors = validate(&schema, &obj, None);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn validate_compiled_matches_validate() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "x-kubernetes-validations": [
+                        {"rule": "self.replicas >= 0", "message": "non-negative"}
+                    ],
+                    "properties": {
+                        "replicas": {"type": "integer"}
+                    }
+                }
+            }
+        });
+        let obj = json!({"spec": {"replicas": -1}});
+
+        let errors_schema = validate(&schema, &obj, None);
+        let compiled = compile_schema(&schema);
+        let errors_compiled = validate_compiled(&compiled, &obj, None);
+
+        assert_eq!(errors_schema.len(), errors_compiled.len());
+        assert_eq!(errors_schema[0].message, errors_compiled[0].message);
+        assert_eq!(errors_schema[0].field_path, errors_compiled[0].field_path);
+    }
+
+    #[test]
+    fn validate_compiled_reuse() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.x > 0", "message": "x must be positive"}
+            ],
+            "properties": {"x": {"type": "integer"}}
+        });
+        let compiled = compile_schema(&schema);
+
+        // Validate multiple objects with the same compiled schema
+        assert_eq!(
+            validate_compiled(&compiled, &json!({"x": 1}), None).len(),
+            0
+        );
+        assert_eq!(
+            validate_compiled(&compiled, &json!({"x": -1}), None).len(),
+            1
+        );
+        assert_eq!(
+            validate_compiled(&compiled, &json!({"x": 5}), None).len(),
+            0
+        );
+        assert_eq!(
+            validate_compiled(&compiled, &json!({"x": 0}), None).len(),
+            1
+        );
+    }
+
+    // ── fieldPath override tests ────────────────────────────────────
+
+    #[test]
+    fn fieldpath_overrides_auto_path() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "integer"}
+                    },
+                    "x-kubernetes-validations": [
+                        {"rule": "self.x >= 0", "message": "bad", "fieldPath": ".spec.x"}
+                    ]
+                }
+            }
+        });
+        let obj = json!({"spec": {"x": -1}});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec.spec.x");
+    }
+
+    #[test]
+    fn fieldpath_with_keys_and_indices() {
+        let resolve = |base: &str, fp: &str| {
+            let base = [PathSegment::Field(base.to_string())];
+            render_path(&effective_path(&base, Some(fp)))
+        };
+        assert_eq!(resolve("spec", ".ports[1].name"), "spec.ports[1].name");
+        assert_eq!(
+            resolve("metadata", ".labels['app.kubernetes.io/name']"),
+            "metadata.labels.app.kubernetes.io/name"
+        );
+        assert_eq!(resolve("spec", ".x[abc]"), "spec.x[abc]");
+        assert_eq!(
+            effective_path(&[], Some("[\"a\"]")),
+            [PathSegment::Key("a".into())]
+        );
+    }
+
+    #[test]
+    fn structured_path_segments() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "containers": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "x-kubernetes-validations": [{"rule": "false"}]
+                            }
+                        },
+                        "labels": {
+                            "type": "object",
+                            "additionalProperties": {
+                                "type": "string",
+                                "x-kubernetes-validations": [{"rule": "false"}]
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        let obj = json!({"spec": {"containers": [{}], "labels": {"app": "x"}}});
+        let mut errors = validate(&schema, &obj, None);
+        errors.sort_by(|a, b| a.field_path.cmp(&b.field_path));
+        assert_eq!(
+            errors[0].path,
+            [
+                PathSegment::Field("spec".into()),
+                PathSegment::Field("containers".into()),
+                PathSegment::Index(0),
+            ]
+        );
+        assert_eq!(
+            errors[1].path,
+            [
+                PathSegment::Field("spec".into()),
+                PathSegment::Field("labels".into()),
+                PathSegment::Key("app".into()),
+            ]
+        );
+        assert_eq!(errors[1].field_path, "spec.labels.app");
+        let compiled = compile_schema(&schema);
+        let mut compiled_errors = validate_compiled(&compiled, &obj, None);
+        compiled_errors.sort_by(|a, b| a.field_path.cmp(&b.field_path));
+        assert_eq!(compiled_errors, errors);
+    }
+
+    #[test]
+    fn fieldpath_without_dot() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"}
+                    },
+                    "x-kubernetes-validations": [
+                        {"rule": "self.name.size() > 0", "message": "bad", "fieldPath": "name"}
+                    ]
+                }
+            }
+        });
+        let obj = json!({"spec": {"name": ""}});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec.name");
+    }
+
+    #[test]
+    fn fieldpath_at_root() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "x": {"type": "integer"}
+            },
+            "x-kubernetes-validations": [
+                {"rule": "self.x >= 0", "message": "bad", "fieldPath": ".spec.x"}
+            ]
+        });
+        let obj = json!({"x": -1});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec.x");
+    }
+
+    #[test]
+    fn fieldpath_none_uses_auto() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "x": {"type": "integer"}
+                    },
+                    "x-kubernetes-validations": [
+                        {"rule": "self.x >= 0", "message": "bad"}
+                    ]
+                }
+            }
+        });
+        let obj = json!({"spec": {"x": -1}});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "spec");
+    }
+
+    // ── ErrorKind tests ─────────────────────────────────────────────
+
+    #[test]
+    fn error_kind_compilation_failure() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >="}
+        ]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::CompilationFailure);
+    }
+
+    #[test]
+    fn error_kind_validation_failure() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+        ]));
+        let obj = json!({"replicas": -1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::ValidationFailure);
+    }
+
+    #[test]
+    fn error_kind_evaluation_error() {
+        let schema = make_schema(json!([
+            {"rule": "self.missing_field > 0"}
+        ]));
+        let obj = json!({"replicas": 1, "name": "app"});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::EvaluationError);
+    }
+
+    // ── Large-array limit tests ─────────────────────────────────────
+
+    fn list_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "x-kubernetes-validations": [
+                        {"rule": "self.size() < 1000", "message": "too many"}
+                    ],
+                    "items": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [
+                            {"rule": "self >= 0", "message": "must be non-negative"}
+                        ]
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn max_items_to_validate_truncates() {
+        let v = Validator::builder().with_max_items_to_validate(3).build();
+        let obj = json!({"items": [0, 1, 2, -3, -4]});
+        let errors = v.validate(&list_schema(), &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::ListTruncated);
+        assert_eq!(errors[0].field_path, "items");
+        assert!(errors[0].message.contains("first 3"));
+    }
+
+    #[test]
+    fn max_items_not_reached_no_truncation() {
+        let v = Validator::builder().with_max_items_to_validate(5).build();
+        let obj = json!({"items": [0, -1, 2]});
+        let errors = v.validate(&list_schema(), &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "items[1]");
+    }
+
+    #[test]
+    fn max_item_failures_stops_early() {
+        let v = Validator::builder().with_max_item_failures(2).build();
+        let obj = json!({"items": [-1, 0, -2, -3, -4]});
+        let errors = v.validate(&list_schema(), &obj, None);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].field_path, "items[0]");
+        assert_eq!(errors[1].field_path, "items[2]");
+        assert_eq!(errors[2].kind, ErrorKind::ListTruncated);
+        assert!(errors[2].message.contains("2 of 5"));
+    }
+
+    #[test]
+    fn max_item_failures_on_last_item_not_truncated() {
+        let v = Validator::builder().with_max_item_failures(1).build();
+        let obj = json!({"items": [0, 1, -1]});
+        let errors = v.validate(&list_schema(), &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::ValidationFailure);
+    }
+
+    #[test]
+    fn item_limits_apply_to_compiled() {
+        let v = Validator::builder().with_max_items_to_validate(2).build();
+        let compiled = compile_schema(&list_schema());
+        let obj = json!({"items": [-1, 0, -2]});
+        let errors = v.validate_compiled(&compiled, &obj, None);
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].field_path, "items[0]");
+        assert_eq!(errors[1].kind, ErrorKind::ListTruncated);
+    }
+
+    // ── List correlation tests ──────────────────────────────────────
+
+    fn map_list_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "containers": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "map",
+                    "x-kubernetes-list-map-keys": ["name"],
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "name": {"type": "string"},
+                            "image": {"type": "string"}
+                        },
+                        "x-kubernetes-validations": [
+                            {"rule": "self.image == oldSelf.image", "message": "image is immutable"}
+                        ]
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn list_map_reorder_is_not_a_change() {
+        let old = json!({"containers": [{"name": "a", "image": "x"}, {"name": "b", "image": "y"}]});
+        let obj = json!({"containers": [{"name": "b", "image": "y"}, {"name": "a", "image": "x"}]});
+        assert!(validate(&map_list_schema(), &obj, Some(&old)).is_empty());
+        let compiled = compile_schema(&map_list_schema());
+        assert!(validate_compiled(&compiled, &obj, Some(&old)).is_empty());
+    }
+
+    #[test]
+    fn list_map_new_item_skips_transition_rule() {
+        let old = json!({"containers": [{"name": "a", "image": "x"}]});
+        let obj = json!({"containers": [{"name": "c", "image": "z"}, {"name": "a", "image": "w"}]});
+        let errors = validate(&map_list_schema(), &obj, Some(&old));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "containers[1]");
+
+        let compiled = compile_schema(&map_list_schema());
+        assert_eq!(validate_compiled(&compiled, &obj, Some(&old)), errors);
+    }
+
+    #[test]
+    fn list_set_only_correlates_shared_items() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "tags": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "set",
+                    "items": {
+                        "type": "string",
+                        "x-kubernetes-validations": [
+                            {"rule": "self == oldSelf", "message": "changed"},
+                            {"rule": "!self.startsWith('x') || oldSelf.size() > 0", "message": "x"}
+                        ]
+                    }
+                }
+            }
+        });
+        let old = json!({"tags": ["a", "b"]});
+        // "x-new" only exists in the new list: its transition rules are skipped.
+        // With index correlation, "b" would be compared against "a".
+        let obj = json!({"tags": ["x-new", "b", "a"]});
+        assert!(validate(&schema, &obj, Some(&old)).is_empty());
+        let compiled = compile_schema(&schema);
+        assert!(validate_compiled(&compiled, &obj, Some(&old)).is_empty());
+    }
+
+    #[test]
+    fn atomic_map_skips_child_transition_rules() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "selector": {
+                    "type": "object",
+                    "x-kubernetes-map-type": "atomic",
+                    "x-kubernetes-validations": [
+                        {"rule": "has(self.app) == has(oldSelf.app)", "message": "whole"}
+                    ],
+                    "properties": {
+                        "app": {
+                            "type": "string",
+                            "x-kubernetes-validations": [
+                                {"rule": "self == oldSelf", "message": "immutable"}
+                            ]
+                        }
+                    }
+                }
+            }
+        });
+        let old = json!({"selector": {"app": "a"}});
+        let obj = json!({"selector": {"app": "b"}});
+        // The atomic node itself still sees oldSelf; its fields do not.
+        assert!(validate(&schema, &obj, Some(&old)).is_empty());
+        let compiled = compile_schema(&schema);
+        assert!(validate_compiled(&compiled, &obj, Some(&old)).is_empty());
+    }
+
+    #[test]
+    fn atomic_list_skips_item_transition_rules() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "args": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "atomic",
+                    "items": {
+                        "type": "string",
+                        "x-kubernetes-validations": [
+                            {"rule": "self == oldSelf", "message": "immutable"}
+                        ]
+                    }
+                }
+            }
+        });
+        let old = json!({"args": ["a"]});
+        let obj = json!({"args": ["b"]});
+        assert!(validate(&schema, &obj, Some(&old)).is_empty());
+        let compiled = compile_schema(&schema);
+        assert!(validate_compiled(&compiled, &obj, Some(&old)).is_empty());
+    }
+
+    #[test]
+    fn int_or_string_type_checks() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "port": {
+                    "x-kubernetes-int-or-string": true,
+                    "x-kubernetes-validations": [
+                        {"rule": "type(self) == string || self > 0", "message": "positive"},
+                        {"rule": "type(self) == int || self.endsWith('%')", "message": "percent"}
+                    ]
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        for (obj, expected) in [
+            (json!({"port": 8080}), vec![]),
+            (json!({"port": "50%"}), vec![]),
+            (json!({"port": 0}), vec!["positive"]),
+            (json!({"port": "http"}), vec!["percent"]),
+        ] {
+            let messages: Vec<_> = validate(&schema, &obj, None)
+                .into_iter()
+                .map(|e| e.message)
+                .collect();
+            assert_eq!(messages, expected);
+            assert_eq!(
+                validate_compiled(&compiled, &obj, None).len(),
+                expected.len()
+            );
+        }
+    }
+
+    #[test]
+    fn embedded_resource_fields_accessible() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "template": {
+                    "type": "object",
+                    "x-kubernetes-embedded-resource": true,
+                    "x-kubernetes-preserve-unknown-fields": true,
+                    "x-kubernetes-validations": [
+                        {"rule": "self.apiVersion == 'v1' && self.kind == 'Pod'", "message": "pod"},
+                        {"rule": "self.metadata.name.startsWith('web-')", "message": "name"}
+                    ]
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        let ok =
+            json!({"template": {"apiVersion": "v1", "kind": "Pod", "metadata": {"name": "web-1"}}});
+        assert!(validate(&schema, &ok, None).is_empty());
+        assert!(validate_compiled(&compiled, &ok, None).is_empty());
+
+        let bad =
+            json!({"template": {"apiVersion": "v1", "kind": "Job", "metadata": {"name": "db"}}});
+        assert_eq!(validate(&schema, &bad, None).len(), 2);
+        assert_eq!(validate_compiled(&compiled, &bad, None).len(), 2);
+    }
+
+    #[test]
+    fn preserve_unknown_fields_visible_but_not_walked() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "config": {
+                    "type": "object",
+                    "x-kubernetes-preserve-unknown-fields": true,
+                    "x-kubernetes-validations": [
+                        {"rule": "has(self.extra) && self.extra.level > 1", "message": "extra"}
+                    ],
+                    "additionalProperties": {
+                        "type": "object",
+                        "x-kubernetes-validations": [{"rule": "false", "message": "walked"}]
+                    }
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        let obj = json!({"config": {"extra": {"level": 2}}});
+        assert!(validate(&schema, &obj, None).is_empty());
+        assert!(validate_compiled(&compiled, &obj, None).is_empty());
+
+        let obj = json!({"config": {"extra": {"level": 0}}});
+        let errors = validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "extra");
+        assert_eq!(validate_compiled(&compiled, &obj, None), errors);
+    }
+
+    // ── Options tests ───────────────────────────────────────────────
+
+    #[test]
+    fn builder_round_trips_options() {
+        let v = Validator::builder()
+            .with_max_errors(2)
+            .with_transition_correlation(TransitionCorrelation::Index)
+            .build();
+        assert_eq!(v.options().max_errors, Some(2));
+        assert_eq!(v.options().correlation, TransitionCorrelation::Index);
+        assert_eq!(Validator::new().options().max_errors, None);
+    }
+
+    #[test]
+    fn index_correlation_ignores_list_type() {
+        let schema = json!({
+            "type": "array",
+            "x-kubernetes-list-type": "set",
+            "items": {
+                "type": "string",
+                "x-kubernetes-validations": [{"rule": "self == oldSelf", "message": "changed"}]
+            }
+        });
+        let old = json!(["a", "b"]);
+        let obj = json!(["b", "a"]);
+        assert!(validate(&schema, &obj, Some(&old)).is_empty());
+
+        let v = Validator::builder()
+            .with_transition_correlation(TransitionCorrelation::Index)
+            .build();
+        assert_eq!(v.validate(&schema, &obj, Some(&old)).len(), 2);
+        let compiled = compile_schema(&schema);
+        assert_eq!(v.validate_compiled(&compiled, &obj, Some(&old)).len(), 2);
+    }
+
+    #[test]
+    fn dedup_collapses_repeated_errors() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "bad"},
+            {"rule": "self.replicas > -1", "message": "bad"},
+            {"rule": "self.replicas > 0", "message": "other"}
+        ]));
+        let obj = json!({"replicas": -1});
+        assert_eq!(validate(&schema, &obj, None).len(), 3);
+
+        let v = Validator::builder().with_dedup(true).build();
+        let errors = v.validate(&schema, &obj, None);
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["bad", "other"]);
+        assert_eq!(errors[0].rule, "self.replicas >= 0");
+
+        let mut all = validate(&schema, &obj, None);
+        dedup(&mut all);
+        assert_eq!(all, errors);
+    }
+
+    #[test]
+    fn warning_rules_reported_separately() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0", "message": "negative"},
+            {"rule": "self.replicas <= 5", "message": "large", "x-kube-cel-severity": "warning"}
+        ]));
+        let obj = json!({"replicas": 10});
+        assert!(validate(&schema, &obj, None).is_empty());
+
+        let report = Validator::new().validate_with_report(&schema, &obj, None);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.warnings.len(), 1);
+        assert_eq!(report.warnings[0].message, "large");
+
+        let compiled = compile_schema(&schema);
+        let report = Validator::new().validate_compiled_with_report(&compiled, &obj, None);
+        assert_eq!(report.warnings.len(), 1);
+
+        let report = Validator::new().validate_with_report(&schema, &json!({"replicas": -1}), None);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn parallel_rules_match_sequential() {
+        let rules: Vec<_> = (0..16)
+            .map(|i| json!({"rule": format!("self.replicas > {i}"), "message": format!("r{i}")}))
+            .chain([json!({"rule": "self.replicas >= oldSelf.replicas"})])
+            .collect();
+        let schema = make_schema(serde_json::Value::Array(rules));
+        let obj = json!({"replicas": 8});
+        let compiled = compile_schema(&schema);
+
+        let sequential = Validator::new().validate_compiled(&compiled, &obj, None);
+        assert_eq!(sequential.len(), 8);
+        let parallel = Validator::builder().with_parallel_rules(2).build();
+        assert_eq!(
+            parallel.validate_compiled(&compiled, &obj, None),
+            sequential
+        );
+        assert_eq!(parallel.validate(&schema, &obj, None), sequential);
+
+        let capped = Validator::builder()
+            .with_parallel_rules(2)
+            .with_max_errors(3)
+            .build();
+        assert_eq!(
+            capped.validate_compiled(&compiled, &obj, None),
+            sequential[..3]
+        );
+    }
+
+    #[test]
+    fn cached_child_values_match_fresh_conversion() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "has(self.spec)"}],
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "x-y": {
+                            "type": "string",
+                            "x-kubernetes-validations": [{"rule": "self == oldSelf", "message": "immutable"}]
+                        },
+                        "at": {
+                            "type": "string",
+                            "format": "date-time",
+                            "x-kubernetes-validations": [{"rule": "self > timestamp('2020-01-01T00:00:00Z')"}]
+                        },
+                        "items": {
+                            "type": "array",
+                            "items": {
+                                "type": "integer",
+                                "x-kubernetes-validations": [{"rule": "self >= 0", "message": "negative"}]
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        let obj = json!({"spec": {"x-y": "b", "at": "2019-06-01T00:00:00Z", "items": [1, -2]}});
+        let old = json!({"spec": {"x-y": "a"}});
+        let v = Validator::new();
+        let sorted = |mut errors: Vec<ValidationError>| {
+            errors.sort_by(|a, b| a.field_path.cmp(&b.field_path));
+            errors
+        };
+        let errors = sorted(v.validate(&schema, &obj, Some(&old)));
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["spec.at", "spec.items[1]", "spec.x-y"]);
+        assert_eq!(
+            sorted(v.validate_compiled(&compile_schema(&schema), &obj, Some(&old))),
+            errors
+        );
+    }
+
+    #[test]
+    fn base_context_is_shared() {
+        assert!(std::ptr::eq(base_context(), base_context()));
+    }
+
+    // ── Deadline and cancellation tests ─────────────────────────────
+
+    #[test]
+    fn expired_deadline_stops_validation() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= 0"},
+            {"rule": "self.replicas > 5"}
+        ]));
+        let v = Validator::builder().with_deadline(Duration::ZERO).build();
+        let errors = v.validate(&schema, &json!({"replicas": -1}), None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::DeadlineExceeded);
+
+        let generous = Validator::builder()
+            .with_deadline(Duration::from_secs(60))
+            .build();
+        assert_eq!(
+            generous
+                .validate(&schema, &json!({"replicas": -1}), None)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn cancellation_stops_validation() {
+        let schema = make_schema(json!([{"rule": "self.replicas >= 0"}]));
+        let token = CancellationToken::new();
+        let v = Validator::builder()
+            .with_cancellation(token.clone())
+            .build();
+        let compiled = compile_schema(&schema);
+        assert!(
+            v.validate_compiled(&compiled, &json!({"replicas": 1}), None)
+                .is_empty()
+        );
+
+        token.cancel();
+        assert!(token.is_cancelled());
+        let errors = v.validate_compiled(&compiled, &json!({"replicas": 1}), None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::Cancelled);
+    }
+
+    // ── Max errors tests ────────────────────────────────────────────
+
+    fn many_failures_schema() -> serde_json::Value {
+        json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.a > 0", "message": "a"},
+                {"rule": "self.b > 0", "message": "b"}
+            ],
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [{"rule": "self > 0", "message": "item"}]
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn fail_fast_stops_at_first_error() {
+        let schema = many_failures_schema();
+        let obj = json!({"a": 0, "b": 0, "items": [0, 0, 0]});
+        assert_eq!(validate(&schema, &obj, None).len(), 5);
+
+        let v = Validator::builder().with_fail_fast().build();
+        let errors = v.validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "a");
+        let compiled = compile_schema(&schema);
+        assert_eq!(v.validate_compiled(&compiled, &obj, None), errors);
+    }
+
+    #[test]
+    fn max_errors_caps_walk() {
+        let schema = many_failures_schema();
+        let obj = json!({"a": 0, "b": 0, "items": [0, 0, 0]});
+        let v = Validator::builder().with_max_errors(3).build();
+        let errors = v.validate(&schema, &obj, None);
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["a", "b", "item"]);
+        assert_eq!(errors[2].field_path, "items[0]");
+
+        let compiled = compile_schema(&schema);
+        assert_eq!(v.validate_compiled(&compiled, &obj, None).len(), 3);
+        assert_eq!(
+            Validator::builder()
+                .with_max_errors(0)
+                .build()
+                .validate(&schema, &obj, None)
+                .len(),
+            1
+        );
+    }
+    #[test]
+    fn validate_many_matches_individual_validation() {
+        let schema = many_failures_schema();
+        let compiled = compile_schema(&schema);
+        let objects = [
+            json!({"a": 1, "b": 1, "items": [1]}),
+            json!({"a": 0, "b": 1, "items": [0, 1]}),
+            json!({"a": 1, "b": 0}),
+        ];
+        let v = Validator::builder().with_max_errors(1).build();
+        let results = v.validate_many(&compiled, &objects);
+        assert_eq!(results.len(), 3);
+        for (object, errors) in objects.iter().zip(&results) {
+            assert_eq!(errors, &v.validate_compiled(&compiled, object, None));
+        }
+        assert!(results[0].is_empty());
+        assert_eq!(results[1].len(), 1);
+        assert_eq!(results[2][0].message, "b");
+        assert!(v.validate_many(&compiled, &[]).is_empty());
+    }
+
+    #[test]
+    fn validate_iter_yields_lazily() {
+        let schema = make_schema(json!([
+            {"rule": "self.replicas >= oldSelf.replicas", "message": "scaled down"}
+        ]));
+        let compiled = compile_schema(&schema);
+        let pairs = [
+            (json!({"replicas": 2}), Some(json!({"replicas": 1}))),
+            (json!({"replicas": 1}), Some(json!({"replicas": 2}))),
+            (json!({"replicas": 0}), None),
+        ];
+        let v = Validator::new();
+        let mut iter = v.validate_iter(&compiled, pairs.iter().map(|(o, old)| (o, old.as_ref())));
+        assert_eq!(iter.next(), Some((0, Vec::new())));
+        let (index, errors) = iter.next().unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(errors[0].message, "scaled down");
+        assert_eq!(iter.next(), Some((2, Vec::new())));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn skip_unchanged_prunes_equal_subtrees() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "self.a > 0", "message": "spec.a"}],
+                    "properties": {"a": {"type": "integer"}}
+                },
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [{"rule": "self > 0", "message": "item"}]
+                    }
+                }
+            }
+        });
+        let old = json!({"spec": {"a": 0}, "items": [0, 0]});
+        let obj = json!({"spec": {"a": 0}, "items": [0, 1]});
+        assert_eq!(validate(&schema, &obj, Some(&old)).len(), 2);
+
+        let v = Validator::builder().with_skip_unchanged(true).build();
+        assert!(v.validate(&schema, &obj, Some(&old)).is_empty());
+        let changed = json!({"spec": {"a": -1}, "items": [-1, 0]});
+        let mut paths: Vec<_> = v
+            .validate_compiled(&compile_schema(&schema), &changed, Some(&old))
+            .into_iter()
+            .map(|e| e.field_path)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["items[0]", "spec"]);
+        // Creates are never pruned.
+        assert_eq!(v.validate(&schema, &obj, None).len(), 2);
+    }
+
+    #[test]
+    fn memoization_reuses_identical_items() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "containers": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "x-kubernetes-validations": [
+                            {"rule": "self.image.contains('@')", "message": "must be pinned"}
+                        ],
+                        "properties": {"image": {"type": "string"}}
+                    }
+                }
+            }
+        });
+        let container = json!({"image": "nginx"});
+        let obj = json!({"containers": [container, {"image": "a@sha256:1"}, container]});
+        let memoized = Validator::builder().with_memoization(true).build();
+        let errors = memoized.validate(&schema, &obj, None);
+        assert_eq!(errors, validate(&schema, &obj, None));
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["containers[0]", "containers[2]"]);
+
+        let compiled = compile_schema(&schema);
+        let report = memoized.validate_compiled_with_report(&compiled, &obj, None);
+        assert_eq!(report.errors, errors);
+        assert_eq!(report.outcomes[2].status, RuleStatus::Failed);
+        assert_eq!(report.outcomes[2].duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn memoization_tells_ref_siblings_apart() {
+        let definition = |rule: &str, message: &str| {
+            json!({
+                "type": "object",
+                "properties": {"n": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [{"rule": rule, "message": message}]
+                }}
+            })
+        };
+        let schema = json!({
+            "definitions": {
+                "X": definition("self > 5", "must be > 5"),
+                "Y": definition("self < 10", "must be < 10")
+            },
+            "type": "object",
+            "properties": {
+                "a": {"$ref": "#/definitions/X"},
+                "b": {"$ref": "#/definitions/Y"}
+            }
+        });
+        let obj = json!({"a": {"n": 1}, "b": {"n": 1}});
+        let memoized = Validator::builder().with_memoization(true).build();
+        let errors = memoized.validate(&schema, &obj, None);
+        assert_eq!(errors, validate(&schema, &obj, None));
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["a.n"]);
+    }
+
+    #[test]
+    fn field_projection_matches_full_conversion() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.spec.replicas <= 3", "messageExpression": "'too many: ' + string(self.spec.replicas)"},
+                {"rule": "!has(self.spec.paused) || !self.spec.paused"},
+                {"rule": "self.spec.replicas == oldSelf.spec.replicas", "message": "immutable"},
+                {"rule": "self.spec.missing == 1"}
+            ],
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": {"type": "integer"},
+                        "paused": {"type": "boolean"},
+                        "containers": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "x-kubernetes-validations": [
+                                    {"rule": "self.image.contains('@')", "message": "must be pinned"}
+                                ],
+                                "properties": {"image": {"type": "string"}}
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        let compiled = compile_schema(&schema);
+        let obj = json!({"spec": {
+            "replicas": 5,
+            "paused": true,
+            "containers": [{"image": "nginx"}, {"image": "a@sha256:1"}]
+        }});
+        let old = json!({"spec": {"replicas": 1, "containers": []}});
+        let projected = Validator::builder().with_field_projection(true).build();
+        let errors = projected.validate_compiled(&compiled, &obj, Some(&old));
+        assert_eq!(errors, validate_compiled(&compiled, &obj, Some(&old)));
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert!(messages.contains(&"too many: 5"));
+        assert!(messages.contains(&"immutable"));
+        assert!(messages.contains(&"must be pinned"));
+        assert_eq!(errors.len(), 5);
+    }
+
+    #[test]
+    fn validate_typed_serializes_objects() {
+        #[derive(serde::Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Spec {
+            max_replicas: i64,
+            labels: std::collections::BTreeMap<String, String>,
+        }
+
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "maxReplicas": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [
+                        {"rule": "self >= oldSelf", "message": "cannot shrink"}
+                    ]
+                },
+                "labels": {"type": "object", "additionalProperties": {"type": "string"}}
+            },
+            "x-kubernetes-validations": [{"rule": "'app' in self.labels"}]
+        });
+        let old = Spec {
+            max_replicas: 3,
+            labels: [("app".to_string(), "web".to_string())].into(),
+        };
+        let new = Spec {
+            max_replicas: 2,
+            labels: Default::default(),
+        };
+        let v = Validator::new();
+        let errors = v.validate_typed(&schema, &new, Some(&old)).unwrap();
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["", "maxReplicas"]);
+        assert!(v.validate_typed(&schema, &old, None).unwrap().is_empty());
+
+        let unserializable: std::collections::HashMap<(i32, i32), i32> = [((1, 2), 3)].into();
+        assert!(v.validate_typed(&schema, &unserializable, None).is_err());
+    }
+
+    #[test]
+    fn hash_json_ignores_key_order() {
+        let a: serde_json::Value = serde_json::from_str(r#"{"a": 1, "b": [true, null]}"#).unwrap();
+        let b: serde_json::Value = serde_json::from_str(r#"{"b": [true, null], "a": 1}"#).unwrap();
+        assert_eq!(hash_json(&a), hash_json(&b));
+        assert_ne!(
+            hash_json(&a),
+            hash_json(&json!({"a": 2, "b": [true, null]}))
+        );
+        assert_ne!(hash_json(&json!("1")), hash_json(&json!(1)));
+    }
+
+    #[test]
+    fn ratcheting_forgives_unchanged_values() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {
+                    "type": "string",
+                    "x-kubernetes-validations": [
+                        {"rule": "self.size() <= 3", "message": "too long"},
+                        {"rule": "self == oldSelf", "message": "immutable"}
+                    ]
+                },
+                "ports": {
+                    "type": "array",
+                    "x-kubernetes-list-type": "set",
+                    "items": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [{"rule": "self < 1024", "message": "port"}]
+                    }
+                }
+            }
+        });
+        let old = json!({"name": "legacy", "ports": [8080]});
+        let v = Validator::builder().with_ratcheting(true).build();
+
+        let unchanged = json!({"name": "legacy", "ports": [8080, 80]});
+        assert!(v.validate(&schema, &unchanged, Some(&old)).is_empty());
+        assert_eq!(validate(&schema, &unchanged, Some(&old)).len(), 2);
+
+        let added = json!({"name": "legacy", "ports": [8080, 9090]});
+        let errors = v.validate_compiled(&compile_schema(&schema), &added, Some(&old));
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["ports[1]"]);
+
+        let renamed = json!({"name": "renamed"});
+        let messages: Vec<_> = v
+            .validate(&schema, &renamed, Some(&old))
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        assert_eq!(messages, ["too long", "immutable"]);
+
+        // Creates are never ratcheted.
+        assert_eq!(v.validate(&schema, &unchanged, None).len(), 2);
+
+        let report = v.validate_with_report(&schema, &unchanged, Some(&old));
+        assert_eq!(
+            report
+                .with_status(&RuleStatus::Skipped(SkipReason::Ratcheted))
+                .count(),
+            2
+        );
     }
 
     #[test]
-    fn transition_rule_skipped_without_old_object() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
-        ]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty());
+    fn structural_validation_runs_in_the_walk() {
+        let schema = json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "replicas": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "x-kubernetes-validations": [{"rule": "self % 2 == 0", "message": "even"}]
+                },
+                "mode": {"type": "string", "enum": ["a", "b"]}
+            }
+        });
+        let obj = json!({"replicas": -1, "mode": "c"});
+        assert_eq!(validate(&schema, &obj, None).len(), 1);
+
+        let v = Validator::builder()
+            .with_structural_validation(true)
+            .build();
+        let mut errors = v.validate(&schema, &obj, None);
+        let mut compiled_errors = v.validate_compiled(&compile_schema(&schema), &obj, None);
+        errors.sort_by(|a, b| (&a.field_path, &a.rule).cmp(&(&b.field_path, &b.rule)));
+        compiled_errors.sort_by(|a, b| (&a.field_path, &a.rule).cmp(&(&b.field_path, &b.rule)));
+        assert_eq!(errors, compiled_errors);
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.field_path.as_str(), e.rule.as_str(), &e.kind))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("mode", "enum", &ErrorKind::SchemaViolation),
+                ("name", "required", &ErrorKind::SchemaViolation),
+                ("replicas", "minimum", &ErrorKind::SchemaViolation),
+                ("replicas", "self % 2 == 0", &ErrorKind::ValidationFailure),
+            ]
+        );
+
+        let ratcheting = Validator::builder()
+            .with_structural_validation(true)
+            .with_ratcheting(true)
+            .build();
+        let old = json!({"name": "x", "replicas": -1, "mode": "c"});
+        let updated = json!({"name": "x", "replicas": -1, "mode": "c"});
+        assert!(
+            ratcheting
+                .validate(&schema, &updated, Some(&old))
+                .is_empty()
+        );
     }
 
     #[test]
-    fn transition_rule_evaluated_with_old_object() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
-        ]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let old = json!({"replicas": 3, "name": "app"});
-        let errors = validate(&schema, &obj, Some(&old));
+    fn validate_pruned_drops_unknown_fields_first() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "!has(self.debug)", "message": "debug not allowed"},
+                {"rule": "self.replicas >= 0", "message": "must be non-negative"}
+            ],
+            "properties": {"replicas": {"type": "integer"}}
+        });
+        let obj = json!({"replicas": -1, "debug": true});
+        assert_eq!(validate(&schema, &obj, None).len(), 2);
+
+        let v = Validator::new();
+        let (pruned, errors) = v.validate_pruned(&schema, obj.clone(), None);
+        assert_eq!(pruned, json!({"replicas": -1}));
         assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].message, "cannot scale down");
-    }
+        assert_eq!(errors[0].message, "must be non-negative");
 
-    #[test]
-    fn transition_rule_passes() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= oldSelf.replicas", "message": "cannot scale down"}
-        ]));
-        let obj = json!({"replicas": 5, "name": "app"});
-        let old = json!({"replicas": 3, "name": "app"});
-        let errors = validate(&schema, &obj, Some(&old));
-        assert!(errors.is_empty());
+        let compiled = compile_schema(&schema);
+        assert_eq!(
+            v.validate_compiled_pruned(&compiled, obj, None),
+            (pruned, errors)
+        );
     }
 
     #[test]
-    fn nested_property_field_path() {
+    fn composition_rules_are_walked() {
         let schema = json!({
             "type": "object",
             "properties": {
                 "spec": {
                     "type": "object",
-                    "properties": {
-                        "replicas": {
-                            "type": "integer",
-                            "x-kubernetes-validations": [
-                                {"rule": "self >= 0", "message": "must be non-negative"}
-                            ]
+                    "allOf": [{
+                        "properties": {
+                            "replicas": {
+                                "type": "integer",
+                                "x-kubernetes-validations": [{"rule": "self >= 0", "message": "replicas"}]
+                            }
                         }
-                    }
+                    }],
+                    "oneOf": [{
+                        "x-kubernetes-validations": [{"rule": "has(self.replicas)", "message": "spec"}]
+                    }]
                 }
             }
         });
-        let obj = json!({"spec": {"replicas": -1}});
-        let errors = validate(&schema, &obj, None);
+        let v = Validator::new();
+        let errors = v.validate(&schema, &json!({"spec": {"replicas": -1}}), None);
+        let paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+        assert_eq!(paths, ["spec.replicas"]);
+        let errors = v.validate_compiled(&compile_schema(&schema), &json!({"spec": {}}), None);
+        assert_eq!(errors[0].message, "spec");
         assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "spec.replicas");
-        assert_eq!(errors[0].message, "must be non-negative");
     }
 
     #[test]
-    fn array_items_validation() {
+    fn null_values_are_not_validated() {
         let schema = json!({
             "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.name == null || self.name.size() > 2", "message": "name too short"}
+            ],
             "properties": {
-                "items": {
-                    "type": "array",
-                    "items": {
-                        "type": "object",
-                        "properties": {
-                            "name": {"type": "string"}
-                        },
-                        "x-kubernetes-validations": [
-                            {"rule": "self.name.size() > 0", "message": "name required"}
-                        ]
-                    }
+                "name": {
+                    "type": "string",
+                    "nullable": true,
+                    "x-kubernetes-validations": [
+                        {"rule": "self.size() > 0", "message": "empty"},
+                        {"rule": "self == oldSelf", "message": "immutable"}
+                    ]
                 }
             }
         });
-        let obj = json!({
-            "items": [
-                {"name": "good"},
-                {"name": ""},
-                {"name": "also-good"}
-            ]
-        });
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "items[1]");
-        assert_eq!(errors[0].message, "name required");
+        let v = Validator::new();
+        let compiled = compile_schema(&schema);
+        assert!(v.validate(&schema, &json!({"name": null}), None).is_empty());
+        assert!(
+            v.validate_compiled(&compiled, &json!({"name": null}), None)
+                .is_empty()
+        );
+
+        // A null old value does not correlate: the transition rule is skipped.
+        let old = json!({"name": null});
+        assert!(
+            v.validate(&schema, &json!({"name": "abc"}), Some(&old))
+                .is_empty()
+        );
+        assert!(
+            v.validate_compiled(&compiled, &json!({"name": "abc"}), Some(&old))
+                .is_empty()
+        );
+        let messages: Vec<_> = v
+            .validate(
+                &schema,
+                &json!({"name": "ab"}),
+                Some(&json!({"name": "abc"})),
+            )
+            .into_iter()
+            .map(|e| e.message)
+            .collect();
+        assert_eq!(messages, ["name too short", "immutable"]);
     }
 
     #[test]
-    fn missing_field_not_validated() {
+    fn boolean_additional_properties() {
         let schema = json!({
             "type": "object",
+            "x-kubernetes-validations": [{"rule": "self.extra.all(k, self.extra[k].x > 0)"}],
             "properties": {
-                "optional_field": {
-                    "type": "integer",
-                    "x-kubernetes-validations": [
-                        {"rule": "self >= 0", "message": "must be non-negative"}
-                    ]
+                "extra": {"type": "object", "additionalProperties": true},
+                "closed": {
+                    "type": "object",
+                    "properties": {"a": {"type": "integer"}},
+                    "additionalProperties": false
                 }
             }
         });
-        let obj = json!({});
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty());
+        let obj = json!({"extra": {"k": {"x": 1}}, "closed": {"a": 1, "b": 2}});
+        assert!(validate(&schema, &obj, None).is_empty());
+
+        let v = Validator::builder()
+            .with_structural_validation(true)
+            .build();
+        let errors = v.validate(&schema, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field_path, "closed.b");
+        assert_eq!(
+            v.validate_compiled(&compile_schema(&schema), &obj, None),
+            errors
+        );
     }
 
     #[test]
-    fn multiple_rules_partial_failure() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= 0", "message": "non-negative"},
-            {"rule": "self.name.size() > 0", "message": "name required"}
-        ]));
-        let obj = json!({"replicas": -1, "name": ""});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 2);
+    fn validate_with_streams_errors_in_order() {
+        let schema = many_failures_schema();
+        let obj = json!({"a": 0, "b": 0, "items": [0, 0, 0]});
+        let v = Validator::new();
+        let mut streamed = Vec::new();
+        v.validate_with(&schema, &obj, None, |err| {
+            streamed.push(err);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(streamed, v.validate(&schema, &obj, None));
+
+        let compiled = compile_schema(&schema);
+        let mut count = 0;
+        v.validate_compiled_with(&compiled, &obj, None, |_| {
+            count += 1;
+            if count == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(count, 2);
     }
 
     #[test]
-    fn compilation_error_reported() {
+    fn validate_with_applies_limits_and_dedup() {
+        let collect = |v: Validator, schema: &serde_json::Value, obj: &serde_json::Value| {
+            let mut messages = Vec::new();
+            v.validate_with(schema, obj, None, |err| {
+                messages.push(err.message);
+                ControlFlow::Continue(())
+            });
+            messages
+        };
+        assert_eq!(
+            collect(
+                Validator::builder().with_max_errors(3).build(),
+                &many_failures_schema(),
+                &json!({"a": 0, "b": 0, "items": [0, 0, 0]}),
+            ),
+            ["a", "b", "item"]
+        );
         let schema = make_schema(json!([
-            {"rule": "self.replicas >="}
+            {"rule": "self.replicas >= 0", "message": "bad"},
+            {"rule": "self.replicas > -1", "message": "bad"},
+            {"rule": "self.replicas > 0", "message": "other"}
         ]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert!(errors[0].message.contains("failed to compile"));
+        assert_eq!(
+            collect(
+                Validator::builder().with_dedup(true).build(),
+                &schema,
+                &json!({"replicas": -1}),
+            ),
+            ["bad", "other"]
+        );
     }
 
-    #[test]
-    fn no_validations_no_errors() {
-        let schema = json!({
+    // ── Cost budget tests ───────────────────────────────────────────
+
+    fn expensive_schema() -> serde_json::Value {
+        json!({
             "type": "object",
             "properties": {
-                "replicas": {"type": "integer"}
-            }
-        });
-        let obj = json!({"replicas": -1});
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty());
+                "items": {"type": "array", "items": {"type": "string"}}
+            },
+            "x-kubernetes-validations": [
+                {"rule": "self.items.all(a, self.items.all(b, a == b || a != b))"}
+            ]
+        })
     }
 
-    #[test]
-    fn display_with_field_path() {
-        let err = ValidationError {
-            rule: "self >= 0".into(),
-            message: "must be non-negative".into(),
-            field_path: "spec.replicas".into(),
-            reason: None,
-            kind: ErrorKind::ValidationFailure,
-        };
-        assert_eq!(err.to_string(), "spec.replicas: must be non-negative");
+    fn items(n: usize) -> serde_json::Value {
+        json!({"items": (0..n).map(|i| format!("item-{i}")).collect::<Vec<_>>()})
     }
 
     #[test]
-    fn display_without_field_path() {
-        let err = ValidationError {
-            rule: "self >= 0".into(),
-            message: "must be non-negative".into(),
-            field_path: String::new(),
-            reason: None,
-            kind: ErrorKind::ValidationFailure,
-        };
-        assert_eq!(err.to_string(), "must be non-negative");
+    fn cost_budget_not_exceeded_for_small_object() {
+        let v = Validator::builder()
+            .with_cost_budget(crate::cost::RUNTIME_COST_BUDGET)
+            .build();
+        assert!(v.validate(&expensive_schema(), &items(10), None).is_empty());
     }
 
     #[test]
-    fn validator_default() {
-        let v = Validator::default();
-        let schema = make_schema(json!([{"rule": "self.replicas >= 0"}]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        assert!(v.validate(&schema, &obj, None).is_empty());
+    fn cost_budget_exceeded_for_large_object() {
+        let v = Validator::builder().with_cost_budget(100_000).build();
+        let errors = v.validate(&expensive_schema(), &items(1000), None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::CostBudgetExceeded);
+        assert!(errors[0].message.contains("budget"));
     }
 
     #[test]
-    fn additional_properties_walking() {
+    fn cost_budget_stops_later_rules() {
         let schema = json!({
             "type": "object",
-            "additionalProperties": {
-                "type": "integer",
-                "x-kubernetes-validations": [
-                    {"rule": "self >= 0", "message": "must be non-negative"}
-                ]
-            }
+            "properties": {
+                "items": {
+                    "type": "array",
+                    "items": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [{"rule": "self >= 0"}]
+                    }
+                }
+            },
+            "x-kubernetes-validations": [{"rule": "self.items.all(x, x >= 0)"}]
         });
-        let obj = json!({"a": 1, "b": -1, "c": 5});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "b");
+        let obj = json!({"items": [-1, -2, -3, -4, -5, -6, -7, -8, -9, -10]});
+        let errors = Validator::builder()
+            .with_cost_budget(40)
+            .build()
+            .validate(&schema, &obj, None);
+        let budget_errors = errors
+            .iter()
+            .filter(|e| e.kind == ErrorKind::CostBudgetExceeded)
+            .count();
+        assert_eq!(budget_errors, 1);
+        assert!(errors.len() < 11);
     }
 
-    // ── Phase 5 tests ───────────────────────────────────────────────
-
     #[test]
-    fn message_expression_produces_dynamic_message() {
-        let schema = make_schema(json!([{
-            "rule": "self.replicas >= 0",
-            "message": "static fallback",
-            "messageExpression": "'replicas is ' + string(self.replicas) + ', must be >= 0'"
-        }]));
-        let obj = json!({"replicas": -5, "name": "app"});
-        let errors = validate(&schema, &obj, None);
+    fn per_rule_cost_limit() {
+        let v = Validator::builder().with_per_rule_cost_limit(1_000).build();
+        let errors = v.validate(&expensive_schema(), &items(100), None);
         assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].message, "replicas is -5, must be >= 0");
+        assert_eq!(errors[0].kind, ErrorKind::CostBudgetExceeded);
+        assert!(errors[0].message.contains("per-rule limit"));
+        assert!(v.validate(&expensive_schema(), &items(3), None).is_empty());
     }
 
     #[test]
-    fn message_expression_falls_back_to_static() {
-        let schema = make_schema(json!([{
-            "rule": "self.replicas >= 0",
-            "message": "static message",
-            "messageExpression": "invalid >="
-        }]));
-        let obj = json!({"replicas": -1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
+    fn cost_budget_applies_to_compiled() {
+        let compiled = compile_schema(&expensive_schema());
+        let v = Validator::builder().with_cost_budget(100_000).build();
+        let errors = v.validate_compiled(&compiled, &items(1000), None);
         assert_eq!(errors.len(), 1);
-        // messageExpression failed to compile → falls back to static message
-        assert_eq!(errors[0].message, "static message");
+        assert_eq!(errors[0].kind, ErrorKind::CostBudgetExceeded);
     }
 
     #[test]
-    fn optional_old_self_evaluated_on_create() {
-        let schema = make_schema(json!([{
-            "rule": "oldSelf == null || self.replicas >= oldSelf.replicas",
-            "message": "cannot scale down",
-            "optionalOldSelf": true
-        }]));
-        // Create (no old object): rule is evaluated with oldSelf = null
-        let obj = json!({"replicas": 1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty()); // oldSelf == null → true
+    fn depth_limit_stops_the_walk() {
+        let mut schema =
+            json!({"type": "integer", "x-kubernetes-validations": [{"rule": "self > 0"}]});
+        let mut obj = json!(0);
+        for _ in 0..4 {
+            schema = json!({"type": "object", "properties": {"a": schema}});
+            obj = json!({"a": obj});
+        }
+        assert_eq!(validate(&schema, &obj, None).len(), 1);
+
+        let v = Validator::builder().with_max_depth(3).build();
+        for errors in [
+            v.validate(&schema, &obj, None),
+            v.validate_compiled(&compile_schema(&schema), &obj, None),
+        ] {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].kind, ErrorKind::DepthLimitExceeded);
+            assert_eq!(errors[0].field_path, "a.a.a");
+        }
+
+        let limits = crate::compilation::CompileOptions {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let compiled = crate::compilation::compile_schema_with_options(&schema, &limits);
+        let errors = Validator::new().validate_compiled(&compiled, &obj, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ErrorKind::DepthLimitExceeded);
+        assert_eq!(errors[0].field_path, "a.a.a");
     }
 
     #[test]
-    fn optional_old_self_with_old_object() {
-        let schema = make_schema(json!([{
-            "rule": "oldSelf == null || self.replicas >= oldSelf.replicas",
-            "message": "cannot scale down",
-            "optionalOldSelf": true
-        }]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let old = json!({"replicas": 3, "name": "app"});
-        let errors = validate(&schema, &obj, Some(&old));
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].message, "cannot scale down");
+    fn object_node_limit() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [{"rule": "self.items.size() < 2"}],
+            "properties": {"items": {"type": "array", "items": {"type": "integer"}}}
+        });
+        let small = json!({"items": [1]});
+        let large = json!({"items": [1, 2, 3]});
+        assert!(!exceeds_node_count(&small, 3));
+        assert!(exceeds_node_count(&large, 4));
+        assert!(!exceeds_node_count(&large, 5));
+
+        let v = Validator::builder().with_max_object_nodes(4).build();
+        assert!(v.validate(&schema, &small, None).is_empty());
+        for errors in [
+            v.validate(&schema, &large, None),
+            v.validate_compiled(&compile_schema(&schema), &large, None),
+            v.validate(&schema, &small, Some(&large)),
+        ] {
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].kind, ErrorKind::CostBudgetExceeded);
+            assert_eq!(errors[0].field_path, "");
+        }
+        assert_eq!(
+            v.validate(&schema, &small, Some(&large))[0].message,
+            "old object has more than 4 nodes"
+        );
     }
 
-    #[test]
-    fn optional_old_self_false_still_skips() {
-        let schema = make_schema(json!([{
-            "rule": "self.replicas >= oldSelf.replicas",
-            "message": "cannot scale down",
-            "optionalOldSelf": false
-        }]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        // optionalOldSelf: false → transition rule skipped on create
-        let errors = validate(&schema, &obj, None);
-        assert!(errors.is_empty());
+    #[test]
+    fn restricted_metadata_hides_other_fields() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.metadata.name.startsWith('web-')", "message": "name"},
+                {"rule": "has(self.metadata.labels)", "message": "labels"}
+            ],
+            "properties": {
+                "metadata": {"type": "object"},
+                "spec": {"type": "object"}
+            }
+        });
+        let obj = json!({
+            "metadata": {"name": "web-1", "labels": {"app": "web"}, "namespace": "default"},
+            "spec": {}
+        });
+        assert!(validate(&schema, &obj, None).is_empty());
+
+        let v = Validator::builder().with_restricted_metadata(true).build();
+        for errors in [
+            v.validate(&schema, &obj, None),
+            v.validate_compiled(&compile_schema(&schema), &obj, None),
+        ] {
+            let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+            assert_eq!(messages, ["labels"]);
+        }
     }
 
     #[test]
-    fn validate_compiled_matches_validate() {
+    fn subresource_updates_validate_their_part() {
         let schema = json!({
             "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "!has(self.status) || self.status.ready == (self.spec.replicas > 0)", "message": "root"}
+            ],
             "properties": {
                 "spec": {
                     "type": "object",
-                    "x-kubernetes-validations": [
-                        {"rule": "self.replicas >= 0", "message": "non-negative"}
-                    ],
-                    "properties": {
-                        "replicas": {"type": "integer"}
-                    }
+                    "x-kubernetes-validations": [{"rule": "self.replicas >= oldSelf.replicas", "message": "spec"}],
+                    "properties": {"replicas": {"type": "integer"}}
+                },
+                "status": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "self.ready || !oldSelf.ready", "message": "status"}],
+                    "properties": {"ready": {"type": "boolean"}}
                 }
             }
         });
-        let obj = json!({"spec": {"replicas": -1}});
-
-        let errors_schema = validate(&schema, &obj, None);
+        let old = json!({"spec": {"replicas": 2}, "status": {"ready": true}});
+        let obj = json!({"spec": {"replicas": 1}, "status": {"ready": false}});
         let compiled = compile_schema(&schema);
-        let errors_compiled = validate_compiled(&compiled, &obj, None);
+        let v = Validator::new();
+        let messages = |errors: Vec<ValidationError>| {
+            let mut messages: Vec<_> = errors.into_iter().map(|e| e.message).collect();
+            messages.sort();
+            messages
+        };
 
-        assert_eq!(errors_schema.len(), errors_compiled.len());
-        assert_eq!(errors_schema[0].message, errors_compiled[0].message);
-        assert_eq!(errors_schema[0].field_path, errors_compiled[0].field_path);
+        assert_eq!(
+            messages(v.validate(&schema, &obj, Some(&old))),
+            ["root", "spec", "status"]
+        );
+        for errors in [
+            v.validate_subresource(&schema, Subresource::Status, &obj, &old),
+            v.validate_compiled_subresource(&compiled, Subresource::Status, &obj, &old),
+        ] {
+            assert_eq!(messages(errors.clone()), ["status"]);
+            assert_eq!(errors[0].field_path, "status");
+        }
+        // The main update sees the old status, so the root rule compares
+        // the new spec with `ready: true`.
+        for errors in [
+            v.validate_subresource(&schema, Subresource::Main, &obj, &old),
+            v.validate_compiled_subresource(&compiled, Subresource::Main, &obj, &old),
+        ] {
+            assert_eq!(messages(errors), ["spec"]);
+        }
+        let scaled_down = json!({"spec": {"replicas": 0}, "status": {"ready": false}});
+        assert_eq!(
+            messages(v.validate_subresource(&schema, Subresource::Main, &scaled_down, &old)),
+            ["root", "spec"]
+        );
     }
 
     #[test]
-    fn validate_compiled_reuse() {
+    fn operation_entry_points() {
         let schema = json!({
             "type": "object",
             "x-kubernetes-validations": [
-                {"rule": "self.x > 0", "message": "x must be positive"}
+                {"rule": "self.replicas >= 0", "message": "non-negative"},
+                {"rule": "self.replicas >= oldSelf.replicas", "message": "no scale down"}
             ],
-            "properties": {"x": {"type": "integer"}}
+            "properties": {"replicas": {"type": "integer"}}
         });
-        let compiled = compile_schema(&schema);
-
-        // Validate multiple objects with the same compiled schema
+        let v = Validator::new();
+        let messages = |errors: Vec<ValidationError>| -> Vec<String> {
+            errors.into_iter().map(|e| e.message).collect()
+        };
+        let old = json!({"replicas": 3});
+        let obj = json!({"replicas": -1});
+        assert_eq!(messages(v.validate_create(&schema, &obj)), ["non-negative"]);
         assert_eq!(
-            validate_compiled(&compiled, &json!({"x": 1}), None).len(),
-            0
+            messages(v.validate_update(&schema, &obj, &old)),
+            ["non-negative", "no scale down"]
         );
+        assert!(v.validate_delete(&schema, &old).is_empty());
         assert_eq!(
-            validate_compiled(&compiled, &json!({"x": -1}), None).len(),
-            1
+            v.validate_operation(&schema, Operation::Create, &obj, Some(&old)),
+            v.validate_create(&schema, &obj)
+        );
+        assert_eq!(Operation::Update.to_string(), "UPDATE");
+    }
+
+    #[test]
+    fn old_schema_formats_apply_to_old_self() {
+        let rule = json!([{"rule": "self.at > oldSelf.at", "message": "must move forward"}]);
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": rule,
+            "properties": {
+                "at": {"type": "string", "format": "date-time"},
+                "windows": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "x-kubernetes-validations": [{"rule": "self.length >= oldSelf.length"}],
+                        "properties": {"length": {"type": "string", "format": "duration"}}
+                    }
+                }
+            }
+        });
+        // The previous version stored a timestamp and durations as plain
+        // strings, with no format.
+        let old_schema = json!({
+            "type": "object",
+            "properties": {
+                "at": {"type": "string"},
+                "windows": {
+                    "type": "array",
+                    "items": {"type": "object", "properties": {"length": {"type": "string"}}}
+                }
+            }
+        });
+        let old = json!({"at": "2024-01-01T00:00:00Z", "windows": [{"length": "1h"}]});
+        let obj = json!({"at": "2025-01-01T00:00:00Z", "windows": [{"length": "30m"}]});
+
+        let v = Validator::new();
+        let kinds = |errors: Vec<ValidationError>| -> Vec<(String, ErrorKind)> {
+            let mut kinds: Vec<_> = errors.into_iter().map(|e| (e.field_path, e.kind)).collect();
+            kinds.sort_by(|a, b| a.0.cmp(&b.0));
+            kinds
+        };
+        let expected = [
+            (String::new(), ErrorKind::EvaluationError),
+            ("windows[0]".to_string(), ErrorKind::EvaluationError),
+        ];
+        assert_eq!(
+            kinds(v.validate_with_old_schema(&schema, &old_schema, &obj, &old)),
+            expected
         );
         assert_eq!(
-            validate_compiled(&compiled, &json!({"x": 5}), None).len(),
-            0
+            kinds(v.validate_compiled_with_old_schema(
+                &compile_schema(&schema),
+                &compile_schema(&old_schema),
+                &obj,
+                &old
+            )),
+            expected
         );
+        let errors = v.validate_with_old_schema(&schema, &schema, &obj, &old);
         assert_eq!(
-            validate_compiled(&compiled, &json!({"x": 0}), None).len(),
-            1
+            kinds(errors),
+            [("windows[0]".to_string(), ErrorKind::ValidationFailure)]
         );
     }
 
-    // ── fieldPath override tests ────────────────────────────────────
+    #[test]
+    fn extra_variables_are_bound() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.replicas <= params.maxReplicas", "message": "too many"},
+                {"rule": "self.cluster == clusterName", "message": "wrong cluster"}
+            ],
+            "properties": {
+                "replicas": {"type": "integer"},
+                "cluster": {"type": "string"}
+            }
+        });
+        let obj = json!({"replicas": 5, "cluster": "prod"});
+        let v = Validator::builder()
+            .with_variable("params", &json!({"maxReplicas": 3}))
+            .with_variable("clusterName", &json!("dev"))
+            .with_variable("clusterName", &json!("prod"))
+            .with_variable("self", &json!({}))
+            .build();
+        for errors in [
+            v.validate(&schema, &obj, None),
+            v.validate_compiled(&compile_schema(&schema), &obj, None),
+        ] {
+            let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+            assert_eq!(messages, ["too many"]);
+        }
+        let unbound = validate(&schema, &obj, None);
+        assert!(unbound.iter().all(|e| e.kind == ErrorKind::EvaluationError));
+    }
 
     #[test]
-    fn fieldpath_overrides_auto_path() {
+    fn custom_functions_are_registered() {
+        let schema = json!({
+            "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "isApprovedRegistry(self.image)", "message": "unapproved"},
+                {"rule": "self.image.lowerAscii() == self.image", "message": "lowercase"}
+            ],
+            "properties": {"image": {"type": "string"}}
+        });
+        let v = Validator::builder()
+            .with_functions(|ctx| {
+                ctx.add_function("isApprovedRegistry", |image: Arc<String>| {
+                    image.starts_with("registry.example.com/")
+                });
+            })
+            .build();
+        let approved = json!({"image": "registry.example.com/app"});
+        let other = json!({"image": "docker.io/app"});
+        assert!(v.validate(&schema, &approved, None).is_empty());
+        let errors = v.validate_compiled(&compile_schema(&schema), &other, None);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unapproved");
+
+        let cloned = v.clone();
+        assert!(cloned.validate(&schema, &approved, None).is_empty());
+        assert_eq!(
+            Validator::new().validate(&schema, &approved, None)[0].kind,
+            ErrorKind::EvaluationError
+        );
+    }
+
+    #[test]
+    fn well_known_timestamps_bind_as_timestamps() {
         let schema = json!({
             "type": "object",
+            "x-kubernetes-validations": [
+                {"rule": "self.metadata.creationTimestamp < timestamp('2030-01-01T00:00:00Z')"}
+            ],
             "properties": {
-                "spec": {
+                "metadata": {"type": "object"},
+                "status": {
                     "type": "object",
                     "properties": {
-                        "x": {"type": "integer"}
-                    },
-                    "x-kubernetes-validations": [
-                        {"rule": "self.x >= 0", "message": "bad", "fieldPath": ".spec.x"}
-                    ]
+                        "conditions": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "x-kubernetes-validations": [
+                                    {"rule": "self.lastTransitionTime > timestamp('2020-01-01T00:00:00Z')", "message": "too old"}
+                                ],
+                                "properties": {"lastTransitionTime": {"type": "string"}}
+                            }
+                        }
+                    }
                 }
             }
         });
-        let obj = json!({"spec": {"x": -1}});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "spec.spec.x");
+        let object = json!({
+            "metadata": {"creationTimestamp": "2024-05-01T00:00:00Z"},
+            "status": {"conditions": [
+                {"lastTransitionTime": "2024-05-02T00:00:00Z"},
+                {"lastTransitionTime": "2019-05-02T00:00:00Z"}
+            ]}
+        });
+        // Without the option, the fields are strings and the comparisons fail.
+        let errors = validate(&schema, &object, None);
+        assert!(errors.iter().all(|e| e.kind == ErrorKind::EvaluationError));
+        assert_eq!(errors.len(), 3);
+
+        let v = Validator::builder()
+            .with_well_known_timestamps(true)
+            .build();
+        let compiled = compile_schema(&schema);
+        for errors in [
+            v.validate(&schema, &object, None),
+            v.validate_compiled(&compiled, &object, None),
+        ] {
+            let paths: Vec<_> = errors
+                .iter()
+                .map(|e| (e.field_path.as_str(), e.message.as_str()))
+                .collect();
+            assert_eq!(paths, [("status.conditions[1]", "too old")]);
+        }
     }
 
     #[test]
-    fn fieldpath_without_dot() {
+    fn custom_formats_convert_and_report_values() {
+        use std::sync::Mutex;
+
         let schema = json!({
             "type": "object",
             "properties": {
-                "spec": {
-                    "type": "object",
-                    "properties": {
-                        "name": {"type": "string"}
-                    },
+                "cidr": {
+                    "type": "string",
+                    "format": "cidr",
                     "x-kubernetes-validations": [
-                        {"rule": "self.name.size() > 0", "message": "bad", "fieldPath": "name"}
+                        {"rule": "self.prefix >= 8", "message": "prefix too short"}
                     ]
                 }
             }
         });
-        let obj = json!({"spec": {"name": ""}});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "spec.name");
+        let cidr = |s: &str| {
+            let (_, prefix) = s.split_once('/').ok_or("must be a CIDR")?;
+            let prefix: i64 = prefix.parse().map_err(|_| "must be a CIDR")?;
+            Ok(cel::Value::Map(
+                [("prefix".to_string(), cel::Value::Int(prefix))]
+                    .into_iter()
+                    .collect::<std::collections::HashMap<_, _>>()
+                    .into(),
+            ))
+        };
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let v = Validator::builder()
+            .with_format("cidr", move |s| cidr(s).map_err(str::to_string))
+            .with_diagnostics(move |d| sink.lock().unwrap().push(d.to_string()))
+            .build();
+        let compiled = compile_schema(&schema);
+        for object in [json!({"cidr": "10.0.0.0/8"}), json!({"cidr": "10.0.0.0/4"})] {
+            assert_eq!(
+                v.validate(&schema, &object, None),
+                v.validate_compiled(&compiled, &object, None)
+            );
+        }
+        let errors = v.validate(&schema, &json!({"cidr": "10.0.0.0/4"}), None);
+        assert_eq!(errors[0].message, "prefix too short");
+
+        let errors = v.validate(&schema, &json!({"cidr": "10.0.0.0"}), None);
+        assert_eq!(errors[0].kind, ErrorKind::EvaluationError);
+        assert_eq!(
+            seen.lock().unwrap().as_slice(),
+            ["cidr: \"10.0.0.0\" is not a valid cidr; bound as a string"]
+        );
+
+        let strict = Validator::builder()
+            .with_format("cidr", move |s| cidr(s).map_err(str::to_string))
+            .with_strict_formats(true)
+            .build();
+        let errors = strict.validate_compiled(&compiled, &json!({"cidr": "x"}), None);
+        assert_eq!(errors[0].message, "invalid value \"x\": must be a CIDR");
+        assert_eq!(errors[0].kind, ErrorKind::SchemaViolation);
     }
 
     #[test]
-    fn fieldpath_at_root() {
+    fn strict_formats_report_unparseable_values() {
         let schema = json!({
             "type": "object",
             "properties": {
-                "x": {"type": "integer"}
-            },
-            "x-kubernetes-validations": [
-                {"rule": "self.x >= 0", "message": "bad", "fieldPath": ".spec.x"}
-            ]
+                "expires": {
+                    "type": "string",
+                    "format": "date-time",
+                    "x-kubernetes-validations": [{"rule": "self == self"}]
+                },
+                "timeout": {"type": "string", "format": "duration"}
+            }
         });
-        let obj = json!({"x": -1});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, ".spec.x");
+        let object = json!({"expires": "tomorrow", "timeout": "5 minutes"});
+        assert!(Validator::new().validate(&schema, &object, None).is_empty());
+
+        let v = Validator::builder().with_strict_formats(true).build();
+        for errors in [
+            v.validate(&schema, &object, None),
+            v.validate_compiled(&compile_schema(&schema), &object, None),
+        ] {
+            let mut paths: Vec<_> = errors.iter().map(|e| e.field_path.as_str()).collect();
+            paths.sort_unstable();
+            assert_eq!(paths, ["expires", "timeout"]);
+            assert!(errors.iter().all(|e| e.kind == ErrorKind::SchemaViolation));
+            let expires = errors.iter().find(|e| e.field_path == "expires").unwrap();
+            assert_eq!(
+                expires.message,
+                "invalid value \"tomorrow\": must be an RFC 3339 date-time"
+            );
+        }
+        let valid = json!({"expires": "2030-01-01T00:00:00Z", "timeout": "5m"});
+        assert!(v.validate(&schema, &valid, None).is_empty());
     }
 
     #[test]
-    fn fieldpath_none_uses_auto() {
+    fn byte_fields_bind_as_decoded_bytes() {
         let schema = json!({
             "type": "object",
             "properties": {
-                "spec": {
-                    "type": "object",
-                    "properties": {
-                        "x": {"type": "integer"}
-                    },
+                "caBundle": {
+                    "type": "string",
+                    "format": "byte",
                     "x-kubernetes-validations": [
-                        {"rule": "self.x >= 0", "message": "bad"}
+                        {"rule": "size(self) > 0", "message": "must not be empty"},
+                        {"rule": "size(self) <= 4", "message": "too long"}
                     ]
                 }
             }
         });
-        let obj = json!({"spec": {"x": -1}});
-        let errors = validate(&schema, &obj, None);
+        // "aGVsbG8=" is 8 characters but 5 decoded bytes.
+        let errors = validate(&schema, &json!({"caBundle": "aGVsbG8="}), None);
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["too long"]);
+        assert_eq!(
+            validate(&schema, &json!({"caBundle": ""}), None)[0].message,
+            "must not be empty"
+        );
+
+        let v = Validator::builder().with_strict_formats(true).build();
+        let errors = v.validate(&schema, &json!({"caBundle": "%%%"}), None);
+        assert_eq!(
+            errors[0].message,
+            "invalid value \"%%%\": must be base64-encoded bytes"
+        );
+    }
+
+    #[test]
+    fn diagnostics_report_silent_fallbacks() {
+        use std::sync::Mutex;
+
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "replicas": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [
+                        {"rule": "self < 5", "message": "too many", "messageExpression": "self"},
+                        {"rule": "self <= oldSelf"}
+                    ]
+                },
+                "expires": {"type": "string", "format": "date-time"}
+            }
+        });
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let v = Validator::builder()
+            .with_diagnostics(move |d| sink.lock().unwrap().push(d.clone()))
+            .build();
+        let object = json!({"replicas": 7, "expires": "soon"});
+        let errors = v.validate(&schema, &object, None);
         assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].field_path, "spec");
+        assert_eq!(errors[0].message, "too many");
+
+        let mut seen = seen
+            .lock()
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        seen.sort();
+        assert_eq!(
+            seen,
+            [
+                "expires: \"soon\" is not a valid date-time; bound as a string",
+                "replicas: messageExpression of \"self < 5\" not used: messageExpression must evaluate to a string",
+                "replicas: transition rule \"self <= oldSelf\" skipped: no old object",
+            ]
+        );
     }
 
-    // ── ErrorKind tests ─────────────────────────────────────────────
+    #[test]
+    fn uncorrelated_items_are_diagnosed_on_updates() {
+        use std::sync::Mutex;
+
+        let schema = json!({
+            "type": "object",
+            "properties": {"items": {
+                "type": "array",
+                "x-kubernetes-list-type": "set",
+                "items": {
+                    "type": "integer",
+                    "x-kubernetes-validations": [{"rule": "self >= oldSelf"}]
+                }
+            }}
+        });
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let v = Validator::builder()
+            .with_diagnostics(move |d| sink.lock().unwrap().push(d.clone()))
+            .build();
+        let object = json!({"items": [1]});
+        let old = json!({"items": [2]});
+        assert!(v.validate(&schema, &object, Some(&old)).is_empty());
+        assert!(
+            v.validate_compiled(&compile_schema(&schema), &object, Some(&old))
+                .is_empty()
+        );
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        for diagnostic in seen.iter() {
+            assert!(
+                matches!(
+                    diagnostic,
+                    Diagnostic::TransitionRuleSkipped { reason: SkipReason::Uncorrelated, field_path, .. }
+                        if field_path == "items[0]"
+                ),
+                "{diagnostic}"
+            );
+        }
+    }
 
     #[test]
-    fn error_kind_compilation_failure() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >="}
-        ]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].kind, ErrorKind::CompilationFailure);
+    fn message_expression_results_follow_apiserver_rules() {
+        let message_for = |expr: &str| {
+            let schema = make_schema(json!([{
+                "rule": "self.replicas >= 0",
+                "message": "static message",
+                "messageExpression": expr
+            }]));
+            let obj = json!({"replicas": -1, "name": "app"});
+            let report = Validator::new().validate_with_report(&schema, &obj, None);
+            let warning = report.warnings.first().map(|w| {
+                assert_eq!(w.kind, ErrorKind::MessageExpressionFailure);
+                w.message.clone()
+            });
+            (report.errors[0].message.clone(), warning)
+        };
+
+        assert_eq!(
+            message_for("'replicas is ' + string(self.replicas)").0,
+            "replicas is -1"
+        );
+        let long = format!(
+            "'{}'",
+            "x".repeat(MAX_EVALUATED_MESSAGE_EXPRESSION_SIZE + 1)
+        );
+        for (expr, cause) in [
+            (
+                "self.replicas",
+                "messageExpression must evaluate to a string",
+            ),
+            ("'  '", "messageExpression evaluated to an empty string"),
+            (
+                "'a\\nb'",
+                "messageExpression should evaluate to a string with no line breaks",
+            ),
+            (&long, "messageExpression beyond allowable length of 5120"),
+            (
+                "self.missing",
+                "messageExpression evaluation failed due to: ",
+            ),
+        ] {
+            let (message, warning) = message_for(expr);
+            assert_eq!(message, "static message", "{expr}");
+            assert!(warning.unwrap().starts_with(cause), "{expr}");
+        }
     }
 
     #[test]
-    fn error_kind_validation_failure() {
-        let schema = make_schema(json!([
-            {"rule": "self.replicas >= 0", "message": "must be non-negative"}
-        ]));
-        let obj = json!({"replicas": -1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
-        assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].kind, ErrorKind::ValidationFailure);
+    fn ref_formats_apply_to_root_rules() {
+        let schema = json!({
+            "definitions": {
+                "Spec": {
+                    "type": "object",
+                    "properties": {"at": {"type": "string", "format": "date-time"}}
+                }
+            },
+            "type": "object",
+            "x-kubernetes-validations": [{
+                "rule": "self.spec.at < timestamp('2024-01-01T00:00:00Z')",
+                "message": "too late"
+            }],
+            "properties": {"spec": {"$ref": "#/definitions/Spec"}}
+        });
+        let object = json!({"spec": {"at": "2025-01-01T00:00:00Z"}});
+        let validator = Validator::new();
+        let raw = validator.validate(&schema, &object, None);
+        assert_eq!(
+            raw,
+            validator.validate_compiled(&compile_schema(&schema), &object, None)
+        );
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].kind, ErrorKind::ValidationFailure);
+        assert_eq!(raw[0].message, "too late");
     }
 
     #[test]
-    fn error_kind_evaluation_error() {
-        let schema = make_schema(json!([
-            {"rule": "self.missing_field > 0"}
-        ]));
-        let obj = json!({"replicas": 1, "name": "app"});
-        let errors = validate(&schema, &obj, None);
+    fn refs_are_resolved_by_both_walks() {
+        let schema = json!({
+            "definitions": {
+                "Node": {
+                    "type": "object",
+                    "x-kubernetes-validations": [{"rule": "self.size <= 3", "message": "too big"}],
+                    "properties": {
+                        "size": {"type": "integer"},
+                        "child": {"$ref": "#/definitions/Node"}
+                    }
+                }
+            },
+            "type": "object",
+            "properties": {"tree": {"$ref": "#/definitions/Node"}}
+        });
+        let object = json!({"tree": {"size": 5, "child": {"size": 1}}});
+        let validator = Validator::new();
+        let compiled = compile_schema(&schema);
+        for errors in [
+            validator.validate(&schema, &object, None),
+            validator.validate_compiled(&compiled, &object, None),
+        ] {
+            assert_eq!(errors.len(), 2, "{errors:?}");
+            assert_eq!(errors[0].field_path, "tree");
+            assert_eq!(errors[0].message, "too big");
+            assert_eq!(errors[1].field_path, "tree.child");
+            assert_eq!(errors[1].kind, ErrorKind::InvalidRule);
+            assert!(
+                errors[1].message.contains("cyclic reference"),
+                "{}",
+                errors[1].message
+            );
+        }
+    }
+
+    #[test]
+    fn message_expression_cost_limit() {
+        let schema = json!({
+            "type": "array",
+            "items": {"type": "string"},
+            "x-kubernetes-validations": [{
+                "rule": "self.size() < 3",
+                "message": "too many",
+                "messageExpression": "self.map(x, x + x)[3]"
+            }]
+        });
+        let list = json!(["a", "b", "c", "d"]);
+        let unlimited = Validator::new().validate(&schema, &list, None);
+        assert_eq!(unlimited[0].message, "dd");
+
+        let v = Validator::builder()
+            .with_message_expression_cost_limit(5)
+            .with_cost_budget(100)
+            .build();
+        let report = v.validate_with_report(&schema, &list, None);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].message, "too many");
+        assert!(
+            report.warnings[0]
+                .message
+                .starts_with("messageExpression cost ")
+        );
+    }
+
+    #[cfg(feature = "k8s-openapi")]
+    #[test]
+    fn validates_against_k8s_openapi_schemas() {
+        use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+            JSONSchemaProps, ValidationRule,
+        };
+
+        let props = JSONSchemaProps {
+            type_: Some("object".into()),
+            x_kubernetes_validations: Some(vec![ValidationRule {
+                rule: "self.replicas >= 0".into(),
+                message: Some("replicas must not be negative".into()),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        };
+        let errors = validate_from(&props, &json!({"replicas": -1}), None);
         assert_eq!(errors.len(), 1);
-        assert_eq!(errors[0].kind, ErrorKind::EvaluationError);
+        assert_eq!(errors[0].message, "replicas must not be negative");
+        assert!(validate_from(&props, &json!({"replicas": 1}), None).is_empty());
     }
 }
@@ -5,26 +5,52 @@
 //! values can then be bound as variables (e.g. `self`, `oldSelf`) in a CEL
 //! evaluation context.
 //!
-//! For schema-aware conversion that respects `format: "date-time"` and
-//! `format: "duration"`, use [`json_to_cel_with_schema`] or
-//! [`json_to_cel_with_compiled`].
+//! For schema-aware conversion that respects `format: "date-time"`,
+//! `format: "duration"`, `format: "byte"`, and `x-kubernetes-int-or-string`, use
+//! [`json_to_cel_with_schema`] or [`json_to_cel_with_compiled`].
+//!
+//! To convert only the fields rules read, build a field [`Projection`] and
+//! use [`json_to_cel_projected`], or evaluate through a [`LazyView`], which
+//! converts each field when it is read.
+//!
+//! Objects convert to `cel` maps, which are `std` hash maps with a random
+//! seed, so no conversion option can give them a stable order. Where the
+//! order shows, this crate sorts by key: `format()` with `%s` lists map
+//! entries sorted by key, and [`cel_to_json`] returns objects with sorted
+//! keys. Comprehensions over maps, such as `self.map(k, k)`, still see the
+//! keys in an unspecified order that can differ between runs; sort such
+//! results (`self.map(k, k).sort()`) where the order shows, e.g. in a
+//! `messageExpression`.
 
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
 
-use cel::Value;
-use cel::objects::{Key, Map};
+use cel::common::ast::{EntryExpr, Expr};
+use cel::context::VariableResolver;
+use cel::extractors::This;
+use cel::objects::{Key, Map, Opaque, OptionalValue};
+use cel::parser::Expression;
+use cel::{Context, ExecutionError, ResolveResult, Value};
 
-use crate::compilation::CompiledSchema;
-use crate::escaping::escape_field_name;
+use crate::compilation::{CompiledSchema, additional_properties_schema, expand_node, resolve_refs};
+use crate::escaping::{escape_field_name, unescape_field_name};
 
 /// The `format` hint from an OpenAPI schema property.
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum SchemaFormat {
     /// `format: "date-time"` — strings should be parsed as CEL `Timestamp`.
     DateTime,
     /// `format: "duration"` — strings should be parsed as CEL `Duration`.
     Duration,
+    /// `format: "byte"` — base64 strings should be decoded into CEL `bytes`.
+    Byte,
+    /// `x-kubernetes-int-or-string: true` — values bind as `int` or `string`.
+    IntOrString,
+    /// Any other `format`, e.g. `ipv4`. Strings bind as CEL `string` unless
+    /// a handler for the format is registered in [`CustomFormats`].
+    Custom(String),
     /// No recognized format or not a string type.
     #[default]
     None,
@@ -33,10 +59,19 @@ pub enum SchemaFormat {
 impl SchemaFormat {
     /// Extract a `SchemaFormat` from a raw JSON schema node.
     pub(crate) fn from_schema(schema: &serde_json::Value) -> Self {
+        if schema
+            .get("x-kubernetes-int-or-string")
+            .and_then(|v| v.as_bool())
+            == Some(true)
+        {
+            return SchemaFormat::IntOrString;
+        }
         match schema.get("format").and_then(|f| f.as_str()) {
             Some("date-time") => SchemaFormat::DateTime,
             Some("duration") => SchemaFormat::Duration,
-            _ => SchemaFormat::None,
+            Some("byte") => SchemaFormat::Byte,
+            Some(name) => SchemaFormat::Custom(name.to_string()),
+            None => SchemaFormat::None,
         }
     }
 }
@@ -54,25 +89,161 @@ impl SchemaFormat {
 /// 3. `f64` — for all other numeric values (floating-point)
 #[must_use]
 pub fn json_to_cel(value: &serde_json::Value) -> Value {
+    to_cel(value, &mut Conversion::new(&NO_FORMATS))
+}
+
+fn to_cel<'v>(value: &'v serde_json::Value, cx: &mut Conversion<'v, '_>) -> Value {
     match value {
         serde_json::Value::Null => Value::Null,
         serde_json::Value::Bool(b) => Value::Bool(*b),
         serde_json::Value::Number(n) => convert_number(n),
         serde_json::Value::String(s) => Value::String(Arc::new(s.clone())),
         serde_json::Value::Array(arr) => {
-            let items: Vec<Value> = arr.iter().map(json_to_cel).collect();
+            let items: Vec<Value> = arr.iter().map(|item| to_cel(item, cx)).collect();
             Value::List(Arc::new(items))
         }
         serde_json::Value::Object(obj) => {
             let mut map = HashMap::with_capacity(obj.len());
             for (k, v) in obj {
-                map.insert(Key::String(Arc::new(escape_field_name(k))), json_to_cel(v));
+                map.insert(cx.key(k), to_cel(v, cx));
             }
             Value::Map(Map { map: Arc::new(map) })
         }
     }
 }
 
+/// The escaped map keys of one conversion, so that keys repeated across
+/// list items (`name`, `image`, ... in every container) share one `Arc`
+/// and are escaped only once.
+#[derive(Default)]
+struct KeyInterner<'v>(HashMap<&'v str, Key>);
+
+impl<'v> KeyInterner<'v> {
+    fn key(&mut self, name: &'v str) -> Key {
+        self.0
+            .entry(name)
+            .or_insert_with(|| Key::String(Arc::new(escape_field_name(name))))
+            .clone()
+    }
+}
+
+/// The state of one conversion.
+struct Conversion<'v, 'f> {
+    keys: KeyInterner<'v>,
+    formats: &'f CustomFormats,
+    /// The document `$ref`s in a raw schema are resolved against.
+    document: Option<&'f serde_json::Value>,
+    /// The `$ref`s expanded on the way to the node being converted.
+    refs: Vec<String>,
+}
+
+impl<'v, 'f> Conversion<'v, 'f> {
+    fn new(formats: &'f CustomFormats) -> Self {
+        Self {
+            keys: KeyInterner::default(),
+            formats,
+            document: None,
+            refs: Vec::new(),
+        }
+    }
+
+    /// A conversion against a node of the raw schema `document`, reached
+    /// through the `$ref`s in `refs`.
+    fn in_document(
+        formats: &'f CustomFormats,
+        document: &'f serde_json::Value,
+        refs: &[String],
+    ) -> Self {
+        Self {
+            document: Some(document),
+            refs: refs.to_vec(),
+            ..Self::new(formats)
+        }
+    }
+
+    fn key(&mut self, name: &'v str) -> Key {
+        self.keys.key(name)
+    }
+}
+
+type FormatFn = dyn Fn(&str) -> Result<Value, String> + Send + Sync;
+
+/// A handler for a custom schema `format`, see [`CustomFormats::with_format`].
+#[derive(Clone)]
+struct FormatHandler(Arc<FormatFn>);
+
+/// Handlers for schema `format` values this crate does not know, such as
+/// `ipv4`, `uri` or organizational formats.
+///
+/// A handler converts a string of its format into a CEL value, or rejects
+/// it with a reason. Rejected strings bind as plain CEL `string`s and are
+/// reported like unparseable `date-time` strings (see
+/// [`ValidatorOptions::with_strict_formats`](crate::validation::ValidatorOptions::with_strict_formats)).
+///
+/// ```
+/// use std::sync::Arc;
+/// use kube_cel::compilation::compile_schema;
+/// use kube_cel::values::{CustomFormats, json_to_cel_with_formats};
+/// use serde_json::json;
+///
+/// let formats = CustomFormats::new().with_format("port", |s| match s.parse::<u16>() {
+///     Ok(port) => Ok(cel::Value::Int(port.into())),
+///     Err(_) => Err("must be a port number".to_string()),
+/// });
+/// let compiled = compile_schema(&json!({"type": "string", "format": "port"}));
+/// let value = json_to_cel_with_formats(&json!("8080"), &compiled, &formats);
+/// assert_eq!(value, cel::Value::Int(8080));
+/// ```
+#[derive(Clone, Default)]
+pub struct CustomFormats {
+    handlers: BTreeMap<String, FormatHandler>,
+}
+
+/// No custom formats.
+static NO_FORMATS: CustomFormats = CustomFormats {
+    handlers: BTreeMap::new(),
+};
+
+impl CustomFormats {
+    /// An empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle strings of schema `format` `name` with `handler`, replacing any
+    /// earlier handler for it. Formats this crate converts itself
+    /// (`date-time`, `duration`, `byte`) cannot be overridden.
+    #[must_use]
+    pub fn with_format<F>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(&str) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.handlers
+            .insert(name.into(), FormatHandler(Arc::new(handler)));
+        self
+    }
+
+    /// Whether no handlers are registered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Run the handler for format `name` on `s`, if there is one.
+    fn convert(&self, name: &str, s: &str) -> Option<Result<Value, String>> {
+        self.handlers
+            .get(name)
+            .map(|FormatHandler(handler)| handler(s))
+    }
+}
+
+impl std::fmt::Debug for CustomFormats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.handlers.keys()).finish()
+    }
+}
+
 fn convert_number(n: &serde_json::Number) -> Value {
     if let Some(i) = n.as_i64() {
         Value::Int(i)
@@ -90,38 +261,79 @@ fn convert_number(n: &serde_json::Number) -> Value {
 /// values whose schema specifies a recognized format, the string is parsed into
 /// the corresponding CEL type (`Timestamp` or `Duration`). On parse failure,
 /// the value falls back to `Value::String`.
+///
+/// Local `$ref`s are resolved against `schema`, and `allOf`/`anyOf`/`oneOf`
+/// branches are folded in, as by [`compile_schema`](crate::compilation::compile_schema),
+/// so the result matches [`json_to_cel_with_compiled`]. Nodes with an
+/// unresolvable or cyclic `$ref` convert without a schema.
 #[must_use]
 pub fn json_to_cel_with_schema(value: &serde_json::Value, schema: &serde_json::Value) -> Value {
+    to_cel_with_schema(
+        value,
+        schema,
+        &mut Conversion::in_document(&NO_FORMATS, schema, &[]),
+    )
+}
+
+fn to_cel_with_schema<'v>(
+    value: &'v serde_json::Value,
+    schema: &serde_json::Value,
+    cx: &mut Conversion<'v, '_>,
+) -> Value {
+    let refs = cx.refs.len();
+    let resolved = match cx.document {
+        Some(document) => match resolve_refs(schema, document, &mut cx.refs) {
+            Ok(resolved) => resolved,
+            Err(_) => {
+                cx.refs.truncate(refs);
+                return to_cel(value, cx);
+            }
+        },
+        None => Cow::Borrowed(schema),
+    };
+    let schema = &*expand_node(&resolved);
+    let converted = to_cel_with_node(value, schema, cx);
+    cx.refs.truncate(refs);
+    converted
+}
+
+/// Convert `value` against a schema node whose `$ref`s and compositions
+/// are already expanded.
+fn to_cel_with_node<'v>(
+    value: &'v serde_json::Value,
+    schema: &serde_json::Value,
+    cx: &mut Conversion<'v, '_>,
+) -> Value {
     let format = SchemaFormat::from_schema(schema);
     match value {
         serde_json::Value::Null => Value::Null,
         serde_json::Value::Bool(b) => Value::Bool(*b),
-        serde_json::Value::Number(n) => convert_number(n),
-        serde_json::Value::String(s) => convert_string_with_format(s, &format),
+        serde_json::Value::Number(n) => convert_number_with_format(n, &format),
+        serde_json::Value::String(s) => convert_string_with_format(s, &format, cx.formats),
         serde_json::Value::Array(arr) => {
             let items: Vec<Value> = arr
                 .iter()
                 .map(|item| match schema.get("items") {
-                    Some(items_schema) => json_to_cel_with_schema(item, items_schema),
-                    None => json_to_cel(item),
+                    Some(items_schema) => to_cel_with_schema(item, items_schema, cx),
+                    None => to_cel(item, cx),
                 })
                 .collect();
             Value::List(Arc::new(items))
         }
         serde_json::Value::Object(obj) => {
             let props = schema.get("properties").and_then(|p| p.as_object());
-            let additional = schema.get("additionalProperties").filter(|a| a.is_object());
+            let additional = additional_properties_schema(schema);
 
             let mut map = HashMap::with_capacity(obj.len());
             for (k, v) in obj {
                 let child_val = if let Some(prop_schema) = props.and_then(|p| p.get(k)) {
-                    json_to_cel_with_schema(v, prop_schema)
+                    to_cel_with_schema(v, prop_schema, cx)
                 } else if let Some(additional_schema) = additional {
-                    json_to_cel_with_schema(v, additional_schema)
+                    to_cel_with_schema(v, additional_schema, cx)
                 } else {
-                    json_to_cel(v)
+                    to_cel(v, cx)
                 };
-                map.insert(Key::String(Arc::new(escape_field_name(k))), child_val);
+                map.insert(cx.key(k), child_val);
             }
             Value::Map(Map { map: Arc::new(map) })
         }
@@ -134,17 +346,61 @@ pub fn json_to_cel_with_schema(value: &serde_json::Value, schema: &serde_json::V
 /// in the compiled schema tree instead of parsing the raw JSON schema.
 #[must_use]
 pub fn json_to_cel_with_compiled(value: &serde_json::Value, compiled: &CompiledSchema) -> Value {
+    to_cel_with_compiled(value, compiled, &mut Conversion::new(&NO_FORMATS))
+}
+
+/// Convert a JSON value like [`json_to_cel_with_compiled`], converting
+/// strings of custom formats with the handlers in `formats`.
+#[must_use]
+pub fn json_to_cel_with_formats(
+    value: &serde_json::Value,
+    compiled: &CompiledSchema,
+    formats: &CustomFormats,
+) -> Value {
+    to_cel_with_compiled(value, compiled, &mut Conversion::new(formats))
+}
+
+/// [`json_to_cel_with_schema`] with custom formats.
+pub(crate) fn json_to_cel_with_schema_formats(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    formats: &CustomFormats,
+) -> Value {
+    json_to_cel_in_document(value, schema, schema, &[], formats)
+}
+
+/// Like [`json_to_cel_with_schema_formats`], for a `schema` node of
+/// `document` reached through the `$ref`s in `refs`.
+pub(crate) fn json_to_cel_in_document(
+    value: &serde_json::Value,
+    schema: &serde_json::Value,
+    document: &serde_json::Value,
+    refs: &[String],
+    formats: &CustomFormats,
+) -> Value {
+    to_cel_with_schema(
+        value,
+        schema,
+        &mut Conversion::in_document(formats, document, refs),
+    )
+}
+
+fn to_cel_with_compiled<'v>(
+    value: &'v serde_json::Value,
+    compiled: &CompiledSchema,
+    cx: &mut Conversion<'v, '_>,
+) -> Value {
     match value {
         serde_json::Value::Null => Value::Null,
         serde_json::Value::Bool(b) => Value::Bool(*b),
-        serde_json::Value::Number(n) => convert_number(n),
-        serde_json::Value::String(s) => convert_string_with_format(s, &compiled.format),
+        serde_json::Value::Number(n) => convert_number_with_format(n, &compiled.format),
+        serde_json::Value::String(s) => convert_string_with_format(s, &compiled.format, cx.formats),
         serde_json::Value::Array(arr) => {
             let items: Vec<Value> = arr
                 .iter()
                 .map(|item| match &compiled.items {
-                    Some(items_compiled) => json_to_cel_with_compiled(item, items_compiled),
-                    None => json_to_cel(item),
+                    Some(items_compiled) => to_cel_with_compiled(item, items_compiled, cx),
+                    None => to_cel(item, cx),
                 })
                 .collect();
             Value::List(Arc::new(items))
@@ -153,21 +409,63 @@ pub fn json_to_cel_with_compiled(value: &serde_json::Value, compiled: &CompiledS
             let mut map = HashMap::with_capacity(obj.len());
             for (k, v) in obj {
                 let child_val = if let Some(prop_compiled) = compiled.properties.get(k) {
-                    json_to_cel_with_compiled(v, prop_compiled)
+                    to_cel_with_compiled(v, prop_compiled, cx)
                 } else if let Some(ref additional) = compiled.additional_properties {
-                    json_to_cel_with_compiled(v, additional)
+                    to_cel_with_compiled(v, additional, cx)
                 } else {
-                    json_to_cel(v)
+                    to_cel(v, cx)
                 };
-                map.insert(Key::String(Arc::new(escape_field_name(k))), child_val);
+                map.insert(cx.key(k), child_val);
             }
             Value::Map(Map { map: Arc::new(map) })
         }
     }
 }
 
+/// Parse a YAML document and convert it like [`json_to_cel`]
+/// (feature = `yaml`).
+///
+/// ```
+/// use kube_cel::values::yaml_to_cel;
+///
+/// let value = yaml_to_cel("replicas: 3\nimage: nginx").unwrap();
+/// assert!(matches!(value, cel::Value::Map(_)));
+/// ```
+#[cfg(feature = "yaml")]
+pub fn yaml_to_cel(yaml: &str) -> Result<Value, crate::yaml::YamlError> {
+    Ok(json_to_cel(&crate::yaml::parse(yaml)?))
+}
+
+/// Parse a YAML document and convert it like [`json_to_cel_with_schema`]
+/// (feature = `yaml`).
+#[cfg(feature = "yaml")]
+pub fn yaml_to_cel_with_schema(
+    yaml: &str,
+    schema: &serde_json::Value,
+) -> Result<Value, crate::yaml::YamlError> {
+    Ok(json_to_cel_with_schema(&crate::yaml::parse(yaml)?, schema))
+}
+
+/// Convert a number using the schema format hint.
+///
+/// IntOrString fields are integers on the API server, so whole floats such as
+/// `3.0` (common after YAML round-trips) bind as `int`.
+fn convert_number_with_format(n: &serde_json::Number, format: &SchemaFormat) -> Value {
+    match (format, n.as_f64()) {
+        (SchemaFormat::IntOrString, Some(f))
+            if n.as_i64().is_none()
+                && f.fract() == 0.0
+                && f >= i64::MIN as f64
+                && f < i64::MAX as f64 =>
+        {
+            Value::Int(f as i64)
+        }
+        _ => convert_number(n),
+    }
+}
+
 /// Convert a string using the schema format hint.
-fn convert_string_with_format(s: &str, format: &SchemaFormat) -> Value {
+fn convert_string_with_format(s: &str, format: &SchemaFormat, formats: &CustomFormats) -> Value {
     match format {
         SchemaFormat::DateTime => {
             if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
@@ -181,10 +479,775 @@ fn convert_string_with_format(s: &str, format: &SchemaFormat) -> Value {
             }
             Value::String(Arc::new(s.to_string()))
         }
-        SchemaFormat::None => Value::String(Arc::new(s.to_string())),
+        SchemaFormat::Byte => {
+            if let Some(bytes) = decode_base64(s) {
+                return Value::Bytes(Arc::new(bytes));
+            }
+            Value::String(Arc::new(s.to_string()))
+        }
+        SchemaFormat::Custom(name) => match formats.convert(name, s) {
+            Some(Ok(value)) => value,
+            _ => Value::String(Arc::new(s.to_string())),
+        },
+        SchemaFormat::IntOrString | SchemaFormat::None => Value::String(Arc::new(s.to_string())),
+    }
+}
+
+/// The part of a value that CEL expressions read through one variable.
+///
+/// Built with [`Projection::of`] from the field selections on the variable,
+/// e.g. `self.spec.replicas`. Converting with [`json_to_cel_projected`] then
+/// skips every field no expression selects. Any other use of the variable,
+/// such as `size(self)` or `self == oldSelf`, needs the whole value.
+///
+/// The selected fields are converted up front, before evaluation. To
+/// convert them as expressions read them instead, use a [`LazyView`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Projection {
+    /// The whole value.
+    All,
+    /// Only these fields of an object, by their escaped CEL name. Values that
+    /// are not objects are converted whole.
+    Fields(BTreeMap<String, Projection>),
+}
+
+impl Default for Projection {
+    /// No fields: the variable is not read.
+    fn default() -> Self {
+        Projection::Fields(BTreeMap::new())
+    }
+}
+
+impl Projection {
+    /// The part of `variable` that `expr` reads.
+    ///
+    /// ```
+    /// use cel::Program;
+    /// use kube_cel::values::Projection;
+    ///
+    /// let program = Program::compile("self.spec.replicas > 0 && has(self.status)").unwrap();
+    /// let projection = Projection::of(program.expression(), "self");
+    /// assert!(projection.includes(&["spec", "replicas"]));
+    /// assert!(!projection.includes(&["spec", "template"]));
+    /// ```
+    #[must_use]
+    pub fn of(expr: &Expression, variable: &str) -> Self {
+        let mut projection = Projection::default();
+        projection.collect(expr, variable);
+        projection
+    }
+
+    /// Whether the value at `path` is converted, e.g. `["spec", "replicas"]`.
+    #[must_use]
+    pub fn includes(&self, path: &[&str]) -> bool {
+        match (self, path.split_first()) {
+            (Projection::All, _) => true,
+            (Projection::Fields(_), None) => false,
+            (Projection::Fields(fields), Some((first, rest))) => {
+                fields.get(*first).is_some_and(|p| p.includes(rest))
+            }
+        }
+    }
+
+    /// Extend this projection with everything `other` reads.
+    pub fn merge(&mut self, other: Projection) {
+        match (self, other) {
+            (Projection::All, _) => {}
+            (this, Projection::All) => *this = Projection::All,
+            (Projection::Fields(fields), Projection::Fields(other)) => {
+                for (name, projection) in other {
+                    match fields.entry(name) {
+                        Entry::Occupied(entry) => entry.into_mut().merge(projection),
+                        Entry::Vacant(entry) => {
+                            entry.insert(projection);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn collect(&mut self, expr: &Expression, variable: &str) {
+        if *self == Projection::All {
+            return;
+        }
+        if let Some(path) = selected_path(expr, variable) {
+            self.insert(&path);
+            return;
+        }
+        match &expr.expr {
+            // `has(self.a.b)` only needs `b` to be kept in `self.a`.
+            Expr::Select(select) => match selected_path(&select.operand, variable) {
+                Some(mut path) => {
+                    path.push(&select.field);
+                    self.insert(&path);
+                }
+                None => self.collect(&select.operand, variable),
+            },
+            Expr::Call(call) => {
+                if let Some(target) = &call.target {
+                    self.collect(target, variable);
+                }
+                call.args.iter().for_each(|arg| self.collect(arg, variable));
+            }
+            Expr::List(list) => list.elements.iter().for_each(|e| self.collect(e, variable)),
+            Expr::Map(map) => map
+                .entries
+                .iter()
+                .for_each(|e| self.collect_entry(&e.expr, variable)),
+            Expr::Struct(st) => st
+                .entries
+                .iter()
+                .for_each(|e| self.collect_entry(&e.expr, variable)),
+            Expr::Comprehension(comp) => {
+                // A comprehension variable shadowing `variable` is not
+                // worth tracking; read the whole value instead.
+                if comp.iter_var == variable
+                    || comp.iter_var2.as_deref() == Some(variable)
+                    || comp.accu_var == variable
+                {
+                    *self = Projection::All;
+                    return;
+                }
+                for e in [
+                    &comp.iter_range,
+                    &comp.accu_init,
+                    &comp.loop_cond,
+                    &comp.loop_step,
+                    &comp.result,
+                ] {
+                    self.collect(e, variable);
+                }
+            }
+            Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => {}
+        }
+    }
+
+    fn collect_entry(&mut self, entry: &EntryExpr, variable: &str) {
+        match entry {
+            EntryExpr::MapEntry(e) => {
+                self.collect(&e.key, variable);
+                self.collect(&e.value, variable);
+            }
+            EntryExpr::StructField(field) => self.collect(&field.value, variable),
+        }
+    }
+
+    fn insert(&mut self, path: &[&str]) {
+        match (self, path.split_first()) {
+            (Projection::All, _) => {}
+            (this, None) => *this = Projection::All,
+            (Projection::Fields(fields), Some((first, rest))) => {
+                fields.entry((*first).to_string()).or_default().insert(rest)
+            }
+        }
+    }
+}
+
+/// The fields `expr` selects from `variable`, e.g. `["spec", "replicas"]`
+/// for `self.spec.replicas`. `has()` tests end the path.
+fn selected_path<'e>(expr: &'e Expression, variable: &str) -> Option<Vec<&'e str>> {
+    match &expr.expr {
+        Expr::Ident(name) if name == variable => Some(Vec::new()),
+        Expr::Select(select) if !select.test => {
+            let mut path = selected_path(&select.operand, variable)?;
+            path.push(&select.field);
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+/// Convert only the part of a JSON value that `projection` covers, using a
+/// pre-compiled [`CompiledSchema`] for formats.
+///
+/// Fields outside the projection are left out of the resulting maps, so
+/// large objects that rules barely touch are not converted in full. Rules
+/// whose [`Projection::of`] is covered by `projection` evaluate the same as
+/// with [`json_to_cel_with_compiled`].
+#[must_use]
+pub fn json_to_cel_projected(
+    value: &serde_json::Value,
+    compiled: &CompiledSchema,
+    projection: &Projection,
+) -> Value {
+    json_to_cel_projected_with_formats(value, compiled, projection, &NO_FORMATS)
+}
+
+/// [`json_to_cel_projected`] with custom formats.
+pub(crate) fn json_to_cel_projected_with_formats(
+    value: &serde_json::Value,
+    compiled: &CompiledSchema,
+    projection: &Projection,
+    formats: &CustomFormats,
+) -> Value {
+    project(
+        value,
+        Some(compiled),
+        projection,
+        &mut Conversion::new(formats),
+    )
+}
+
+fn project<'v>(
+    value: &'v serde_json::Value,
+    compiled: Option<&CompiledSchema>,
+    projection: &Projection,
+    cx: &mut Conversion<'v, '_>,
+) -> Value {
+    let (Projection::Fields(fields), serde_json::Value::Object(obj)) = (projection, value) else {
+        return match compiled {
+            Some(compiled) => to_cel_with_compiled(value, compiled, cx),
+            None => to_cel(value, cx),
+        };
+    };
+    let mut map = HashMap::with_capacity(fields.len());
+    for (key, projection) in fields {
+        let name = unescape_field_name(key);
+        // Only names that escape back to `key` are visible under it.
+        let Some((name, child)) = obj
+            .get_key_value(&name)
+            .filter(|(name, _)| escape_field_name(name) == *key)
+        else {
+            continue;
+        };
+        let child_compiled = compiled.and_then(|c| {
+            c.properties
+                .get(name)
+                .or(c.additional_properties.as_deref())
+        });
+        map.insert(cx.key(name), project(child, child_compiled, projection, cx));
+    }
+    Value::Map(Map { map: Arc::new(map) })
+}
+
+/// A lazy view of JSON values bound to CEL variables: the fields an
+/// expression selects are converted when it reads them, and nothing else.
+///
+/// [`LazyView::prepare`] rewrites each field selection on a bound variable,
+/// such as `self.spec.replicas` or `has(self.status)`, into a lookup the
+/// view resolves on access; [`LazyView::evaluate`] runs the rewritten
+/// expression. Each selected value is converted once per view, with the
+/// schema formats of a [`CompiledSchema`] if one is bound. Any other use of
+/// a variable, such as `size(self)` or `self == oldSelf`, converts the
+/// whole value, also on first access. Expressions evaluate as they would
+/// over the fully converted values, errors for missing fields included.
+///
+/// ```
+/// use cel::{Context, Program, Value};
+/// use kube_cel::values::LazyView;
+/// use serde_json::json;
+///
+/// let object = json!({"spec": {"replicas": 3, "template": {"large": "..."}}});
+/// let view = LazyView::new().bind("self", &object);
+/// let program = Program::compile("self.spec.replicas > 1").unwrap();
+/// let expression = view.prepare(program.expression());
+/// let result = view.evaluate(&expression, &Context::default());
+/// assert_eq!(result, Ok(Value::Bool(true)));
+/// ```
+#[derive(Debug, Default)]
+pub struct LazyView<'a> {
+    variables: Vec<(&'a str, &'a serde_json::Value, Option<&'a CompiledSchema>)>,
+    /// Converted values by the identifier [`LazyView::prepare`] gave them.
+    converted: Mutex<HashMap<String, Value>>,
+}
+
+impl<'a> LazyView<'a> {
+    /// An empty view.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bind `value` to `variable`, converted without a schema.
+    #[must_use]
+    pub fn bind(mut self, variable: &'a str, value: &'a serde_json::Value) -> Self {
+        self.variables.push((variable, value, None));
+        self
+    }
+
+    /// Bind `value` to `variable`, converted with the formats of `compiled`.
+    #[must_use]
+    pub fn bind_with_compiled(
+        mut self,
+        variable: &'a str,
+        value: &'a serde_json::Value,
+        compiled: &'a CompiledSchema,
+    ) -> Self {
+        self.variables.push((variable, value, Some(compiled)));
+        self
+    }
+
+    /// Rewrite the field selections on the bound variables of `expr` into
+    /// lookups this view resolves. The result can be evaluated with any
+    /// view binding the same variables.
+    #[must_use]
+    pub fn prepare(&self, expr: &Expression) -> Expression {
+        let mut expr = expr.clone();
+        let variables: Vec<&str> = self.variables.iter().map(|(name, ..)| *name).collect();
+        bind_selections(&mut expr, &variables);
+        expr
+    }
+
+    /// Evaluate an expression from [`LazyView::prepare`] in a scope of
+    /// `ctx` where the bound variables resolve through this view.
+    pub fn evaluate(&self, expr: &Expression, ctx: &Context<'_>) -> ResolveResult {
+        let mut scope = ctx.new_inner_scope();
+        scope.set_variable_resolver(self);
+        Value::resolve(expr, &scope).map_err(|e| match &e {
+            // The resolver cannot return errors, so a selection that misses
+            // surfaces as an undeclared reference to its identifier; report
+            // the missing key as a plain selection would.
+            ExecutionError::UndeclaredReference(name) => match self.missing_key(name) {
+                Some(key) => ExecutionError::NoSuchKey(Arc::new(key.to_string())),
+                None => e,
+            },
+            _ => e,
+        })
+    }
+
+    /// The value and schema at the variable and fields of `path`, e.g.
+    /// `self.spec.replicas`, or the first field that is missing.
+    fn lookup<'p>(
+        &self,
+        path: &'p str,
+    ) -> Option<Result<(&'a serde_json::Value, Option<&'a CompiledSchema>), &'p str>> {
+        let mut segments = path.split('.');
+        let variable = segments.next()?;
+        let &(_, mut value, mut compiled) =
+            self.variables.iter().find(|(name, ..)| *name == variable)?;
+        for key in segments {
+            let name = unescape_field_name(key);
+            // Only names that escape back to `key` are visible under it.
+            let Some((name, child)) = value
+                .as_object()
+                .and_then(|obj| obj.get_key_value(&name))
+                .filter(|(name, _)| escape_field_name(name) == key)
+            else {
+                return Some(Err(key));
+            };
+            compiled = compiled.and_then(|c| {
+                c.properties
+                    .get(name)
+                    .or(c.additional_properties.as_deref())
+            });
+            value = child;
+        }
+        Some(Ok((value, compiled)))
+    }
+
+    /// The key a selection identifier misses, if it is one of this view's.
+    fn missing_key<'p>(&self, ident: &'p str) -> Option<&'p str> {
+        let path = ident
+            .strip_prefix("has(")
+            .and_then(|path| path.strip_suffix(')'))
+            .map_or(ident, |path| path.rsplit_once('.').map_or(path, |(p, _)| p));
+        self.lookup(path)?.err()
+    }
+}
+
+impl VariableResolver for LazyView<'_> {
+    fn resolve(&self, ident: &str) -> Option<Value> {
+        if let Some(value) = self.converted.lock().ok()?.get(ident) {
+            return Some(value.clone());
+        }
+        let value = match ident
+            .strip_prefix("has(")
+            .and_then(|path| path.strip_suffix(')'))
+        {
+            // `has()` on a value that is not an object is false.
+            Some(path) => {
+                let (path, field) = path.rsplit_once('.')?;
+                let (value, _) = self.lookup(path)?.ok()?;
+                let present = value.as_object().is_some_and(|obj| {
+                    obj.get_key_value(&unescape_field_name(field))
+                        .is_some_and(|(name, _)| escape_field_name(name) == field)
+                });
+                Value::Bool(present)
+            }
+            None => match self.lookup(ident)?.ok()? {
+                (value, Some(compiled)) => json_to_cel_with_compiled(value, compiled),
+                (value, None) => json_to_cel(value),
+            },
+        };
+        self.converted
+            .lock()
+            .ok()?
+            .insert(ident.to_string(), value.clone());
+        Some(value)
+    }
+}
+
+/// Rewrite the selections of `expr` on `variables` into identifiers
+/// [`LazyView`] resolves, named after the selection so that no source
+/// expression can spell them: `self.spec.replicas` becomes the identifier
+/// `self.spec.replicas`, and `has(self.status)` the identifier
+/// `has(self.status)`. Comprehensions that shadow a variable leave it alone
+/// in their scope.
+fn bind_selections(expr: &mut Expression, variables: &[&str]) {
+    let selection = variables.iter().find_map(|variable| {
+        let path = selected_path(expr, variable).filter(|path| !path.is_empty())?;
+        Some(
+            std::iter::once(*variable)
+                .chain(path)
+                .collect::<Vec<_>>()
+                .join("."),
+        )
+    });
+    if let Some(ident) = selection {
+        expr.expr = Expr::Ident(ident);
+        return;
+    }
+    match &mut expr.expr {
+        Expr::Select(select) if select.test => {
+            let path = variables.iter().find_map(|variable| {
+                selected_path(&select.operand, variable).map(|path| (variable, path))
+            });
+            match path {
+                Some((variable, path)) => {
+                    let path: Vec<&str> = std::iter::once(*variable)
+                        .chain(path)
+                        .chain(std::iter::once(select.field.as_str()))
+                        .collect();
+                    expr.expr = Expr::Ident(format!("has({})", path.join(".")));
+                }
+                None => bind_selections(&mut select.operand, variables),
+            }
+        }
+        Expr::Select(select) => bind_selections(&mut select.operand, variables),
+        Expr::Ident(_) | Expr::Literal(_) | Expr::Unspecified => {}
+        Expr::Call(call) => call
+            .target
+            .iter_mut()
+            .map(|t| t.as_mut())
+            .chain(call.args.iter_mut())
+            .for_each(|e| bind_selections(e, variables)),
+        Expr::List(list) => list
+            .elements
+            .iter_mut()
+            .for_each(|e| bind_selections(e, variables)),
+        Expr::Map(map) => map
+            .entries
+            .iter_mut()
+            .for_each(|entry| match &mut entry.expr {
+                EntryExpr::StructField(field) => bind_selections(&mut field.value, variables),
+                EntryExpr::MapEntry(e) => {
+                    bind_selections(&mut e.key, variables);
+                    bind_selections(&mut e.value, variables);
+                }
+            }),
+        Expr::Struct(st) => st
+            .entries
+            .iter_mut()
+            .for_each(|entry| match &mut entry.expr {
+                EntryExpr::StructField(field) => bind_selections(&mut field.value, variables),
+                EntryExpr::MapEntry(e) => {
+                    bind_selections(&mut e.key, variables);
+                    bind_selections(&mut e.value, variables);
+                }
+            }),
+        Expr::Comprehension(comp) => {
+            bind_selections(&mut comp.iter_range, variables);
+            bind_selections(&mut comp.accu_init, variables);
+            let visible: Vec<&str> = variables
+                .iter()
+                .copied()
+                .filter(|&v| {
+                    v != comp.iter_var && comp.iter_var2.as_deref() != Some(v) && v != comp.accu_var
+                })
+                .collect();
+            for e in [&mut comp.loop_cond, &mut comp.loop_step, &mut comp.result] {
+                bind_selections(e, &visible);
+            }
+        }
+    }
+}
+
+/// Convert a CEL value back to JSON, the reverse of [`json_to_cel`], e.g. to
+/// serialize a `messageExpression` result or a computed variable.
+///
+/// Map keys are unescaped with [`unescape_field_name`], timestamps become
+/// RFC 3339 strings and durations Go duration strings. Values without a
+/// plain JSON form follow protobuf's JSON mapping: bytes become base64
+/// strings, and non-finite doubles `"NaN"`, `"Infinity"` or `"-Infinity"`.
+/// Optional values become their value or `null`; functions and other opaque
+/// values become `null`. Use [`try_cel_to_json`] to reject these instead.
+///
+/// ```
+/// use std::sync::Arc;
+/// use cel::Value;
+/// use kube_cel::values::cel_to_json;
+/// use serde_json::json;
+///
+/// let value = Value::List(Arc::new(vec![
+///     Value::Bytes(Arc::new(b"hi".to_vec())),
+///     Value::Float(f64::NAN),
+/// ]));
+/// assert_eq!(cel_to_json(&value), json!(["aGk=", "NaN"]));
+/// ```
+#[must_use]
+pub fn cel_to_json(value: &Value) -> serde_json::Value {
+    // Only strict conversion fails.
+    convert_to_json(value, false).unwrap_or_default()
+}
+
+/// Convert a CEL value back to JSON like [`cel_to_json`], but fail on bytes,
+/// non-finite doubles and opaque values instead of encoding them.
+pub fn try_cel_to_json(value: &Value) -> Result<serde_json::Value, String> {
+    convert_to_json(value, true)
+}
+
+fn convert_to_json(value: &Value, strict: bool) -> Result<serde_json::Value, String> {
+    let unrepresentable =
+        |value: &Value| format!("{} cannot be represented in JSON", value.type_of());
+    Ok(match value {
+        Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Int(i) => serde_json::Value::from(*i),
+        Value::UInt(u) => serde_json::Value::from(*u),
+        Value::Float(f) => match serde_json::Number::from_f64(*f) {
+            Some(n) => serde_json::Value::Number(n),
+            None if strict => return Err(format!("{f} cannot be represented in JSON")),
+            None if f.is_nan() => serde_json::Value::from("NaN"),
+            None if *f > 0.0 => serde_json::Value::from("Infinity"),
+            None => serde_json::Value::from("-Infinity"),
+        },
+        Value::String(s) => serde_json::Value::String((**s).clone()),
+        Value::Bytes(bytes) if !strict => {
+            use base64::Engine;
+            serde_json::Value::String(base64::engine::general_purpose::STANDARD.encode(&**bytes))
+        }
+        Value::Timestamp(ts) => {
+            serde_json::Value::String(ts.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true))
+        }
+        Value::Duration(d) => serde_json::Value::String(format_go_duration(*d)),
+        Value::List(items) => serde_json::Value::Array(
+            items
+                .iter()
+                .map(|item| convert_to_json(item, strict))
+                .collect::<Result<_, _>>()?,
+        ),
+        Value::Map(map) => {
+            let mut object = serde_json::Map::with_capacity(map.map.len());
+            for (key, value) in map.map.iter() {
+                let key = match key {
+                    Key::String(s) => unescape_field_name(s),
+                    Key::Int(i) => i.to_string(),
+                    Key::Uint(u) => u.to_string(),
+                    Key::Bool(b) => b.to_string(),
+                };
+                object.insert(key, convert_to_json(value, strict)?);
+            }
+            serde_json::Value::Object(object)
+        }
+        Value::Opaque(opaque) if !strict => match opaque.downcast_ref::<OptionalValue>() {
+            Some(optional) => match optional.value() {
+                Some(value) => convert_to_json(value, strict)?,
+                None => serde_json::Value::Null,
+            },
+            None => serde_json::Value::Null,
+        },
+        Value::Function(..) | Value::Opaque(_) | Value::Bytes(_) if strict => {
+            return Err(unrepresentable(value));
+        }
+        Value::Function(..) | Value::Opaque(_) | Value::Bytes(_) => serde_json::Value::Null,
+    })
+}
+
+/// Format a duration like Go's `time.Duration.String`, e.g. `1h30m0s` or
+/// `1.5ms`, except that microseconds use the `us` unit.
+pub(crate) fn format_go_duration(d: chrono::Duration) -> String {
+    let nanos = i128::from(d.num_seconds()) * 1_000_000_000 + i128::from(d.subsec_nanos());
+    let sign = if nanos < 0 { "-" } else { "" };
+    let nanos = nanos.unsigned_abs();
+    if nanos == 0 {
+        return "0s".to_string();
+    }
+    let decimal = |value: u128, digits: u32| {
+        let unit = 10u128.pow(digits);
+        let fraction = value % unit;
+        if fraction == 0 {
+            return (value / unit).to_string();
+        }
+        let fraction = format!("{fraction:0width$}", width = digits as usize);
+        format!("{}.{}", value / unit, fraction.trim_end_matches('0'))
+    };
+    if nanos < 1_000 {
+        return format!("{sign}{nanos}ns");
+    }
+    if nanos < 1_000_000 {
+        return format!("{sign}{}us", decimal(nanos, 3));
+    }
+    if nanos < 1_000_000_000 {
+        return format!("{sign}{}ms", decimal(nanos, 6));
+    }
+    let hours = nanos / 3_600_000_000_000;
+    let minutes = nanos / 60_000_000_000 % 60;
+    let seconds = decimal(nanos % 60_000_000_000, 9);
+    match (hours, minutes) {
+        (0, 0) => format!("{sign}{seconds}s"),
+        (0, _) => format!("{sign}{minutes}m{seconds}s"),
+        _ => format!("{sign}{hours}h{minutes}m{seconds}s"),
+    }
+}
+
+/// Fields Kubernetes always serializes as RFC 3339 timestamps, whatever a
+/// schema says about them, by path from the object root. `[]` stands for
+/// every item of a list.
+pub(crate) const WELL_KNOWN_TIMESTAMPS: &[&[&str]] = &[
+    &["metadata", "creationTimestamp"],
+    &["metadata", "deletionTimestamp"],
+    &["metadata", "managedFields", "[]", "time"],
+    &["status", "conditions", "[]", "lastTransitionTime"],
+];
+
+/// Parse the [`WELL_KNOWN_TIMESTAMPS`] strings inside `value`, the value at
+/// path `at` from the object root, into timestamps. Strings that do not
+/// parse are left as they are.
+pub(crate) fn parse_well_known_timestamps(value: &mut Value, at: &[&str]) {
+    for path in WELL_KNOWN_TIMESTAMPS {
+        if let Some(rest) = path.strip_prefix(at) {
+            parse_timestamps(value, rest);
+        }
+    }
+}
+
+fn parse_timestamps(value: &mut Value, path: &[&str]) {
+    match (path.split_first(), value) {
+        (None, value) => {
+            if let Value::String(s) = value
+                && let Ok(ts) = chrono::DateTime::parse_from_rfc3339(s)
+            {
+                *value = Value::Timestamp(ts);
+            }
+        }
+        (Some((&"[]", rest)), Value::List(items)) => {
+            for item in Arc::make_mut(items) {
+                parse_timestamps(item, rest);
+            }
+        }
+        (Some((field, rest)), Value::Map(map)) => {
+            let key = Key::String(Arc::new((*field).to_string()));
+            if let Some(child) = Arc::make_mut(&mut map.map).get_mut(&key) {
+                parse_timestamps(child, rest);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Decode a `format: byte` string, which Kubernetes encodes with padded
+/// standard base64.
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.decode(s).ok()
+}
+
+/// Why `value` does not parse as its schema `format`, if it is a string
+/// that [`json_to_cel_with_schema`] would otherwise fall back to binding as a
+/// plain CEL `string`. Custom formats are checked with their handler in
+/// `formats`.
+pub(crate) fn format_error(
+    value: &serde_json::Value,
+    format: &SchemaFormat,
+    formats: &CustomFormats,
+) -> Option<String> {
+    let s = value.as_str()?;
+    let expected = match format {
+        SchemaFormat::Custom(name) => {
+            let reason = formats.convert(name, s)?.err()?;
+            return Some(format!("invalid value {s:?}: {reason}"));
+        }
+        SchemaFormat::DateTime if chrono::DateTime::parse_from_rfc3339(s).is_err() => {
+            "an RFC 3339 date-time"
+        }
+        SchemaFormat::Duration if parse_go_duration(s).is_none() => "a duration",
+        SchemaFormat::Byte if decode_base64(s).is_none() => "base64-encoded bytes",
+        _ => return None,
+    };
+    Some(format!("invalid value {s:?}: must be {expected}"))
+}
+
+/// A CEL type value, as returned by `type(x)`.
+///
+/// Type values compare equal by name, so `type(self) == int` and
+/// `type(self) == type(oldSelf)` work in rules. This is mainly useful for
+/// `x-kubernetes-int-or-string` fields, which bind as either `int` or `string`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CelType(String);
+
+impl CelType {
+    /// The CEL name of this type (e.g. `"int"`, `"string"`).
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.0
     }
 }
 
+impl Opaque for CelType {
+    fn runtime_type_name(&self) -> &str {
+        "type"
+    }
+}
+
+/// CEL names of the types that can be bound from JSON and schema formats.
+const TYPE_NAMES: &[&str] = &[
+    "int",
+    "uint",
+    "double",
+    "bool",
+    "string",
+    "bytes",
+    "list",
+    "map",
+    "null_type",
+    "type",
+];
+
+/// The types of the `google.protobuf` package, bound as the nested map
+/// `google` → `protobuf` → type because CEL parses their names as field
+/// selections.
+const PROTOBUF_TYPE_NAMES: &[&str] = &["Timestamp", "Duration"];
+
+/// Register the `type()` function and the type identifiers (`int`, `string`,
+/// `google.protobuf.Timestamp`, ...).
+pub(crate) fn register_types(ctx: &mut Context<'_>) {
+    ctx.add_function("type", type_of);
+    let cel_type = |name: String| Value::Opaque(Arc::new(CelType(name)));
+    for name in TYPE_NAMES {
+        ctx.add_variable_from_value(*name, cel_type(name.to_string()));
+    }
+    let protobuf: HashMap<Key, Value> = PROTOBUF_TYPE_NAMES
+        .iter()
+        .map(|name| {
+            let value = cel_type(format!("google.protobuf.{name}"));
+            (Key::from(*name), value)
+        })
+        .collect();
+    let google = HashMap::from([(Key::from("protobuf"), Value::Map(Map::from(protobuf)))]);
+    ctx.add_variable_from_value("google", Value::Map(Map::from(google)));
+}
+
+/// The CEL type of a value.
+fn type_of(This(value): This<Value>) -> ResolveResult {
+    let name = match &value {
+        Value::List(_) => "list",
+        Value::Map(_) => "map",
+        Value::Function(..) => "function",
+        Value::Int(_) => "int",
+        Value::UInt(_) => "uint",
+        Value::Float(_) => "double",
+        Value::String(_) => "string",
+        Value::Bytes(_) => "bytes",
+        Value::Bool(_) => "bool",
+        Value::Duration(_) => "google.protobuf.Duration",
+        Value::Timestamp(_) => "google.protobuf.Timestamp",
+        Value::Opaque(o) => o.runtime_type_name(),
+        Value::Null => "null_type",
+    };
+    Ok(Value::Opaque(Arc::new(CelType(name.to_string()))))
+}
+
 /// Parse a Go-style duration string into a [`chrono::Duration`].
 ///
 /// Supported units: `h` (hours), `m` (minutes), `s` (seconds), `ms` (milliseconds),
@@ -283,6 +1346,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::approx_constant)]
     fn test_float() {
         assert_eq!(json_to_cel(&json!(3.14)), Value::Float(3.14));
         assert_eq!(json_to_cel(&json!(0.0)), Value::Float(0.0));
@@ -527,6 +1591,158 @@ mod tests {
         assert_eq!(result, Value::String(Arc::new("not-a-duration".into())));
     }
 
+    #[test]
+    fn repeated_keys_share_one_allocation() {
+        let value = json!([{"name": "a", "x-y": 1}, {"name": "b", "x-y": 2}]);
+        let key_of = |item: &Value, name: &str| match item {
+            Value::Map(map) => map
+                .map
+                .keys()
+                .find_map(|k| match k {
+                    Key::String(s) if s.as_str() == name => Some(Arc::clone(s)),
+                    _ => None,
+                })
+                .unwrap(),
+            _ => panic!("expected a map"),
+        };
+        let schema = json!({"type": "array", "items": {"type": "object"}});
+        let compiled = crate::compilation::compile_schema(&schema);
+        for converted in [
+            json_to_cel(&value),
+            json_to_cel_with_schema(&value, &schema),
+            json_to_cel_with_compiled(&value, &compiled),
+        ] {
+            let Value::List(items) = converted else {
+                panic!("expected a list");
+            };
+            for name in ["name", "x__dash__y"] {
+                assert!(Arc::ptr_eq(
+                    &key_of(&items[0], name),
+                    &key_of(&items[1], name)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn well_known_timestamps_are_parsed_below_their_node() {
+        let object = json!({
+            "metadata": {"creationTimestamp": "2024-01-02T03:04:05Z", "name": "x"},
+            "status": {"conditions": [
+                {"lastTransitionTime": "2024-01-02T03:04:05Z"},
+                {"lastTransitionTime": "yesterday"}
+            ]}
+        });
+        let ts =
+            Value::Timestamp(chrono::DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z").unwrap());
+
+        let mut root = json_to_cel(&object);
+        parse_well_known_timestamps(&mut root, &[]);
+        let mut expected = json_to_cel(&json!({"name": "x"}));
+        if let Value::Map(map) = &mut expected {
+            Arc::make_mut(&mut map.map).insert(
+                Key::String(Arc::new("creationTimestamp".into())),
+                ts.clone(),
+            );
+        }
+        let Value::Map(map) = &root else {
+            panic!("expected a map")
+        };
+        assert_eq!(
+            map.map.get(&Key::String(Arc::new("metadata".into()))),
+            Some(&expected)
+        );
+
+        let mut condition = json_to_cel(&object["status"]["conditions"][1]);
+        parse_well_known_timestamps(&mut condition, &["status", "conditions", "[]"]);
+        assert_eq!(
+            condition,
+            json_to_cel(&json!({"lastTransitionTime": "yesterday"}))
+        );
+        let mut condition = json_to_cel(&object["status"]["conditions"][0]);
+        parse_well_known_timestamps(&mut condition, &["status", "conditions", "[]"]);
+        let Value::Map(map) = &condition else {
+            panic!("expected a map")
+        };
+        assert_eq!(map.map.values().next(), Some(&ts));
+
+        // Elsewhere, the same names are left alone.
+        let mut spec = json_to_cel(&json!({"creationTimestamp": "2024-01-02T03:04:05Z"}));
+        let before = spec.clone();
+        parse_well_known_timestamps(&mut spec, &["spec"]);
+        assert_eq!(spec, before);
+    }
+
+    #[test]
+    fn custom_formats_convert_strings() {
+        let formats = CustomFormats::new().with_format("upper", |s| {
+            if s.chars().all(|c| c.is_ascii_uppercase()) {
+                Ok(Value::String(Arc::new(s.to_lowercase())))
+            } else {
+                Err("must be upper case".to_string())
+            }
+        });
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "a": {"type": "string", "format": "upper"},
+                "b": {"type": "string", "format": "unknown"}
+            }
+        });
+        let compiled = crate::compilation::compile_schema(&schema);
+        assert_eq!(
+            compiled.properties["a"].format,
+            SchemaFormat::Custom("upper".into())
+        );
+        let value = json!({"a": "ABC", "b": "ABC"});
+        assert_eq!(
+            json_to_cel_with_formats(&value, &compiled, &formats),
+            json_to_cel(&json!({"a": "abc", "b": "ABC"}))
+        );
+        assert_eq!(
+            json_to_cel_with_schema_formats(&value, &schema, &formats),
+            json_to_cel(&json!({"a": "abc", "b": "ABC"}))
+        );
+        // Without a handler, or when it rejects the string, it stays as is.
+        assert_eq!(
+            json_to_cel_with_compiled(&value, &compiled),
+            json_to_cel(&value)
+        );
+        let rejected = json!({"a": "abc"});
+        assert_eq!(
+            json_to_cel_with_formats(&rejected, &compiled, &formats),
+            json_to_cel(&rejected)
+        );
+
+        let upper = SchemaFormat::Custom("upper".into());
+        assert_eq!(format_error(&json!("ABC"), &upper, &formats), None);
+        assert_eq!(
+            format_error(&json!("abc"), &upper, &formats).as_deref(),
+            Some("invalid value \"abc\": must be upper case")
+        );
+        assert_eq!(format_error(&json!("abc"), &upper, &NO_FORMATS), None);
+        assert_eq!(format!("{formats:?}"), r#"{"upper"}"#);
+    }
+
+    #[test]
+    fn byte_decoded_from_schema() {
+        let schema = json!({"type": "string", "format": "byte"});
+        assert_eq!(
+            json_to_cel_with_schema(&json!("aGVsbG8="), &schema),
+            Value::Bytes(Arc::new(b"hello".to_vec()))
+        );
+        assert_eq!(
+            json_to_cel_with_schema(&json!("not base64!"), &schema),
+            Value::String(Arc::new("not base64!".into()))
+        );
+        let compiled = crate::compilation::compile_schema(&schema);
+        assert_eq!(compiled.format, SchemaFormat::Byte);
+        assert_eq!(
+            json_to_cel_with_compiled(&json!(""), &compiled),
+            Value::Bytes(Arc::new(Vec::new()))
+        );
+    }
+
     #[test]
     fn nested_object_properties_format() {
         let schema = json!({
@@ -611,4 +1827,324 @@ mod tests {
             Value::String(Arc::new("2024-01-01T00:00:00Z".into()))
         );
     }
+
+    // ── IntOrString tests ───────────────────────────────────────────
+
+    #[test]
+    fn int_or_string_binds_int_or_string() {
+        let schema = json!({"x-kubernetes-int-or-string": true});
+        assert_eq!(json_to_cel_with_schema(&json!(80), &schema), Value::Int(80));
+        assert_eq!(
+            json_to_cel_with_schema(&json!(80.0), &schema),
+            Value::Int(80)
+        );
+        assert_eq!(
+            json_to_cel_with_schema(&json!("50%"), &schema),
+            Value::String(Arc::new("50%".into()))
+        );
+    }
+
+    #[test]
+    fn int_or_string_ignores_format() {
+        let schema = json!({"x-kubernetes-int-or-string": true, "format": "duration"});
+        assert_eq!(
+            json_to_cel_with_schema(&json!("1h"), &schema),
+            Value::String(Arc::new("1h".into()))
+        );
+    }
+
+    #[test]
+    fn type_function() {
+        let mut ctx = Context::default();
+        register_types(&mut ctx);
+        let eval = |expr: &str| cel::Program::compile(expr).unwrap().execute(&ctx).unwrap();
+        assert_eq!(eval("type(1) == int"), Value::Bool(true));
+        assert_eq!(eval("type('a') == string"), Value::Bool(true));
+        assert_eq!(eval("type('a') == int"), Value::Bool(false));
+        assert_eq!(eval("type([1]) == type([])"), Value::Bool(true));
+        assert_eq!(eval("type(int) == type"), Value::Bool(true));
+        assert_eq!(
+            eval("type(timestamp('2024-01-01T00:00:00Z')) == google.protobuf.Timestamp"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("type(duration('1h')) == google.protobuf.Duration"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("type(1) == google.protobuf.Duration"),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn format_errors() {
+        let date_time = SchemaFormat::DateTime;
+        let duration = SchemaFormat::Duration;
+        assert_eq!(
+            format_error(&json!("2024-01-01T00:00:00Z"), &date_time, &NO_FORMATS),
+            None
+        );
+        assert_eq!(
+            format_error(&json!("yesterday"), &date_time, &NO_FORMATS).as_deref(),
+            Some("invalid value \"yesterday\": must be an RFC 3339 date-time")
+        );
+        assert_eq!(format_error(&json!("1h30m"), &duration, &NO_FORMATS), None);
+        assert!(format_error(&json!("90 minutes"), &duration, &NO_FORMATS).is_some());
+        assert_eq!(format_error(&json!(5), &duration, &NO_FORMATS), None);
+        assert_eq!(
+            format_error(&json!("anything"), &SchemaFormat::None, &NO_FORMATS),
+            None
+        );
+        assert_eq!(
+            format_error(&json!("aGk="), &SchemaFormat::Byte, &NO_FORMATS),
+            None
+        );
+        assert!(format_error(&json!("not base64!"), &SchemaFormat::Byte, &NO_FORMATS).is_some());
+    }
+
+    #[test]
+    fn cel_to_json_reverses_json_to_cel() {
+        let value = json!({
+            "metadata": {"namespace": "prod", "labels": {"app.kubernetes.io/name": "web"}},
+            "spec": {"replicas": 3, "ratio": 0.5, "ports": [80, 443], "paused": false, "x": null}
+        });
+        assert_eq!(cel_to_json(&json_to_cel(&value)), value);
+        assert_eq!(try_cel_to_json(&json_to_cel(&value)).unwrap(), value);
+
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "at": {"type": "string", "format": "date-time"},
+                "every": {"type": "string", "format": "duration"}
+            }
+        });
+        let value = json!({"at": "2024-01-02T03:04:05Z", "every": "1h30m0s"});
+        assert_eq!(
+            cel_to_json(&json_to_cel_with_schema(&value, &schema)),
+            value
+        );
+        assert!(try_cel_to_json(&Value::Bytes(Arc::new(vec![1]))).is_err());
+        assert!(try_cel_to_json(&Value::Float(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn cel_to_json_encodes_values_without_a_json_form() {
+        assert_eq!(
+            cel_to_json(&Value::Bytes(Arc::new(b"hello".to_vec()))),
+            json!("aGVsbG8=")
+        );
+        assert_eq!(cel_to_json(&Value::Float(f64::NAN)), json!("NaN"));
+        assert_eq!(cel_to_json(&Value::Float(f64::INFINITY)), json!("Infinity"));
+        assert_eq!(
+            cel_to_json(&Value::Float(f64::NEG_INFINITY)),
+            json!("-Infinity")
+        );
+
+        let some = Value::Opaque(Arc::new(OptionalValue::of(Value::Int(1))));
+        let none = Value::Opaque(Arc::new(OptionalValue::none()));
+        assert_eq!(cel_to_json(&some), json!(1));
+        assert_eq!(cel_to_json(&none), json!(null));
+        assert!(try_cel_to_json(&some).is_err());
+
+        // Results of evaluated expressions convert back as well.
+        let program =
+            cel::Program::compile("{'at': timestamp('2024-01-02T03:04:05Z'), 'n': [1u, 2.5]}")
+                .unwrap();
+        let value = program.execute(&Context::default()).unwrap();
+        assert_eq!(
+            cel_to_json(&value),
+            json!({"at": "2024-01-02T03:04:05Z", "n": [1, 2.5]})
+        );
+    }
+
+    #[test]
+    fn cel_to_json_sorts_object_keys() {
+        let value = json_to_cel(&json!({"c": 1, "a": {"z": 2, "b": 3}, "b": 4}));
+        assert_eq!(
+            cel_to_json(&value).to_string(),
+            r#"{"a":{"b":3,"z":2},"b":4,"c":1}"#
+        );
+    }
+
+    #[test]
+    fn go_duration_formatting() {
+        let cases = [
+            (chrono::Duration::zero(), "0s"),
+            (chrono::Duration::nanoseconds(12), "12ns"),
+            (chrono::Duration::microseconds(1500), "1.5ms"),
+            (chrono::Duration::nanoseconds(2_500), "2.5us"),
+            (chrono::Duration::seconds(45), "45s"),
+            (chrono::Duration::milliseconds(90_250), "1m30.25s"),
+            (chrono::Duration::hours(2), "2h0m0s"),
+            (chrono::Duration::seconds(-5), "-5s"),
+        ];
+        for (duration, expected) in cases {
+            assert_eq!(format_go_duration(duration), expected);
+            assert_eq!(parse_go_duration(expected), Some(duration));
+        }
+    }
+
+    fn projection(rule: &str, variable: &str) -> Projection {
+        Projection::of(cel::Program::compile(rule).unwrap().expression(), variable)
+    }
+
+    #[test]
+    fn projection_follows_field_selections() {
+        let p = projection(
+            "self.spec.replicas > 0 && has(self.status.ready) && self.spec.tags.all(t, t != '')",
+            "self",
+        );
+        assert!(p.includes(&["spec", "replicas"]));
+        assert!(p.includes(&["spec", "tags"]));
+        assert!(p.includes(&["status", "ready"]));
+        assert!(!p.includes(&["spec", "template"]));
+        assert!(!p.includes(&["status", "conditions"]));
+        assert!(!p.includes(&["metadata"]));
+
+        assert_eq!(projection("size(self) > 0", "self"), Projection::All);
+        assert_eq!(projection("self.x == oldSelf", "oldSelf"), Projection::All);
+        assert_eq!(projection("self.x > 0", "oldSelf"), Projection::default());
+        assert_eq!(
+            projection("self.exists(self, self > 0)", "self"),
+            Projection::All
+        );
+    }
+
+    #[test]
+    fn projection_merges() {
+        let mut p = projection("self.a.b == 1", "self");
+        p.merge(projection("self.a.c == 1", "self"));
+        assert!(p.includes(&["a", "b"]) && p.includes(&["a", "c"]));
+        p.merge(projection("self.a == {}", "self"));
+        assert!(p.includes(&["a", "d"]));
+        assert!(!p.includes(&["e"]));
+    }
+
+    #[test]
+    fn projected_conversion_skips_unread_fields() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "at": {"type": "string", "format": "date-time"},
+                "big": {"type": "array", "items": {"type": "string"}},
+                "dash-ed": {"type": "integer"},
+                "list": {"type": "array", "items": {"type": "integer"}}
+            }
+        });
+        let compiled = crate::compilation::compile_schema(&schema);
+        let value = json!({
+            "at": "2024-01-02T03:04:05Z",
+            "big": ["a", "b"],
+            "dash-ed": 1,
+            "list": [1, 2]
+        });
+        let p = projection(
+            "self.at < now() && self.dash__dash__ed == 1 && self.list.x",
+            "self",
+        );
+        let Value::Map(map) = json_to_cel_projected(&value, &compiled, &p) else {
+            panic!("expected a map");
+        };
+        let get = |k: &str| map.map.get(&Key::String(Arc::new(k.to_string()))).cloned();
+        assert!(matches!(get("at"), Some(Value::Timestamp(_))));
+        assert_eq!(get("dash__dash__ed"), Some(Value::Int(1)));
+        // Not an object: converted whole, so `self.list.x` fails as before.
+        assert_eq!(get("list"), Some(json_to_cel(&json!([1, 2]))));
+        assert_eq!(get("big"), None);
+
+        assert_eq!(
+            json_to_cel_projected(&value, &compiled, &Projection::All),
+            json_to_cel_with_compiled(&value, &compiled)
+        );
+    }
+
+    #[test]
+    fn projected_conversion_keeps_names_exact() {
+        // `a_b` is bound as `a__b`, so `self.a_b` must not find it.
+        let value = json!({"a_b": 1});
+        let p = projection("self.a_b == 1", "self");
+        let compiled = crate::compilation::compile_schema(&json!({"type": "object"}));
+        assert_eq!(
+            json_to_cel_projected(&value, &compiled, &p),
+            json_to_cel_with_compiled(&json!({}), &compiled)
+        );
+    }
+
+    #[test]
+    fn lazy_view_evaluates_like_full_conversion() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "at": {"type": "string", "format": "date-time"},
+                        "port": {"x-kubernetes-int-or-string": true}
+                    }
+                }
+            }
+        });
+        let compiled = crate::compilation::compile_schema(&schema);
+        let value = json!({
+            "metadata": {"name": "web"},
+            "spec": {"replicas": 3, "items": [1, 2, 3], "at": "2024-01-02T03:04:05Z", "port": 80, "a-b": true, "a_b": 1}
+        });
+        let old = json!({"metadata": {"name": "web"}, "spec": {"replicas": 1}});
+        let view = LazyView::new()
+            .bind_with_compiled("self", &value, &compiled)
+            .bind_with_compiled("oldSelf", &old, &compiled);
+        let mut eager = Context::default();
+        eager.add_variable_from_value("self", json_to_cel_with_compiled(&value, &compiled));
+        eager.add_variable_from_value("oldSelf", json_to_cel_with_compiled(&old, &compiled));
+        for rule in [
+            "self.spec.replicas > oldSelf.spec.replicas",
+            "self.spec.at > timestamp('2020-01-01T00:00:00Z')",
+            "self.spec.port == 80",
+            "self.spec.a__dash__b && !has(self.spec.a_b)",
+            "has(self.status) || has(self.spec.replicas)",
+            "has(self.status.phase)",
+            "self.spec.missing",
+            "self.metadata.name.missing",
+            "self.spec.items.all(x, x <= self.spec.replicas)",
+            "self.spec.items.exists(self, self == 3)",
+            "size(self) == 2 && self.metadata == oldSelf.metadata",
+        ] {
+            let program = cel::Program::compile(rule).unwrap();
+            let lazy = view.evaluate(&view.prepare(program.expression()), &Context::default());
+            let eager = Value::resolve(program.expression(), &eager);
+            assert_eq!(format!("{lazy:?}"), format!("{eager:?}"), "{rule}");
+        }
+    }
+
+    #[test]
+    fn lazy_view_converts_only_selected_fields() {
+        let value = json!({"spec": {"replicas": 3, "template": {"large": [1, 2, 3]}}});
+        let view = LazyView::new().bind("self", &value);
+        let program = cel::Program::compile("self.spec.replicas > 1 && !has(self.status)").unwrap();
+        let result = view.evaluate(&view.prepare(program.expression()), &Context::default());
+        assert_eq!(result, Ok(Value::Bool(true)));
+        let converted = view.converted.lock().unwrap();
+        let mut idents: Vec<&str> = converted.keys().map(String::as_str).collect();
+        idents.sort_unstable();
+        assert_eq!(idents, ["has(self.status)", "self.spec.replicas"]);
+    }
+
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml_converts_like_json() {
+        let yaml = "at: 2024-01-02T03:04:05Z\nports: [80, 443]\nname: web";
+        let json = json!({"at": "2024-01-02T03:04:05Z", "ports": [80, 443], "name": "web"});
+        assert_eq!(yaml_to_cel(yaml).unwrap(), json_to_cel(&json));
+
+        let schema = json!({
+            "type": "object",
+            "properties": {"at": {"type": "string", "format": "date-time"}}
+        });
+        assert_eq!(
+            yaml_to_cel_with_schema(yaml, &schema).unwrap(),
+            json_to_cel_with_schema(&json, &schema)
+        );
+        assert!(yaml_to_cel("a: [").is_err());
+    }
 }
@@ -0,0 +1,357 @@
+//! A validating admission webhook built on axum.
+//!
+//! [`AdmissionWebhook`] answers `admission.k8s.io/v1` `AdmissionReview`
+//! requests by validating the object against the registered CRD schemas and
+//! `ValidatingAdmissionPolicy`s. [`AdmissionWebhook::router`] serves it at
+//! `POST /validate`:
+//!
+//! ```rust,ignore
+//! use kube_cel::webhook::AdmissionWebhook;
+//!
+//! let webhook = AdmissionWebhook::new().with_crd(&crd)?.with_policy(policy);
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:8443").await?;
+//! axum::serve(listener, webhook.router()).await?;
+//! ```
+//!
+//! TLS, which the apiserver requires of webhooks, is left to the server or
+//! a proxy in front of it.
+
+use std::sync::Arc;
+
+use axum::Json;
+use axum::Router;
+use axum::extract::State;
+use axum::routing::post;
+use serde_json::json;
+
+use crate::crd::{CrdError, CrdValidator};
+use crate::policy::{CompiledPolicy, PolicyRequest};
+use crate::status::to_status;
+use crate::validation::Validator;
+
+/// The path [`AdmissionWebhook::router`] serves reviews at.
+pub const VALIDATE_PATH: &str = "/validate";
+
+/// The `AdmissionReview` API version answered when a review names none.
+const ADMISSION_API_VERSION: &str = "admission.k8s.io/v1";
+
+/// Validates the objects of `AdmissionReview` requests.
+///
+/// An object is checked against the schema of every registered CRD whose
+/// group and kind match the request's `kind`, and against every registered
+/// policy.
+/// The request is denied with the first failure's status.
+#[derive(Clone, Debug, Default)]
+pub struct AdmissionWebhook {
+    validator: Validator,
+    crds: Vec<CrdValidator>,
+    policies: Vec<Arc<CompiledPolicy>>,
+}
+
+impl AdmissionWebhook {
+    /// Create a webhook with a default [`Validator`] and nothing registered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a custom [`Validator`] for CRDs registered afterwards and for
+    /// policies.
+    #[must_use]
+    pub fn with_validator(mut self, validator: Validator) -> Self {
+        self.validator = validator;
+        self
+    }
+
+    /// Validate objects of a `CustomResourceDefinition` against its schemas.
+    pub fn with_crd(mut self, crd: &serde_json::Value) -> Result<Self, CrdError> {
+        self.crds.push(self.validator.for_crd(crd)?);
+        Ok(self)
+    }
+
+    /// Evaluate a compiled `ValidatingAdmissionPolicy` for every request.
+    #[must_use]
+    pub fn with_policy(mut self, policy: CompiledPolicy) -> Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+
+    /// Answer an `AdmissionReview` request with an `AdmissionReview`
+    /// response.
+    #[must_use]
+    pub fn review(&self, review: &serde_json::Value) -> serde_json::Value {
+        let api_version = review
+            .get("apiVersion")
+            .and_then(|v| v.as_str())
+            .unwrap_or(ADMISSION_API_VERSION);
+        let response = match review.get("request") {
+            Some(request) => self.admit(request),
+            None => json!({
+                "uid": "",
+                "allowed": false,
+                "status": failure("AdmissionReview has no request", "BadRequest", 400),
+            }),
+        };
+        json!({
+            "apiVersion": api_version,
+            "kind": "AdmissionReview",
+            "response": response,
+        })
+    }
+
+    /// A router serving [`review`](Self::review) at [`VALIDATE_PATH`].
+    ///
+    /// Reviews run on tokio's blocking pool, as CEL evaluation is CPU-bound.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route(VALIDATE_PATH, post(handle))
+            .with_state(Arc::new(self))
+    }
+
+    /// The `AdmissionResponse` for an `AdmissionRequest`.
+    fn admit(&self, request: &serde_json::Value) -> serde_json::Value {
+        let uid = request.get("uid").cloned().unwrap_or_else(|| json!(""));
+        let object = request.get("object").filter(|o| !o.is_null());
+        let old_object = request.get("oldObject").filter(|o| !o.is_null());
+        let group = request
+            .pointer("/kind/group")
+            .and_then(|g| g.as_str())
+            .unwrap_or_default();
+        let kind = request
+            .pointer("/kind/kind")
+            .and_then(|k| k.as_str())
+            .unwrap_or_default();
+        let name = request
+            .get("name")
+            .and_then(|n| n.as_str())
+            .unwrap_or_default();
+
+        let deny =
+            |status: serde_json::Value| json!({"uid": uid, "allowed": false, "status": status});
+
+        if let Some(object) = object {
+            for crd in self
+                .crds
+                .iter()
+                .filter(|crd| crd.group() == group && crd.kind() == kind)
+            {
+                match crd.validate(object, old_object) {
+                    Ok(errors) if errors.is_empty() => {}
+                    Ok(errors) => return deny(to_status(&errors, kind, name)),
+                    Err(e) => return deny(failure(&e.to_string(), "BadRequest", 400)),
+                }
+            }
+        }
+
+        let policy_request = PolicyRequest {
+            object,
+            old_object,
+            request: Some(request),
+            ..Default::default()
+        };
+        let mut audit_annotations = serde_json::Map::new();
+        for policy in &self.policies {
+            let result = self.validator.validate_policy(policy, &policy_request);
+            if let Some(violation) = result.violations.first() {
+                let code = match violation.reason.as_str() {
+                    "Unauthorized" => 401,
+                    "Forbidden" => 403,
+                    "RequestEntityTooLarge" => 413,
+                    _ => 422,
+                };
+                return deny(failure(&violation.message, &violation.reason, code));
+            }
+            for (key, value) in result.audit_annotations {
+                audit_annotations.insert(key, value.into());
+            }
+        }
+
+        let mut response = json!({"uid": uid, "allowed": true});
+        if !audit_annotations.is_empty() {
+            response["auditAnnotations"] = audit_annotations.into();
+        }
+        response
+    }
+}
+
+/// A failed `metav1.Status`.
+fn failure(message: &str, reason: &str, code: u16) -> serde_json::Value {
+    json!({
+        "kind": "Status",
+        "apiVersion": "v1",
+        "metadata": {},
+        "status": "Failure",
+        "message": message,
+        "reason": reason,
+        "code": code,
+    })
+}
+
+async fn handle(
+    State(webhook): State<Arc<AdmissionWebhook>>,
+    Json(review): Json<serde_json::Value>,
+) -> Json<serde_json::Value> {
+    let response = tokio::task::spawn_blocking(move || webhook.review(&review))
+        .await
+        .unwrap_or_else(|e| {
+            json!({
+                "apiVersion": ADMISSION_API_VERSION,
+                "kind": "AdmissionReview",
+                "response": {
+                    "uid": "",
+                    "allowed": false,
+                    "status": failure(&format!("validation failed: {e}"), "InternalError", 500),
+                },
+            })
+        });
+    Json(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use axum::http::{Request, header};
+    use tower::ServiceExt;
+
+    fn crd() -> serde_json::Value {
+        json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "spec": {
+                "group": "example.com",
+                "names": {"kind": "Widget", "plural": "widgets"},
+                "scope": "Namespaced",
+                "versions": [{"name": "v1", "served": true, "storage": true, "schema": {"openAPIV3Schema": {
+                    "type": "object",
+                    "properties": {"spec": {"type": "object", "properties": {"replicas": {
+                        "type": "integer",
+                        "x-kubernetes-validations": [{"rule": "self >= 0", "message": "must be non-negative"}]
+                    }}}}
+                }}}]
+            }
+        })
+    }
+
+    fn webhook() -> AdmissionWebhook {
+        let policy = CompiledPolicy::from_manifest(&json!({
+            "validations": [{
+                "expression": "request.operation != 'CREATE' || object.spec.replicas <= 5",
+                "message": "at most 5 replicas on create",
+                "reason": "Forbidden"
+            }],
+            "auditAnnotations": [{"key": "replicas", "valueExpression": "string(object.spec.replicas)"}]
+        }))
+        .unwrap();
+        AdmissionWebhook::new()
+            .with_crd(&crd())
+            .unwrap()
+            .with_policy(policy)
+    }
+
+    fn review(replicas: i64) -> serde_json::Value {
+        json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": "705ab4f5",
+                "kind": {"group": "example.com", "version": "v1", "kind": "Widget"},
+                "name": "demo",
+                "operation": "CREATE",
+                "object": {"apiVersion": "example.com/v1", "kind": "Widget", "spec": {"replicas": replicas}}
+            }
+        })
+    }
+
+    #[test]
+    fn reviews_are_answered() {
+        let webhook = webhook();
+
+        let response = &webhook.review(&review(3))["response"];
+        assert_eq!(response["uid"], "705ab4f5");
+        assert_eq!(response["allowed"], true);
+        assert_eq!(response["auditAnnotations"], json!({"replicas": "3"}));
+
+        let response = &webhook.review(&review(-1))["response"];
+        assert_eq!(response["allowed"], false);
+        assert_eq!(response["status"]["code"], 422);
+        assert_eq!(
+            response["status"]["message"],
+            "Widget \"demo\" is invalid: spec.replicas: Invalid value: must be non-negative"
+        );
+
+        let response = &webhook.review(&review(10))["response"];
+        assert_eq!(response["allowed"], false);
+        assert_eq!(response["status"]["code"], 403);
+        assert_eq!(
+            response["status"]["message"],
+            "at most 5 replicas on create"
+        );
+
+        let response = &webhook.review(&json!({"kind": "AdmissionReview"}))["response"];
+        assert_eq!(response["allowed"], false);
+        assert_eq!(response["status"]["code"], 400);
+    }
+
+    #[test]
+    fn crds_are_matched_by_kind() {
+        let crd = |kind: &str, plural: &str, rule: &str| {
+            json!({
+                "apiVersion": "apiextensions.k8s.io/v1",
+                "kind": "CustomResourceDefinition",
+                "spec": {
+                    "group": "example.com",
+                    "names": {"kind": kind, "plural": plural},
+                    "versions": [{"name": "v1", "served": true, "schema": {"openAPIV3Schema": {
+                        "type": "object",
+                        "properties": {"spec": {
+                            "type": "object",
+                            "x-kubernetes-validations": [{"rule": rule, "message": format!("{kind} rule")}],
+                            "properties": {"replicas": {"type": "integer"}}
+                        }}
+                    }}}]
+                }
+            })
+        };
+        let webhook = AdmissionWebhook::new()
+            .with_crd(&crd("Widget", "widgets", "has(self.replicas)"))
+            .unwrap()
+            .with_crd(&crd("Gadget", "gadgets", "true"))
+            .unwrap();
+        assert_eq!(webhook.crds[1].kind(), "Gadget");
+        assert_eq!(webhook.crds[1].plural(), "gadgets");
+
+        let review = |kind: &str| {
+            json!({"request": {
+                "uid": "1",
+                "kind": {"group": "example.com", "version": "v1", "kind": kind},
+                "name": "g",
+                "operation": "CREATE",
+                "object": {"apiVersion": "example.com/v1", "kind": kind, "spec": {}}
+            }})
+        };
+        assert_eq!(
+            webhook.review(&review("Gadget"))["response"]["allowed"],
+            true
+        );
+        let response = &webhook.review(&review("Widget"))["response"];
+        assert_eq!(response["allowed"], false);
+        assert_eq!(
+            response["status"]["message"],
+            "Widget \"g\" is invalid: spec: Invalid value: Widget rule"
+        );
+    }
+
+    #[tokio::test]
+    async fn router_serves_reviews() {
+        let request = Request::post(VALIDATE_PATH)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(review(-1).to_string()))
+            .unwrap();
+        let response = webhook().router().oneshot(request).await.unwrap();
+        assert!(response.status().is_success());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let review: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(review["kind"], "AdmissionReview");
+        assert_eq!(review["response"]["allowed"], false);
+    }
+}
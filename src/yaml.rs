@@ -0,0 +1,225 @@
+//! Loading CRDs and objects from YAML (feature = `yaml`).
+//!
+//! CRDs and manifests usually live in YAML files. These helpers parse YAML
+//! into the [`serde_json::Value`]s the rest of the crate works with:
+//!
+//! ```
+//! use kube_cel::yaml::validate_yaml;
+//!
+//! let schema = r#"
+//! type: object
+//! x-kubernetes-validations:
+//!   - rule: self.replicas >= 0
+//!     message: must be non-negative
+//! properties:
+//!   replicas: {type: integer}
+//! "#;
+//! let schema = kube_cel::yaml::parse(schema).unwrap();
+//! let errors = validate_yaml(&schema, "replicas: -1", None).unwrap();
+//! assert_eq!(errors[0].message, "must be non-negative");
+//! ```
+
+use serde::Deserialize;
+
+use crate::compilation::{CompiledSchema, compile_schema};
+use crate::crd::{CompiledCrd, CrdError};
+use crate::validation::{ValidationError, Validator};
+
+/// Errors loading YAML input.
+#[derive(Debug)]
+pub enum YamlError {
+    /// The input is not valid YAML, or not representable as JSON.
+    Parse(serde_yaml::Error),
+    /// No document in the input is a `CustomResourceDefinition`.
+    NoCrd,
+    /// The CRD document is malformed.
+    Crd(CrdError),
+}
+
+impl std::fmt::Display for YamlError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YamlError::Parse(err) => write!(f, "invalid YAML: {err}"),
+            YamlError::NoCrd => write!(f, "no CustomResourceDefinition document found"),
+            YamlError::Crd(err) => write!(f, "invalid CRD: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for YamlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            YamlError::Parse(err) => Some(err),
+            YamlError::Crd(err) => Some(err),
+            YamlError::NoCrd => None,
+        }
+    }
+}
+
+impl From<serde_yaml::Error> for YamlError {
+    fn from(err: serde_yaml::Error) -> Self {
+        YamlError::Parse(err)
+    }
+}
+
+impl From<CrdError> for YamlError {
+    fn from(err: CrdError) -> Self {
+        YamlError::Crd(err)
+    }
+}
+
+/// Parse a single YAML document.
+pub fn parse(yaml: &str) -> Result<serde_json::Value, YamlError> {
+    Ok(serde_yaml::from_str(yaml)?)
+}
+
+/// Parse every document of a multi-document (`---`-separated) YAML stream.
+/// Empty documents are skipped.
+pub fn parse_documents(yaml: &str) -> Result<Vec<serde_json::Value>, YamlError> {
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(yaml) {
+        let value = serde_json::Value::deserialize(document)?;
+        if !value.is_null() {
+            documents.push(value);
+        }
+    }
+    Ok(documents)
+}
+
+/// Compile a schema written in YAML.
+pub fn compile_schema_from_yaml(yaml: &str) -> Result<CompiledSchema, YamlError> {
+    Ok(compile_schema(&parse(yaml)?))
+}
+
+/// Compile the first `CustomResourceDefinition` in a YAML stream.
+pub fn compile_crd_from_yaml(yaml: &str) -> Result<CompiledCrd, YamlError> {
+    let crd = parse_documents(yaml)?
+        .into_iter()
+        .find(|doc| doc.get("kind").and_then(|k| k.as_str()) == Some("CustomResourceDefinition"))
+        .ok_or(YamlError::NoCrd)?;
+    Ok(CompiledCrd::compile(&crd)?)
+}
+
+/// Validate an object (and optional old object) written in YAML against
+/// `schema`, with a default [`Validator`]. Use
+/// [`Validator::validate_yaml`] to configure the validator.
+pub fn validate_yaml(
+    schema: &serde_json::Value,
+    object_yaml: &str,
+    old_yaml: Option<&str>,
+) -> Result<Vec<ValidationError>, YamlError> {
+    Validator::new().validate_yaml(schema, object_yaml, old_yaml)
+}
+
+/// Validate an object (and optional old object) written in YAML against a
+/// compiled schema, with a default [`Validator`].
+pub fn validate_compiled_yaml(
+    compiled: &CompiledSchema,
+    object_yaml: &str,
+    old_yaml: Option<&str>,
+) -> Result<Vec<ValidationError>, YamlError> {
+    Validator::new().validate_compiled_yaml(compiled, object_yaml, old_yaml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRD: &str = r#"
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: widgets
+---
+apiVersion: apiextensions.k8s.io/v1
+kind: CustomResourceDefinition
+metadata:
+  name: widgets.example.com
+spec:
+  group: example.com
+  names: {kind: Widget, plural: widgets}
+  scope: Namespaced
+  versions:
+    - name: v1
+      served: true
+      storage: true
+      schema:
+        openAPIV3Schema:
+          type: object
+          properties:
+            spec:
+              type: object
+              x-kubernetes-validations:
+                - rule: self.replicas <= oldSelf.replicas + 1
+                  message: scale up one at a time
+              properties:
+                replicas: {type: integer}
+"#;
+
+    #[test]
+    fn compiles_crd_from_multi_document_yaml() {
+        assert_eq!(parse_documents(CRD).unwrap().len(), 2);
+        let crd = compile_crd_from_yaml(CRD).unwrap();
+        assert_eq!(crd.versions().collect::<Vec<_>>(), ["v1"]);
+
+        let object =
+            parse("apiVersion: example.com/v1\nkind: Widget\nspec: {replicas: 5}").unwrap();
+        let old = parse("apiVersion: example.com/v1\nkind: Widget\nspec: {replicas: 1}").unwrap();
+        let errors = Validator::new()
+            .validate_compiled_crd(&crd, &object, Some(&old))
+            .unwrap();
+        assert_eq!(errors[0].message, "scale up one at a time");
+    }
+
+    #[test]
+    fn validates_yaml_objects() {
+        let schema = parse(
+            "type: object\nx-kubernetes-validations: [{rule: self.a == oldSelf.a}]\nproperties: {a: {type: string}}",
+        )
+        .unwrap();
+        assert_eq!(
+            validate_yaml(&schema, "a: x", Some("a: y")).unwrap().len(),
+            1
+        );
+        assert!(validate_yaml(&schema, "a: x", None).unwrap().is_empty());
+        assert!(matches!(
+            validate_yaml(&schema, "a: [", None),
+            Err(YamlError::Parse(_))
+        ));
+        assert!(compile_schema_from_yaml("type: object").is_ok());
+    }
+
+    #[test]
+    fn validates_yaml_against_compiled_schemas() {
+        let compiled = compile_schema_from_yaml(
+            "type: object\nx-kubernetes-validations: [{rule: self.n < 3, message: too big}]\nproperties: {n: {type: integer}}",
+        )
+        .unwrap();
+        let errors = validate_compiled_yaml(&compiled, "n: 5", None).unwrap();
+        assert_eq!(errors[0].message, "too big");
+
+        let v = Validator::builder().with_max_errors(1).build();
+        assert!(
+            v.validate_compiled_yaml(&compiled, "n: 1", None)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            v.validate_compiled_yaml(&compiled, "n: 1", Some("n: [")),
+            Err(YamlError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn reports_missing_crd() {
+        assert!(matches!(
+            compile_crd_from_yaml("kind: ConfigMap"),
+            Err(YamlError::NoCrd)
+        ));
+        let err = compile_crd_from_yaml("kind: CustomResourceDefinition\nspec: {}").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid CRD: document is not a CustomResourceDefinition"
+        );
+    }
+}
@@ -6,7 +6,7 @@
 //! rule extraction, compilation, and evaluation.
 
 use cel::{Context, Value};
-use kube_cel::compilation::{CompilationError, compile_schema};
+use kube_cel::compilation::{CompilationError, Reason, compile_schema};
 use kube_cel::values::json_to_cel;
 use serde_json::json;
 
@@ -101,7 +101,7 @@ fn transition_rule_compile_and_eval() {
 
     assert!(compiled.is_transition_rule);
     assert_eq!(compiled.rule.message.as_deref(), Some("cannot scale down"));
-    assert_eq!(compiled.rule.reason.as_deref(), Some("FieldValueForbidden"));
+    assert_eq!(compiled.rule.reason, Some(Reason::FieldValueForbidden));
 
     // Evaluate with self and oldSelf
     let mut ctx = Context::default();
@@ -145,7 +145,7 @@ fn message_and_reason_preserved() {
         compiled.rule.message_expression.as_deref(),
         Some("\"x is \" + string(self.x)")
     );
-    assert_eq!(compiled.rule.reason.as_deref(), Some("FieldValueInvalid"));
+    assert_eq!(compiled.rule.reason, Some(Reason::FieldValueInvalid));
     assert_eq!(compiled.rule.field_path.as_deref(), Some(".spec.x"));
 }
 
@@ -5,6 +5,7 @@
 //! End-to-end tests with realistic CRD schemas, matching the plan's
 //! usage example and covering nested schemas, transition rules, and arrays.
 
+use kube_cel::compilation::Reason;
 use kube_cel::validation::{Validator, validate};
 use serde_json::json;
 
@@ -124,7 +125,7 @@ fn transition_rule_end_to_end() {
     let errors2 = validate(&schema, &obj2, Some(&old));
     assert_eq!(errors2.len(), 1);
     assert_eq!(errors2[0].message, "cannot scale down");
-    assert_eq!(errors2[0].reason.as_deref(), Some("FieldValueForbidden"));
+    assert_eq!(errors2[0].reason, Some(Reason::FieldValueForbidden));
     assert_eq!(errors2[0].field_path, "spec");
 
     // Create (no old): transition rule skipped
@@ -516,6 +517,46 @@ fn array_length_mismatch_with_old_self() {
     assert_eq!(errors[0].field_path, "items[1]");
 }
 
+#[test]
+fn list_map_items_correlated_by_key() {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "ports": {
+                "type": "array",
+                "x-kubernetes-list-type": "map",
+                "x-kubernetes-list-map-keys": ["name"],
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string"},
+                        "port": {"type": "integer"}
+                    },
+                    "x-kubernetes-validations": [
+                        {"rule": "self.port == oldSelf.port", "message": "port is immutable"}
+                    ]
+                }
+            }
+        }
+    });
+
+    // Reordered and with a new entry inserted at the front: each item is
+    // compared against the old item with the same name, not the same index.
+    let old = json!({"ports": [
+        {"name": "http", "port": 80},
+        {"name": "https", "port": 443}
+    ]});
+    let obj = json!({"ports": [
+        {"name": "metrics", "port": 9090},
+        {"name": "https", "port": 443},
+        {"name": "http", "port": 8080}
+    ]});
+    let errors = validate(&schema, &obj, Some(&old));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].field_path, "ports[2]");
+    assert_eq!(errors[0].message, "port is immutable");
+}
+
 #[test]
 fn realistic_istio_like_crd() {
     // Simplified Istio VirtualService-like schema
@@ -880,18 +921,14 @@ fn compiled_schema_matches_schema_validation() {
         }
     });
 
-    let mut errors_schema = validate(&schema, &obj, None);
+    let errors_schema = validate(&schema, &obj, None);
     let compiled = compile_schema(&schema);
-    let mut errors_compiled = validate_compiled(&compiled, &obj, None);
+    let errors_compiled = validate_compiled(&compiled, &obj, None);
 
     // Both should produce the same number of errors
     assert_eq!(errors_schema.len(), errors_compiled.len());
 
-    // Sort by field_path for deterministic comparison (HashMap iteration order may differ)
-    errors_schema.sort_by(|a, b| a.field_path.cmp(&b.field_path));
-    errors_compiled.sort_by(|a, b| a.field_path.cmp(&b.field_path));
-
-    // Same field paths and messages
+    // Same field paths and messages, in the same order
     for (a, b) in errors_schema.iter().zip(errors_compiled.iter()) {
         assert_eq!(a.field_path, b.field_path);
         assert_eq!(a.message, b.message);
@@ -936,14 +973,14 @@ fn kube_core_rule_json_compatibility() {
         Some("host must match spec.host")
     );
     assert_eq!(r0.rule.field_path.as_deref(), Some("spec.host"));
-    assert_eq!(r0.rule.reason.as_deref(), Some("FieldValueInvalid"));
+    assert_eq!(r0.rule.reason, Some(Reason::FieldValueInvalid));
     assert!(!r0.is_transition_rule);
 
     // Second rule: messageExpression + transition rule
     let r1 = results[1].as_ref().unwrap();
     assert!(r1.rule.message.is_none());
     assert!(r1.rule.message_expression.is_some());
-    assert_eq!(r1.rule.reason.as_deref(), Some("FieldValueForbidden"));
+    assert_eq!(r1.rule.reason, Some(Reason::FieldValueForbidden));
     assert!(r1.is_transition_rule);
 
     // Third rule: minimal (only rule field)
@@ -971,20 +1008,20 @@ fn kube_core_reason_values() {
     let results = &compiled.validations;
     assert_eq!(results.len(), 4);
     assert_eq!(
-        results[0].as_ref().unwrap().rule.reason.as_deref(),
-        Some("FieldValueInvalid")
+        results[0].as_ref().unwrap().rule.reason,
+        Some(Reason::FieldValueInvalid)
     );
     assert_eq!(
-        results[1].as_ref().unwrap().rule.reason.as_deref(),
-        Some("FieldValueForbidden")
+        results[1].as_ref().unwrap().rule.reason,
+        Some(Reason::FieldValueForbidden)
     );
     assert_eq!(
-        results[2].as_ref().unwrap().rule.reason.as_deref(),
-        Some("FieldValueRequired")
+        results[2].as_ref().unwrap().rule.reason,
+        Some(Reason::FieldValueRequired)
     );
     assert_eq!(
-        results[3].as_ref().unwrap().rule.reason.as_deref(),
-        Some("FieldValueDuplicate")
+        results[3].as_ref().unwrap().rule.reason,
+        Some(Reason::FieldValueDuplicate)
     );
 }
 